@@ -0,0 +1,26 @@
+//! Message-path encryption at a few sizes:
+//! `cargo bench --bench crypto`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use eurus::crypto::{decrypt_bytes, encrypt, generate_key, RoomKey};
+use std::hint::black_box;
+
+fn encrypt_decrypt(c: &mut Criterion) {
+    let room_key = RoomKey::new(generate_key());
+    let mut group = c.benchmark_group("message");
+    for size in [64, 1024, 64 * 1024] {
+        let plaintext = vec![7u8; size];
+        let sealed = encrypt(&room_key, &plaintext).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("encrypt", size), &plaintext, |b, plaintext| {
+            b.iter(|| encrypt(&room_key, black_box(plaintext)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("decrypt", size), &sealed, |b, sealed| {
+            b.iter(|| decrypt_bytes(&room_key, black_box(sealed)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encrypt_decrypt);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+//! Records the git commit being built as `EURUS_GIT_COMMIT`, for the about
+//! view. Builds from a source tarball get "unknown".
+
+use std::fs;
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=EURUS_GIT_COMMIT={}", commit);
+
+    // Rebuild when HEAD moves: on checkout, and on commit to the current branch
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Ok(head) = fs::read_to_string(".git/HEAD") {
+        if let Some(branch) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed=.git/{}", branch);
+        }
+    }
+}
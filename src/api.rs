@@ -17,6 +17,55 @@ pub struct SendMessagePayload<'a> {
     #[serde(rename = "roomId")]
     pub room_id: &'a str,
     pub ciphertext: &'a str,
+    /// Echoed back so the sender can match the server's copy to its local one
+    #[serde(rename = "clientId", skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<&'a str>,
+}
+
+/// Ask for messages older than `before` (a message id); the newest page if unset
+#[derive(Serialize)]
+pub struct FetchHistoryPayload<'a> {
+    #[serde(rename = "roomId")]
+    pub room_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<&'a str>,
+    pub limit: usize,
+}
+
+#[derive(Serialize)]
+pub struct EditMessagePayload<'a> {
+    #[serde(rename = "roomId")]
+    pub room_id: &'a str,
+    #[serde(rename = "messageId")]
+    pub message_id: &'a str,
+    pub ciphertext: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct DeleteMessagePayload<'a> {
+    #[serde(rename = "roomId")]
+    pub room_id: &'a str,
+    #[serde(rename = "messageId")]
+    pub message_id: &'a str,
+}
+
+/// Reactions are plaintext; only the room binding matters
+#[derive(Serialize)]
+pub struct ReactionPayload<'a> {
+    #[serde(rename = "roomId")]
+    pub room_id: &'a str,
+    #[serde(rename = "messageId")]
+    pub message_id: &'a str,
+    pub emoji: &'a str,
+    pub remove: bool,
+}
+
+#[derive(Serialize)]
+pub struct PinPayload<'a> {
+    #[serde(rename = "roomId")]
+    pub room_id: &'a str,
+    #[serde(rename = "messageId")]
+    pub message_id: &'a str,
 }
 
 #[derive(Serialize)]
@@ -78,6 +127,21 @@ pub struct CreateDMPayload<'a> {
     pub target_username: &'a str,
 }
 
+/// A message for one member of the room. With `sealed_key` the ciphertext
+/// is under a one-off key sealed to the recipient's SSH key; without it,
+/// it's under the room key and only the server's routing keeps other
+/// members from seeing it.
+#[derive(Serialize)]
+pub struct WhisperPayload<'a> {
+    #[serde(rename = "roomId")]
+    pub room_id: &'a str,
+    #[serde(rename = "targetUsername")]
+    pub target_username: &'a str,
+    pub ciphertext: &'a str,
+    #[serde(rename = "sealedKey", skip_serializing_if = "Option::is_none")]
+    pub sealed_key: Option<&'a str>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VoiceSignalPayload {
     #[serde(rename = "roomId")]
@@ -101,18 +165,187 @@ pub struct ClientMessage<'a, T> {
     pub payload: T,
 }
 
+/// Application-level heartbeat; the server echoes it back unchanged
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HeartbeatPayload {
+    #[serde(default)]
+    pub seq: u64,
+}
+
+/// Plaintext carried inside a message's ciphertext.
+/// Plain messages are still sent as bare text so older clients can read them;
+/// the JSON form is only used when there is metadata (like a reply) to carry,
+/// or when the text itself could be mistaken for it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MessageEnvelope {
+    #[serde(rename = "eurus")]
+    pub version: u8,
+    pub text: String,
+    #[serde(rename = "replyTo", default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    #[serde(default, skip_serializing_if = "MessageKind::is_text")]
+    pub kind: MessageKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<EnvelopeSignature>,
+}
+
+/// The sender's SSH signature over `MessageEnvelope::signed_bytes`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeSignature {
+    /// OpenSSH public key that made it
+    pub key: String,
+    /// Hex-encoded signature
+    pub sig: String,
+}
+
+/// What a message is, so clients can render it differently
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageKind {
+    #[default]
+    Text,
+    /// IRC-style `/me` action, shown as "* alice waves"
+    Action,
+}
+
+impl MessageKind {
+    fn is_text(&self) -> bool {
+        *self == MessageKind::Text
+    }
+}
+
+impl MessageEnvelope {
+    pub const VERSION: u8 = 1;
+
+    pub fn new(text: String) -> Self {
+        Self { version: Self::VERSION, text, ..Default::default() }
+    }
+
+    fn has_metadata(&self) -> bool {
+        self.reply_to.is_some() || !self.kind.is_text() || self.sig.is_some()
+    }
+
+    /// What a signature covers: everything but the signature, tied to the
+    /// room so it can't be replayed into another one
+    pub fn signed_bytes(&self, room_id: &str) -> Vec<u8> {
+        let unsigned = Self { sig: None, ..self.clone() };
+        let body = serde_json::to_string(&unsigned).unwrap_or_default();
+        format!("eurus-message:{}:{}", room_id, body).into_bytes()
+    }
+
+    /// Plain messages go out as bare text. Text that starts with `{` is
+    /// always wrapped, so typing an envelope can't pass for one.
+    pub fn encode(&self) -> String {
+        if self.has_metadata() || self.text.starts_with('{') {
+            serde_json::to_string(self).unwrap_or_else(|_| self.text.clone())
+        } else {
+            self.text.clone()
+        }
+    }
+
+    /// Anything that isn't a well-formed envelope of our version is treated
+    /// as bare text
+    pub fn decode(plaintext: &str) -> Self {
+        if plaintext.starts_with('{') {
+            if let Ok(envelope) = serde_json::from_str::<Self>(plaintext) {
+                if envelope.version == Self::VERSION {
+                    return envelope;
+                }
+            }
+        }
+        Self::new(plaintext.to_string())
+    }
+}
+
 // ============================================================================
 // SERVER → CLIENT MESSAGES
 // ============================================================================
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct MessagePayload {
-    #[allow(dead_code)]
     pub id: String,
     pub username: String,
     pub ciphertext: String,
     #[allow(dead_code)]
     pub timestamp: String,
+    #[serde(default)]
+    pub edited: bool,
+    #[serde(default)]
+    pub reactions: Vec<ReactionSummary>,
+    #[serde(rename = "clientId", default)]
+    pub client_id: Option<String>,
+}
+
+/// A whisper for us, or one we sent from another session
+#[derive(Deserialize, Debug, Clone)]
+pub struct WhisperEventPayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    pub username: String,
+    #[serde(rename = "targetUsername")]
+    pub target_username: String,
+    pub ciphertext: String,
+    pub timestamp: String,
+    #[serde(rename = "sealedKey", default)]
+    pub sealed_key: Option<String>,
+}
+
+/// A page of older messages, oldest first
+#[derive(Deserialize, Debug, Clone)]
+pub struct HistoryPayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    pub messages: Vec<MessagePayload>,
+    /// Whether anything older remains; servers that leave it out are
+    /// assumed to have more whenever they filled the page
+    #[serde(rename = "hasMore", default)]
+    pub has_more: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReactionSummary {
+    pub emoji: String,
+    pub usernames: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PinEventPayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    pub pinned: bool,
+    #[serde(default)]
+    pub username: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReactionEventPayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    pub username: String,
+    pub emoji: String,
+    #[serde(default)]
+    pub remove: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MessageEditedPayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    pub ciphertext: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MessageDeletedPayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -155,6 +388,12 @@ pub struct RoomJoinedPayload {
     pub messages: Vec<MessagePayload>,
     #[serde(rename = "onlineUsers", default)]
     pub online_users: Vec<OnlineUser>,
+    /// Ids of pinned messages, oldest first
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    /// The room only accepts messages signed with the sender's SSH key
+    #[serde(rename = "requireSignatures", default)]
+    pub require_signatures: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -201,6 +440,29 @@ pub struct RoomsListPayload {
 #[derive(Deserialize, Debug, Clone)]
 pub struct ErrorPayload {
     pub message: String,
+    /// Machine-readable reason; older servers only send the message
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
+    /// Seconds to wait before trying again, sent with `RateLimited`
+    #[serde(rename = "retryAfter", default)]
+    pub retry_after: Option<u64>,
+}
+
+/// Server error reasons the client reacts to
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    RateLimited,
+    /// The token is invalid or expired; reconnecting won't help
+    Unauthorized,
+    RoomFull,
+    RoomNotFound,
+    /// Not allowed in this room (e.g. owner-only actions)
+    Forbidden,
+    MessageTooLarge,
+    /// A code this client doesn't know yet
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -248,8 +510,6 @@ pub struct RoomDeletedPayload {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct OwnershipTransferredPayload {
-    #[serde(rename = "roomId")]
-    pub room_id: String,
     #[serde(rename = "newOwnerUsername")]
     pub new_owner_username: String,
     #[serde(rename = "newOwnerId")]
@@ -257,6 +517,45 @@ pub struct OwnershipTransferredPayload {
     pub new_owner_id: String,
 }
 
+/// Whether a room member is at their keyboard
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MemberStatus {
+    #[default]
+    Active,
+    Away,
+    /// A status this client doesn't know yet
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RoomMember {
+    pub username: String,
+    #[serde(default)]
+    pub status: MemberStatus,
+    /// OpenSSH public key, for sealing whispers to them
+    #[serde(rename = "publicKey", default)]
+    pub public_key: Option<String>,
+}
+
+/// Everyone in a room, sent on join by servers that support rosters
+#[derive(Deserialize, Debug, Clone)]
+pub struct RoomMembersPayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    pub members: Vec<RoomMember>,
+}
+
+/// A member's status changed
+#[derive(Deserialize, Debug, Clone)]
+pub struct PresencePayload {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    pub username: String,
+    pub status: MemberStatus,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct VoiceStatePayload {
     #[serde(rename = "roomId")]
@@ -271,9 +570,17 @@ pub struct VoiceStatePayload {
 #[serde(rename_all = "camelCase")]
 pub enum ServerMessage {
     Message(MessagePayload),
+    Whisper(WhisperEventPayload),
+    MessageEdited(MessageEditedPayload),
+    MessageDeleted(MessageDeletedPayload),
+    Reaction(ReactionEventPayload),
+    Pin(PinEventPayload),
     UserJoined(UserJoinedPayload),
     UserLeft(UserLeftPayload),
     RoomJoined(RoomJoinedPayload),
+    RoomMembers(RoomMembersPayload),
+    Presence(PresencePayload),
+    History(HistoryPayload),
     RoomCreated(RoomCreatedPayload),
     RoomsList(RoomsListPayload),
     Info(InfoPayload),
@@ -285,4 +592,28 @@ pub enum ServerMessage {
     OwnershipTransferred(OwnershipTransferredPayload),
     VoiceSignal(VoiceSignalPayload),
     VoiceState(VoiceStatePayload),
+    Heartbeat(HeartbeatPayload),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_round_trip() {
+        assert_eq!(MessageEnvelope::new("hi".to_string()).encode(), "hi");
+        let reply = MessageEnvelope { reply_to: Some("m1".to_string()), ..MessageEnvelope::new("yes".to_string()) };
+        assert_eq!(MessageEnvelope::decode(&reply.encode()), reply);
+
+        // Typed JSON stays text, on the way out and on the way in
+        let forged = r#"{"eurus":1,"text":"x","replyTo":"m1","kind":"action"}"#;
+        let sent = MessageEnvelope::new(forged.to_string()).encode();
+        assert_ne!(sent, forged);
+        let received = MessageEnvelope::decode(&sent);
+        assert_eq!((received.text.as_str(), received.reply_to, received.kind), (forged, None, MessageKind::Text));
+
+        // Other versions aren't ours to read
+        let future = r#"{"eurus":2,"text":"x","replyTo":"m1"}"#;
+        assert_eq!(MessageEnvelope::decode(future), MessageEnvelope::new(future.to_string()));
+    }
 }
@@ -1,11 +1,78 @@
+#[cfg(feature = "clipboard")]
 use arboard::{Clipboard, ImageData};
+#[cfg(feature = "clipboard")]
 use image::ImageFormat;
+#[cfg(feature = "clipboard")]
 use std::io::Cursor;
 
+use crate::config::ImageConfig;
+
+/// Clipboard images bigger than this (in pixels) are refused outright rather
+/// than scaled: 64 megapixels is already 256MB of RGBA
+#[cfg(feature = "clipboard")]
+const MAX_SOURCE_PIXELS: usize = 64 * 1024 * 1024;
+
+/// Tries at shrinking an image that's still over the byte limit
+#[cfg(feature = "clipboard")]
+const SHRINK_ATTEMPTS: usize = 3;
+
+/// Status line text for clipboard keys when there's no clipboard to use
+#[cfg(feature = "clipboard")]
+pub const UNAVAILABLE: &str = "Clipboard not available";
+#[cfg(not(feature = "clipboard"))]
+pub const UNAVAILABLE: &str = "Clipboard support not compiled in";
+
+/// An image taken from the clipboard, encoded as PNG
+#[cfg_attr(not(feature = "clipboard"), allow(dead_code))]
+pub struct PastedImage {
+    pub png: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Original size, if it had to be scaled down to fit the limits
+    pub resized_from: Option<(u32, u32)>,
+}
+
+impl PastedImage {
+    /// Short description for the composer, like "[image 640x480, 24KB]"
+    pub fn label(&self) -> String {
+        format!("[image {}x{}, {}KB]", self.width, self.height, self.png.len().div_ceil(1024))
+    }
+}
+
+#[cfg(feature = "clipboard")]
 pub struct ClipboardManager {
     clipboard: Clipboard,
 }
 
+/// Without the `clipboard` feature `new` always fails, so callers only ever
+/// hold `None` and the methods can't be reached
+#[cfg(not(feature = "clipboard"))]
+pub struct ClipboardManager(std::convert::Infallible);
+
+#[cfg(not(feature = "clipboard"))]
+impl ClipboardManager {
+    pub fn new() -> Result<Self, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn copy_text(&mut self, _text: &str) -> Result<(), String> {
+        match self.0 {}
+    }
+
+    pub fn paste_text(&mut self) -> Result<String, String> {
+        match self.0 {}
+    }
+
+    pub fn has_image(&mut self) -> bool {
+        match self.0 {}
+    }
+
+    pub fn paste_image(&mut self, _limits: &ImageConfig) -> Result<PastedImage, String> {
+        match self.0 {}
+    }
+}
+
+#[cfg(feature = "clipboard")]
 impl ClipboardManager {
     pub fn new() -> Result<Self, String> {
         match Clipboard::new() {
@@ -33,41 +100,27 @@ impl ClipboardManager {
         self.clipboard.get_image().is_ok()
     }
     
-    /// Paste image from clipboard as PNG bytes
-    #[allow(dead_code)]
-    pub fn paste_image(&mut self) -> Result<Vec<u8>, String> {
+    /// Paste image from clipboard as PNG bytes, downscaled to fit `limits`
+    pub fn paste_image(&mut self, limits: &ImageConfig) -> Result<PastedImage, String> {
         let img = self.clipboard
             .get_image()
             .map_err(|e| format!("No image in clipboard: {}", e))?;
-        
-        // Convert ImageData to PNG bytes
-        let rgba = img.bytes.to_vec();
-        let width = img.width;
-        let height = img.height;
-        
+
+        // Refuse before copying or encoding anything
+        if img.width.saturating_mul(img.height) > MAX_SOURCE_PIXELS {
+            return Err(format!("Clipboard image is {}x{}, too large to attach", img.width, img.height));
+        }
+
         // Create image buffer
         let img_buffer = image::RgbaImage::from_raw(
-            width as u32,
-            height as u32,
-            rgba,
+            img.width as u32,
+            img.height as u32,
+            img.bytes.into_owned(),
         ).ok_or("Failed to create image buffer")?;
-        
-        // Encode as PNG
-        let mut png_bytes = Vec::new();
-        let mut cursor = Cursor::new(&mut png_bytes);
-        
-        image::write_buffer_with_format(
-            &mut cursor,
-            &img_buffer,
-            width as u32,
-            height as u32,
-            image::ColorType::Rgba8,
-            ImageFormat::Png,
-        ).map_err(|e| format!("Failed to encode image: {}", e))?;
-        
-        Ok(png_bytes)
+
+        shrink_and_encode(img_buffer, limits)
     }
-    
+
     /// Copy image to clipboard from PNG bytes
     #[allow(dead_code)]
     pub fn copy_image(&mut self, png_bytes: &[u8]) -> Result<(), String> {
@@ -89,3 +142,94 @@ impl ClipboardManager {
             .map_err(|e| format!("Failed to copy image: {}", e))
     }
 }
+
+/// Largest size with the same aspect ratio whose longer side is at most `max`
+#[cfg(feature = "clipboard")]
+fn fit_within(width: u32, height: u32, max: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max {
+        return (width, height);
+    }
+    let scale = |side: u32| ((side as u64 * max as u64 / longest as u64) as u32).max(1);
+    (scale(width), scale(height))
+}
+
+/// Scale `img` down to `limits.max_dimension`, encode it as PNG, and keep
+/// shrinking while the PNG is over `limits.max_kb`
+#[cfg(feature = "clipboard")]
+fn shrink_and_encode(mut img: image::RgbaImage, limits: &ImageConfig) -> Result<PastedImage, String> {
+    use image::imageops::{resize, FilterType};
+
+    let original = img.dimensions();
+    let (width, height) = fit_within(original.0, original.1, limits.max_dimension);
+    if (width, height) != original {
+        img = resize(&img, width, height, FilterType::Triangle);
+    }
+
+    let max_bytes = limits.max_kb as usize * 1024;
+    let mut attempts = 0;
+    loop {
+        let mut png = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode image: {}", e))?;
+
+        // Describe what will actually be sent, read back from the PNG itself
+        let (width, height) = image::ImageReader::with_format(Cursor::new(&png), ImageFormat::Png)
+            .into_dimensions()
+            .map_err(|e| format!("Failed to read encoded image: {}", e))?;
+
+        if png.len() <= max_bytes {
+            let resized_from = ((width, height) != original).then_some(original);
+            return Ok(PastedImage { png, width, height, resized_from });
+        }
+        if attempts == SHRINK_ATTEMPTS {
+            return Err(format!(
+                "Image is still {}KB as PNG at {}x{} (limit {}KB)",
+                png.len().div_ceil(1024), width, height, limits.max_kb
+            ));
+        }
+        attempts += 1;
+        // PNG size goes roughly with area; aim a little under the limit
+        let scale = (max_bytes as f64 / png.len() as f64).sqrt() * 0.9;
+        let shrink = |side: u32| ((side as f64 * scale) as u32).max(1);
+        img = resize(&img, shrink(width), shrink(height), FilterType::Triangle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pasted_image_label() {
+        let image = PastedImage { png: vec![0; 24 * 1024 + 1], width: 640, height: 480, resized_from: None };
+        assert_eq!(image.label(), "[image 640x480, 25KB]");
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn test_shrink_and_encode() {
+        assert_eq!(fit_within(4000, 3000, 1920), (1920, 1440));
+        assert_eq!(fit_within(300, 4000, 1000), (75, 1000));
+        assert_eq!(fit_within(640, 480, 1920), (640, 480));
+
+        let limits = ImageConfig { max_dimension: 200, max_kb: 1024 };
+        let image = shrink_and_encode(image::RgbaImage::new(800, 600), &limits).unwrap();
+        assert_eq!((image.width, image.height), (200, 150));
+        assert_eq!(image.resized_from, Some((800, 600)));
+
+        let small = shrink_and_encode(image::RgbaImage::new(100, 50), &limits).unwrap();
+        assert_eq!(small.resized_from, None);
+
+        // Noise barely compresses, so it has to shrink again to fit 16KB
+        let noise = image::RgbaImage::from_fn(200, 200, |x, y| {
+            let v = (x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503)).to_le_bytes();
+            image::Rgba([v[0], v[1], v[2], 255])
+        });
+        let limits = ImageConfig { max_dimension: 200, max_kb: 16 };
+        let shrunk = shrink_and_encode(noise, &limits).unwrap();
+        assert!(shrunk.png.len() <= 16 * 1024);
+        assert!(shrunk.width < 200);
+        assert_eq!(shrunk.resized_from, Some((200, 200)));
+    }
+}
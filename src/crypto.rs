@@ -1,14 +1,77 @@
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
-    Aes256Gcm, Nonce,
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        AeadInPlace, KeyInit, OsRng,
+    },
+    Aes256Gcm, Nonce, Tag,
 };
+use curve25519_dalek::MontgomeryPoint;
+use hkdf::Hkdf;
 use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::ops::Deref;
+use zeroize::Zeroize;
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
 
 // AES-256-GCM requires a 32-byte key.
 pub type AesKey = aes_gcm::Key<Aes256Gcm>;
 
+/// Something `encrypt` and `decrypt_bytes` can get a cipher from
+pub trait CipherKey {
+    fn with_cipher<T>(&self, f: impl FnOnce(&Aes256Gcm) -> T) -> T;
+}
+
+/// A one-off key builds its cipher for each call
+impl CipherKey for AesKey {
+    fn with_cipher<T>(&self, f: impl FnOnce(&Aes256Gcm) -> T) -> T {
+        f(&Aes256Gcm::new(self))
+    }
+}
+
+/// A room's key with its cipher built once. Nearly every message uses the
+/// room key, and building a cipher (AES key schedule plus GHASH setup) costs
+/// about as much as sealing a short message. Both are wiped when dropped.
+pub struct RoomKey {
+    key: AesKey,
+    cipher: Aes256Gcm,
+}
+
+impl RoomKey {
+    pub fn new(key: AesKey) -> Self {
+        Self { cipher: Aes256Gcm::new(&key), key }
+    }
+}
+
+impl Deref for RoomKey {
+    type Target = AesKey;
+
+    fn deref(&self) -> &AesKey {
+        &self.key
+    }
+}
+
+impl CipherKey for RoomKey {
+    fn with_cipher<T>(&self, f: impl FnOnce(&Aes256Gcm) -> T) -> T {
+        f(&self.cipher)
+    }
+}
+
+impl Drop for RoomKey {
+    // The cipher zeroizes its own key schedule
+    fn drop(&mut self) {
+        self.key.as_mut_slice().zeroize();
+    }
+}
+
+impl std::fmt::Debug for RoomKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RoomKey(..)")
+    }
+}
+
 /// Generates a new, random 32-byte key for AES-256-GCM encryption.
-#[allow(dead_code)]
 pub fn generate_key() -> AesKey {
     Aes256Gcm::generate_key(OsRng)
 }
@@ -16,11 +79,31 @@ pub fn generate_key() -> AesKey {
 /// Decodes a hex-encoded key string into an AesKey.
 /// Returns None if the hex is invalid or not exactly 32 bytes.
 pub fn key_from_hex(hex_key: &str) -> Option<AesKey> {
-    let bytes = hex::decode(hex_key).ok()?;
-    if bytes.len() != 32 {
-        return None;
+    parse_key_hex(hex_key).ok()
+}
+
+/// Like `key_from_hex`, but says what's wrong with the input, for showing to
+/// the user.
+pub fn parse_key_hex(hex_key: &str) -> Result<AesKey, String> {
+    if let Some((i, c)) = hex_key.chars().enumerate().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' at position {} isn't a hex digit", c, i + 1));
     }
-    Some(*AesKey::from_slice(&bytes))
+    if hex_key.len() != 64 {
+        return Err(format!("{} hex characters, need 64", hex_key.len()));
+    }
+    let bytes = hex::decode(hex_key).map_err(|e| e.to_string())?;
+    Ok(*AesKey::from_slice(&bytes))
+}
+
+/// Short SHA-256 digest of a room key, for two people to compare out loud
+/// without revealing the key itself, e.g. "3f8a 9c01 b2c1 0044".
+pub fn key_fingerprint(key: &AesKey) -> String {
+    let digest = Sha256::digest(key);
+    digest[..8]
+        .chunks(2)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 /// Encrypts the given plaintext using AES-256-GCM.
@@ -32,19 +115,38 @@ pub fn key_from_hex(hex_key: &str) -> Option<AesKey> {
 /// 4. Hex-encode the combined (nonce + ciphertext) for easy transport.
 ///
 /// Returns the hex-encoded string or an error.
-pub fn encrypt(key: &AesKey, plaintext: &[u8]) -> Result<String, aes_gcm::Error> {
-    let cipher = Aes256Gcm::new(key);
+pub fn encrypt(key: &impl CipherKey, plaintext: &[u8]) -> Result<String, aes_gcm::Error> {
+    encrypt_with_aad(key, plaintext, b"")
+}
+
+/// `encrypt`, also authenticating `aad` (which isn't sent). The ciphertext
+/// only opens with `decrypt_with_aad` and the same `aad`, so it can't be
+/// moved to somewhere the `aad` is different.
+pub fn encrypt_with_aad(key: &impl CipherKey, plaintext: &[u8], aad: &[u8]) -> Result<String, aes_gcm::Error> {
     let mut nonce_bytes = [0u8; 12];
     OsRng.fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
+    encrypt_with_nonce(key, &nonce_bytes, plaintext, aad)
+}
 
-    let ciphertext = cipher.encrypt(nonce, plaintext)?;
+/// `encrypt_with_aad` with the nonce supplied, so tests can check exact
+/// output against known-answer vectors. Never reuse a nonce with the same key.
+fn encrypt_with_nonce(key: &impl CipherKey, nonce_bytes: &[u8; 12], plaintext: &[u8], aad: &[u8]) -> Result<String, aes_gcm::Error> {
+    // One buffer laid out as it's sent, sealed in place
+    let mut combined = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+    combined.extend_from_slice(nonce_bytes);
+    combined.extend_from_slice(plaintext);
+    let tag = key.with_cipher(|cipher| {
+        cipher.encrypt_in_place_detached(Nonce::from_slice(nonce_bytes), aad, &mut combined[NONCE_LEN..])
+    })?;
+    combined.extend_from_slice(&tag);
 
-    let mut combined = Vec::with_capacity(nonce.len() + ciphertext.len());
-    combined.extend_from_slice(nonce.as_slice());
-    combined.extend_from_slice(&ciphertext);
+    Ok(hex::encode(&combined))
+}
 
-    Ok(hex::encode(combined))
+/// Length of the hex string `encrypt` produces for `plaintext_len` bytes:
+/// nonce + ciphertext + tag, doubled by hex encoding.
+pub fn encrypted_len(plaintext_len: usize) -> usize {
+    (NONCE_LEN + plaintext_len + TAG_LEN) * 2
 }
 
 /// Decrypts a hex-encoded ciphertext that was encrypted with `encrypt`.
@@ -54,21 +156,283 @@ pub fn encrypt(key: &AesKey, plaintext: &[u8]) -> Result<String, aes_gcm::Error>
 /// 2. Split the 12-byte nonce from the front of the data.
 /// 3. Decrypt the remaining ciphertext using the key and nonce.
 ///
-/// Returns the decrypted plaintext as a String or an error if decryption fails.
-pub fn decrypt(key: &AesKey, hex_ciphertext: &str) -> Result<String, String> {
-    let combined = hex::decode(hex_ciphertext).map_err(|e| format!("Hex decode error: {}", e))?;
+/// Returns the raw plaintext bytes, or an error if the ciphertext is malformed
+/// or fails authentication.
+pub fn decrypt_bytes(key: &impl CipherKey, hex_ciphertext: &str) -> Result<Vec<u8>, String> {
+    decrypt_with_aad(key, hex_ciphertext, b"")
+}
 
-    if combined.len() < 12 {
+/// Decrypts what `encrypt_with_aad` sealed with the same `aad`
+pub fn decrypt_with_aad(key: &impl CipherKey, hex_ciphertext: &str, aad: &[u8]) -> Result<Vec<u8>, String> {
+    let mut combined = hex::decode(hex_ciphertext).map_err(|e| format!("Hex decode error: {}", e))?;
+
+    if combined.len() < NONCE_LEN {
         return Err("Ciphertext is too short to contain a nonce".to_string());
     }
+    if combined.len() < NONCE_LEN + TAG_LEN {
+        return Err(format!("Decryption error: {}", aes_gcm::Error));
+    }
+
+    // Open in place, then shift the plaintext down over the nonce
+    let tag_at = combined.len() - TAG_LEN;
+    let (head, tag) = combined.split_at_mut(tag_at);
+    let (nonce, ciphertext) = head.split_at_mut(NONCE_LEN);
+    key.with_cipher(|cipher| {
+        cipher.decrypt_in_place_detached(Nonce::from_slice(nonce), aad, ciphertext, Tag::from_slice(tag))
+    })
+    .map_err(|e| format!("Decryption error: {}", e))?;
+    combined.truncate(tag_at);
+    combined.drain(..NONCE_LEN);
+    Ok(combined)
+}
 
-    let (nonce_bytes, ciphertext) = combined.split_at(12);
-    let nonce = Nonce::from_slice(nonce_bytes);
+/// Random prefix of every nonce in a stream; the STREAM construction fills
+/// the other 5 bytes with the chunk counter and the last-chunk flag
+pub const STREAM_HEADER_LEN: usize = 7;
 
-    let cipher = Aes256Gcm::new(key);
-    let plaintext_bytes = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| format!("Decryption error: {}", e))?;
+/// Encrypts a large payload (a file) as one authenticated stream of chunks.
+/// Each chunk's nonce carries its position and whether it's the last, so
+/// the receiver rejects chunks that are reordered, dropped, or cut short.
+///
+/// Send `header()` first, then each `encrypt_chunk` output, then the output
+/// of `finish` as the final frame. Frames are raw bytes, no hex.
+pub struct StreamEncryptor {
+    header: [u8; STREAM_HEADER_LEN],
+    inner: EncryptorBE32<Aes256Gcm>,
+}
 
-    String::from_utf8(plaintext_bytes).map_err(|e| format!("UTF-8 conversion error: {}", e))
+impl StreamEncryptor {
+    pub fn header(&self) -> &[u8; STREAM_HEADER_LEN] {
+        &self.header
+    }
+
+    /// Encrypt the next chunk; the frame is the chunk plus a 16-byte tag
+    pub fn encrypt_chunk(&mut self, chunk: &[u8]) -> Result<Vec<u8>, String> {
+        self.inner.encrypt_next(chunk).map_err(|e| format!("Encryption error: {}", e))
+    }
+
+    /// Encrypt the final chunk (possibly empty), ending the stream
+    pub fn finish(self, chunk: &[u8]) -> Result<Vec<u8>, String> {
+        self.inner.encrypt_last(chunk).map_err(|e| format!("Encryption error: {}", e))
+    }
 }
+
+/// Start encrypting a stream under `key` with a fresh random header
+pub fn stream_encryptor(key: &AesKey) -> StreamEncryptor {
+    let mut header = [0u8; STREAM_HEADER_LEN];
+    OsRng.fill_bytes(&mut header);
+    StreamEncryptor { header, inner: EncryptorBE32::new(key, header.as_slice().into()) }
+}
+
+/// Decrypts the frames of a `StreamEncryptor`, in the order they were made.
+/// A stream is only complete once `finish` accepts its last frame; frames
+/// that stop before that mean it was truncated.
+pub struct StreamDecryptor {
+    inner: DecryptorBE32<Aes256Gcm>,
+}
+
+impl StreamDecryptor {
+    pub fn decrypt_chunk(&mut self, frame: &[u8]) -> Result<Vec<u8>, String> {
+        self.inner.decrypt_next(frame).map_err(|e| format!("Decryption error: {}", e))
+    }
+
+    /// Decrypt the final frame; fails if it wasn't sealed as the last one
+    pub fn finish(self, frame: &[u8]) -> Result<Vec<u8>, String> {
+        self.inner.decrypt_last(frame).map_err(|e| format!("Decryption error: {}", e))
+    }
+}
+
+/// Start decrypting a stream from its header
+pub fn stream_decryptor(key: &AesKey, header: &[u8]) -> Result<StreamDecryptor, String> {
+    if header.len() != STREAM_HEADER_LEN {
+        return Err(format!("Stream header is {} bytes, need {}", header.len(), STREAM_HEADER_LEN));
+    }
+    Ok(StreamDecryptor { inner: DecryptorBE32::new(key, header.into()) })
+}
+
+/// HKDF info string for keys derived by `seal_for_pubkey`
+const SEAL_INFO: &[u8] = b"eurus sealed v1";
+
+/// Encrypts `plaintext` so only the holder of `public_key`'s private half can
+/// read it. The ed25519 key is converted to X25519 and combined with a fresh
+/// ephemeral key; HKDF-SHA256 of the shared secret keys AES-256-GCM.
+/// RSA and other key types are refused.
+///
+/// Returns hex: the 32-byte ephemeral public key followed by `encrypt` output.
+pub fn seal_for_pubkey(public_key: &ssh_key::PublicKey, plaintext: &[u8]) -> Result<String, String> {
+    let recipient = match public_key.key_data() {
+        ssh_key::public::KeyData::Ed25519(key) => ed25519_dalek::VerifyingKey::from_bytes(&key.0)
+            .map_err(|e| format!("Invalid ed25519 key: {}", e))?
+            .to_montgomery(),
+        other => return Err(format!("Can't seal to {} keys, only ed25519", other.algorithm())),
+    };
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let ephemeral = MontgomeryPoint::mul_base_clamped(secret);
+    let key = sealing_key(recipient.mul_clamped(secret), &ephemeral, &recipient)?;
+    let ciphertext = encrypt(&key, plaintext).map_err(|e| format!("Encryption error: {}", e))?;
+    Ok(format!("{}{}", hex::encode(ephemeral.as_bytes()), ciphertext))
+}
+
+/// Decrypts the output of `seal_for_pubkey` with the matching ed25519 private key.
+pub fn open_with_privkey(private_key: &ssh_key::PrivateKey, sealed: &str) -> Result<Vec<u8>, String> {
+    let ssh_key::private::KeypairData::Ed25519(keypair) = private_key.key_data() else {
+        return Err(format!("Can't open sealed messages with {} keys, only ed25519", private_key.algorithm()));
+    };
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&keypair.private.to_bytes());
+    let recipient = signing_key.verifying_key().to_montgomery();
+
+    let ephemeral_hex = sealed.get(..64).ok_or("Sealed message is too short")?;
+    let ephemeral: [u8; 32] = hex::decode(ephemeral_hex)
+        .map_err(|e| format!("Hex decode error: {}", e))?
+        .try_into()
+        .map_err(|_| "Bad ephemeral key".to_string())?;
+    let ephemeral = MontgomeryPoint(ephemeral);
+    let key = sealing_key(ephemeral.mul_clamped(signing_key.to_scalar_bytes()), &ephemeral, &recipient)?;
+    decrypt_bytes(&key, &sealed[64..])
+}
+
+/// Opens a room key sealed with `seal_for_pubkey`, trying each of `private_keys`
+pub fn open_sealed_key(private_keys: &[ssh_key::PrivateKey], sealed: &str) -> Option<AesKey> {
+    private_keys
+        .iter()
+        .find_map(|private_key| open_with_privkey(private_key, sealed).ok())
+        .filter(|key| key.len() == 32)
+        .map(|key| *AesKey::from_slice(&key))
+}
+
+/// AES key for a sealed message, bound to both public keys
+fn sealing_key(shared: MontgomeryPoint, ephemeral: &MontgomeryPoint, recipient: &MontgomeryPoint) -> Result<AesKey, String> {
+    // A low-order point gives an all-zero secret anyone could compute
+    if shared.as_bytes() == &[0u8; 32] {
+        return Err("Invalid public key".to_string());
+    }
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(SEAL_INFO, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(*AesKey::from_slice(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssh_key::private::{Ed25519Keypair, PrivateKey};
+
+    const RSA_KEY: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQDRqlHMSv6xKiSUQS/7gca0QfX85moxbKLb5msOwnCqaYCGs3EXAN+3tnWBmfLwlLbADT7/5IqQsUmJ5YHaUwGyOOR3BDu+lVuzUidKDuYFXyYMtdhDpepSgx/OfBCgjFe6lOGzwsN0ZBcS3Dj/TjWd6eQTdMODoJW8Orfb8oeJFQ== x";
+
+    /// AES-256-GCM test cases 13-15 from McGrew and Viega, "The Galois/Counter
+    /// Mode of Operation", laid out as `encrypt` sends them: nonce, ciphertext, tag
+    #[test]
+    fn test_known_answer_vectors() {
+        let cases = [
+            ("00".repeat(32), "000000000000000000000000", String::new(), "530f8afbc74536b9a963b4f1c4cb738b"),
+            (
+                "00".repeat(32),
+                "000000000000000000000000",
+                "00".repeat(16),
+                "cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919",
+            ),
+            (
+                "feffe9928665731c6d6a8f9467308308".repeat(2),
+                "cafebabefacedbaddecaf888",
+                "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                 1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255"
+                    .to_string(),
+                "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+                 8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662898015ad\
+                 b094dac5d93471bdec1a502270e3cc6c",
+            ),
+        ];
+        for (key, nonce, plaintext, expected) in cases {
+            let key = key_from_hex(&key).unwrap();
+            let nonce: [u8; 12] = hex::decode(nonce).unwrap().try_into().unwrap();
+            let plaintext = hex::decode(plaintext).unwrap();
+            let sealed = encrypt_with_nonce(&key, &nonce, &plaintext, b"").unwrap();
+            assert_eq!(sealed, format!("{}{}", hex::encode(nonce), expected));
+            assert_eq!(sealed.len(), encrypted_len(plaintext.len()));
+            assert_eq!(decrypt_bytes(&key, &sealed).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_room_key_cipher() {
+        let (a, b) = (generate_key(), generate_key());
+        let room_key = RoomKey::new(a);
+        assert_eq!(*room_key, a);
+
+        // The cached cipher and a fresh one agree
+        let sealed = encrypt(&room_key, b"for a").unwrap();
+        assert_eq!(decrypt_bytes(&a, &sealed).unwrap(), b"for a");
+        assert_eq!(decrypt_bytes(&room_key, &encrypt(&a, b"back").unwrap()).unwrap(), b"back");
+        assert!(decrypt_bytes(&RoomKey::new(b), &sealed).is_err());
+        assert!(decrypt_bytes(&room_key, &sealed[..sealed.len() - 2]).is_err(), "truncated tag");
+        assert_eq!(format!("{:?}", room_key), "RoomKey(..)", "never printed");
+    }
+
+    #[test]
+    fn test_aad_is_bound() {
+        let key = generate_key();
+        let sealed = encrypt_with_aad(&key, b"secret", b"here").unwrap();
+        assert_eq!(decrypt_with_aad(&key, &sealed, b"here").unwrap(), b"secret");
+        assert!(decrypt_with_aad(&key, &sealed, b"there").is_err());
+        assert!(decrypt_bytes(&key, &sealed).is_err(), "needs the aad");
+        assert_eq!(decrypt_with_aad(&key, &encrypt(&key, b"plain").unwrap(), b"").unwrap(), b"plain");
+    }
+
+    #[test]
+    fn test_stream_round_trip_and_tampering() {
+        const CHUNK: usize = 64 * 1024;
+        let key = generate_key();
+        let data: Vec<u8> = (0..5 * 1024 * 1024 + 123).map(|i| (i * 31 % 251) as u8).collect();
+
+        let mut encryptor = stream_encryptor(&key);
+        let header = *encryptor.header();
+        let chunks: Vec<&[u8]> = data.chunks(CHUNK).collect();
+        let (last, body) = chunks.split_last().unwrap();
+        let mut frames: Vec<Vec<u8>> = body.iter().map(|chunk| encryptor.encrypt_chunk(chunk).unwrap()).collect();
+        frames.push(encryptor.finish(last).unwrap());
+        assert_eq!(frames[0].len(), CHUNK + 16);
+
+        let open = |frames: &[Vec<u8>]| -> Result<Vec<u8>, String> {
+            let mut decryptor = stream_decryptor(&key, &header)?;
+            let (last, body) = frames.split_last().unwrap();
+            let mut out = Vec::new();
+            for frame in body {
+                out.extend(decryptor.decrypt_chunk(frame)?);
+            }
+            out.extend(decryptor.finish(last)?);
+            Ok(out)
+        };
+        assert_eq!(open(&frames).unwrap(), data);
+
+        let mut swapped = frames.clone();
+        swapped.swap(1, 2);
+        assert!(open(&swapped).is_err(), "reordered");
+        assert!(open(&frames[..frames.len() - 1]).is_err(), "truncated");
+        let mut dropped = frames.clone();
+        dropped.remove(3);
+        assert!(open(&dropped).is_err(), "chunk dropped");
+        assert!(stream_decryptor(&generate_key(), &header).unwrap().decrypt_chunk(&frames[0]).is_err());
+        assert!(stream_decryptor(&key, &header[..6]).is_err());
+    }
+
+    #[test]
+    fn test_seal_round_trip() {
+        let alice = PrivateKey::from(Ed25519Keypair::from_seed(&[1; 32]));
+        let bob = PrivateKey::from(Ed25519Keypair::from_seed(&[2; 32]));
+
+        let sealed = seal_for_pubkey(alice.public_key(), b"for alice").unwrap();
+        assert_ne!(sealed, seal_for_pubkey(alice.public_key(), b"for alice").unwrap(), "fresh ephemeral key each time");
+        assert_eq!(open_with_privkey(&alice, &sealed).unwrap(), b"for alice");
+        assert!(open_with_privkey(&bob, &sealed).is_err());
+        assert!(open_with_privkey(&alice, &sealed[..40]).is_err());
+
+        let rsa = ssh_key::PublicKey::from_openssh(RSA_KEY).unwrap();
+        assert!(seal_for_pubkey(&rsa, b"x").unwrap_err().contains("only ed25519"));
+    }
+}
+
@@ -1,5 +1,5 @@
-/// Emoji shortcode mappings for Discord/Slack-style emoji picker
-/// Format: (shortcode, emoji, description)
+//! Emoji shortcode mappings for Discord/Slack-style emoji picker
+//! Format: (shortcode, emoji, description)
 
 pub const EMOJIS: &[(&str, &str, &str)] = &[
     // Smileys & Emotion
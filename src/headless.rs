@@ -0,0 +1,224 @@
+//! Non-interactive mode for scripts and CI:
+//!
+//! `eurus send --room <id> --key <hex> --message "text" [--timeout <secs>]`
+//!
+//! Connects with the usual config and token, joins the room, sends one
+//! encrypted message and waits for the server to echo it back.
+
+use std::time::Duration;
+
+use crate::api::{ClientMessage, JoinRoomPayload, MessageEnvelope, SendMessagePayload, ServerMessage};
+use crate::config::Config;
+use crate::crypto::{encrypt, key_from_hex};
+use crate::proxy::{self, ProxyError};
+use crate::secrets::{self, Secrets};
+use crate::transport::{self, AuthRejected, Handshake, Transport};
+use crate::{load_auth_token, tls};
+
+/// Exit codes, so scripts can tell failures apart
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_CONNECT_FAILED: i32 = 3;
+pub const EXIT_AUTH_FAILED: i32 = 4;
+pub const EXIT_TIMEOUT: i32 = 5;
+pub const EXIT_SERVER_ERROR: i32 = 6;
+
+const USAGE: &str = "usage: eurus send --room <id> --key <hex> --message <text> [--timeout <secs>]";
+
+struct SendArgs {
+    room: String,
+    key: String,
+    message: String,
+    timeout: Duration,
+}
+
+fn parse_args(args: &[String]) -> Result<SendArgs, String> {
+    let (mut room, mut key, mut message) = (None, None, None);
+    let mut timeout = Duration::from_secs(10);
+
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or_else(|| format!("{} needs a value", flag));
+        match flag.as_str() {
+            "--room" | "-r" => room = Some(value()?),
+            "--key" | "-k" => key = Some(value()?),
+            "--message" | "-m" => message = Some(value()?),
+            "--timeout" | "-t" => {
+                let secs = value()?;
+                let secs: u64 = secs.parse().map_err(|_| format!("invalid timeout '{}'", secs))?;
+                timeout = Duration::from_secs(secs);
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+
+    Ok(SendArgs {
+        room: room.ok_or("--room is required")?,
+        key: key.ok_or("--key is required")?,
+        message: message.ok_or("--message is required")?,
+        timeout,
+    })
+}
+
+/// Run `eurus send ...` and return the process exit code
+pub async fn run_send(args: &[String]) -> i32 {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let Some(key) = key_from_hex(&args.key) else {
+        eprintln!("--key must be a 64-character hex room key");
+        return EXIT_USAGE;
+    };
+    if args.message.trim().is_empty() {
+        eprintln!("--message is empty");
+        return EXIT_USAGE;
+    }
+
+    let mut config = Config::load();
+    let problems = config.validate();
+    for problem in &problems {
+        eprintln!("config: {}", problem);
+    }
+    if problems.iter().any(|p| p.is_invalid()) {
+        return EXIT_USAGE;
+    }
+    let token = if config.auth.keyring {
+        match Secrets::open().and_then(|secrets| secrets.get(secrets::TOKEN)) {
+            Ok(token) => token,
+            Err(e) => {
+                eprintln!("Can't read the token from the keyring: {}", e);
+                return EXIT_AUTH_FAILED;
+            }
+        }
+    } else {
+        load_auth_token(&config.auth.token_path)
+    };
+    let handshake = Handshake {
+        url: config.server.url.clone(),
+        token,
+        subprotocol: config.network.subprotocol.clone(),
+        extra_headers: config.network.extra_headers.clone(),
+    };
+    let connector = match tls::build_connector(&config.network) {
+        Ok(connector) => connector,
+        Err(e) => {
+            eprintln!("TLS configuration error: {}", e);
+            return EXIT_CONNECT_FAILED;
+        }
+    };
+    let proxy = match proxy::for_server(&config.network, &config.server.url) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            eprintln!("Invalid proxy: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let connect_timeout = Duration::from_secs(config.network.connect_timeout);
+    let ping_interval = Duration::from_secs(config.network.ping_interval);
+    let mut transport = match transport::connect(&handshake, connector, &config.network.send_queue, connect_timeout, ping_interval, proxy.as_ref()).await {
+        Ok(transport) => transport,
+        Err(e) if e.is::<AuthRejected>() => {
+            eprintln!("Authentication failed: {}", e);
+            return EXIT_AUTH_FAILED;
+        }
+        Err(e) if e.is::<ProxyError>() => {
+            eprintln!("Connection failed through the {}", e);
+            return EXIT_CONNECT_FAILED;
+        }
+        Err(e) => {
+            eprintln!("Connection failed: {}", e);
+            return EXIT_CONNECT_FAILED;
+        }
+    };
+
+    let exchange = async {
+        // Join, then send once the server confirms
+        let join = ClientMessage {
+            message_type: "joinRoom",
+            payload: JoinRoomPayload { room_id: Some(&args.room), room_name: None },
+        };
+        send_json(transport.as_ref(), &join)?;
+
+        let client_id = hex::encode(rand::random::<[u8; 8]>());
+        let content = MessageEnvelope::new(args.message.clone()).encode();
+        let ciphertext = encrypt(&key, content.as_bytes()).map_err(|_| {
+            eprintln!("Failed to encrypt message");
+            EXIT_USAGE
+        })?;
+        let mut sent = false;
+
+        while let Some(text) = transport.recv().await {
+            match serde_json::from_str::<ServerMessage>(&text) {
+                Ok(ServerMessage::RoomJoined(payload)) if !sent && payload.room_id == args.room => {
+                    let msg = ClientMessage {
+                        message_type: "sendMessage",
+                        payload: SendMessagePayload {
+                            room_id: &args.room,
+                            ciphertext: &ciphertext,
+                            client_id: Some(&client_id),
+                        },
+                    };
+                    send_json(transport.as_ref(), &msg)?;
+                    sent = true;
+                }
+                // Servers that don't echo client ids are matched by ciphertext
+                Ok(ServerMessage::Message(payload))
+                    if sent
+                        && (payload.client_id.as_deref() == Some(client_id.as_str())
+                            || payload.ciphertext == ciphertext) =>
+                {
+                    return Ok(());
+                }
+                Ok(ServerMessage::Error(payload)) => {
+                    eprintln!("Server error: {}", payload.message);
+                    return Err(EXIT_SERVER_ERROR);
+                }
+                _ => {}
+            }
+        }
+        eprintln!("Connection closed");
+        Err(EXIT_CONNECT_FAILED)
+    };
+
+    match tokio::time::timeout(args.timeout, exchange).await {
+        Ok(Ok(())) => EXIT_OK,
+        Ok(Err(code)) => code,
+        Err(_) => {
+            eprintln!("Timed out waiting for the server to acknowledge the message");
+            EXIT_TIMEOUT
+        }
+    }
+}
+
+fn send_json<T: serde::Serialize>(transport: &dyn Transport, msg: &T) -> Result<(), i32> {
+    let json = serde_json::to_string(msg).map_err(|_| EXIT_USAGE)?;
+    transport.send(json).map_err(|_| {
+        eprintln!("Connection closed");
+        EXIT_CONNECT_FAILED
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_send_args() {
+        let parsed = parse_args(&args(&["--room", "abc", "-k", "00", "-m", "hi", "--timeout", "3"])).unwrap();
+        assert_eq!((parsed.room.as_str(), parsed.key.as_str(), parsed.message.as_str()), ("abc", "00", "hi"));
+        assert_eq!(parsed.timeout, Duration::from_secs(3));
+
+        assert!(parse_args(&args(&["--room", "abc", "--key", "00"])).is_err());
+        assert!(parse_args(&args(&["--room"])).is_err());
+        assert!(parse_args(&args(&["--bogus", "x"])).is_err());
+    }
+}
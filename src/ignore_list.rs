@@ -0,0 +1,95 @@
+//! Users whose messages are hidden and whose voice is muted, kept in
+//! `~/.config/eurus/ignored.toml` so the list can be edited by hand:
+//!
+//! ```toml
+//! users = ["spammer", "loudmouth"]
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct IgnoreFile {
+    #[serde(default)]
+    users: BTreeSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    file: IgnoreFile,
+    path: Option<PathBuf>,
+}
+
+impl IgnoreList {
+    pub fn load() -> Self {
+        let path = dirs::config_dir().map(|dir| dir.join("eurus").join("ignored.toml"));
+        let file = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self { file, path }
+    }
+
+    pub fn contains(&self, username: &str) -> bool {
+        self.file.users.contains(username)
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &str> {
+        self.file.users.iter().map(String::as_str)
+    }
+
+    /// Add `username` and save. False if they were already ignored.
+    pub fn ignore(&mut self, username: &str) -> io::Result<bool> {
+        if !self.file.users.insert(username.to_string()) {
+            return Ok(false);
+        }
+        self.save().map(|()| true)
+    }
+
+    /// Remove `username` and save. False if they weren't ignored.
+    pub fn unignore(&mut self, username: &str) -> io::Result<bool> {
+        if !self.file.users.remove(username) {
+            return Ok(false);
+        }
+        self.save().map(|()| true)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string(&self.file).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eurus").join("ignored.toml");
+        let mut list = IgnoreList { file: IgnoreFile::default(), path: Some(path.clone()) };
+
+        assert!(list.ignore("mallory").unwrap());
+        assert!(!list.ignore("mallory").unwrap(), "already ignored");
+        assert!(list.ignore("eve").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "users = [\"eve\", \"mallory\"]\n");
+        assert!(list.contains("eve") && !list.contains("alice"));
+
+        assert!(list.unignore("eve").unwrap());
+        assert!(!list.unignore("eve").unwrap());
+        let saved: IgnoreFile = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, list.file);
+        assert_eq!(list.users().collect::<Vec<_>>(), ["mallory"]);
+    }
+}
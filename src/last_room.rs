@@ -0,0 +1,175 @@
+//! The room joined most recently, so the room menu can offer to go straight
+//! back to it. Kept in `~/.config/eurus/last_room.toml`:
+//!
+//! ```toml
+//! server_url = "wss://chat.example.com"
+//! room_id = "3f8a9c..."
+//! name = "standup"
+//!
+//! [sealed_key]
+//! public_key = "ssh-ed25519 AAAA... me@laptop"
+//! sealed = "9c01..."
+//! ```
+//!
+//! A room key is only kept for rooms whose server doesn't hand it out, and
+//! then only sealed to one of the user's ed25519 SSH keys or, with
+//! `auth.keyring`, in the secret store; never in the clear.
+
+use crate::crypto::{open_sealed_key, seal_for_pubkey, AesKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedRoom {
+    pub server_url: String,
+    pub room_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_key: Option<SealedKey>,
+    /// The room needs a key from the user, and there was no SSH key to seal
+    /// it to
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub key_needed: bool,
+    /// The key is in the secret store (`auth.keyring`) rather than sealed here
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub key_in_secrets: bool,
+}
+
+/// A room key sealed with `crypto::seal_for_pubkey`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SealedKey {
+    /// OpenSSH public key it was sealed to
+    pub public_key: String,
+    pub sealed: String,
+}
+
+impl SavedRoom {
+    /// Keep `key` for this room, sealed to `public_key` (an OpenSSH ed25519 key)
+    pub fn seal_key(&mut self, key: &AesKey, public_key: &str) -> Result<(), String> {
+        let parsed = ssh_key::PublicKey::from_openssh(public_key).map_err(|e| format!("Invalid SSH key: {}", e))?;
+        let sealed = seal_for_pubkey(&parsed, key.as_slice())?;
+        self.sealed_key = Some(SealedKey { public_key: public_key.to_string(), sealed });
+        self.key_needed = false;
+        Ok(())
+    }
+
+    /// The room key, opened with whichever of `private_keys` it was sealed to
+    pub fn open_key(&self, private_keys: &[ssh_key::PrivateKey]) -> Option<AesKey> {
+        open_sealed_key(private_keys, &self.sealed_key.as_ref()?.sealed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LastRoom {
+    room: Option<SavedRoom>,
+    path: Option<PathBuf>,
+}
+
+impl LastRoom {
+    pub fn load() -> Self {
+        let path = dirs::config_dir().map(|dir| dir.join("eurus").join("last_room.toml"));
+        let room = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok());
+        Self { room, path }
+    }
+
+    pub fn get(&self) -> Option<&SavedRoom> {
+        self.room.as_ref()
+    }
+
+    /// The saved room, if it's on the server at `server_url`
+    pub fn for_server(&self, server_url: &str) -> Option<&SavedRoom> {
+        self.get().filter(|room| room.server_url == server_url)
+    }
+
+    /// Remember `room` in place of the last one and save
+    pub fn set(&mut self, room: SavedRoom) -> io::Result<()> {
+        if self.room.as_ref() == Some(&room) {
+            return Ok(());
+        }
+        self.room = Some(room);
+        self.save()
+    }
+
+    /// Forget the saved room and delete the file
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.room = None;
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let (Some(path), Some(room)) = (&self.path, &self.room) else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string(room).map_err(io::Error::other)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // Only the owner should see which rooms they use
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)?.write_all(contents.as_bytes())
+
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_key;
+    use ssh_key::private::{Ed25519Keypair, PrivateKey};
+
+    #[test]
+    fn test_last_room_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eurus").join("last_room.toml");
+        let mut last = LastRoom { room: None, path: Some(path.clone()) };
+
+        let me = PrivateKey::from(Ed25519Keypair::from_seed(&[1; 32]));
+        let someone_else = PrivateKey::from(Ed25519Keypair::from_seed(&[2; 32]));
+        let key = generate_key();
+        let mut room = SavedRoom {
+            server_url: "wss://chat.example.com".to_string(),
+            room_id: "room-1".to_string(),
+            name: "standup".to_string(),
+            sealed_key: None,
+            key_needed: true,
+            key_in_secrets: false,
+        };
+        room.seal_key(&key, &me.public_key().to_openssh().unwrap()).unwrap();
+        assert!(!room.key_needed);
+        last.set(room.clone()).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(&hex::encode(key)), "key saved in the clear");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let loaded = LastRoom { room: toml::from_str(&contents).ok(), path: Some(path.clone()) };
+        let saved = loaded.for_server("wss://chat.example.com").unwrap();
+        assert_eq!(saved, &room);
+        assert!(loaded.for_server("wss://elsewhere.example.com").is_none());
+        assert_eq!(saved.open_key(&[someone_else.clone(), me]), Some(key));
+        assert_eq!(saved.open_key(&[someone_else]), None);
+
+        last.clear().unwrap();
+        assert!(!path.exists());
+        assert!(last.for_server("wss://chat.example.com").is_none());
+        last.clear().unwrap();
+    }
+}
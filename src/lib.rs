@@ -0,0 +1,3 @@
+//! The parts of eurus that stand on their own, so benches can reach them
+
+pub mod crypto;
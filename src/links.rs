@@ -0,0 +1,68 @@
+//! URL detection for chat messages
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Conservative http(s) matcher: scheme, a host with at least one dot, and an
+/// optional path made of URL-safe characters. Trailing punctuation is trimmed
+/// separately since it's usually part of the sentence, not the link.
+fn url_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"https?://[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)+(?::\d+)?(?:[/?#][A-Za-z0-9\-._~:/?#\[\]@!$&'()*+,;=%]*)?")
+            .expect("valid URL regex")
+    })
+}
+
+/// Byte ranges of URLs found in `text`
+pub fn find_urls(text: &str) -> Vec<(usize, usize)> {
+    url_regex()
+        .find_iter(text)
+        .map(|m| (m.start(), m.start() + trimmed_len(m.as_str())))
+        .collect()
+}
+
+/// The last URL in `text`, if any
+pub fn last_url(text: &str) -> Option<&str> {
+    find_urls(text).last().map(|&(start, end)| &text[start..end])
+}
+
+/// Length of `url` after dropping trailing sentence punctuation.
+/// A closing paren is kept when it balances one inside the URL.
+fn trimmed_len(url: &str) -> usize {
+    let mut end = url.len();
+    while let Some(c) = url[..end].chars().last() {
+        let strip = match c {
+            '.' | ',' | ';' | ':' | '!' | '?' | '\'' | '"' => true,
+            ')' => url[..end].matches('(').count() < url[..end].matches(')').count(),
+            _ => false,
+        };
+        if !strip {
+            break;
+        }
+        end -= c.len_utf8();
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<&str> {
+        find_urls(text).into_iter().map(|(s, e)| &text[s..e]).collect()
+    }
+
+    #[test]
+    fn test_trailing_punctuation_is_not_part_of_url() {
+        assert_eq!(urls("see https://example.com/docs."), vec!["https://example.com/docs"]);
+        assert_eq!(urls("(https://example.com/a)"), vec!["https://example.com/a"]);
+        assert_eq!(urls("https://en.wikipedia.org/wiki/Rust_(language)!"), vec!["https://en.wikipedia.org/wiki/Rust_(language)"]);
+    }
+
+    #[test]
+    fn test_rejects_non_urls() {
+        assert!(urls("http:// nope, ftp://example.com, https://localhost").is_empty());
+        assert_eq!(last_url("a https://a.io then http://b.io/x?y=1"), Some("http://b.io/x?y=1"));
+    }
+}
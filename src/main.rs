@@ -1,38 +1,63 @@
 mod api;
-mod crypto;
 mod clipboard;
 mod config;
 mod vim;
 mod emoji;
 mod voice;
 mod ssh;
-
-use crate::crypto::{decrypt, encrypt, key_from_hex, AesKey};
-use crate::clipboard::ClipboardManager;
-use crate::config::Config;
+mod render;
+mod tls;
+mod links;
+mod markup;
+mod spell;
+mod headless;
+mod proxy;
+mod room_labels;
+mod ignore_list;
+mod last_room;
+mod secrets;
+mod room_link;
+mod transport;
+
+use eurus::crypto;
+
+use crate::crypto::{decrypt_bytes, encrypt, encrypted_len, generate_key, key_fingerprint, key_from_hex, open_sealed_key, parse_key_hex, seal_for_pubkey, AesKey, CipherKey, RoomKey};
+use crate::clipboard::{ClipboardManager, PastedImage};
+use crate::config::{Config, IgnoredMessages, NotifyLevel, OwnMessageStyle, COMPOSER_MIN_ROWS, NetworkConfig, QueueFullPolicy};
+use crate::proxy::{Proxy, ProxyError};
+use crate::render::{composer, Spinner};
+use crate::room_labels::{short_room_id, RoomLabels};
+use crate::ignore_list::IgnoreList;
+use crate::last_room::{LastRoom, SavedRoom};
+use crate::secrets::Secrets;
+use crate::transport::{AuthRejected, Closed, Handshake, SendError, Transport};
 use crate::vim::{VimMode, VimState};
-use crate::voice::manager::{VoiceManager, VoiceEvent, VoiceConnectionStatus};
-use api::{ClientMessage, CreateRoomPayload, JoinRoomPayload, ListRoomsPayload, SendMessagePayload, ServerMessage, RoomInfo, TypingPayload, CreateInvitePayload, JoinViaInvitePayload, RenameRoomPayload, DeleteRoomPayload, TransferOwnershipPayload, CreateDMPayload, VoiceSignalPayload};
-use futures_util::{SinkExt, StreamExt};
+#[cfg(feature = "voice")]
+use crate::voice::manager::VoiceManager;
+use crate::voice::{VoiceCommand, VoiceConnectionStatus, VoiceEvent};
+use crate::voice::stats::VoiceStats;
+use api::{ClientMessage, CreateRoomPayload, JoinRoomPayload, ListRoomsPayload, SendMessagePayload, ServerMessage, RoomInfo, TypingPayload, CreateInvitePayload, JoinViaInvitePayload, RenameRoomPayload, DeleteRoomPayload, TransferOwnershipPayload, CreateDMPayload, VoiceSignalPayload, HeartbeatPayload, EditMessagePayload, DeleteMessagePayload, ReactionPayload, MessageEnvelope, EnvelopeSignature, MessageKind, PinPayload, ErrorPayload, ErrorCode, FetchHistoryPayload, MessagePayload, MemberStatus, WhisperPayload};
 use ratatui::{
     crossterm::{
-        event::{self, DisableMouseCapture, EnableMouseCapture, EnableFocusChange, Event, KeyCode, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind},
-        execute,
-        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+        event::{self, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind},
+        cursor, execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle},
     },
     prelude::*,
     widgets::*,
 };
-use std::{error::Error, io};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{error::Error, io::{self, Write}};
 use tokio::sync::mpsc;
-use tokio::time::{interval, Duration};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::time::Duration;
+use tokio_tungstenite::Connector;
 use tui_textarea::TextArea;
 use notify_rust::Notification;
 
 // --- Application State ---
 
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq)]
 enum CurrentScreen {
     // Registration flow
     Registration,         // No SSH keys found error
@@ -41,7 +66,6 @@ enum CurrentScreen {
     RegistrationSuccess,  // Show token + success
     
     // Re-authentication flow
-    ReAuthenticate,       // Challenge-response re-auth for existing users
     PassphraseInput,      // Enter passphrase for encrypted key
     
     // Main flow
@@ -50,13 +74,27 @@ enum CurrentScreen {
     RoomTypeSelection,
     RoomCreation,
     CreateRoomInput,
+    JoinRoomInput,        // Join by room id (and optionally key)
     InRoom,
     RoomSwitcher,
     Help,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 enum CurrentlyEditing {
     RoomName,
+    RoomId,
+    RoomKey,
+}
+
+/// Delivery progress of a message we sent
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum DeliveryState {
+    Queued,   // Written while offline, sent once we reconnect
+    Pending,  // Sent, waiting for the server's echo
+    Sent,     // Echoed back by the server
+    Failed,   // Couldn't be sent or timed out; can be retried
 }
 
 /// A chat message with content and timestamp
@@ -64,57 +102,140 @@ enum CurrentlyEditing {
 struct ChatMessage {
     content: String,      // The actual text content
     sender: Option<String>, // Username of sender (None for system messages)
-    timestamp: String,    // Formatted time like "2:34 PM"
-    date: String,         // Formatted date like "January 24, 2026"
+    time: chrono::DateTime<chrono::Local>,  // When it was sent, in local time
     is_system: bool,      // Whether it is a system message
+    id: Option<String>,   // Server-assigned id (None for system messages)
+    edited: bool,
+    deleted: bool,
+    reactions: Vec<(String, Vec<String>)>,  // Emoji -> usernames, in order of first use
+    reply_to: Option<String>,  // Id of the message this one replies to
+    is_action: bool,  // A /me action, shown as "* sender text"
+    from_me: bool,  // Sent by this user, from here or another of their sessions
+    client_id: Option<String>,  // Our id for a local echo, matched against the server's echo
+    delivery: Option<DeliveryState>,  // Only set on our own messages
+    sent_at: Option<std::time::Instant>,
+    whisper_to: Option<String>,  // Set on whispers: who it was for
+    whisper_sealed: bool,  // Sealed to the recipient's SSH key rather than under the room key
+    unsigned: bool,  // One of ours that went out without a signature
 }
 
 impl ChatMessage {
     fn new(content: String, sender: Option<String>, timestamp: Option<String>) -> Self {
-        let (formatted_time, formatted_date) = if let Some(ts) = timestamp {
-            // Parse ISO timestamp and format as local time and date
-            if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&ts) {
-                let local_dt = dt.with_timezone(&chrono::Local);
-                (
-                    local_dt.format("%I:%M %p").to_string(),
-                    local_dt.format("%B %d, %Y").to_string()
-                )
-            } else {
-                let now = chrono::Local::now();
-                (
-                    now.format("%I:%M %p").to_string(),
-                    now.format("%B %d, %Y").to_string()
-                )
-            }
-        } else {
-            let now = chrono::Local::now();
-            (
-                now.format("%I:%M %p").to_string(),
-                now.format("%B %d, %Y").to_string()
-            )
-        };
+        // ISO timestamps from the server; anything missing or unparseable is "now"
+        let time = timestamp
+            .and_then(|ts| chrono::DateTime::parse_from_rfc3339(&ts).ok())
+            .map(|dt| dt.with_timezone(&chrono::Local))
+            .unwrap_or_else(chrono::Local::now);
         
+        // Everything shown in the timeline comes from here or from
+        // decrypt_text, so untrusted text is sanitized at the door
         Self {
-            content,
-            sender,
-            timestamp: formatted_time,
-            date: formatted_date,
+            content: render::sanitize(&content),
+            sender: sender.map(|s| render::sanitize(&s)),
+            time,
             is_system: false,
+            id: None,
+            edited: false,
+            deleted: false,
+            reactions: Vec::new(),
+            reply_to: None,
+            is_action: false,
+            from_me: false,
+            client_id: None,
+            delivery: None,
+            sent_at: None,
+            whisper_to: None,
+            whisper_sealed: false,
+            unsigned: false,
+        }
+    }
+
+    /// Build a message from decrypted plaintext, unpacking any envelope metadata
+    fn from_plaintext(plaintext: &str, sender: String, timestamp: String, id: String) -> Self {
+        let envelope = MessageEnvelope::decode(plaintext);
+        let mut msg = Self::new(envelope.text, Some(sender), Some(timestamp)).with_id(id);
+        msg.reply_to = envelope.reply_to;
+        msg.is_action = envelope.kind == MessageKind::Action;
+        msg
+    }
+
+    /// Time of day, like "02:34 PM"
+    fn timestamp(&self) -> String {
+        self.time.format("%I:%M %p").to_string()
+    }
+
+    /// Calendar date, like "January 24, 2026"
+    fn date(&self) -> String {
+        self.time.format("%B %d, %Y").to_string()
+    }
+
+    fn with_id(mut self, id: String) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    fn has_reacted(&self, emoji: &str, username: &str) -> bool {
+        self.reactions.iter().any(|(e, users)| e == emoji && users.iter().any(|u| u == username))
+    }
+
+    fn apply_reaction(&mut self, emoji: &str, username: &str, remove: bool) {
+        let pos = self.reactions.iter().position(|(e, _)| e == emoji);
+        match (pos, remove) {
+            (Some(i), true) => {
+                self.reactions[i].1.retain(|u| u != username);
+                if self.reactions[i].1.is_empty() {
+                    self.reactions.remove(i);
+                }
+            }
+            (Some(i), false) => {
+                if !self.reactions[i].1.iter().any(|u| u == username) {
+                    self.reactions[i].1.push(username.to_string());
+                }
+            }
+            (None, false) => self.reactions.push((emoji.to_string(), vec![username.to_string()])),
+            (None, true) => {}
         }
     }
     
     fn system(content: String) -> Self {
-        let now = chrono::Local::now();
         Self {
-            content,
+            // Notices often quote usernames and other server-supplied text
+            content: render::sanitize(&content),
             sender: None,
-            timestamp: now.format("%I:%M %p").to_string(),
-            date: now.format("%B %d, %Y").to_string(),
+            time: chrono::Local::now(),
             is_system: true,
+            id: None,
+            edited: false,
+            deleted: false,
+            reactions: Vec::new(),
+            reply_to: None,
+            is_action: false,
+            from_me: false,
+            client_id: None,
+            delivery: None,
+            sent_at: None,
+            whisper_to: None,
+            whisper_sealed: false,
+            unsigned: false,
         }
     }
 }
 
+/// Counters for the diagnostics overlay (F12), so a single screenshot
+/// covers what a bug report needs
+#[derive(Debug, Default)]
+struct Diagnostics {
+    messages_sent: u64,
+    messages_received: u64,
+    decrypt_failures: u64,
+    /// Successful connects; every one after the first is a reconnect
+    connections: u64,
+    /// Round trip of the last answered heartbeat
+    rtt: Option<Duration>,
+    /// Last report from the voice manager
+    voice: VoiceStats,
+}
+
 /// Voice chat state - single source of truth for voice UI
 /// All state changes come from VoiceEvent handlers, never from commands
 #[derive(Default)]
@@ -123,10 +244,10 @@ struct VoiceState {
     pub status: VoiceConnectionStatus,
     /// Whether microphone is muted
     pub is_muted: bool,
-    /// Whether currently transmitting audio
+    /// Whether currently transmitting audio (from TxActivity events)
     pub is_transmitting: bool,
-    /// Last time we transmitted (for UI indicator timeout)
-    pub tx_last_time: Option<std::time::Instant>,
+    /// Joined without a microphone (from ListenOnly events)
+    pub listen_only: bool,
     /// List of users in the voice channel (from server VoiceState messages)
     pub room_users: Vec<String>,
     /// Connected peers (WebRTC connections established)
@@ -144,33 +265,123 @@ impl VoiceState {
         self.status = VoiceConnectionStatus::Disconnected;
         self.is_muted = false;
         self.is_transmitting = false;
-        self.tx_last_time = None;
+        self.listen_only = false;
         self.connected_peers.clear();
         // Note: room_users is NOT cleared here - it comes from server
     }
+
+    /// Mute and transmit activity as the one mic indicator shows them
+    pub fn mic_state(&self) -> MicState {
+        if self.listen_only {
+            MicState::ListenOnly
+        } else if self.is_muted {
+            MicState::Muted
+        } else if self.is_transmitting {
+            MicState::Transmitting
+        } else {
+            MicState::Idle
+        }
+    }
+}
+
+/// What the mic indicator shows; having no mic wins over being muted,
+/// which wins over activity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MicState {
+    ListenOnly,
+    Muted,
+    Transmitting,
+    Idle,
+}
+
+impl MicState {
+    fn indicator(self) -> (&'static str, &'static str, Style) {
+        match self {
+            MicState::ListenOnly => ("🎧", "Listen only", Style::default().fg(Color::Yellow)),
+            MicState::Muted => ("🔇", "Muted", Style::default().fg(Color::Red)),
+            MicState::Transmitting => ("🎙", "Transmitting", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+            MicState::Idle => ("🎙", "Idle", Style::default().fg(Color::DarkGray)),
+        }
+    }
+}
+
+/// Progress reports from a background connection attempt
+enum ConnectionEvent {
+    /// A new attempt is starting (1-based)
+    Attempt { attempt: usize, max_attempts: usize },
+    /// The connection is open
+    Connected(Box<dyn Transport>),
+    /// All attempts failed
+    Failed(String),
+    /// The server rejected our credentials during the handshake
+    AuthFailed(String),
 }
 
 struct App<'a> {
     // Inputs
     room_name_input: TextArea<'a>,
+    room_id_input: TextArea<'a>,
+    room_key_input: TextArea<'a>,  // Masked unless room_key_revealed
+    room_key_revealed: bool,
     message_input: TextArea<'a>,
 
     // State
     current_screen: CurrentScreen,
     currently_editing: Option<CurrentlyEditing>,
     status_message: String,
+    status_level: StatusLevel,
+    /// When a transient status was set; it reverts to the idle status after
+    /// STATUS_TIMEOUT. `None` for sticky statuses.
+    status_set_at: Option<std::time::Instant>,
+    /// Recent status messages, newest last (F2 to view)
+    status_log: VecDeque<StatusEntry>,
+    show_status_log: bool,
+    status_log_scroll: usize,
     should_quit: bool,
     vim_state: VimState,
     message_scroll_offset: usize,
+    composer_width: usize,  // Columns the composer wrapped to on the last draw (0 before the first)
+    composer_scroll: usize,  // First visual row shown in the composer
+    history_loading: bool,  // A fetchHistory request is in flight
+    history_exhausted: bool,  // The server has nothing older for this room
+    selected_message: Option<usize>,  // Index into `messages` picked with J/K in Normal mode
+    replying_to: Option<String>,  // Id of the message the composer is replying to
+    pending_image: Option<PastedImage>,  // Image pasted into the composer, shown until sent or removed
+    thread_root: Option<String>,  // Root message id of the open thread overlay
+    pinned_messages: Vec<String>,  // Pinned message ids in the current room, oldest first
+    show_pinned: bool,  // Show pinned messages overlay
+    show_room_info: bool,  // Show the room details overlay (full room id etc.)
+    show_about: bool,  // Show the version/build info overlay
+    show_diagnostics: bool,  // Show the live counters overlay
+    diagnostics: Diagnostics,
+    pending_url: Option<String>,  // Link waiting for the user to confirm opening it
+    code_copy_cycle: Option<(usize, usize)>,  // (message index, next code block) for repeated yc
+    outbox: VecDeque<String>,  // Client ids of messages queued while offline, oldest first
+    spellcheck_enabled: bool,
+    spell: Option<spell::SpellChecker>,  // Loaded lazily the first time spell-check is on
+    editing_message_id: Option<String>,  // Set while the composer holds an edit of an earlier message
+    deleting_message_id: Option<String>,  // Delete sent, waiting for the server to confirm it
     current_username: Option<String>,
 
     // Room Data
     room_id: Option<String>,
     room_name: Option<String>,
     room_display_name: Option<String>,
-    room_key: Option<AesKey>,
+    room_labels: RoomLabels,  // User-assigned labels/colors, keyed by room id
+    ignored: IgnoreList,  // Users whose messages are hidden and voice muted
+    room_key: Option<RoomKey>,  // Current room's key with its cipher, wiped when dropped
+    supplied_room_key: Option<(String, AesKey)>,  // Key the user gave for a room, for when its join reply has none
+    room_requires_signatures: bool,  // The room only accepts signed messages
     messages: Vec<ChatMessage>,
     online_users: Vec<String>,  // Usernames of online users in current room
+    away_users: HashSet<String>,  // Online users the server reports as away
+    member_keys: HashMap<String, String>,  // Username -> OpenSSH public key, from the roster
+    whisper_keys: Option<Vec<ssh_key::PrivateKey>>,  // Unlocked keys for sealed whispers, loaded on first use
+    last_room: LastRoom,  // Offered as "rejoin" on the room menu
+    secrets: Option<Secrets>,  // Where the token and room keys go with `auth.keyring`
+    pending_rejoin: Option<(SavedRoom, std::path::PathBuf)>,  // Rejoin waiting on the passphrase for this SSH key file
+    show_roster: bool,  // Show the member sidebar in rooms
+    show_timestamps: bool,  // Starts from ui.show_timestamps; T flips it for the session
     
     // Typing indicators (username -> timestamp when they started typing)
     typing_users: std::collections::HashMap<String, std::time::Instant>,
@@ -178,7 +389,13 @@ struct App<'a> {
     
     // UI State
     show_user_list: bool,  // Show user list overlay
-    is_focused: bool,      // Is terminal focused?
+    pending_operation: Option<String>,  // Label shown with a spinner while something is in flight
+    spinner: Spinner,
+    needs_redraw: bool,  // Set whenever state changes; the loop skips drawing otherwise
+    is_focused: bool,      // Is terminal focused? Stays true if the terminal never reports focus
+    unread_count: usize,   // Messages received while unfocused
+    terminal_title: Option<String>, // Title last written to the terminal
+    terminal_caps: TerminalCaps,    // What init_terminal managed to turn on
     
     // Room List
     public_rooms: Vec<RoomInfo>,
@@ -193,10 +410,20 @@ struct App<'a> {
     user_rooms: Vec<RoomInfo>,  // Rooms user is a member of
     switcher_selected_index: usize,
 
-    // WebSocket
-    ws_sender: Option<mpsc::UnboundedSender<String>>,
+    // Connection
+    transport: Option<Box<dyn Transport>>,
     reconnect_attempts: usize,
     is_reconnecting: bool,
+    auth_rejected: bool,  // Server refused our token; don't reconnect until the user logs in again
+    rate_limited_until: Option<std::time::Instant>,  // Hold outgoing messages until then
+    connection_tx: Option<mpsc::UnboundedSender<(u64, ConnectionEvent)>>,
+    connect_task: Option<tokio::task::JoinHandle<()>>,  // Running connect attempt, if any
+    connect_id: u64,  // Identifies the current attempt; events from older attempts are dropped
+    heartbeat_seq: u64,
+    heartbeat_sent_at: Option<std::time::Instant>,  // Outstanding heartbeat, cleared when echoed
+    last_heartbeat: Option<std::time::Instant>,  // Last echo received on the current connection
+    heartbeats_unanswered: u32,  // Probes sent since the last echo
+    pending_voice_join: Option<String>,  // Room whose voice join waits on a heartbeat echo
     
     // Clipboard & Config
     clipboard: Option<ClipboardManager>,
@@ -208,12 +435,14 @@ struct App<'a> {
     // Registration / Re-authentication
     available_keys: Vec<ssh::SshKey>,
     selected_key_index: usize,
-    using_agent: bool,  // Whether keys are from ssh-agent
+    using_agent: bool,  // Whether ssh-agent was reachable when keys were listed
+    pending_keygen: Option<std::path::PathBuf>,  // Key path waiting for the user to confirm generating it
     username_input: TextArea<'a>,
     passphrase_input: TextArea<'a>,
     registration_token: Option<String>,
     registration_error: Option<String>,
     pending_challenge: Option<String>,  // Challenge from server during re-auth
+    signing_key: Option<ssh::SigningKey>,  // Key we logged in with, if it can sign without asking again
     
     // Emoji Picker
     emoji_picker_active: bool,
@@ -222,8 +451,9 @@ struct App<'a> {
     emoji_selected_index: usize,
 
     // Voice Chat
-    voice_tx: Option<mpsc::UnboundedSender<voice::manager::VoiceCommand>>,
+    voice_tx: Option<mpsc::UnboundedSender<VoiceCommand>>,
     voice: VoiceState,
+    voice_peer_names: HashMap<String, String>,  // Voice peer user id -> username, from their signals
 }
 
 impl<'a> Default for App<'a> {
@@ -237,28 +467,56 @@ impl<'a> Default for App<'a> {
         message_input.set_block(Block::default().borders(Borders::ALL).title("Message"));
 
         let clipboard = ClipboardManager::new().ok();
-        if clipboard.is_none() {
+        if clipboard.is_none() && cfg!(feature = "clipboard") {
             eprintln!("Warning: Failed to initialize clipboard");
         }
+        let config = Config::load();
 
         App {
             room_name_input,
+            room_id_input: room_id_input(),
+            room_key_input: room_key_input(),
+            room_key_revealed: false,
             message_input,
             current_screen: CurrentScreen::RoomChoice,
             currently_editing: None,
             status_message: "Create or Join a secure room.".to_string(),
+            status_level: StatusLevel::Info,
+            status_set_at: None,
+            status_log: VecDeque::new(),
+            show_status_log: false,
+            status_log_scroll: 0,
             should_quit: false,
             current_username: None,
             room_id: None,
             room_name: None,
             room_display_name: None,
+            // Tests must never read or write the user's real files
+            room_labels: if cfg!(test) { RoomLabels::default() } else { RoomLabels::load() },
+            ignored: if cfg!(test) { IgnoreList::default() } else { IgnoreList::load() },
             room_key: None,
+            supplied_room_key: None,
+            room_requires_signatures: false,
             messages: Vec::new(),
             online_users: Vec::new(),
+            away_users: HashSet::new(),
+            member_keys: HashMap::new(),
+            whisper_keys: None,
+            last_room: if cfg!(test) { LastRoom::default() } else { LastRoom::load() },
+            pending_rejoin: None,
+            secrets: None,
+            show_roster: false,
+            show_timestamps: config.ui.show_timestamps,
             typing_users: std::collections::HashMap::new(),
             last_typing_sent: None,
             show_user_list: false,
+            pending_operation: None,
+            spinner: Spinner::default(),
+            needs_redraw: true,
             is_focused: true, // Assume focused initially
+            unread_count: 0,
+            terminal_title: None,
+            terminal_caps: TerminalCaps::default(),
             public_rooms: Vec::new(),
             private_rooms: Vec::new(),
             selected_room_index: 0,
@@ -266,30 +524,57 @@ impl<'a> Default for App<'a> {
             selected_room_type: false,  // false = public, true = private
             user_rooms: Vec::new(),
             switcher_selected_index: 0,
-            ws_sender: None,
+            transport: None,
             reconnect_attempts: 0,
             is_reconnecting: false,
+            auth_rejected: false,
+            rate_limited_until: None,
+            connection_tx: None,
+            connect_task: None,
+            connect_id: 0,
+            heartbeat_seq: 0,
+            heartbeat_sent_at: None,
+            last_heartbeat: None,
+            heartbeats_unanswered: 0,
+            pending_voice_join: None,
             clipboard,
-            config: Config::load(),
+            config,
             vim_state: VimState::default(),
             message_scroll_offset: 0,
+            composer_width: 0,
+            composer_scroll: 0,
+            history_loading: false,
+            history_exhausted: false,
+            selected_message: None,
+            replying_to: None,
+            pending_image: None,
+            thread_root: None,
+            pinned_messages: Vec::new(),
+            show_pinned: false,
+            show_room_info: false,
+            show_about: false,
+            show_diagnostics: false,
+            diagnostics: Diagnostics::default(),
+            pending_url: None,
+            code_copy_cycle: None,
+            outbox: std::collections::VecDeque::new(),
+            spellcheck_enabled: false,
+            spell: None,
+            editing_message_id: None,
+            deleting_message_id: None,
             command_input: None,
             available_keys: Vec::new(),
             selected_key_index: 0,
             using_agent: false,
+            pending_keygen: None,
+            signing_key: None,
             username_input: {
                 let mut input = TextArea::default();
                 input.set_placeholder_text("Enter your username...");
                 input.set_block(Block::default().borders(Borders::ALL).title("Username"));
                 input
             },
-            passphrase_input: {
-                let mut input = TextArea::default();
-                input.set_placeholder_text("Enter passphrase...");
-                input.set_block(Block::default().borders(Borders::ALL).title("Passphrase"));
-                input.set_mask_char('\u{2022}'); // Bullet character to hide passphrase
-                input
-            },
+            passphrase_input: passphrase_input(),
             registration_token: None,
             registration_error: None,
             pending_challenge: None,
@@ -300,37 +585,269 @@ impl<'a> Default for App<'a> {
             emoji_partial: String::new(),
             voice_tx: None,
             voice: VoiceState::default(),
+            voice_peer_names: HashMap::new(),
+        }
+    }
+}
+
+impl<'a> App<'a> {
+    /// Show `msg` in the footer (coloured by `level`) and record it in the
+    /// status log
+    fn set_status(&mut self, msg: impl Into<String>, level: StatusLevel) {
+        self.log_status(msg.into(), level);
+        self.status_set_at = Some(std::time::Instant::now());
+    }
+
+    /// Like `set_status`, but the message stays until something replaces it.
+    /// For states the user needs to keep seeing, like a lost connection.
+    fn set_sticky_status(&mut self, msg: impl Into<String>, level: StatusLevel) {
+        self.log_status(msg.into(), level);
+        self.status_set_at = None;
+    }
+
+    fn log_status(&mut self, msg: String, level: StatusLevel) {
+        // Statuses often quote server text
+        self.status_message = render::sanitize(&msg);
+        self.status_level = level;
+        if self.status_log.len() == STATUS_LOG_LEN {
+            self.status_log.pop_front();
+        }
+        self.status_log.push_back(StatusEntry {
+            time: chrono::Local::now().format("%H:%M:%S").to_string(),
+            message: self.status_message.clone(),
+            level,
+        });
+    }
+
+    /// Blank the footer (the composer title already shows the vim mode)
+    fn clear_status(&mut self) {
+        self.status_message.clear();
+        self.status_level = StatusLevel::Info;
+        self.status_set_at = None;
+    }
+
+    /// What the footer shows when nothing else is going on
+    fn idle_status(&self) -> (&'static str, StatusLevel) {
+        let needs_server = matches!(
+            self.current_screen,
+            CurrentScreen::RoomChoice
+                | CurrentScreen::RoomList
+                | CurrentScreen::RoomTypeSelection
+                | CurrentScreen::CreateRoomInput
+                | CurrentScreen::JoinRoomInput
+                | CurrentScreen::RoomCreation
+                | CurrentScreen::InRoom
+                | CurrentScreen::RoomSwitcher
+        );
+        if needs_server && self.transport.is_none() && self.connect_task.is_none() {
+            return ("Disconnected from server.", StatusLevel::Warn);
+        }
+        let text = match self.current_screen {
+            CurrentScreen::Registration => "No SSH keys found. g to generate one, r to retry.",
+            CurrentScreen::KeySelection => "Select SSH key",
+            CurrentScreen::UsernameInput => "Enter your desired username",
+            CurrentScreen::RegistrationSuccess => "Registration successful!",
+            CurrentScreen::PassphraseInput => "Enter passphrase for encrypted key",
+            CurrentScreen::RoomChoice => "Create or Join a secure room.",
+            CurrentScreen::RoomList => "Enter to join, Tab to switch public/private",
+            CurrentScreen::RoomTypeSelection => "Select room type: Tab to switch, Enter to continue",
+            CurrentScreen::CreateRoomInput => "Enter a room name",
+            CurrentScreen::JoinRoomInput => "Tab to switch fields, Ctrl+R to show the key, Ctrl+T to check it, Enter to join",
+            CurrentScreen::RoomSwitcher => "Select room to switch",
+            CurrentScreen::Help => "Press Esc, q, or Enter to close help",
+            CurrentScreen::RoomCreation | CurrentScreen::InRoom => "",
+        };
+        (text, StatusLevel::Info)
+    }
+
+    /// Revert a transient status to the idle one once it has been up long enough
+    fn expire_status(&mut self) {
+        if self.status_set_at.is_some_and(|at| at.elapsed() >= STATUS_TIMEOUT) {
+            let (text, level) = self.idle_status();
+            self.status_message = text.to_string();
+            self.status_level = level;
+            self.status_set_at = None;
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Whether something on screen animates on its own (spinner, expiring
+    /// typing indicators) and needs periodic redraws while idle
+    fn is_animating(&self) -> bool {
+        self.pending_operation.is_some() || !self.typing_users.is_empty()
+    }
+}
+
+/// How a status message is presented: footer colour and log marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatusLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl StatusLevel {
+    fn style(self) -> Style {
+        match self {
+            StatusLevel::Info => Style::default().fg(Color::White),
+            StatusLevel::Warn => Style::default().fg(Color::Yellow),
+            StatusLevel::Error => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
         }
     }
 }
 
+struct StatusEntry {
+    time: String,
+    message: String,
+    level: StatusLevel,
+}
+
+/// How long a transient status stays in the footer
+const STATUS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Status messages kept for the log pane
+const STATUS_LOG_LEN: usize = 200;
+
+/// Redraw interval for animated widgets
+const ANIMATION_FRAME: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How long after the last redraw the event loop keeps polling at
+/// `ui.poll_ms` before backing off to `ui.idle_poll_ms`
+const POLL_BACKOFF_AFTER: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Quiet period after the last change to config.toml before reloading it,
+/// so an editor's burst of writes is read once
+const CONFIG_RELOAD_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(300);
+
 // --- Main Application Logic ---
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let mut terminal = init_terminal()?;
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    match take_config_flag(&mut args) {
+        Ok(Some(path)) => Config::set_path(&path),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("eurus: {}", e);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    }
+    if let Err(e) = Config::check_explicit_path() {
+        eprintln!("eurus: {}", e);
+        std::process::exit(headless::EXIT_USAGE);
+    }
+
+    // `eurus send ...` runs without the TUI
+    if args.first().map(String::as_str) == Some("send") {
+        std::process::exit(headless::run_send(&args[1..]).await);
+    }
+
     let mut app = App::default();
+    if app.config.auth.keyring {
+        // Asks for a passphrase without a keyring, so before the TUI starts
+        match Secrets::open() {
+            Ok(secrets) => app.secrets = Some(secrets),
+            Err(e) => {
+                eprintln!("eurus: {}", e);
+                std::process::exit(1);
+            }
+        }
+        if let Err(e) = move_token_into_secrets(&mut app) {
+            eprintln!("eurus: couldn't move the token file into the keyring: {}", e);
+        }
+    }
+    if !app.config.auth.remember_last_room {
+        // Turning it off also forgets what was remembered before
+        let _ = forget_last_room(&mut app);
+    }
+    // Restores the terminal on every way out of main, errors included
+    let mut guard = TerminalGuard { restore_title: false };
+    install_panic_hook(app.config.ui.terminal_title);
+    let (mut terminal, caps) = match init_terminal(app.config.ui.mouse) {
+        Ok(initialized) => initialized,
+        Err(e) => {
+            // exit skips the guard
+            let _ = restore_terminal(false);
+            eprintln!("eurus: {}", e);
+            std::process::exit(1);
+        }
+    };
+    app.terminal_caps = caps;
+    if app.config.ui.mouse && !caps.mouse {
+        app.set_status("This terminal doesn't support mouse capture; scroll with the keyboard", StatusLevel::Warn);
+    }
+    if app.config.ui.terminal_title {
+        save_terminal_title()?;
+        guard.restore_title = true;
+    }
     run_app(&mut terminal, &mut app).await?;
-    restore_terminal(&mut terminal)?;
     Ok(())
 }
 
+/// Remove `--config <path>` (or `--config=<path>`) from the arguments,
+/// returning the path
+fn take_config_flag(args: &mut Vec<String>) -> Result<Option<String>, String> {
+    let Some(index) = args.iter().position(|arg| arg == "--config" || arg.starts_with("--config=")) else {
+        return Ok(None);
+    };
+    let flag = args.remove(index);
+    let path = match flag.strip_prefix("--config=") {
+        Some(path) => path.to_string(),
+        None if index < args.len() => args.remove(index),
+        None => return Err("--config needs a path".to_string()),
+    };
+    if path.is_empty() {
+        return Err("--config needs a path".to_string());
+    }
+    Ok(Some(path))
+}
+
 async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> io::Result<()> {
-    let (ws_incoming_tx, mut ws_incoming_rx) = mpsc::unbounded_channel::<String>();
+    let (connection_tx, mut connection_rx) = mpsc::unbounded_channel::<(u64, ConnectionEvent)>();
+    app.connection_tx = Some(connection_tx);
 
-    // Setup Voice Manager
-    let (voice_cmd_tx, voice_cmd_rx) = mpsc::unbounded_channel::<voice::manager::VoiceCommand>();
-    let (voice_event_tx, mut voice_event_rx) = mpsc::unbounded_channel::<voice::manager::VoiceEvent>();
-    app.voice_tx = Some(voice_cmd_tx);
-    
-    // Spawn Voice Manager Task
-    tokio::spawn(async move {
-        let mut manager = VoiceManager::new(voice_event_tx);
-        manager.run(voice_cmd_rx).await;
-    });
+    if app.config.ui.spellcheck {
+        set_spellcheck(app, true);
+    }
+    let problems = app.config.validate();
+    report_config_problems(app, &problems);
+
+    // Reload config.toml when it changes. The watcher only signals; the
+    // reload itself happens in the loop below, between draws.
+    let (config_changed_tx, mut config_changed_rx) = mpsc::unbounded_channel::<()>();
+    let _config_watcher = match Config::config_path() {
+        Some(path) if app.config.ui.watch_config => match config::watch(&path, config_changed_tx) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                app.set_status(format!("Not watching config file for changes: {}", e), StatusLevel::Warn);
+                None
+            }
+        },
+        _ => None,
+    };
+    let mut config_changed_at: Option<std::time::Instant> = None;
+
+    // Setup Voice Manager. Without voice support `voice_tx` stays empty and
+    // nothing ever arrives on the event channel.
+    #[cfg(feature = "voice")]
+    let mut voice_event_rx = {
+        let (voice_cmd_tx, voice_cmd_rx) = mpsc::unbounded_channel::<VoiceCommand>();
+        let (voice_event_tx, voice_event_rx) = mpsc::unbounded_channel::<VoiceEvent>();
+        app.voice_tx = Some(voice_cmd_tx);
+        let audio_config = app.config.audio.clone();
+
+        // Spawn Voice Manager Task
+        tokio::spawn(async move {
+            let mut manager = VoiceManager::new(voice_event_tx, audio_config);
+            manager.run(voice_cmd_rx).await;
+        });
+        voice_event_rx
+    };
+    #[cfg(not(feature = "voice"))]
+    let (_, mut voice_event_rx) = mpsc::unbounded_channel::<VoiceEvent>();
 
     // Check if user is registered (has auth token)
-    let token = load_auth_token(&app.config.auth.token_path);
+    let token = auth_token(app);
     let mut token_exists = token.is_some();
     
     if let Some(t) = &token {
@@ -345,7 +862,7 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> i
                 // Token expires in less than 24 hours, try to refresh silently
                 match refresh_token(&app.config.server.url, t).await {
                     Ok(new_token) => {
-                        if let Err(e) = save_auth_token(&new_token) {
+                        if let Err(e) = store_auth_token(app, &new_token) {
                             // Log but don't fail - old token still works
                             eprintln!("Warning: Failed to save refreshed token: {}", e);
                         }
@@ -364,105 +881,95 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> i
     
     if !token_exists {
         // No token found - need to register or re-authenticate
-        let (keys, from_agent) = ssh::get_available_keys();
+        let (keys, agent_reachable) = ssh::get_available_keys();
         app.available_keys = keys;
-        app.using_agent = from_agent;
+        app.using_agent = agent_reachable;
         if app.available_keys.is_empty() {
             app.current_screen = CurrentScreen::Registration;
-            app.status_message = "No SSH keys found. Create one to register.".to_string();
+            app.set_status("No SSH keys found. Create one to register.", StatusLevel::Warn);
         } else {
             app.current_screen = CurrentScreen::KeySelection;
             app.selected_key_index = 0;
-            let source = if from_agent { "ssh-agent" } else { "~/.ssh" };
-            app.status_message = format!("Welcome! Select an SSH key ({}).", source);
+            let source = if agent_reachable { "ssh-agent + ~/.ssh" } else { "~/.ssh" };
+            app.set_status(format!("Welcome! Select an SSH key ({}).", source), StatusLevel::Info);
         }
     } else {
-        // Token exists - establish WebSocket connection
-        if let Ok(()) = establish_connection(app, ws_incoming_tx.clone()).await {
-            app.status_message = "Connected! Create or Join a secure room.".to_string();
-        } else {
-            app.status_message = "Failed to connect to server. Check if server is running.".to_string();
-        }
+        app.signing_key = saved_login_key(app);
+        // Token exists - establish WebSocket connection in the background
+        start_connection(app);
     }
 
+    let mut last_draw = std::time::Instant::now();
     loop {
-        terminal.draw(|f| ui(f, app))?;
-
         // Clear expired typing indicators (older than 3 seconds)
+        let typing_before = app.typing_users.len();
         app.typing_users.retain(|_, timestamp| {
             timestamp.elapsed() < std::time::Duration::from_secs(3)
         });
+        if app.typing_users.len() != typing_before {
+            app.needs_redraw = true;
+        }
 
-        if app.should_quit {
-            break;
+        // Keep animated widgets moving even when nothing else changed
+        if app.is_animating() && last_draw.elapsed() >= ANIMATION_FRAME {
+            app.needs_redraw = true;
         }
 
-        // Establish connection after registration completes
-        if app.current_screen == CurrentScreen::RoomChoice 
-            && app.ws_sender.is_none() 
-            && load_auth_token(&app.config.auth.token_path).is_some() 
-        {
-            if let Ok(()) = establish_connection(app, ws_incoming_tx.clone()).await {
-                app.status_message = "Connected! Create or Join a secure room.".to_string();
-            } else {
-                app.status_message = "Failed to connect to server.".to_string();
-            }
+        // Only redraw when something changed to keep idle CPU low
+        if app.needs_redraw {
+            terminal.draw(|f| ui(f, app))?;
+            app.needs_redraw = false;
+            last_draw = std::time::Instant::now();
         }
 
-        // Handle incoming WebSocket messages without blocking UI
-        if let Ok(text) = ws_incoming_rx.try_recv() {
-            // Check for disconnect signal
-            if text == "__DISCONNECT__" {
-                app.messages.push(ChatMessage::system("[SYSTEM] Connection lost. Attempting to reconnect...".to_string()));
-                app.ws_sender = None;
-                
-                // Attempt reconnection in background
-                if app.current_screen == CurrentScreen::InRoom {
-                    if let Some(room_id) = app.room_id.clone() {
-                        // Try to reconnect and rejoin the room
-                        match establish_connection(app, ws_incoming_tx.clone()).await {
-                            Ok(_) => {
-                                // Rejoin the room after reconnection
-                                if let Some(sender) = &app.ws_sender {
-                                    let join_payload = JoinRoomPayload {
-                                        room_id: Some(&room_id),
-                                        room_name: None,
-                                    };
-                                    let join_msg = ClientMessage {
-                                        message_type: "joinRoom",
-                                        payload: join_payload,
-                                    };
-                                    if let Ok(json) = serde_json::to_string(&join_msg) {
-                                        let _ = sender.send(json);
-                                    }
-                                }
-                                app.messages.push(ChatMessage::system("[SYSTEM] Reconnected successfully!".to_string()));
-                            }
-                            Err(_) => {
-                                app.messages.push(ChatMessage::system("[SYSTEM] Failed to reconnect. Please restart.".to_string()));
-                            }
-                        }
-                    }
-                }
-            } else {
-                match serde_json::from_str::<ServerMessage>(&text) {
-                    Ok(server_msg) => handle_server_message(app, server_msg),
-                    Err(_) => {
-                        // Raw info messages from the server
-                        app.messages.push(ChatMessage::system(format!("[SERVER] {}", text)));
-                    }
-                };
+        if app.should_quit {
+            // Hang up before the socket closes, so peers hear about it
+            if app.voice.status != VoiceConnectionStatus::Disconnected {
+                send_leave_voice_signal(app);
+                voice_torn_down(app).await;
             }
+            break;
+        }
+
+        update_terminal_title(app);
+        check_heartbeat(app);
+        check_delivery_timeouts(app);
+        check_rate_limit(app);
+        app.expire_status();
+
+        process_network_events(app, &mut connection_rx);
+
+        while config_changed_rx.try_recv().is_ok() {
+            config_changed_at = Some(std::time::Instant::now());
+        }
+        if config_changed_at.is_some_and(|at| at.elapsed() >= CONFIG_RELOAD_DEBOUNCE) {
+            config_changed_at = None;
+            reload_config(app);
         }
 
         // Handle Voice Events - ALL voice state changes happen here
         // CRITICAL: Drain ALL pending events to avoid race conditions
         // (e.g., user typing :vc before Disconnected event is processed)
         while let Ok(event) = voice_event_rx.try_recv() {
+            app.needs_redraw = true;
             match event {
                 VoiceEvent::Signal { target_id, signal_type, data } => {
+                    // Without a connection or room the handshake can never finish, so
+                    // leave voice instead of hanging in "Connecting" forever
+                    if app.transport.is_none() || app.room_id.is_none() {
+                        // A call riding out a reconnect loses the signal but
+                        // keeps going
+                        let waiting = app.voice.status == VoiceConnectionStatus::Reconnecting && app.room_id.is_some();
+                        if !waiting && app.voice.status != VoiceConnectionStatus::Disconnected {
+                            if let Some(voice_tx) = &app.voice_tx {
+                                let _ = voice_tx.send(VoiceCommand::Leave);
+                            }
+                            app.set_status("Voice signaling unavailable: not connected to server. Leaving voice.", StatusLevel::Warn);
+                        }
+                        continue;
+                    }
                     // Send this signal to the server via WebSocket
-                    if let (Some(ws_sender), Some(room_id)) = (&app.ws_sender, &app.room_id) {
+                    if let (Some(transport), Some(room_id)) = (&app.transport, &app.room_id) {
                         let payload = VoiceSignalPayload {
                             room_id: room_id.clone(),
                             target_user_id: target_id,
@@ -476,27 +983,37 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> i
                             payload,
                         };
                         if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = ws_sender.send(json);
+                            let _ = transport.send(json);
                         }
                     }
                 }
                 VoiceEvent::Connecting => {
                     app.voice.status = VoiceConnectionStatus::Connecting;
-                    app.status_message = "Connecting to voice...".to_string();
+                    app.pending_operation = Some("Connecting to voice...".to_string());
                 }
                 VoiceEvent::Connected => {
+                    app.pending_operation = None;
                     app.voice.status = VoiceConnectionStatus::Connected;
                     app.voice.is_muted = false;
                     app.voice.is_transmitting = false;
-                    app.status_message = "Connected to voice.".to_string();
+                    if app.voice.listen_only {
+                        app.set_status("Connected to voice (listening only, no microphone).", StatusLevel::Warn);
+                    } else {
+                        app.set_status("Connected to voice.", StatusLevel::Info);
+                    }
                 }
                 VoiceEvent::Disconnected => {
                     app.voice.reset();
-                    app.status_message = "Disconnected from voice.".to_string();
+                    app.set_status("Disconnected from voice.", StatusLevel::Info);
+                }
+                VoiceEvent::ListenOnly(reason) => {
+                    app.voice.listen_only = true;
+                    app.set_status(format!("No microphone ({}); listening only.", reason), StatusLevel::Warn);
                 }
                 VoiceEvent::ConnectionFailed(reason) => {
+                    app.pending_operation = None;
                     app.voice.reset();
-                    app.status_message = format!("Voice connection failed: {}", reason);
+                    app.set_status(format!("Voice connection failed: {}", reason), StatusLevel::Error);
                 }
                 VoiceEvent::PeerConnected(peer_id) => {
                     if !app.voice.connected_peers.contains(&peer_id) {
@@ -509,37 +1026,95 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> i
                 VoiceEvent::PeerConnectionFailed(peer_id) => {
                     app.voice.connected_peers.retain(|p| p != &peer_id);
                 }
+                VoiceEvent::SignalingFailed { peer_id, reason } => {
+                    app.set_status(format!("Voice: {} ({})", reason, peer_id), StatusLevel::Warn);
+                }
                 VoiceEvent::MuteStateChanged(muted) => {
                     app.voice.is_muted = muted;
-                    app.status_message = if muted { 
+                    app.set_status(if muted { 
                         "Microphone muted.".to_string() 
                     } else { 
                         "Microphone unmuted.".to_string() 
-                    };
+                    }, StatusLevel::Info);
                 }
                 VoiceEvent::TxActivity(active) => {
                     app.voice.is_transmitting = active;
-                    if active {
-                        app.voice.tx_last_time = Some(std::time::Instant::now());
-                    }
                 }
                 VoiceEvent::AudioError(e) => {
-                    app.status_message = format!("Audio error: {}", e);
+                    app.set_status(format!("Audio: {}", e), StatusLevel::Warn);
+                }
+                VoiceEvent::Stats(stats) => {
+                    app.needs_redraw = app.show_diagnostics;
+                    app.diagnostics.voice = stats;
                 }
             }
         }
 
-        // Handle user input
-        if event::poll(std::time::Duration::from_millis(50))? {
+        // Handle user input. Poll quickly while things are happening (anything
+        // that redraws counts) and back off once idle.
+        let poll_interval = if app.is_animating() || last_draw.elapsed() < POLL_BACKOFF_AFTER {
+            app.config.ui.poll_interval()
+        } else {
+            app.config.ui.idle_poll_interval()
+        };
+        if event::poll(poll_interval)? {
             let event = event::read()?;
+            app.needs_redraw = true;
             match event {
                 Event::FocusGained => {
                     app.is_focused = true;
+                    app.unread_count = 0;
                 }
                 Event::FocusLost => {
                     app.is_focused = false;
                 }
                 Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    // Typing means we're focused, whatever the terminal reported
+                    // (some multiplexers send FocusLost but never FocusGained)
+                    if !app.is_focused {
+                        app.is_focused = true;
+                        app.unread_count = 0;
+                    }
+
+                    // F2 shows the status log from any screen
+                    if key.code == KeyCode::F(2) {
+                        app.show_status_log = !app.show_status_log;
+                        app.status_log_scroll = 0;
+                        continue;
+                    }
+                    if app.show_status_log {
+                        handle_status_log_key(app, key);
+                        continue;
+                    }
+                    // F1 shows version and build info from any screen
+                    if key.code == KeyCode::F(1) {
+                        app.show_about = !app.show_about;
+                        continue;
+                    }
+                    if app.show_about {
+                        if matches!(key.code, KeyCode::Esc | KeyCode::Char('q')) {
+                            app.show_about = false;
+                        }
+                        continue;
+                    }
+                    // F12 shows live connection and voice counters from any screen
+                    if key.code == KeyCode::F(12) {
+                        app.show_diagnostics = !app.show_diagnostics;
+                        continue;
+                    }
+                    if app.show_diagnostics {
+                        if matches!(key.code, KeyCode::Esc | KeyCode::Char('q')) {
+                            app.show_diagnostics = false;
+                        }
+                        continue;
+                    }
+
+                    // Esc aborts an in-flight connection attempt from any screen
+                    if key.code == KeyCode::Esc && app.connect_task.is_some() {
+                        cancel_connection(app);
+                        continue;
+                    }
+
                     // Handle command mode input
                     if app.command_input.is_some() {
                         match key.code {
@@ -578,29 +1153,27 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> i
                         CurrentScreen::RegistrationSuccess => handle_registration_success_screen(app, key).await,
                         
                         // Re-authentication screens
-                        CurrentScreen::ReAuthenticate => handle_re_authenticate_screen(app, key).await,
                         CurrentScreen::PassphraseInput => handle_passphrase_input_screen(app, key).await,
                         
                         // Main screens
-                        CurrentScreen::RoomChoice => handle_room_choice_screen(app, key).await,
-                        CurrentScreen::RoomList => handle_room_list_screen(app, key, ws_incoming_tx.clone()).await,
-                        CurrentScreen::RoomTypeSelection => handle_room_type_selection_screen(app, key).await,
-                        CurrentScreen::CreateRoomInput => {
-                            handle_create_room_input_screen(app, key, ws_incoming_tx.clone()).await
-                        }
-                        CurrentScreen::RoomCreation => {
-                            handle_room_creation_screen(app, key, ws_incoming_tx.clone()).await
+                        CurrentScreen::RoomChoice
+                        | CurrentScreen::RoomList
+                        | CurrentScreen::RoomTypeSelection
+                        | CurrentScreen::CreateRoomInput
+                        | CurrentScreen::JoinRoomInput
+                        | CurrentScreen::RoomCreation => {
+                            let actions = app.handle_key(key);
+                            run_actions(app, actions);
                         }
                         CurrentScreen::InRoom => handle_in_room_screen(app, key).await,
                         CurrentScreen::RoomSwitcher => handle_room_switcher_screen(app, key).await,
                         CurrentScreen::Help => handle_help_screen(app, key),
                     }
                 }
-                Event::Mouse(mouse_event) => {
-                    // Handle mouse events (selection, scrolling, etc.)
-                    if app.current_screen == CurrentScreen::InRoom {
-                        handle_mouse_in_room(app, mouse_event);
-                    }
+                Event::Paste(text) => handle_paste(app, &text),
+                // Handle mouse events (selection, scrolling, etc.)
+                Event::Mouse(mouse_event) if app.terminal_caps.mouse && app.current_screen == CurrentScreen::InRoom => {
+                    handle_mouse_in_room(app, mouse_event);
                 }
                 _ => {}
             }
@@ -615,6 +1188,17 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> i
 
 async fn handle_registration_screen(app: &mut App<'_>, key: event::KeyEvent) {
     // This screen shows when no SSH keys are found
+
+    // Waiting for confirmation to write a new key
+    if let Some(path) = app.pending_keygen.take() {
+        if key.code == KeyCode::Enter {
+            generate_first_key(app, &path);
+        } else {
+            app.set_status("Key generation cancelled", StatusLevel::Info);
+        }
+        return;
+    }
+
     match key.code {
         KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => {
             app.should_quit = true;
@@ -624,38 +1208,76 @@ async fn handle_registration_screen(app: &mut App<'_>, key: event::KeyEvent) {
         }
         KeyCode::Char('r') | KeyCode::Char('R') => {
             // Retry scanning for keys
-            let (keys, from_agent) = ssh::get_available_keys();
+            let (keys, agent_reachable) = ssh::get_available_keys();
             app.available_keys = keys;
-            app.using_agent = from_agent;
+            app.using_agent = agent_reachable;
             if !app.available_keys.is_empty() {
                 app.current_screen = CurrentScreen::KeySelection;
                 app.selected_key_index = 0;
-                let source = if from_agent { "ssh-agent" } else { "~/.ssh" };
-                app.status_message = format!("Select SSH key ({}).", source);
+                let source = if agent_reachable { "ssh-agent + ~/.ssh" } else { "~/.ssh" };
+                app.set_status(format!("Select SSH key ({}).", source), StatusLevel::Info);
             } else {
-                app.status_message = "Still no SSH keys found. Create one first.".to_string();
+                app.set_status("Still no SSH keys found. Create one first.", StatusLevel::Warn);
             }
         }
+        KeyCode::Char('g') | KeyCode::Char('G') => match ssh::default_key_path() {
+            Some(path) if path.exists() || path.with_extension("pub").exists() => {
+                app.set_status(format!("{} already exists; not touching it", path.display()), StatusLevel::Warn);
+            }
+            Some(path) => {
+                app.set_sticky_status(
+                    format!("Write a new ed25519 key to {} ? Enter to confirm, any other key to cancel", path.display()),
+                    StatusLevel::Info,
+                );
+                app.pending_keygen = Some(path);
+            }
+            None => app.set_status("Can't find your home directory", StatusLevel::Error),
+        },
         _ => {}
     }
 }
 
+/// Write a new key at `path` and move on to picking it for registration
+fn generate_first_key(app: &mut App<'_>, path: &std::path::Path) {
+    let comment = std::env::var("USER").map_or_else(|_| "eurus".to_string(), |user| format!("{} (eurus)", user));
+    let new_key = match ssh::generate_ed25519_key(path, &comment) {
+        Ok(new_key) => new_key,
+        Err(e) => {
+            app.set_status(format!("Couldn't create a key: {}", e), StatusLevel::Error);
+            return;
+        }
+    };
+
+    let (mut keys, agent_reachable) = ssh::get_available_keys();
+    let index = match keys.iter().position(|key| key.public_key == new_key.public_key) {
+        Some(index) => index,
+        None => {
+            keys.push(new_key);
+            keys.len() - 1
+        }
+    };
+    app.available_keys = keys;
+    app.using_agent = agent_reachable;
+    app.selected_key_index = index;
+    app.current_screen = CurrentScreen::KeySelection;
+    app.set_status(
+        format!("Created {0} without a passphrase (add one with ssh-keygen -p -f {0}). Enter to register with it.", path.display()),
+        StatusLevel::Info,
+    );
+}
+
 async fn handle_key_selection_screen(app: &mut App<'_>, key: event::KeyEvent) {
     match key.code {
-        KeyCode::Up | KeyCode::Char('k') => {
-            if app.selected_key_index > 0 {
-                app.selected_key_index -= 1;
-            }
+        KeyCode::Up | KeyCode::Char('k') if app.selected_key_index > 0 => {
+            app.selected_key_index -= 1;
         }
-        KeyCode::Down | KeyCode::Char('j') => {
-            if app.selected_key_index + 1 < app.available_keys.len() {
-                app.selected_key_index += 1;
-            }
+        KeyCode::Down | KeyCode::Char('j') if app.selected_key_index + 1 < app.available_keys.len() => {
+            app.selected_key_index += 1;
         }
         KeyCode::Enter => {
             // Proceed to username input
             app.current_screen = CurrentScreen::UsernameInput;
-            app.status_message = "Enter your desired username".to_string();
+            app.set_status("Enter your desired username", StatusLevel::Info);
         }
         KeyCode::Char(':') => {
             app.command_input = Some(String::new());
@@ -672,7 +1294,7 @@ async fn handle_username_input_screen(app: &mut App<'_>, key: event::KeyEvent) {
         KeyCode::Enter => {
             let username = app.username_input.lines().join("").trim().to_string();
             if username.is_empty() {
-                app.status_message = "Username cannot be empty".to_string();
+                app.set_status("Username cannot be empty", StatusLevel::Warn);
                 return;
             }
             
@@ -681,22 +1303,22 @@ async fn handle_username_input_screen(app: &mut App<'_>, key: event::KeyEvent) {
                 let public_key = ssh_key.public_key.clone();
                 
                 // First check if user already exists
-                app.status_message = "Checking username...".to_string();
+                app.set_status("Checking username...", StatusLevel::Info);
                 
                 match check_user_exists(&app.config.server.url, &username).await {
                     Ok((true, Some(server_key_type))) => {
                         // User exists - need to re-authenticate
                         // Check if selected key type matches
                         if ssh_key.key_type != server_key_type {
-                            app.status_message = format!(
+                            app.set_status(format!(
                                 "Key mismatch: server has {} key, you selected {} key",
                                 server_key_type, ssh_key.key_type
-                            );
+                            ), StatusLevel::Error);
                             return;
                         }
                         
                         // Request challenge from server
-                        app.status_message = "Welcome back! Authenticating...".to_string();
+                        app.set_status("Welcome back! Authenticating...", StatusLevel::Info);
                         
                         match request_challenge(&app.config.server.url, &username).await {
                             Ok(challenge) => {
@@ -707,7 +1329,7 @@ async fn handle_username_input_screen(app: &mut App<'_>, key: event::KeyEvent) {
                                 let challenge_bytes = match hex::decode(&challenge) {
                                     Ok(b) => b,
                                     Err(_) => {
-                                        app.status_message = "Invalid challenge from server".to_string();
+                                        app.set_status("Invalid challenge from server", StatusLevel::Error);
                                         return;
                                     }
                                 };
@@ -726,68 +1348,70 @@ async fn handle_username_input_screen(app: &mut App<'_>, key: event::KeyEvent) {
                                         ).await {
                                             Ok(token) => {
                                                 // Success! Save token and proceed
-                                                if let Err(e) = save_auth_token(&token) {
+                                                if let Err(e) = store_auth_token(app, &token) {
                                                     app.registration_error = Some(format!("Failed to save token: {}", e));
                                                 }
                                                 app.current_username = extract_username_from_token(&token);
                                                 app.registration_token = Some(token);
+                                                set_login_key(app, &ssh_key, None);
                                                 app.current_screen = CurrentScreen::RegistrationSuccess;
-                                                app.status_message = "Re-authentication successful!".to_string();
+                                                app.set_status("Re-authentication successful!", StatusLevel::Info);
                                             }
                                             Err(e) => {
-                                                app.status_message = format!("Authentication failed: {}", e);
+                                                app.set_status(format!("Authentication failed: {}", e), StatusLevel::Error);
                                             }
                                         }
                                     }
                                     Err(ssh::SignError::NeedsPassphrase) => {
                                         // Key is encrypted, need passphrase
                                         app.current_screen = CurrentScreen::PassphraseInput;
-                                        app.status_message = "Enter passphrase for encrypted key".to_string();
+                                        app.set_status("Enter passphrase for encrypted key", StatusLevel::Info);
                                     }
                                     Err(e) => {
-                                        app.status_message = format!("Signing failed: {}", e);
+                                        app.set_status(format!("Signing failed: {}", e), StatusLevel::Error);
                                     }
                                 }
                             }
                             Err(e) => {
-                                app.status_message = format!("Failed to get challenge: {}", e);
+                                app.set_status(format!("Failed to get challenge: {}", e), StatusLevel::Error);
                             }
                         }
                     }
                     Ok((true, None)) => {
                         // User exists but no key type info (shouldn't happen)
-                        app.status_message = "User exists but key type unknown".to_string();
+                        app.set_status("User exists but key type unknown", StatusLevel::Error);
                     }
                     Ok((false, _)) => {
                         // User doesn't exist - proceed with registration
-                        app.status_message = "Registering...".to_string();
+                        app.set_status("Registering...", StatusLevel::Info);
                         
                         match register_user(&app.config.server.url, &username, &public_key).await {
                             Ok(token) => {
                                 // Save token
-                                if let Err(e) = save_auth_token(&token) {
+                                if let Err(e) = store_auth_token(app, &token) {
                                     app.registration_error = Some(format!("Failed to save token: {}", e));
                                 }
                                 app.current_username = extract_username_from_token(&token);
                                 app.registration_token = Some(token);
+                                set_login_key(app, &ssh_key, None);
                                 app.current_screen = CurrentScreen::RegistrationSuccess;
-                                app.status_message = "Registration successful!".to_string();
+                                app.set_status("Registration successful!", StatusLevel::Info);
                             }
                             Err(e) => {
                                 app.registration_error = Some(e.to_string());
-                                app.status_message = format!("Registration failed: {}", e);
+                                app.set_status(format!("Registration failed: {}", e), StatusLevel::Error);
                             }
                         }
                     }
                     Err(e) => {
-                        app.status_message = format!("Error: {}", e);
+                        app.set_status(format!("Error: {}", e), StatusLevel::Error);
                     }
                 }
             }
         }
         KeyCode::Esc => {
             app.current_screen = CurrentScreen::KeySelection;
-            app.status_message = "Select SSH key to use for registration".to_string();
+            app.set_status("Select SSH key to use for registration", StatusLevel::Info);
         }
         KeyCode::Char(':') => {
             app.command_input = Some(String::new());
@@ -803,22 +1427,10 @@ async fn handle_registration_success_screen(app: &mut App<'_>, key: event::KeyEv
         KeyCode::Enter => {
             // Continue to main app
             app.current_screen = CurrentScreen::RoomChoice;
-            app.status_message = "Create or Join a secure room.".to_string();
-        }
-        KeyCode::Char(':') => {
-            app.command_input = Some(String::new());
-        }
-        _ => {}
-    }
-}
-
-// Re-authentication screen handler (currently unused - re-auth happens in username input)
-async fn handle_re_authenticate_screen(app: &mut App<'_>, key: event::KeyEvent) {
-    match key.code {
-        KeyCode::Esc => {
-            app.current_screen = CurrentScreen::KeySelection;
-            app.pending_challenge = None;
-            app.status_message = "Select SSH key".to_string();
+            app.set_status("Create or Join a secure room.", StatusLevel::Info);
+            if app.transport.is_none() {
+                start_connection(app);
+            }
         }
         KeyCode::Char(':') => {
             app.command_input = Some(String::new());
@@ -829,6 +1441,12 @@ async fn handle_re_authenticate_screen(app: &mut App<'_>, key: event::KeyEvent)
 
 async fn handle_passphrase_input_screen(app: &mut App<'_>, key: event::KeyEvent) {
     match key.code {
+        KeyCode::Enter if app.pending_rejoin.is_some() => unlock_rejoin(app),
+        KeyCode::Esc if app.pending_rejoin.take().is_some() => {
+            app.passphrase_input = passphrase_input();
+            app.current_screen = CurrentScreen::RoomChoice;
+            app.set_status("Create or Join a secure room.", StatusLevel::Info);
+        }
         KeyCode::Enter => {
             let passphrase = app.passphrase_input.lines().join("").to_string();
             let username = app.username_input.lines().join("").trim().to_string();
@@ -839,12 +1457,12 @@ async fn handle_passphrase_input_screen(app: &mut App<'_>, key: event::KeyEvent)
                     let challenge_bytes = match hex::decode(&challenge) {
                         Ok(b) => b,
                         Err(_) => {
-                            app.status_message = "Invalid challenge".to_string();
+                            app.set_status("Invalid challenge", StatusLevel::Error);
                             return;
                         }
                     };
                     
-                    app.status_message = "Signing...".to_string();
+                    app.set_status("Signing...", StatusLevel::Info);
                     
                     // Try to sign with passphrase
                     match ssh::sign_challenge(&ssh_key, &challenge_bytes, Some(&passphrase)) {
@@ -858,42 +1476,31 @@ async fn handle_passphrase_input_screen(app: &mut App<'_>, key: event::KeyEvent)
                                 &ssh_key.public_key,
                             ).await {
                                 Ok(token) => {
-                                    if let Err(e) = save_auth_token(&token) {
+                                    if let Err(e) = store_auth_token(app, &token) {
                                         app.registration_error = Some(format!("Failed to save token: {}", e));
                                     }
                                     app.current_username = extract_username_from_token(&token);
                                     app.registration_token = Some(token);
+                                    set_login_key(app, &ssh_key, Some(&passphrase));
                                     app.current_screen = CurrentScreen::RegistrationSuccess;
-                                    app.status_message = "Re-authentication successful!".to_string();
+                                    app.set_status("Re-authentication successful!", StatusLevel::Info);
                                     
                                     // Clear sensitive data
                                     app.pending_challenge = None;
-                                    app.passphrase_input = {
-                                        let mut input = TextArea::default();
-                                        input.set_placeholder_text("Enter passphrase...");
-                                        input.set_block(Block::default().borders(Borders::ALL).title("Passphrase"));
-                                        input.set_mask_char('\u{2022}');
-                                        input
-                                    };
+                                    app.passphrase_input = passphrase_input();
                                 }
                                 Err(e) => {
-                                    app.status_message = format!("Authentication failed: {}", e);
+                                    app.set_status(format!("Authentication failed: {}", e), StatusLevel::Error);
                                 }
                             }
                         }
                         Err(ssh::SignError::Decrypt(_)) => {
-                            app.status_message = "Wrong passphrase. Try again.".to_string();
+                            app.set_status("Wrong passphrase. Try again.", StatusLevel::Error);
                             // Clear passphrase input for retry
-                            app.passphrase_input = {
-                                let mut input = TextArea::default();
-                                input.set_placeholder_text("Enter passphrase...");
-                                input.set_block(Block::default().borders(Borders::ALL).title("Passphrase"));
-                                input.set_mask_char('\u{2022}');
-                                input
-                            };
+                            app.passphrase_input = passphrase_input();
                         }
                         Err(e) => {
-                            app.status_message = format!("Signing failed: {}", e);
+                            app.set_status(format!("Signing failed: {}", e), StatusLevel::Error);
                         }
                     }
                 }
@@ -903,15 +1510,9 @@ async fn handle_passphrase_input_screen(app: &mut App<'_>, key: event::KeyEvent)
             // Go back to username input
             app.current_screen = CurrentScreen::UsernameInput;
             app.pending_challenge = None;
-            app.status_message = "Enter username".to_string();
+            app.set_status("Enter username", StatusLevel::Info);
             // Clear passphrase
-            app.passphrase_input = {
-                let mut input = TextArea::default();
-                input.set_placeholder_text("Enter passphrase...");
-                input.set_block(Block::default().borders(Borders::ALL).title("Passphrase"));
-                input.set_mask_char('\u{2022}');
-                input
-            };
+            app.passphrase_input = passphrase_input();
         }
         KeyCode::Char(':') => {
             app.command_input = Some(String::new());
@@ -922,250 +1523,863 @@ async fn handle_passphrase_input_screen(app: &mut App<'_>, key: event::KeyEvent)
     }
 }
 
-async fn handle_room_choice_screen(app: &mut App<'_>, key: event::KeyEvent) {
-    match key.code {
-        KeyCode::Char('c') | KeyCode::Char('C') => {
-            app.current_screen = CurrentScreen::RoomTypeSelection;
-            app.selected_room_type = false;  // Default to public
-            app.status_message = "Select room type: Tab to switch, Enter to continue".to_string();
-        }
-        KeyCode::Char('j') | KeyCode::Char('J') => {
-            app.current_screen = CurrentScreen::RoomList;
-            app.status_message = "Loading rooms...".to_string();
-            
-            // Request room list
-            if let Some(ws_sender) = &app.ws_sender {
-                let list_message = ClientMessage {
-                    message_type: "listRooms",
-                    payload: ListRoomsPayload {},
-                };
-                if let Ok(json) = serde_json::to_string(&list_message) {
-                    let _ = ws_sender.send(json);
-                }
+/// Open the remembered room's key with the passphrase just typed for the
+/// SSH key it was sealed to, and rejoin
+fn unlock_rejoin(app: &mut App<'_>) {
+    let Some((room, path)) = app.pending_rejoin.take() else {
+        return;
+    };
+    let passphrase = app.passphrase_input.lines().join("");
+    app.passphrase_input = passphrase_input();
+    match ssh::load_private_key(&path, Some(&passphrase)) {
+        Ok(private_key) => match room.open_key(std::slice::from_ref(&private_key)) {
+            Some(room_key) => {
+                let actions = app.join_room_by_id(room.room_id, Some(room_key));
+                run_actions(app, actions);
+            }
+            None => {
+                app.current_screen = CurrentScreen::RoomChoice;
+                app.set_status(format!("{} doesn't open the saved room key", path.display()), StatusLevel::Error);
             }
+        },
+        Err(ssh::SignError::Decrypt(_)) => {
+            app.set_status("Wrong passphrase. Try again.", StatusLevel::Error);
+            app.pending_rejoin = Some((room, path));
         }
-        KeyCode::Char(':') => {
-            app.command_input = Some(String::new());
+        Err(e) => {
+            app.current_screen = CurrentScreen::RoomChoice;
+            app.set_status(e.to_string(), StatusLevel::Error);
         }
-        _ => {}
     }
 }
 
-async fn handle_room_type_selection_screen(app: &mut App<'_>, key: event::KeyEvent) {
-    match key.code {
-        KeyCode::Tab => {
-            // Toggle between public and private
-            app.selected_room_type = !app.selected_room_type;
-            let type_str = if app.selected_room_type { "Private" } else { "Public" };
-            app.status_message = format!("Room type: {} - Tab to switch, Enter to continue", type_str);
-        }
-        KeyCode::Enter => {
-            // Continue to room name input
-            app.current_screen = CurrentScreen::CreateRoomInput;
-            app.currently_editing = Some(CurrentlyEditing::RoomName);
-            let type_str = if app.selected_room_type { "private" } else { "public" };
-            app.status_message = format!("Creating {} room - enter a name", type_str);
+/// Side effects requested by `App::handle_key`, carried out by `run_actions`.
+/// Keeping them out of the key handlers lets screen transitions be tested
+/// without a server.
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Send a serialized client message to the server
+    Send(String),
+    Quit,
+}
+
+/// Text color for your own messages with `ui.own_messages = "tint"`
+const OWN_MESSAGE_TINT: Color = Color::Rgb(150, 190, 230);
+
+/// Shown in place of each character of a hidden room key
+const KEY_MASK: char = '•';
+
+fn room_id_input() -> TextArea<'static> {
+    let mut input = TextArea::default();
+    input.set_placeholder_text("Room id, e.g. 3f8a9c...");
+    input
+}
+
+fn room_key_input() -> TextArea<'static> {
+    let mut input = TextArea::default();
+    input.set_placeholder_text("64 hex characters (optional)");
+    input.set_mask_char(KEY_MASK);
+    input
+}
+
+fn passphrase_input() -> TextArea<'static> {
+    let mut input = TextArea::default();
+    input.set_placeholder_text("Enter passphrase...");
+    input.set_block(Block::default().borders(Borders::ALL).title("Passphrase"));
+    input.set_mask_char('\u{2022}'); // Bullet character to hide passphrase
+    input
+}
+
+/// The first non-blank line of pasted text, for single-line inputs
+fn first_line(text: &str) -> &str {
+    text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("")
+}
+
+/// Bracketed paste: the text goes to whatever input has focus in one piece,
+/// so newlines in it don't submit forms or send messages
+fn handle_paste(app: &mut App<'_>, text: &str) {
+    if let Some(cmd) = &mut app.command_input {
+        cmd.push_str(first_line(text));
+        return;
+    }
+    match app.current_screen {
+        CurrentScreen::UsernameInput => {
+            app.username_input.insert_str(first_line(text));
         }
-        KeyCode::Esc => {
-            // Go back to main menu
-            app.current_screen = CurrentScreen::RoomChoice;
-            app.status_message = "Create or Join a secure room.".to_string();
+        CurrentScreen::PassphraseInput => {
+            app.passphrase_input.insert_str(first_line(text));
         }
-        KeyCode::Char(':') => {
-            app.command_input = Some(String::new());
+        CurrentScreen::CreateRoomInput | CurrentScreen::JoinRoomInput => app.paste_into_form(text),
+        CurrentScreen::InRoom => {
+            for (i, line) in text.lines().enumerate() {
+                if i > 0 {
+                    app.message_input.insert_newline();
+                }
+                app.message_input.insert_str(line);
+            }
         }
         _ => {}
     }
 }
 
-async fn handle_room_list_screen(
-    app: &mut App<'_>,
-    key: event::KeyEvent,
-    _ws_tx: mpsc::UnboundedSender<String>,
-) {
-    match key.code {
-        KeyCode::Up => {
-            if app.selected_room_index > 0 {
-                app.selected_room_index -= 1;
-            }
+/// Serialize a client message for `Action::Send`
+fn client_json<T: serde::Serialize>(message_type: &str, payload: T) -> Option<String> {
+    serde_json::to_string(&ClientMessage { message_type, payload }).ok()
+}
+
+impl<'a> App<'a> {
+    /// Handle a key on the room menu screens (choose, list, create, join).
+    /// Updates state and returns the IO to perform. Registration and the
+    /// in-room screens still go through their async handlers, so they return
+    /// nothing here.
+    fn handle_key(&mut self, key: event::KeyEvent) -> Vec<Action> {
+        // Ids and keys are typed here, so ':' is text
+        if key.code == KeyCode::Char(':') && self.current_screen != CurrentScreen::JoinRoomInput {
+            self.command_input = Some(String::new());
+            return Vec::new();
+        }
+        match self.current_screen {
+            CurrentScreen::RoomChoice => self.room_choice_key(key),
+            CurrentScreen::RoomTypeSelection => self.room_type_selection_key(key),
+            CurrentScreen::RoomList => self.room_list_key(key),
+            CurrentScreen::CreateRoomInput => self.create_room_input_key(key),
+            CurrentScreen::JoinRoomInput => self.join_room_input_key(key),
+            CurrentScreen::RoomCreation => self.room_creation_key(key),
+            _ => Vec::new(),
         }
-        KeyCode::Down => {
-            let room_count = if app.viewing_private {
-                app.private_rooms.len()
-            } else {
-                app.public_rooms.len()
-            };
-            if app.selected_room_index + 1 < room_count {
-                app.selected_room_index += 1;
+    }
+
+    fn room_choice_key(&mut self, key: event::KeyEvent) -> Vec<Action> {
+        match key.code {
+            KeyCode::Esc => vec![Action::Quit],
+            KeyCode::Char('c') | KeyCode::Char('C') => {
+                self.current_screen = CurrentScreen::RoomTypeSelection;
+                self.selected_room_type = false;  // Default to public
+                self.set_status("Select room type: Tab to switch, Enter to continue", StatusLevel::Info);
+                Vec::new()
             }
-        }
-        KeyCode::Tab => {
-            app.viewing_private = !app.viewing_private;
-            app.selected_room_index = 0;
-        }
-        KeyCode::Enter => {
-            let room_name = if app.viewing_private {
-                app.private_rooms.get(app.selected_room_index).map(|r| r.name.clone())
-            } else {
-                app.public_rooms.get(app.selected_room_index).map(|r| r.name.clone())
-            };
-            
-            if let Some(name) = room_name {
-                app.status_message = format!("Joining room: {}", name);
-                
-                // Join room using existing WebSocket connection
-                if let Some(ws_sender) = &app.ws_sender {
-                    let join_payload = JoinRoomPayload {
-                        room_id: None,
-                        room_name: Some(&name),
-                    };
-                    let join_message = ClientMessage {
-                        message_type: "joinRoom",
-                        payload: join_payload,
-                    };
-                    if let Ok(json) = serde_json::to_string(&join_message) {
-                        if ws_sender.send(json).is_ok() {
-                            app.messages.clear();
-                            app.current_screen = CurrentScreen::InRoom;
-                        }
-                    }
-                }
+            KeyCode::Char('j') | KeyCode::Char('J') => {
+                self.current_screen = CurrentScreen::RoomList;
+                self.set_status("Loading rooms...", StatusLevel::Info);
+                // Request room list
+                client_json("listRooms", ListRoomsPayload {}).map(Action::Send).into_iter().collect()
             }
-        }
-        KeyCode::Char('r') | KeyCode::Char('R') => {
-            // Refresh room list
-            if let Some(ws_sender) = &app.ws_sender {
-                let list_message = ClientMessage {
-                    message_type: "listRooms",
-                    payload: ListRoomsPayload {},
+            KeyCode::Char('i') | KeyCode::Char('I') => {
+                self.current_screen = CurrentScreen::JoinRoomInput;
+                self.currently_editing = Some(CurrentlyEditing::RoomId);
+                Vec::new()
+            }
+            KeyCode::Char('r') | KeyCode::Char('R') => self.rejoin_last_room(),
+            KeyCode::Char('p') | KeyCode::Char('P') => {
+                let pasted = match &mut self.clipboard {
+                    Some(clipboard) => clipboard.paste_text(),
+                    None => Err(clipboard::UNAVAILABLE.to_string()),
                 };
-                if let Ok(json) = serde_json::to_string(&list_message) {
-                    let _ = ws_sender.send(json);
+                match pasted {
+                    Ok(text) => self.join_pasted(&text),
+                    Err(e) => {
+                        self.set_status(e, StatusLevel::Error);
+                        Vec::new()
+                    }
                 }
             }
+            _ => Vec::new(),
         }
-        KeyCode::Esc => {
-            app.current_screen = CurrentScreen::RoomChoice;
+    }
+
+    /// Text pasted into the room name or join form. A room id paste that also
+    /// carries a key (link, "id:key", two lines, "ID: x / Key: y") fills in
+    /// both fields.
+    fn paste_into_form(&mut self, text: &str) {
+        match self.currently_editing {
+            Some(CurrentlyEditing::RoomName) => {
+                self.room_name_input.insert_str(first_line(text));
+            }
+            Some(CurrentlyEditing::RoomKey) => {
+                self.room_key_input.insert_str(first_line(text));
+            }
+            Some(CurrentlyEditing::RoomId) => match room_link::parse(text) {
+                Ok(room_link::RoomLink { room_id, key: Some(key) }) => {
+                    self.room_id_input = room_id_input();
+                    self.room_id_input.insert_str(room_id);
+                    self.room_key_input = room_key_input();
+                    self.room_key_input.insert_str(hex::encode(key));
+                    if self.room_key_revealed {
+                        self.room_key_input.clear_mask_char();
+                    }
+                    self.currently_editing = Some(CurrentlyEditing::RoomKey);
+                    self.set_status("Filled in room id and key", StatusLevel::Info);
+                }
+                Ok(room_link::RoomLink { room_id, key: None }) => {
+                    self.room_id_input.insert_str(room_id);
+                }
+                Err(_) => {
+                    self.room_id_input.insert_str(first_line(text));
+                }
+            },
+            None => {}
         }
-        // Vim-style navigation
-        KeyCode::Char('j') => {
-            let room_count = if app.viewing_private {
-                app.private_rooms.len()
-            } else {
-                app.public_rooms.len()
+    }
+
+    /// Join the room in a pasted `radiochat://` link or "id:key" pair
+    fn join_pasted(&mut self, text: &str) -> Vec<Action> {
+        let link = match room_link::parse(text) {
+            Ok(link) => link,
+            Err(e) => {
+                self.set_status(format!("Clipboard doesn't hold a room link or id:key ({})", e), StatusLevel::Warn);
+                return Vec::new();
+            }
+        };
+        self.join_room_by_id(link.room_id, link.key)
+    }
+
+    /// Go back to the room remembered from last time, unsealing its key with
+    /// our SSH keys (asking for a passphrase if the key file has one)
+    fn rejoin_last_room(&mut self) -> Vec<Action> {
+        let Some(room) = self.last_room.for_server(&self.config.server.url).cloned() else {
+            self.set_status("No room to rejoin yet", StatusLevel::Info);
+            return Vec::new();
+        };
+        if room.key_in_secrets {
+            let stored = self.secrets.as_ref().and_then(|secrets| secrets.get(&secrets::room_key_entry(&room.room_id)).ok().flatten());
+            return match stored.and_then(|key_hex| key_from_hex(&key_hex)) {
+                Some(key) => self.join_room_by_id(room.room_id, Some(key)),
+                None => {
+                    self.fill_join_form(&room.room_id, "The keyring no longer has this room's key; enter it to rejoin");
+                    Vec::new()
+                }
             };
-            if app.selected_room_index + 1 < room_count {
-                app.selected_room_index += 1;
+        }
+        let Some(sealed) = &room.sealed_key else {
+            if room.key_needed {
+                self.fill_join_form(&room.room_id, "No SSH key held this room's key; enter it to rejoin");
+                return Vec::new();
+            }
+            return self.join_room_by_id(room.room_id, None);
+        };
+        let keys = self.whisper_keys.get_or_insert_with(ssh::unlocked_ed25519_keys);
+        if let Some(key) = room.open_key(keys) {
+            return self.join_room_by_id(room.room_id, Some(key));
+        }
+        match ssh::find_key_file(&sealed.public_key) {
+            Some(path) => {
+                self.set_status(format!("Enter the passphrase for {} to unlock the room key", path.display()), StatusLevel::Info);
+                self.pending_rejoin = Some((room, path));
+                self.current_screen = CurrentScreen::PassphraseInput;
             }
+            None => self.fill_join_form(&room.room_id, "The SSH key holding this room's key is gone; enter the key to rejoin"),
         }
-        KeyCode::Char('k') => {
-            if app.selected_room_index > 0 {
-                app.selected_room_index -= 1;
+        Vec::new()
+    }
+
+    /// Open the join form with `room_id` filled in and the key field focused
+    fn fill_join_form(&mut self, room_id: &str, status: &str) {
+        self.leave_join_form();
+        self.room_id_input.insert_str(room_id);
+        self.currently_editing = Some(CurrentlyEditing::RoomKey);
+        self.current_screen = CurrentScreen::JoinRoomInput;
+        self.set_status(status, StatusLevel::Info);
+    }
+
+    /// Join a room by id, keeping `key` for it in case the server sends none
+    fn join_room_by_id(&mut self, room_id: String, key: Option<AesKey>) -> Vec<Action> {
+        let Some(join) = client_json("joinRoom", JoinRoomPayload { room_id: Some(&room_id), room_name: None }) else {
+            return Vec::new();
+        };
+        self.set_status(format!("Joining room {}...", short_room_id(&room_id)), StatusLevel::Info);
+        self.supplied_room_key = key.map(|key| (room_id, key));
+        self.messages.clear();
+        self.current_screen = CurrentScreen::InRoom;
+        vec![Action::Send(join)]
+    }
+
+    fn join_room_input_key(&mut self, key: event::KeyEvent) -> Vec<Action> {
+        let editing_key = self.currently_editing == Some(CurrentlyEditing::RoomKey);
+        match key.code {
+            KeyCode::Esc => {
+                self.leave_join_form();
+                self.current_screen = CurrentScreen::RoomChoice;
+            }
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                self.currently_editing = Some(if editing_key { CurrentlyEditing::RoomId } else { CurrentlyEditing::RoomKey });
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.room_key_revealed = !self.room_key_revealed;
+                if self.room_key_revealed {
+                    self.room_key_input.clear_mask_char();
+                } else {
+                    self.room_key_input.set_mask_char(KEY_MASK);
+                }
+            }
+            KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Dry run: check what was typed without connecting
+                match self.join_form_values() {
+                    Ok((room_id, Some(room_key))) => self.set_status(
+                        format!("Room {} looks right, key fingerprint {}", short_room_id(&room_id), key_fingerprint(&room_key)),
+                        StatusLevel::Info,
+                    ),
+                    Ok((room_id, None)) => self.set_status(
+                        format!("Room {} looks right, no key entered", short_room_id(&room_id)),
+                        StatusLevel::Info,
+                    ),
+                    Err(e) => self.set_status(e, StatusLevel::Warn),
+                }
+            }
+            KeyCode::Enter => {
+                let (room_id, room_key) = match self.join_form_values() {
+                    Ok(values) => values,
+                    Err(e) => {
+                        self.set_status(e, StatusLevel::Warn);
+                        return Vec::new();
+                    }
+                };
+                self.leave_join_form();
+                return self.join_room_by_id(room_id, room_key);
+            }
+            _ if editing_key => {
+                self.room_key_input.input(key);
+            }
+            _ => {
+                self.room_id_input.input(key);
             }
         }
-        KeyCode::Char(':') => {
-            app.command_input = Some(String::new());
+        Vec::new()
+    }
+
+    /// The room id and optional key typed into the join form, or what's wrong
+    /// with them
+    fn join_form_values(&self) -> Result<(String, Option<AesKey>), String> {
+        let room_id = self.room_id_input.lines().join("").trim().to_string();
+        let key_hex = self.room_key_input.lines().join("").trim().to_string();
+        if room_id.is_empty() {
+            return Err("Enter a room id".to_string());
+        }
+        let room_key = match key_hex.as_str() {
+            "" => None,
+            key_hex => Some(parse_key_hex(key_hex).map_err(|e| format!("Room key: {}", e))?),
+        };
+        room_link::check_room_id(&room_id).map_err(|e| format!("Invalid {}", e))?;
+        Ok((room_id, room_key))
+    }
+
+    /// Forget what was typed in the join form, masking the key again
+    fn leave_join_form(&mut self) {
+        self.room_id_input = room_id_input();
+        self.room_key_input = room_key_input();
+        self.room_key_revealed = false;
+        self.currently_editing = None;
+    }
+
+    fn room_type_selection_key(&mut self, key: event::KeyEvent) -> Vec<Action> {
+        match key.code {
+            KeyCode::Tab => {
+                // Toggle between public and private
+                self.selected_room_type = !self.selected_room_type;
+                let type_str = if self.selected_room_type { "Private" } else { "Public" };
+                self.set_status(format!("Room type: {} - Tab to switch, Enter to continue", type_str), StatusLevel::Info);
+            }
+            KeyCode::Enter => {
+                // Continue to room name input
+                self.current_screen = CurrentScreen::CreateRoomInput;
+                self.currently_editing = Some(CurrentlyEditing::RoomName);
+                let type_str = if self.selected_room_type { "private" } else { "public" };
+                self.set_status(format!("Creating {} room - enter a name", type_str), StatusLevel::Info);
+            }
+            KeyCode::Esc => {
+                // Go back to main menu
+                self.current_screen = CurrentScreen::RoomChoice;
+                self.set_status("Create or Join a secure room.", StatusLevel::Info);
+            }
+            _ => {}
         }
-        _ => {}
+        Vec::new()
     }
-}
 
-async fn handle_create_room_input_screen(
-    app: &mut App<'_>,
-    key: event::KeyEvent,
-    _ws_tx: mpsc::UnboundedSender<String>,
-) {
-    match key.code {
-        KeyCode::Enter => {
-            let room_name = app.room_name_input.lines().join("");
-            if room_name.is_empty() {
-                app.status_message = "Room name cannot be empty!".to_string();
-                return;
+    fn room_list_key(&mut self, key: event::KeyEvent) -> Vec<Action> {
+        let room_count = if self.viewing_private {
+            self.private_rooms.len()
+        } else {
+            self.public_rooms.len()
+        };
+        match key.code {
+            // Arrow and vim-style navigation
+            KeyCode::Up | KeyCode::Char('k') if self.selected_room_index > 0 => {
+                self.selected_room_index -= 1;
             }
-            
-            app.status_message = "Creating room...".to_string();
-            
-            // Create room using existing WebSocket connection
-            if let Some(ws_sender) = &app.ws_sender {
-                let room_type_str = if app.selected_room_type { "private" } else { "public" };
-                let create_payload = CreateRoomPayload {
-                    name: &room_name,
-                    display_name: None,
-                    room_type: room_type_str,
-                };
-                let create_message = ClientMessage {
-                    message_type: "createRoom",
-                    payload: create_payload,
+            KeyCode::Down | KeyCode::Char('j') if self.selected_room_index + 1 < room_count => {
+                self.selected_room_index += 1;
+            }
+            KeyCode::Tab => {
+                self.viewing_private = !self.viewing_private;
+                self.selected_room_index = 0;
+            }
+            KeyCode::Enter => {
+                let room_name = if self.viewing_private {
+                    self.private_rooms.get(self.selected_room_index).map(|r| r.name.clone())
+                } else {
+                    self.public_rooms.get(self.selected_room_index).map(|r| r.name.clone())
                 };
-                if let Ok(json) = serde_json::to_string(&create_message) {
-                    if ws_sender.send(json).is_ok() {
-                        app.current_screen = CurrentScreen::RoomCreation;
+                if let Some(name) = room_name {
+                    if self.transport.is_none() {
+                        self.set_sticky_status("Not connected to the server.", StatusLevel::Error);
+                        return Vec::new();
                     }
+                    self.set_status(format!("Joining room: {}", name), StatusLevel::Info);
+                    let join = client_json("joinRoom", JoinRoomPayload { room_id: None, room_name: Some(&name) });
+                    self.messages.clear();
+                    self.current_screen = CurrentScreen::InRoom;
+                    return join.map(Action::Send).into_iter().collect();
                 }
             }
+            KeyCode::Char('r') | KeyCode::Char('R') => {
+                // Refresh room list
+                return client_json("listRooms", ListRoomsPayload {}).map(Action::Send).into_iter().collect();
+            }
+            KeyCode::Esc => {
+                self.current_screen = CurrentScreen::RoomChoice;
+            }
+            _ => {}
         }
-        KeyCode::Esc => {
-            app.current_screen = CurrentScreen::RoomChoice;
-        }
-        KeyCode::Char(':') => {
-            app.command_input = Some(String::new());
+        Vec::new()
+    }
+
+    fn create_room_input_key(&mut self, key: event::KeyEvent) -> Vec<Action> {
+        match key.code {
+            KeyCode::Enter => {
+                let room_name = self.room_name_input.lines().join("");
+                if room_name.is_empty() {
+                    self.set_status("Room name cannot be empty!", StatusLevel::Warn);
+                    return Vec::new();
+                }
+
+                if self.transport.is_none() {
+                    self.set_sticky_status("Not connected to the server.", StatusLevel::Error);
+                    return Vec::new();
+                }
+                self.set_status("Creating room...", StatusLevel::Info);
+                let room_type_str = if self.selected_room_type { "private" } else { "public" };
+                let create = client_json(
+                    "createRoom",
+                    CreateRoomPayload { name: &room_name, display_name: None, room_type: room_type_str },
+                );
+                self.current_screen = CurrentScreen::RoomCreation;
+                return create.map(Action::Send).into_iter().collect();
+            }
+            KeyCode::Esc => {
+                self.current_screen = CurrentScreen::RoomChoice;
+            }
+            _ => {
+                self.room_name_input.input(key);
+            }
         }
-        _ => {
-            app.room_name_input.input(key);
+        Vec::new()
+    }
+
+    fn room_creation_key(&mut self, key: event::KeyEvent) -> Vec<Action> {
+        match key.code {
+            KeyCode::Enter => {
+                if let Some(room_name) = self.room_name.clone() {
+                    // Join the room we just created using joinRoom message
+                    if self.transport.is_none() {
+                        self.set_sticky_status("Not connected to the server.", StatusLevel::Error);
+                        return Vec::new();
+                    }
+                    let join = client_json("joinRoom", JoinRoomPayload { room_id: None, room_name: Some(&room_name) });
+                    self.messages.clear();
+                    self.current_screen = CurrentScreen::InRoom;
+                    self.set_status(format!("Joined room: #{}", room_name), StatusLevel::Info);
+                    return join.map(Action::Send).into_iter().collect();
+                } else {
+                    // If no room was created (e.g. error), Enter should just go back
+                    self.current_screen = CurrentScreen::RoomChoice;
+                    self.set_status("Create or Join a secure room.", StatusLevel::Info);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                // Go back to main menu
+                self.current_screen = CurrentScreen::RoomChoice;
+                self.set_status("Create or Join a secure room.", StatusLevel::Info);
+            }
+            _ => {}
         }
+        Vec::new()
     }
 }
 
-async fn handle_room_creation_screen(
-    app: &mut App<'_>,
-    key: event::KeyEvent,
-    _ws_tx: mpsc::UnboundedSender<String>,
-) {
-    match key.code {
-        KeyCode::Enter => {
-            if let Some(room_name) = &app.room_name {
-                // Join the room we just created using joinRoom message
-                if let Some(ws_sender) = &app.ws_sender {
-                    let join_payload = JoinRoomPayload {
-                        room_id: None,
-                        room_name: Some(room_name),
-                    };
-                    let join_message = ClientMessage {
-                        message_type: "joinRoom",
-                        payload: join_payload,
-                    };
-                    if let Ok(json) = serde_json::to_string(&join_message) {
-                        if ws_sender.send(json).is_ok() {
-                            app.messages.clear();
-                            app.current_screen = CurrentScreen::InRoom;
-                            app.status_message = format!("Joined room: #{}", room_name);
-                        }
-                    }
+/// Carry out the side effects returned by `App::handle_key`
+fn run_actions(app: &mut App<'_>, actions: Vec<Action>) {
+    for action in actions {
+        match action {
+            Action::Send(json) => {
+                let sent = app.transport.as_ref().is_some_and(|sender| sender.send(json).is_ok());
+                if !sent {
+                    app.set_sticky_status("Not connected to the server.", StatusLevel::Error);
                 }
-            } else {
-                // If no room was created (e.g. error), Enter should just go back
-                app.current_screen = CurrentScreen::RoomChoice;
-                app.status_message = "Create or Join a secure room.".to_string();
             }
+            Action::Quit => app.should_quit = true,
         }
-        KeyCode::Esc => {
-            // Go back to main menu
-            app.current_screen = CurrentScreen::RoomChoice;
-            app.status_message = "Create or Join a secure room.".to_string();
+    }
+}
+
+/// Show the SSH keys to register or log in with
+fn choose_login_key(app: &mut App<'_>) {
+    let (keys, agent_reachable) = ssh::get_available_keys();
+    app.available_keys = keys;
+    app.using_agent = agent_reachable;
+    if app.available_keys.is_empty() {
+        app.current_screen = CurrentScreen::Registration;
+        app.set_status("No SSH keys found", StatusLevel::Warn);
+    } else {
+        app.current_screen = CurrentScreen::KeySelection;
+        app.selected_key_index = 0;
+        let source = if agent_reachable { "ssh-agent + ~/.ssh" } else { "~/.ssh" };
+        app.set_status(format!("Select SSH key ({}).", source), StatusLevel::Info);
+    }
+}
+
+/// Sign out on this machine: leave the room, drop the connection, and delete
+/// the saved token and remembered room
+async fn logout(app: &mut App<'_>) {
+    if app.room_id.is_some() {
+        leave_room(app).await;
+    }
+    cancel_connection(app);
+    app.transport = None;
+    app.current_username = None;
+    app.whisper_keys = None;
+    let forgotten = forget_login(app);
+    choose_login_key(app);
+    match forgotten {
+        Ok(()) => app.set_status("Logged out. Choose a key to log in again.", StatusLevel::Info),
+        Err(e) => app.set_sticky_status(format!("Logged out, but couldn't delete saved credentials: {}", e), StatusLevel::Error),
+    }
+}
+
+/// Delete the saved token (file or secret store), the login key record and
+/// the remembered room
+fn forget_login(app: &mut App<'_>) -> Result<(), Box<dyn Error>> {
+    app.signing_key = None;
+    if let Some(secrets) = &mut app.secrets {
+        secrets.delete(secrets::TOKEN)?;
+    }
+    let files = [token_file(&app.config.auth.token_path), login_key_file(&app.config.auth.token_path)];
+    for path in files.into_iter().flatten() {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
         }
-        KeyCode::Char('q') => {
-             // Go back to main menu
-            app.current_screen = CurrentScreen::RoomChoice;
-            app.status_message = "Create or Join a secure room.".to_string();
+    }
+    forget_last_room(app)
+}
+
+/// Public key of the key the saved token was issued for, next to the token
+/// file. Lets a later start sign with the same key.
+fn login_key_file(token_path: &str) -> Option<std::path::PathBuf> {
+    token_file(token_path).map(|path| path.with_file_name("login_key.pub"))
+}
+
+/// After a login: sign messages with `key` from now on if it can without
+/// asking again (agent, unencrypted, or just unlocked with `passphrase`),
+/// and remember it for the next start
+fn set_login_key(app: &mut App<'_>, key: &ssh::SshKey, passphrase: Option<&str>) {
+    app.signing_key = ssh::SigningKey::open(key, passphrase).ok();
+    let save = |path: std::path::PathBuf| -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
         }
-        KeyCode::Char(':') => {
-            app.command_input = Some(String::new());
+        std::fs::write(path, format!("{}\n", key.public_key))
+    };
+    let saved = login_key_file(&app.config.auth.token_path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))
+        .and_then(save);
+    if let Err(e) = saved {
+        app.registration_error = Some(format!("Failed to save login key: {}", e));
+    }
+}
+
+/// The key a saved token was issued for, when it's still available and can
+/// sign without a passphrase
+fn saved_login_key(app: &App<'_>) -> Option<ssh::SigningKey> {
+    let public_key = std::fs::read_to_string(login_key_file(&app.config.auth.token_path)?).ok()?;
+    let key = ssh::find_key(public_key.trim())?;
+    ssh::SigningKey::open(&key, None).ok()
+}
+
+/// Leave the current room (and its voice call) and return to the main menu
+async fn leave_room(app: &mut App<'_>) {
+    if app.voice.status == VoiceConnectionStatus::Disconnected {
+        leave_voice_call(app);
+    } else {
+        // Tear the call down while the room (and socket) are still there,
+        // so peers hear we've gone and no audio streams outlive the room
+        send_leave_voice_signal(app);
+        if voice_torn_down(app).await {
+            app.voice.reset();
+        }
+        app.voice.room_users.clear();
+    }
+    app.room_id = None;
+    app.room_name = None;
+    app.room_key = None;
+    app.messages.clear();
+    app.online_users.clear();
+    app.away_users.clear();
+    app.member_keys.clear();
+    app.typing_users.clear();
+    app.pending_image = None;
+    app.pending_voice_join = None;
+    app.current_screen = CurrentScreen::RoomChoice;
+    app.set_status("Left room. Press C to create or J to join.", StatusLevel::Info);
+}
+
+/// Hang up the current room's voice call, telling the server and the voice
+/// manager
+fn leave_voice_call(app: &mut App<'_>) {
+    send_leave_voice_signal(app);
+    // Now send Leave command to voice manager for local cleanup
+    if let Some(voice_tx) = &app.voice_tx {
+        let _ = voice_tx.send(VoiceCommand::Leave);
+    }
+    // Note: voice state reset happens via VoiceEvent::Disconnected handler
+    // Clear room_users separately since that's from server, not voice events
+    app.voice.room_users.clear();
+}
+
+/// Tell the room's peers we're hanging up. Goes straight onto the socket
+/// rather than waiting for the voice manager's own signal, which the event
+/// handler drops once room_id is cleared.
+fn send_leave_voice_signal(app: &App<'_>) {
+    if app.voice.status != VoiceConnectionStatus::Disconnected {
+        if let (Some(transport), Some(room_id)) = (&app.transport, &app.room_id) {
+            let payload = VoiceSignalPayload {
+                room_id: room_id.clone(),
+                target_user_id: None,
+                sender_user_id: None,
+                sender_username: None,
+                signal_type: "leave_voice".to_string(),
+                data: "".to_string(),
+            };
+            let msg = ClientMessage {
+                message_type: "voiceSignal",
+                payload,
+            };
+            if let Ok(json) = serde_json::to_string(&msg) {
+                let _ = transport.send(json);
+            }
+        }
+    }
+}
+
+/// Join another room from inside one. The server moves us out of the old
+/// room on join, but its voice call can't follow, so that's left first.
+/// Picking the room we're already in does nothing.
+fn switch_room(app: &mut App<'_>, room: &RoomInfo) {
+    if app.room_id.as_deref() == Some(room.room_id.as_str()) {
+        app.current_screen = CurrentScreen::InRoom;
+        app.set_status(format!("Already in #{}", room.name), StatusLevel::Info);
+        return;
+    }
+    if app.transport.is_none() {
+        app.set_sticky_status("Not connected to the server.", StatusLevel::Error);
+        return;
+    }
+    let Some(join) = client_json("joinRoom", JoinRoomPayload { room_id: None, room_name: Some(&room.name) }) else {
+        return;
+    };
+    leave_voice_call(app);
+    app.messages.clear();
+    app.current_screen = CurrentScreen::InRoom;
+    app.set_status(format!("Switching to #{}", room.name), StatusLevel::Info);
+    run_actions(app, vec![Action::Send(join)]);
+}
+
+/// Leave voice and wait (briefly) until peers and audio are torn down, so the
+/// next key press never races a half-closed call
+async fn leave_voice_and_wait(app: &mut App<'_>) {
+    if app.voice_tx.is_none() {
+        return;
+    }
+    send_leave_voice_signal(app);
+    if voice_torn_down(app).await {
+        app.set_status("Left voice. Press Esc again to leave the room.", StatusLevel::Info);
+    } else {
+        app.set_status("Leaving voice...", StatusLevel::Info);
+    }
+}
+
+/// Have the voice manager leave, waiting up to two seconds for it to
+/// confirm peers and audio are gone. False if it didn't confirm in time.
+async fn voice_torn_down(app: &App<'_>) -> bool {
+    let Some(voice_tx) = &app.voice_tx else { return false };
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+    if voice_tx.send(VoiceCommand::LeaveAndNotify(done_tx)).is_err() {
+        return false;
+    }
+    matches!(tokio::time::timeout(Duration::from_secs(2), done_rx).await, Ok(Ok(())))
+}
+
+/// Ask the voice manager to join the room's call.
+/// State changes arrive later via VoiceEvent::Connecting/Connected.
+///
+/// Signaling rides on the chat socket, so the join needs a live connection:
+/// with none it's refused, and if the server hasn't echoed a heartbeat
+/// lately one is sent and the join waits for its echo. A dead socket then
+/// surfaces as a lost connection instead of a call stuck connecting.
+fn join_voice(app: &mut App<'_>, room_id: String) {
+    if app.voice_tx.is_none() {
+        app.set_status(voice::UNAVAILABLE, StatusLevel::Warn);
+        return;
+    }
+    // Check if already connected (using voice state, not server state)
+    if app.voice.is_connected() {
+        app.set_status("Already in voice chat.", StatusLevel::Warn);
+    } else if matches!(app.voice.status, VoiceConnectionStatus::Connecting) || app.pending_voice_join.is_some() {
+        app.set_status("Already connecting to voice...", StatusLevel::Warn);
+    } else if app.transport.is_none() {
+        app.set_status("Cannot join voice: not connected to server.", StatusLevel::Error);
+    } else if server_unverified(app) {
+        app.pending_voice_join = Some(room_id);
+        if app.heartbeat_sent_at.is_none() {
+            send_heartbeat(app);
+        }
+        app.set_status("Checking the server connection before joining voice...", StatusLevel::Info);
+    } else {
+        send_voice_join(app, room_id);
+    }
+}
+
+/// Whether the server echoes heartbeats but hasn't answered one recently
+/// enough to trust the socket for voice signaling. Servers that never echo
+/// (or heartbeats turned off) can't be checked, so they're trusted.
+fn server_unverified(app: &App<'_>) -> bool {
+    let Some(acked) = app.last_heartbeat else {
+        return false;
+    };
+    app.config.network.heartbeat_interval > 0
+        && (app.heartbeat_sent_at.is_some() || acked.elapsed() >= VOICE_JOIN_HEARTBEAT_AGE)
+}
+
+fn send_voice_join(app: &mut App<'_>, room_id: String) {
+    if let Some(voice_tx) = &app.voice_tx {
+        let _ = voice_tx.send(VoiceCommand::Join(room_id));
+        // Show it now rather than when the manager gets round to saying so
+        app.voice.status = VoiceConnectionStatus::Connecting;
+        app.pending_operation = Some("Connecting to voice...".to_string());
+        app.set_status("Joining voice...", StatusLevel::Info);
+    }
+}
+
+/// Ask the voice manager to leave; state resets via VoiceEvent::Disconnected
+fn leave_voice(app: &mut App<'_>) {
+    app.pending_voice_join = None;
+    if let Some(voice_tx) = &app.voice_tx {
+        let _ = voice_tx.send(VoiceCommand::Leave);
+        app.set_status("Leaving voice...", StatusLevel::Info);
+    }
+}
+
+/// Re-read config.toml and apply it. A file that doesn't parse leaves the
+/// current config in place.
+fn reload_config(app: &mut App<'_>) {
+    let Some(path) = Config::config_path() else { return };
+    match Config::load_from(&path) {
+        Ok(config) => apply_config(app, config),
+        Err(e) => app.set_sticky_status(format!("Config not reloaded: {}", e), StatusLevel::Error),
+    }
+}
+
+/// Switch to a newly loaded config. UI settings apply at once and server and
+/// network settings from the next connection; audio devices are picked when
+/// the voice manager starts, so those need a restart.
+fn apply_config(app: &mut App<'_>, mut config: Config) {
+    let problems = config.validate();
+    // The original title is only saved at startup, so it can't be turned on later
+    config.ui.terminal_title &= app.config.ui.terminal_title;
+    let old = std::mem::replace(&mut app.config, config);
+
+    if app.config.ui.spellcheck_dictionary != old.ui.spellcheck_dictionary {
+        app.spell = None;
+        if app.spellcheck_enabled {
+            set_spellcheck(app, true);
         }
+    }
+    if app.config.ui.spellcheck != old.ui.spellcheck {
+        set_spellcheck(app, app.config.ui.spellcheck);
+    }
+    if app.config.ui.show_timestamps != old.ui.show_timestamps {
+        app.show_timestamps = app.config.ui.show_timestamps;
+    }
+    app.needs_redraw = true;
+
+    if app.config.audio != old.audio {
+        app.set_status("Config reloaded; audio changes apply after a restart", StatusLevel::Info);
+    } else {
+        app.set_status("Config reloaded", StatusLevel::Info);
+    }
+    report_config_problems(app, &problems);
+}
+
+/// Log each config problem. The footer shows the only one, or a count
+/// pointing at the status log; an unusable value stays up until replaced.
+fn report_config_problems(app: &mut App<'_>, problems: &[config::ConfigProblem]) {
+    for problem in problems {
+        let level = if problem.is_invalid() { StatusLevel::Error } else { StatusLevel::Warn };
+        app.set_status(format!("Config: {}", problem), level);
+    }
+    if problems.len() > 1 {
+        let level = if problems.iter().any(|p| p.is_invalid()) { StatusLevel::Error } else { StatusLevel::Warn };
+        app.set_status(format!("{} config problems (F2 for the status log)", problems.len()), level);
+    }
+    if problems.iter().any(|p| p.is_invalid()) {
+        app.status_set_at = None;
+    }
+}
+
+/// Turn composer spell-checking on or off, loading the dictionary on first use
+fn set_spellcheck(app: &mut App<'_>, enabled: bool) {
+    if enabled && app.spell.is_none() {
+        match spell::SpellChecker::load(app.config.ui.spellcheck_dictionary.as_deref()) {
+            Ok(checker) => app.spell = Some(checker),
+            Err(e) => {
+                app.set_status(format!("Spell-check unavailable: {}", e), StatusLevel::Warn);
+                return;
+            }
+        }
+    }
+    app.spellcheck_enabled = enabled;
+    if !enabled {
+        let _ = app.message_input.set_search_pattern("");
+    }
+}
+
+fn handle_status_log_key(app: &mut App<'_>, key: event::KeyEvent) {
+    let max_scroll = app.status_log.len().saturating_sub(1);
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => app.show_status_log = false,
+        KeyCode::Char('k') | KeyCode::Up => app.status_log_scroll = (app.status_log_scroll + 1).min(max_scroll),
+        KeyCode::Char('j') | KeyCode::Down => app.status_log_scroll = app.status_log_scroll.saturating_sub(1),
+        KeyCode::PageUp => app.status_log_scroll = (app.status_log_scroll + 10).min(max_scroll),
+        KeyCode::PageDown => app.status_log_scroll = app.status_log_scroll.saturating_sub(10),
+        KeyCode::Char('G') | KeyCode::End => app.status_log_scroll = 0,
         _ => {}
     }
 }
 
+/// Show or hide message times for this session; the config file is left alone
+fn toggle_timestamps(app: &mut App<'_>) {
+    app.show_timestamps = !app.show_timestamps;
+    app.set_status(format!("Timestamps {}", if app.show_timestamps { "shown" } else { "hidden" }), StatusLevel::Info);
+}
+
 async fn handle_in_room_screen(app: &mut App<'_>, key: event::KeyEvent) {
+    if key.code == KeyCode::F(7) {
+        set_spellcheck(app, !app.spellcheck_enabled);
+        if app.spell.is_some() {
+            app.set_status(format!("Spell-check {}", if app.spellcheck_enabled { "on" } else { "off" }), StatusLevel::Info);
+        }
+        return;
+    }
+
     // Handle clipboard keybindings (work in any mode)
     if key.modifiers.contains(KeyModifiers::CONTROL) {
         match key.code {
@@ -1174,10 +2388,12 @@ async fn handle_in_room_screen(app: &mut App<'_>, key: event::KeyEvent) {
                     let text = app.message_input.lines().join("\n");
                     if !text.is_empty() {
                         match clipboard.copy_text(&text) {
-                            Ok(_) => app.status_message = "Text copied to clipboard".to_string(),
-                            Err(e) => app.status_message = format!("Failed to copy: {}", e),
+                            Ok(_) => app.set_status("Text copied to clipboard", StatusLevel::Info),
+                            Err(e) => app.set_status(format!("Failed to copy: {}", e), StatusLevel::Error),
                         }
                     }
+                } else {
+                    app.set_status(clipboard::UNAVAILABLE, StatusLevel::Warn);
                 }
                 return;
             }
@@ -1190,10 +2406,12 @@ async fn handle_in_room_screen(app: &mut App<'_>, key: event::KeyEvent) {
                                     app.message_input.insert_str(line);
                                     app.message_input.insert_newline();
                                 }
-                                app.status_message = "Text pasted from clipboard".to_string();
+                                app.set_status("Text pasted from clipboard", StatusLevel::Info);
                             }
-                            Err(e) => app.status_message = format!("Failed to paste: {}", e),
+                            Err(e) => app.set_status(format!("Failed to paste: {}", e), StatusLevel::Error),
                         }
+                    } else {
+                        app.set_status(clipboard::UNAVAILABLE, StatusLevel::Warn);
                     }
                 }
                 return;
@@ -1201,10 +2419,27 @@ async fn handle_in_room_screen(app: &mut App<'_>, key: event::KeyEvent) {
             KeyCode::Char('v') => {
                 if let Some(clipboard) = &mut app.clipboard {
                     if clipboard.has_image() {
-                        app.status_message = "Image paste not yet implemented".to_string();
+                        match clipboard.paste_image(&app.config.images) {
+                            Ok(image) => {
+                                let resized = match image.resized_from {
+                                    Some((width, height)) => {
+                                        format!(" (resized {}x{} → {}x{})", width, height, image.width, image.height)
+                                    }
+                                    None => String::new(),
+                                };
+                                app.set_status(
+                                    format!("Attached {}{}. Esc in normal mode removes it.", image.label(), resized),
+                                    StatusLevel::Info,
+                                );
+                                app.pending_image = Some(image);
+                            }
+                            Err(e) => app.set_status(format!("Failed to paste image: {}", e), StatusLevel::Error),
+                        }
                     } else {
-                        app.status_message = "No image in clipboard".to_string();
+                        app.set_status("No image in clipboard", StatusLevel::Warn);
                     }
+                } else {
+                    app.set_status(clipboard::UNAVAILABLE, StatusLevel::Warn);
                 }
                 return;
             }
@@ -1221,62 +2456,203 @@ async fn handle_in_room_screen(app: &mut App<'_>, key: event::KeyEvent) {
 
 async fn handle_normal_mode(app: &mut App<'_>, key: event::KeyEvent) {
     // Close user list overlay if open
-    if app.show_user_list {
-        if key.code == KeyCode::Esc {
-            app.show_user_list = false;
-            app.status_message = "-- NORMAL --".to_string();
-            return;
+    if app.show_user_list && key.code == KeyCode::Esc {
+        app.show_user_list = false;
+        app.clear_status();
+        return;
+    }
+
+    // Waiting for confirmation to open a link
+    if let Some(url) = app.pending_url.take() {
+        if key.code == KeyCode::Enter {
+            match open::that_detached(&url) {
+                Ok(()) => app.set_status(format!("Opened {}", url), StatusLevel::Info),
+                Err(e) => app.set_status(format!("Failed to open link: {}", e), StatusLevel::Error),
+            }
+        } else {
+            app.clear_status();
         }
+        return;
     }
     
+    if app.show_pinned {
+        if matches!(key.code, KeyCode::Esc | KeyCode::Char('P')) {
+            app.show_pinned = false;
+        }
+        return;
+    }
+
+    if app.show_room_info {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.show_room_info = false,
+            KeyCode::Char('y') => copy_room_id(app),
+            _ => {}
+        }
+        return;
+    }
+
+    // Thread overlay: Esc closes, r replies to the latest message in the thread
+    if let Some(root) = app.thread_root.clone() {
+        match key.code {
+            KeyCode::Esc => app.thread_root = None,
+            KeyCode::Char('r') => {
+                app.replying_to = thread_members(&app.messages, &root)
+                    .last()
+                    .and_then(|i| app.messages[*i].id.clone());
+                app.thread_root = None;
+                app.vim_state.enter_insert_mode();
+                app.clear_status();
+            }
+            _ => {}
+        }
+        return;
+    }
+
     match key.code {
+        // Open the thread for the selected message
+        KeyCode::Enter if app.selected_message.is_some_and(|i| has_thread(&app.messages, i)) => {
+            if let Some(id) = app.selected_message.and_then(|i| app.messages[i].id.as_deref()) {
+                app.thread_root = Some(thread_root_of(&app.messages, id));
+                app.selected_message = None;
+            }
+        }
+        KeyCode::Char('P') => {
+            app.show_pinned = true;
+        }
+        // Toggle mute while in a call
+        KeyCode::Char('M') if app.voice.is_connected() => {
+            if let Some(voice_tx) = &app.voice_tx {
+                let _ = voice_tx.send(VoiceCommand::Mute(!app.voice.is_muted));
+            }
+        }
+        // Show or hide the member sidebar
+        KeyCode::Char('U') => {
+            app.show_roster = !app.show_roster;
+        }
+        KeyCode::Char('T') => toggle_timestamps(app),
+        // Join or leave the room's voice call
+        KeyCode::Char('V') => {
+            if app.voice.status == VoiceConnectionStatus::Disconnected {
+                if let Some(room_id) = app.room_id.clone() {
+                    join_voice(app, room_id);
+                }
+            } else {
+                leave_voice(app);
+            }
+        }
+        // Retry the selected (or most recent) failed message
+        KeyCode::Char('R') => {
+            let failed = |i: &usize| app.messages[*i].delivery == Some(DeliveryState::Failed);
+            let target = match app.selected_message {
+                Some(i) => Some(i).filter(failed),
+                None => (0..app.messages.len()).rev().find(failed),
+            };
+            match target {
+                Some(index) => {
+                    transmit_message(app, index);
+                    app.selected_message = None;
+                }
+                None => app.set_status("No failed message to retry", StatusLevel::Warn),
+            }
+        }
+        // Message selection (J/K move through the timeline, Esc clears)
+        KeyCode::Char('K') => {
+            let previous = app.selected_message;
+            app.selected_message = step_message_selection(app, false);
+            // Stepping past the oldest message loads more
+            if previous.is_some() && app.selected_message == previous {
+                request_older_history(app);
+            }
+        }
+        KeyCode::Char('J') => {
+            app.selected_message = step_message_selection(app, true);
+        }
+        KeyCode::Char('r') if app.selected_message.is_some() => {
+            let target = app.selected_message.and_then(|i| app.messages.get(i));
+            match target.filter(|m| !m.deleted).and_then(|m| m.id.clone()) {
+                Some(id) => {
+                    app.replying_to = Some(id);
+                    app.selected_message = None;
+                    app.vim_state.enter_insert_mode();
+                    app.clear_status();
+                }
+                None => app.set_status("Can't reply to that message", StatusLevel::Warn),
+            }
+        }
+        KeyCode::Esc if app.replying_to.is_some() => {
+            app.replying_to = None;
+            app.set_status("Reply cancelled", StatusLevel::Info);
+        }
+        KeyCode::Esc if app.pending_image.is_some() => {
+            app.pending_image = None;
+            app.set_status("Image removed", StatusLevel::Info);
+        }
+        KeyCode::Esc if app.editing_message_id.is_some() => {
+            app.editing_message_id = None;
+            app.message_input = TextArea::default();
+            app.set_status("Edit cancelled", StatusLevel::Info);
+        }
+        KeyCode::Esc if app.selected_message.is_some() => {
+            app.selected_message = None;
+        }
+        // Esc steps out one level: voice call first, then the room
+        KeyCode::Esc if app.voice.status != VoiceConnectionStatus::Disconnected => {
+            leave_voice_and_wait(app).await;
+        }
+        KeyCode::Esc => {
+            leave_room(app).await;
+        }
+
         // Enter Insert mode
+        // yi - copy the full room id
+        KeyCode::Char('i') if app.vim_state.pending_command == Some('y') => {
+            app.vim_state.reset();
+            copy_room_id(app);
+        }
         KeyCode::Char('i') => {
             app.vim_state.enter_insert_mode();
-            app.status_message = "-- INSERT --".to_string();
+            app.clear_status();
         }
         KeyCode::Char('a') => {
             app.vim_state.enter_insert_mode();
             // Move cursor right by one (append after cursor)
             app.message_input.move_cursor(tui_textarea::CursorMove::Forward);
-            app.status_message = "-- INSERT --".to_string();
+            app.clear_status();
         }
         KeyCode::Char('A') => {
             app.vim_state.enter_insert_mode();
             app.message_input.move_cursor(tui_textarea::CursorMove::End);
-            app.status_message = "-- INSERT --".to_string();
+            app.clear_status();
         }
         KeyCode::Char('I') => {
             app.vim_state.enter_insert_mode();
             app.message_input.move_cursor(tui_textarea::CursorMove::Head);
-            app.status_message = "-- INSERT --".to_string();
+            app.clear_status();
         }
         KeyCode::Char('o') => {
             app.vim_state.enter_insert_mode();
             app.message_input.move_cursor(tui_textarea::CursorMove::End);
             app.message_input.insert_newline();
-            app.status_message = "-- INSERT --".to_string();
+            app.clear_status();
         }
         KeyCode::Char('O') => {
             app.vim_state.enter_insert_mode();
             app.message_input.move_cursor(tui_textarea::CursorMove::Head);
             app.message_input.insert_newline();
             app.message_input.move_cursor(tui_textarea::CursorMove::Up);
-            app.status_message = "-- INSERT --".to_string();
+            app.clear_status();
         }
 
         // Navigation (hjkl)
         KeyCode::Char('h') | KeyCode::Left => {
             app.message_input.move_cursor(tui_textarea::CursorMove::Back);
+            snap_cursor_to_grapheme(app, false);
         }
-        KeyCode::Char('j') | KeyCode::Down => {
-            app.message_input.move_cursor(tui_textarea::CursorMove::Down);
-        }
-        KeyCode::Char('k') | KeyCode::Up => {
-            app.message_input.move_cursor(tui_textarea::CursorMove::Up);
-        }
+        KeyCode::Char('j') | KeyCode::Down => move_composer_row(app, true),
+        KeyCode::Char('k') | KeyCode::Up => move_composer_row(app, false),
         KeyCode::Char('l') | KeyCode::Right => {
             app.message_input.move_cursor(tui_textarea::CursorMove::Forward);
+            snap_cursor_to_grapheme(app, true);
         }
 
         // Word movement
@@ -1295,6 +2671,27 @@ async fn handle_normal_mode(app: &mut App<'_>, key: event::KeyEvent) {
             app.message_input.move_cursor(tui_textarea::CursorMove::End);
         }
 
+        // zg - add the word under the cursor to the personal dictionary
+        KeyCode::Char('g') if app.vim_state.pending_command == Some('z') => {
+            app.vim_state.reset();
+            let (row, col) = app.message_input.cursor();
+            let word = app.message_input.lines().get(row).and_then(|line| spell::word_at(line, col));
+            match (word, &mut app.spell) {
+                (Some(word), Some(checker)) => {
+                    let result = checker.add_word(&word);
+                    match result {
+                        Ok(()) => app.set_status(format!("Added \"{}\" to your dictionary", word), StatusLevel::Info),
+                        Err(e) => app.set_status(format!("Failed to save dictionary: {}", e), StatusLevel::Error),
+                    }
+                }
+                (None, _) => app.set_status("No word under cursor", StatusLevel::Warn),
+                (_, None) => app.set_status("Spell-check is off (F7 to enable)", StatusLevel::Warn),
+            }
+        }
+        KeyCode::Char('z') => {
+            app.vim_state.pending_command = Some('z');
+        }
+
         // Document movement
         KeyCode::Char('g') => {
             if app.vim_state.pending_command == Some('g') {
@@ -1319,10 +2716,58 @@ async fn handle_normal_mode(app: &mut App<'_>, key: event::KeyEvent) {
                 app.vim_state.pending_command = Some('d');
             }
         }
+        // gx - open the link in the selected (or latest linked) message
+        KeyCode::Char('x') if app.vim_state.pending_command == Some('g') => {
+            app.vim_state.reset();
+            let candidates: Box<dyn Iterator<Item = &ChatMessage>> = match app.selected_message {
+                Some(i) => Box::new(app.messages.get(i).into_iter()),
+                None => Box::new(app.messages.iter().rev()),
+            };
+            let url = candidates
+                .filter(|m| !m.deleted)
+                .find_map(|m| links::last_url(&m.content).map(str::to_string));
+            match url {
+                Some(url) => {
+                    app.set_sticky_status(format!("Open {} ? Enter to confirm, any other key to cancel", url), StatusLevel::Info);
+                    app.pending_url = Some(url);
+                }
+                None => app.set_status("No link found in that message", StatusLevel::Warn),
+            }
+        }
         KeyCode::Char('x') => {
             app.message_input.delete_next_char();
         }
 
+        // yc - copy a code block from the selected message; repeat to cycle blocks
+        KeyCode::Char('c') if app.vim_state.pending_command == Some('y') => {
+            app.vim_state.reset();
+            let Some(index) = app.selected_message else {
+                app.set_status("Select a message with J/K first", StatusLevel::Warn);
+                return;
+            };
+            let blocks = app.messages.get(index).map(|m| markup::fenced_code_blocks(&m.content)).unwrap_or_default();
+            if blocks.is_empty() {
+                app.set_status("No code block in that message", StatusLevel::Warn);
+                return;
+            }
+            let block = match app.code_copy_cycle {
+                Some((i, next)) if i == index => next % blocks.len(),
+                _ => 0,
+            };
+            app.code_copy_cycle = Some((index, block + 1));
+            if let Some(clipboard) = &mut app.clipboard {
+                match clipboard.copy_text(&blocks[block]) {
+                    Ok(_) if blocks.len() > 1 => app.set_status(
+                        format!("Copied code block {}/{} (yc again for next)", block + 1, blocks.len()),
+                        StatusLevel::Info,
+                    ),
+                    Ok(_) => app.set_status("Copied code block", StatusLevel::Info),
+                    Err(e) => app.set_status(format!("Failed to copy: {}", e), StatusLevel::Error),
+                }
+            } else {
+                app.set_status(clipboard::UNAVAILABLE, StatusLevel::Warn);
+            }
+        }
         // Yank (copy)
         KeyCode::Char('y') => {
             if app.vim_state.pending_command == Some('y') {
@@ -1331,8 +2776,10 @@ async fn handle_normal_mode(app: &mut App<'_>, key: event::KeyEvent) {
                     let (row, _) = app.message_input.cursor();
                     if let Some(line) = app.message_input.lines().get(row) {
                         let _ = clipboard.copy_text(line);
-                        app.status_message = "Line yanked".to_string();
+                        app.set_status("Line yanked", StatusLevel::Info);
                     }
+                } else {
+                    app.set_status(clipboard::UNAVAILABLE, StatusLevel::Warn);
                 }
                 app.vim_state.reset();
             } else {
@@ -1348,6 +2795,8 @@ async fn handle_normal_mode(app: &mut App<'_>, key: event::KeyEvent) {
                         app.message_input.insert_str(line);
                     }
                 }
+            } else {
+                app.set_status(clipboard::UNAVAILABLE, StatusLevel::Warn);
             }
         }
 
@@ -1458,7 +2907,7 @@ async fn handle_insert_mode(app: &mut App<'_>, key: event::KeyEvent) {
         KeyCode::Esc => {
             // Exit Insert mode back to Normal mode
             app.vim_state.enter_normal_mode();
-            app.status_message = "-- NORMAL --".to_string();
+            app.clear_status();
         }
         KeyCode::Enter => {
             // Shift+Enter inserts a newline, plain Enter sends the message
@@ -1470,6 +2919,9 @@ async fn handle_insert_mode(app: &mut App<'_>, key: event::KeyEvent) {
                 send_message(app).await;
             }
         }
+        // Up/Down follow wrapped rows, not logical lines
+        KeyCode::Up => move_composer_row(app, false),
+        KeyCode::Down => move_composer_row(app, true),
         KeyCode::Char(':') => {
             // Start emoji picker detection
             app.message_input.input(Event::Key(key));
@@ -1482,6 +2934,9 @@ async fn handle_insert_mode(app: &mut App<'_>, key: event::KeyEvent) {
         _ => {
             // Pass all other keys to the text input
             app.message_input.input(Event::Key(key));
+            if matches!(key.code, KeyCode::Left | KeyCode::Right) {
+                snap_cursor_to_grapheme(app, key.code == KeyCode::Right);
+            }
             // Send typing indicator (debounced)
             if matches!(key.code, KeyCode::Char(_) | KeyCode::Backspace) {
                 send_typing_indicator(app);
@@ -1490,55 +2945,595 @@ async fn handle_insert_mode(app: &mut App<'_>, key: event::KeyEvent) {
     }
 }
 
+/// Move the message selection one step, skipping system messages.
+/// Starts from the newest message; stepping past it clears the selection.
+fn step_message_selection(app: &App<'_>, forward: bool) -> Option<usize> {
+    let selectable = |i: &usize| !app.messages[*i].is_system;
+    match (app.selected_message, forward) {
+        (None, false) => (0..app.messages.len()).rev().find(selectable),
+        (None, true) => None,
+        (Some(current), false) => (0..current.min(app.messages.len())).rev().find(selectable)
+            .or((current < app.messages.len()).then_some(current)),
+        (Some(current), true) => (current + 1..app.messages.len()).find(selectable),
+    }
+}
+
+/// Most pins kept per room; older pins fall off first
+const MAX_PINS: usize = 20;
+
+fn truncate_pins(pins: &mut Vec<String>) {
+    if pins.len() > MAX_PINS {
+        pins.drain(..pins.len() - MAX_PINS);
+    }
+}
+
+/// Follow reply references up to the first message of a thread.
+/// Stops at a parent that isn't in local history.
+fn thread_root_of(messages: &[ChatMessage], id: &str) -> String {
+    let mut current = id.to_string();
+    // Bounded by history length so a reply cycle can't loop forever
+    for _ in 0..messages.len() {
+        let parent = messages
+            .iter()
+            .find(|m| m.id.as_deref() == Some(current.as_str()))
+            .and_then(|m| m.reply_to.clone());
+        match parent {
+            Some(p) if messages.iter().any(|m| m.id.as_ref() == Some(&p)) => current = p,
+            _ => break,
+        }
+    }
+    current
+}
+
+/// Indices of the root message and every reply that descends from it, in timeline order
+fn thread_members(messages: &[ChatMessage], root: &str) -> Vec<usize> {
+    let mut ids: std::collections::HashSet<&str> = std::collections::HashSet::from([root]);
+    let mut members = Vec::new();
+    // Replies always come after their parent, so one pass picks up the whole chain
+    for (i, msg) in messages.iter().enumerate() {
+        let Some(id) = msg.id.as_deref() else { continue };
+        if id == root || msg.reply_to.as_deref().is_some_and(|p| ids.contains(p)) {
+            ids.insert(id);
+            members.push(i);
+        }
+    }
+    members
+}
+
+/// Whether the message at `index` is part of a thread (has replies or is a reply)
+fn has_thread(messages: &[ChatMessage], index: usize) -> bool {
+    let Some(msg) = messages.get(index) else { return false };
+    msg.reply_to.is_some()
+        || msg.id.as_ref().is_some_and(|id| messages.iter().any(|m| m.reply_to.as_ref() == Some(id)))
+}
+
+/// The message `:edit`/`:del` act on: the selection, else your latest message
+fn own_target_message(app: &App<'_>) -> Result<usize, &'static str> {
+    let me = app.current_username.as_deref();
+    let index = match app.selected_message {
+        Some(i) => i,
+        None => (0..app.messages.len())
+            .rev()
+            .find(|i| !app.messages[*i].is_system && app.messages[*i].sender.as_deref() == me)
+            .ok_or("You haven't sent any messages here")?,
+    };
+    let msg = app.messages.get(index).ok_or("No message selected")?;
+    if msg.sender.as_deref() != me || me.is_none() {
+        return Err("You can only change your own messages");
+    }
+    if msg.deleted {
+        return Err("That message was deleted");
+    }
+    if msg.id.is_none() {
+        return Err("That message can't be changed");
+    }
+    Ok(index)
+}
+
+/// Commands that can also be typed into the composer as `/command ...`
+const SLASH_COMMANDS: &[&str] = &["label", "color", "notify", "w", "whisper", "ignore", "unignore"];
+
 async fn send_message(app: &mut App<'_>) {
-    let content = app.message_input.lines().join("\n");
-    if !content.is_empty() {
-        if let (Some(sender), Some(key), Some(room_id)) =
-            (&app.ws_sender, &app.room_key, &app.room_id)
-        {
+    let text = composer_text(app);
+    if text.trim().is_empty() {
+        if app.pending_image.is_some() {
+            app.set_status("Images can't be sent yet. Esc in normal mode removes it.", StatusLevel::Warn);
+        }
+        return;
+    }
+    if let Some(cmd) = text.strip_prefix('/') {
+        let name = cmd.split_whitespace().next().unwrap_or("");
+        if SLASH_COMMANDS.contains(&name) {
+            let cmd = cmd.to_string();
+            reset_composer(app);
+            execute_command(app, &cmd).await;
+            return;
+        }
+    }
+    let limit = app.config.ui.max_message_length;
+    let size = composer_encrypted_len(app);
+    if size > limit {
+        app.set_status(format!(
+            "Message too long: {} bytes once encrypted (limit {}). Encryption roughly doubles its size.",
+            size, limit
+        ), StatusLevel::Warn);
+        return;
+    }
+    if app.room_key.is_none() || app.room_id.is_none() {
+        app.set_status("Error: Not connected to a room or missing encryption key.", StatusLevel::Error);
+        return;
+    }
+    let (text, kind) = split_action(text);
+    if text.trim().is_empty() {
+        app.set_status("Usage: /me <action>", StatusLevel::Warn);
+        return;
+    }
+
+    if let Some(message_id) = app.editing_message_id.clone() {
+        // Edits keep the reply reference of the original message
+        let reply_to = app.messages.iter().find(|m| m.id.as_ref() == Some(&message_id)).and_then(|m| m.reply_to.clone());
+        let mut envelope = MessageEnvelope { reply_to, kind, ..MessageEnvelope::new(text) };
+        let signed = match sign_envelope(app, &mut envelope) {
+            Ok(signed) => signed,
+            Err(e) => {
+                app.set_status(e, StatusLevel::Error);
+                return;
+            }
+        };
+        let content = envelope.encode();
+        if let Some(msg) = app.messages.iter_mut().find(|m| m.id.as_ref() == Some(&message_id)) {
+            msg.unsigned = !signed;
+        }
+        if let (Some(sender), Some(key), Some(room_id)) = (&app.transport, &app.room_key, &app.room_id) {
             match encrypt(key, content.as_bytes()) {
                 Ok(ciphertext) => {
-                    let payload = SendMessagePayload {
-                        room_id,
-                        ciphertext: &ciphertext,
-                    };
                     let msg = ClientMessage {
-                        message_type: "sendMessage",
-                        payload,
+                        message_type: "edit",
+                        payload: EditMessagePayload { room_id, message_id: &message_id, ciphertext: &ciphertext },
                     };
                     if let Ok(json) = serde_json::to_string(&msg) {
-                        if sender.send(json).is_ok() {
-                            app.message_input = TextArea::default();
-                            app.message_input.set_placeholder_text("Type your encrypted message...");
-                            app.message_input.set_block(
-                                Block::default().borders(Borders::ALL).title("Message"),
-                            );
-                            // Reset emoji picker state
-                            app.emoji_picker_active = false;
-                            app.emoji_matches.clear();
-                            app.emoji_partial.clear();
-                            app.emoji_selected_index = 0;
-                            // Stay in current vim mode after sending
-                            if app.vim_state.mode == VimMode::Normal {
-                                app.status_message = "-- NORMAL --".to_string();
-                            } else {
-                                app.status_message = "-- INSERT --".to_string();
+                        match sender.send(json) {
+                            Ok(()) => {
+                                app.editing_message_id = None;
+                                reset_composer(app);
+                            }
+                            Err(SendError::QueueFull) => {
+                                app.set_status("Send queue full. Try the edit again shortly.", StatusLevel::Warn);
+                            }
+                            Err(SendError::Closed) => {
+                                app.set_sticky_status("Connection lost. Restart to reconnect.", StatusLevel::Error);
                             }
-                        } else {
-                            app.status_message = "Connection lost. Restart to reconnect.".to_string();
                         }
                     }
                 }
                 Err(_) => {
-                    app.status_message = "FATAL: Failed to encrypt message.".to_string();
+                    app.set_status("FATAL: Failed to encrypt message.", StatusLevel::Error);
+                }
+            }
+        } else {
+            app.set_status("Error: Not connected to a room or missing encryption key.", StatusLevel::Error);
+        }
+        return;
+    }
+
+    // Show the message right away; the server's echo confirms it
+    let mut msg = ChatMessage::new(text, app.current_username.clone(), None);
+    msg.client_id = Some(hex::encode(rand::random::<[u8; 8]>()));
+    msg.reply_to = app.replying_to.take();
+    msg.is_action = kind == MessageKind::Action;
+    msg.from_me = true;
+    app.messages.push(msg);
+    enforce_message_limit(app);
+    app.message_scroll_offset = 0;
+    // Reset first so an offline/failure status from sending isn't wiped
+    reset_composer(app);
+    transmit_message(app, app.messages.len() - 1);
+    if app.pending_image.is_some() {
+        app.set_status("Sent the text only; images can't be sent yet and it's still attached.", StatusLevel::Warn);
+    }
+}
+
+/// Send `text` to `target` alone. When the roster has their ed25519 key the
+/// text goes under a one-off key sealed to it; otherwise it's under the room
+/// key and stays private only as long as the server delivers it to `target`
+/// alone. The status line says which.
+fn send_whisper(app: &mut App<'_>, target: &str, text: &str) {
+    let (Some(transport), Some(room_key), Some(room_id)) = (&app.transport, &app.room_key, &app.room_id) else {
+        app.set_status("Error: Not connected to a room or missing encryption key.", StatusLevel::Error);
+        return;
+    };
+    let sealed = match app.member_keys.get(target) {
+        Some(public_key) => {
+            let content_key = generate_key();
+            let sealed_key = ssh_key::PublicKey::from_openssh(public_key)
+                .map_err(|e| e.to_string())
+                .and_then(|public_key| seal_for_pubkey(&public_key, &content_key));
+            match sealed_key {
+                Ok(sealed_key) => Some((content_key, sealed_key)),
+                Err(e) => {
+                    app.set_status(format!("Can't whisper to {}: {}", target, e), StatusLevel::Warn);
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+    let mut envelope = MessageEnvelope::new(text.to_string());
+    let signed = match sign_envelope(app, &mut envelope) {
+        Ok(signed) => signed,
+        Err(e) => {
+            app.set_status(e, StatusLevel::Error);
+            return;
+        }
+    };
+    let encrypted = match &sealed {
+        Some((content_key, _)) => encrypt(content_key, envelope.encode().as_bytes()),
+        None => encrypt(room_key, envelope.encode().as_bytes()),
+    };
+    let Ok(ciphertext) = encrypted else {
+        app.set_status("FATAL: Failed to encrypt message.", StatusLevel::Error);
+        return;
+    };
+    let sealed_key = sealed.as_ref().map(|(_, sealed_key)| sealed_key.as_str());
+    let payload = WhisperPayload { room_id, target_username: target, ciphertext: &ciphertext, sealed_key };
+    let Some(json) = client_json("whisper", payload) else {
+        return;
+    };
+    match transport.send(json) {
+        Ok(()) => {
+            app.diagnostics.messages_sent += 1;
+            let mut msg = ChatMessage::new(text.to_string(), app.current_username.clone(), None);
+            msg.whisper_to = Some(render::sanitize(target));
+            msg.whisper_sealed = sealed.is_some();
+            msg.unsigned = !signed;
+            msg.from_me = true;
+            app.messages.push(msg);
+            app.message_scroll_offset = 0;
+            let note = if sealed.is_some() {
+                "sealed to their SSH key"
+            } else {
+                "encrypted with the room key; the server routes it to them only"
+            };
+            app.set_status(format!("Whispered to {} ({})", target, note), StatusLevel::Info);
+        }
+        Err(SendError::QueueFull) => app.set_status("Send queue full. Try again shortly.", StatusLevel::Warn),
+        Err(SendError::Closed) => app.set_sticky_status("Connection lost. Restart to reconnect.", StatusLevel::Error),
+    }
+}
+
+/// The one-off key in a sealed whisper, opened with whichever of our SSH
+/// keys it was sealed to
+fn open_whisper_key(app: &mut App<'_>, sealed_key: &str) -> Option<AesKey> {
+    let keys = app.whisper_keys.get_or_insert_with(ssh::unlocked_ed25519_keys);
+    open_sealed_key(keys, sealed_key)
+}
+
+/// Save the room just joined for "rejoin" on the room menu. `needs_key`
+/// rooms (the server doesn't hand out their key) keep it in the secret store
+/// with `auth.keyring`, else sealed to our first ed25519 SSH key, or nothing
+/// if there isn't one; never in the clear.
+fn remember_room(app: &mut App<'_>, needs_key: bool) {
+    if !app.config.auth.remember_last_room {
+        return;
+    }
+    let (Some(room_id), Some(name)) = (app.room_id.clone(), app.room_display_name.clone()) else {
+        return;
+    };
+    let mut room = SavedRoom {
+        server_url: app.config.server.url.clone(),
+        room_id,
+        name,
+        sealed_key: None,
+        key_needed: needs_key,
+        key_in_secrets: false,
+    };
+    // Only one room is remembered, so the last one's key can go
+    let replaced = app.last_room.get().filter(|last| last.key_in_secrets && last.room_id != room.room_id);
+    if let (Some(last), Some(secrets)) = (replaced, &mut app.secrets) {
+        let _ = secrets.delete(&secrets::room_key_entry(&last.room_id));
+    }
+    if let (true, Some(key), Some(secrets)) = (needs_key, &app.room_key, &mut app.secrets) {
+        match secrets.set(&secrets::room_key_entry(&room.room_id), &hex::encode(key.as_slice())) {
+            Ok(()) => {
+                room.key_in_secrets = true;
+                room.key_needed = false;
+            }
+            Err(e) => app.set_status(format!("Couldn't store the room key to remember it: {}", e), StatusLevel::Warn),
+        }
+    } else if let (true, Some(key)) = (needs_key, &app.room_key) {
+        let sealed = ssh::scan_ssh_key_files()
+            .into_iter()
+            .find(|ssh_key| ssh_key.key_type == "ed25519")
+            .map(|ssh_key| room.seal_key(key, &ssh_key.public_key));
+        if let Some(Err(e)) = sealed {
+            app.set_status(format!("Couldn't seal the room key to remember it: {}", e), StatusLevel::Warn);
+        }
+    }
+    if let Err(e) = app.last_room.set(room) {
+        app.set_status(format!("Couldn't remember this room: {}", e), StatusLevel::Warn);
+    }
+}
+
+/// Forget the remembered room, and its key in the secret store
+fn forget_last_room(app: &mut App<'_>) -> Result<(), Box<dyn Error>> {
+    let stored_key = app.last_room.get().filter(|room| room.key_in_secrets).map(|room| secrets::room_key_entry(&room.room_id));
+    if let (Some(entry), Some(secrets)) = (stored_key, &mut app.secrets) {
+        secrets.delete(&entry)?;
+    }
+    Ok(app.last_room.clear()?)
+}
+
+/// Split a leading `/me` off composer text: "/me waves" is an action "waves"
+fn split_action(text: String) -> (String, MessageKind) {
+    match text.strip_prefix("/me") {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+            (rest.trim_start().to_string(), MessageKind::Action)
+        }
+        _ => (text, MessageKind::Text),
+    }
+}
+
+/// Decrypt a message body. Plaintext that authenticates but isn't valid UTF-8
+/// is shown lossily with a marker; that's a sender problem, not tampering.
+fn decrypt_text(key: &impl CipherKey, ciphertext: &str) -> Result<String, String> {
+    let bytes = decrypt_bytes(key, ciphertext)?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => render::sanitize(&text),
+        Err(e) => format!("[non-text content] {}", render::sanitize(&String::from_utf8_lossy(e.as_bytes()))),
+    })
+}
+
+/// Keep moving the composer cursor while it sits inside a grapheme cluster,
+/// so Left/Right step over a letter and its combining marks (or a whole
+/// emoji sequence) in one go
+fn snap_cursor_to_grapheme(app: &mut App<'_>, forward: bool) {
+    let step = if forward { tui_textarea::CursorMove::Forward } else { tui_textarea::CursorMove::Back };
+    loop {
+        let (row, col) = app.message_input.cursor();
+        let inside = app.message_input.lines().get(row).is_some_and(|line| render::inside_grapheme(line, col));
+        if !inside {
+            break;
+        }
+        app.message_input.move_cursor(step);
+    }
+}
+
+/// The composer's text as it would be sent: surrounding blank lines and
+/// trailing spaces dropped, but the first line's indentation kept (code blocks)
+fn composer_text(app: &App<'_>) -> String {
+    let joined = app.message_input.lines().join("\n");
+    joined.trim_end().trim_start_matches(['\n', '\r']).to_string()
+}
+
+/// Size of the composer's message on the wire, after envelope and encryption
+fn composer_encrypted_len(app: &App<'_>) -> usize {
+    let reply_to = match &app.editing_message_id {
+        Some(id) => app.messages.iter().find(|m| m.id.as_ref() == Some(id)).and_then(|m| m.reply_to.clone()),
+        None => app.replying_to.clone(),
+    };
+    let (text, kind) = split_action(composer_text(app));
+    let mut envelope = MessageEnvelope { reply_to, kind, ..MessageEnvelope::new(text) };
+    if let Signing::Sign(key) = should_sign(app) {
+        // Sized like the real thing: 64 bytes for ed25519, up to 512 for RSA
+        let sig_len = if key.key.key_type == "ed25519" { 64 } else { 512 };
+        envelope.sig = Some(EnvelopeSignature { key: key.key.public_key.clone(), sig: "0".repeat(sig_len * 2) });
+    }
+    encrypted_len(envelope.encode().len())
+}
+
+/// Whether what we send gets signed
+#[derive(Debug)]
+enum Signing<'a> {
+    Sign(&'a ssh::SigningKey),
+    /// No key to sign with, and the room doesn't mind
+    Unsigned,
+    /// No key to sign with, but the room only takes signed messages
+    Refuse,
+}
+
+/// The one place that decides about signing, for messages, edits and
+/// whispers alike: sign when we logged in with a key that can, otherwise
+/// send unsigned unless the room requires signatures
+fn should_sign<'a>(app: &'a App<'_>) -> Signing<'a> {
+    match (&app.signing_key, app.room_requires_signatures) {
+        (Some(key), _) => Signing::Sign(key),
+        (None, false) => Signing::Unsigned,
+        (None, true) => Signing::Refuse,
+    }
+}
+
+/// Sign `envelope` for the current room if `should_sign` says so. Ok(false)
+/// means it goes out unsigned; an error means it mustn't go out at all.
+fn sign_envelope(app: &App<'_>, envelope: &mut MessageEnvelope) -> Result<bool, String> {
+    let Some(room_id) = &app.room_id else {
+        return Ok(false);
+    };
+    match should_sign(app) {
+        Signing::Unsigned => Ok(false),
+        Signing::Refuse => Err(
+            "This room only accepts signed messages. Add your SSH key to ssh-agent, or log in again with :register.".to_string(),
+        ),
+        Signing::Sign(key) => match key.sign(&envelope.signed_bytes(room_id)) {
+            Ok(sig) => {
+                envelope.sig = Some(EnvelopeSignature { key: key.key.public_key.clone(), sig: hex::encode(sig) });
+                Ok(true)
+            }
+            Err(e) if app.room_requires_signatures => Err(format!("This room only accepts signed messages: {}", e)),
+            // An agent that went away or a key that now wants a passphrase
+            // shouldn't stop the message
+            Err(_) => Ok(false),
+        },
+    }
+}
+
+/// Drop the oldest messages beyond `ui.message_limit`, keeping any
+/// selection pointed at the same message (or clearing it if that was dropped).
+fn enforce_message_limit(app: &mut App<'_>) {
+    let excess = app.messages.len().saturating_sub(app.config.ui.message_limit);
+    if excess == 0 {
+        return;
+    }
+    app.messages.drain(..excess);
+    app.selected_message = app.selected_message.and_then(|i| i.checked_sub(excess));
+    app.code_copy_cycle = app.code_copy_cycle.and_then(|(i, block)| Some((i.checked_sub(excess)?, block)));
+}
+
+/// Encrypt and send the locally echoed message at `index`, updating its
+/// delivery state. Used for first sends and retries, which reuse the client id.
+fn transmit_message(app: &mut App<'_>, index: usize) {
+    let Some(msg) = app.messages.get(index) else { return };
+    let kind = if msg.is_action { MessageKind::Action } else { MessageKind::Text };
+    let mut envelope = MessageEnvelope { reply_to: msg.reply_to.clone(), kind, ..MessageEnvelope::new(msg.content.clone()) };
+    let signed = match sign_envelope(app, &mut envelope) {
+        Ok(signed) => signed,
+        Err(e) => {
+            app.set_status(e, StatusLevel::Error);
+            app.messages[index].delivery = Some(DeliveryState::Failed);
+            return;
+        }
+    };
+    let content = envelope.encode();
+    let msg = &app.messages[index];
+
+    let mut encrypt_failed = false;
+    let mut queue_full = false;
+    let rate_limited = app.rate_limited_until.is_some();
+    let sent = match (&app.transport, &app.room_key, &app.room_id) {
+        // Held in the outbox like an offline message until the limit passes
+        _ if rate_limited => false,
+        (Some(sender), Some(key), Some(room_id)) => match encrypt(key, content.as_bytes()) {
+            Ok(ciphertext) => {
+                let payload = SendMessagePayload {
+                    room_id,
+                    ciphertext: &ciphertext,
+                    client_id: msg.client_id.as_deref(),
+                };
+                match serde_json::to_string(&ClientMessage { message_type: "sendMessage", payload }) {
+                    Ok(json) => match sender.send(json) {
+                        Ok(()) => {
+                            app.diagnostics.messages_sent += 1;
+                            true
+                        }
+                        Err(e) => {
+                            queue_full = e == SendError::QueueFull;
+                            false
+                        }
+                    },
+                    Err(_) => false,
                 }
             }
+            Err(_) => {
+                app.set_status("FATAL: Failed to encrypt message.", StatusLevel::Error);
+                encrypt_failed = true;
+                false
+            }
+        },
+        _ => false,
+    };
+
+    let msg = &mut app.messages[index];
+    msg.sent_at = Some(std::time::Instant::now());
+    msg.unsigned = !signed;
+    if sent {
+        msg.delivery = Some(DeliveryState::Pending);
+    } else if !encrypt_failed && !queue_full && app.outbox.len() < MAX_OUTBOX {
+        // Offline: hold it until we reconnect and rejoin the room
+        msg.delivery = Some(DeliveryState::Queued);
+        if let Some(cid) = msg.client_id.clone() {
+            app.outbox.push_back(cid);
+        }
+        if rate_limited {
+            app.set_status(format!("Rate limited. {} message(s) queued, will send shortly.", app.outbox.len()), StatusLevel::Warn);
         } else {
-            app.status_message = "Error: Not connected to a room or missing encryption key.".to_string();
+            app.set_status(format!("Offline. {} message(s) queued, will send on reconnect.", app.outbox.len()), StatusLevel::Warn);
+        }
+    } else {
+        msg.delivery = Some(DeliveryState::Failed);
+        if queue_full {
+            app.set_status("Send queue full. Message not sent; press R to retry.", StatusLevel::Warn);
+        } else if !encrypt_failed {
+            app.set_status(format!("Offline queue full ({} messages). Message not sent; press R to retry later.", MAX_OUTBOX), StatusLevel::Warn);
+        }
+    }
+}
+
+/// Most messages held while offline
+const MAX_OUTBOX: usize = 50;
+
+/// Send queued messages in the order they were written
+fn flush_outbox(app: &mut App<'_>) {
+    let queued: Vec<String> = app.outbox.drain(..).collect();
+    for cid in queued {
+        // Anything that still can't go out is queued again, keeping its order
+        if let Some(index) = app.messages.iter().position(|m| m.client_id.as_ref() == Some(&cid)) {
+            transmit_message(app, index);
+        }
+    }
+}
+
+/// How long a sent message may wait for the server's echo before it's marked failed
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Mark pending messages that never got an echo as failed
+fn check_delivery_timeouts(app: &mut App<'_>) {
+    for msg in app.messages.iter_mut() {
+        if msg.delivery == Some(DeliveryState::Pending)
+            && msg.sent_at.is_some_and(|t| t.elapsed() >= DELIVERY_TIMEOUT)
+        {
+            msg.delivery = Some(DeliveryState::Failed);
+            app.needs_redraw = true;
         }
     }
 }
 
+/// Move the composer cursor one visual row, using the wrap width of the
+/// last draw
+fn move_composer_row(app: &mut App<'_>, down: bool) {
+    if app.composer_width == 0 {
+        let logical = if down { tui_textarea::CursorMove::Down } else { tui_textarea::CursorMove::Up };
+        app.message_input.move_cursor(logical);
+        return;
+    }
+    let lines = app.message_input.lines();
+    let rows = composer::wrap(lines, app.composer_width);
+    if let Some((line, col)) = composer::step(&rows, lines, app.message_input.cursor(), down) {
+        app.message_input.move_cursor(tui_textarea::CursorMove::Jump(line as u16, col as u16));
+    }
+}
+
+/// Rows kept for the message list when the composer grows
+const MIN_CHAT_HEIGHT: u16 = 5;
+
+/// Height of the composer box, borders included, when it is `width` columns
+/// wide: one text row per wrapped row, between `COMPOSER_MIN_ROWS` and
+/// `ui.composer_max_rows`. Past that the composer scrolls. It only grows
+/// into `available` rows while the message list keeps `MIN_CHAT_HEIGHT`.
+fn composer_height(app: &App<'_>, width: u16, available: u16) -> u16 {
+    // Same wrap width as the draw: inside the borders, less the cursor column
+    let wrap_width = (width as usize).saturating_sub(3).max(1);
+    let rows = composer::wrap(app.message_input.lines(), wrap_width).len();
+    let max_rows = app.config.ui.composer_max_rows.max(COMPOSER_MIN_ROWS);
+    let wanted = (rows as u16).clamp(COMPOSER_MIN_ROWS, max_rows) + 2;
+    let room = available.saturating_sub(MIN_CHAT_HEIGHT).max(COMPOSER_MIN_ROWS + 2);
+    wanted.min(room).min(available)
+}
+
+fn reset_composer(app: &mut App<'_>) {
+    app.replying_to = None;
+    app.message_input = TextArea::default();
+    app.message_input.set_placeholder_text("Type your encrypted message...");
+    app.message_input.set_block(
+        Block::default().borders(Borders::ALL).title("Message"),
+    );
+    // Reset emoji picker state
+    app.emoji_picker_active = false;
+    app.emoji_matches.clear();
+    app.emoji_partial.clear();
+    app.emoji_selected_index = 0;
+    // Stay in current vim mode after sending
+    app.clear_status();
+}
+
 fn send_typing_indicator(app: &mut App<'_>) {
     // Debounce: only send typing event every 2 seconds
     let should_send = match app.last_typing_sent {
@@ -1547,7 +3542,7 @@ fn send_typing_indicator(app: &mut App<'_>) {
     };
     
     if should_send {
-        if let (Some(sender), Some(room_id)) = (&app.ws_sender, &app.room_id) {
+        if let (Some(sender), Some(room_id)) = (&app.transport, &app.room_id) {
             let payload = TypingPayload { room_id };
             let msg = ClientMessage {
                 message_type: "typing",
@@ -1566,40 +3561,18 @@ async fn handle_room_switcher_screen(app: &mut App<'_>, key: event::KeyEvent) {
         KeyCode::Esc => {
             // Close switcher, return to room
             app.current_screen = CurrentScreen::InRoom;
-            app.status_message = "-- NORMAL --".to_string();
+            app.clear_status();
         }
-        KeyCode::Up | KeyCode::Char('k') => {
-            if app.switcher_selected_index > 0 {
-                app.switcher_selected_index -= 1;
-            }
+        KeyCode::Up | KeyCode::Char('k') if app.switcher_selected_index > 0 => {
+            app.switcher_selected_index -= 1;
         }
-        KeyCode::Down | KeyCode::Char('j') => {
-            if app.switcher_selected_index + 1 < app.user_rooms.len() {
-                app.switcher_selected_index += 1;
-            }
+        KeyCode::Down | KeyCode::Char('j') if app.switcher_selected_index + 1 < app.user_rooms.len() => {
+            app.switcher_selected_index += 1;
         }
         KeyCode::Enter => {
             // Switch to selected room
-            if let Some(room) = app.user_rooms.get(app.switcher_selected_index) {
-                let room_name = room.name.clone();
-                
-                // Join the new room
-                if let Some(ws_sender) = &app.ws_sender {
-                    let join_payload = JoinRoomPayload {
-                        room_id: None,
-                        room_name: Some(&room_name),
-                    };
-                    let join_message = ClientMessage {
-                        message_type: "joinRoom",
-                        payload: join_payload,
-                    };
-                    if let Ok(json) = serde_json::to_string(&join_message) {
-                        let _ = ws_sender.send(json);
-                    }
-                }
-                app.messages.clear();
-                app.current_screen = CurrentScreen::InRoom;
-                app.status_message = format!("Switching to #{}", room_name);
+            if let Some(room) = app.user_rooms.get(app.switcher_selected_index).cloned() {
+                switch_room(app, &room);
             }
         }
         KeyCode::Char(':') => {
@@ -1615,10 +3588,10 @@ fn handle_help_screen(app: &mut App, key: event::KeyEvent) {
             // Return to previous screen (RoomChoice or InRoom)
             if app.room_id.is_some() {
                 app.current_screen = CurrentScreen::InRoom;
-                app.status_message = "-- NORMAL --".to_string();
+                app.clear_status();
             } else {
                 app.current_screen = CurrentScreen::RoomChoice;
-                app.status_message = "Create or Join a secure room.".to_string();
+                app.set_status("Create or Join a secure room.", StatusLevel::Info);
             }
         }
         KeyCode::Char(':') => {
@@ -1633,52 +3606,13 @@ fn handle_help_screen(app: &mut App, key: event::KeyEvent) {
 async fn execute_command(app: &mut App<'_>, cmd: &str) {
     let cmd = cmd.trim();
     let parts: Vec<&str> = cmd.split_whitespace().collect();
-    let command = parts.first().map(|s| *s).unwrap_or("");
+    let command = parts.first().copied().unwrap_or("");
     
     match command {
         // Quit commands
         "q" | "quit" | "leave" => {
             match app.current_screen {
-                CurrentScreen::InRoom => {
-                    // Leave room, return to main menu
-                    // Bug 2 fix: send leave_voice WS message BEFORE clearing room_id,
-                    // so it doesn't get silently dropped in the voice event handler.
-                    if app.voice.is_connected() || matches!(app.voice.status, VoiceConnectionStatus::Connecting) {
-                        if let (Some(ws_sender), Some(room_id)) = (&app.ws_sender, &app.room_id) {
-                            let payload = VoiceSignalPayload {
-                                room_id: room_id.clone(),
-                                target_user_id: None,
-                                sender_user_id: None,
-                                sender_username: None,
-                                signal_type: "leave_voice".to_string(),
-                                data: "".to_string(),
-                            };
-                            let msg = ClientMessage {
-                                message_type: "voiceSignal",
-                                payload,
-                            };
-                            if let Ok(json) = serde_json::to_string(&msg) {
-                                let _ = ws_sender.send(json);
-                            }
-                        }
-                    }
-                    // Now send Leave command to voice manager for local cleanup
-                    if let Some(voice_tx) = &app.voice_tx {
-                        let _ = voice_tx.send(voice::manager::VoiceCommand::Leave);
-                    }
-                    // Note: voice state reset happens via VoiceEvent::Disconnected handler
-                    // Clear room_users separately since that's from server, not voice events
-                    app.voice.room_users.clear();
-                    
-                    app.room_id = None;
-                    app.room_name = None;
-                    app.room_key = None;
-                    app.messages.clear();
-                    app.online_users.clear();
-                    app.typing_users.clear();
-                    app.current_screen = CurrentScreen::RoomChoice;
-                    app.status_message = "Left room. Press C to create or J to join.".to_string();
-                }
+                CurrentScreen::InRoom => leave_room(app).await,
                 CurrentScreen::RoomChoice => {
                     // Quit application
                     app.should_quit = true;
@@ -1686,7 +3620,7 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                 _ => {
                     // For all other screens (menus, inputs, etc.), go back to Main Menu
                     app.current_screen = CurrentScreen::RoomChoice;
-                    app.status_message = "Create or Join a secure room.".to_string();
+                    app.set_status("Create or Join a secure room.", StatusLevel::Info);
                 }
             }
         }
@@ -1697,32 +3631,36 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
         // Help command
         "h" | "help" => {
             app.current_screen = CurrentScreen::Help;
-            app.status_message = "Press Esc, q, or Enter to close help".to_string();
+            app.set_status("Press Esc, q, or Enter to close help", StatusLevel::Info);
         }
+        "roster" => {
+            app.show_roster = !app.show_roster;
+        }
+        "timestamps" | "ts" => toggle_timestamps(app),
         // Users command - show online users
         "u" | "users" => {
             if app.current_screen == CurrentScreen::InRoom {
                 app.show_user_list = !app.show_user_list;
                 if app.show_user_list {
-                    app.status_message = format!("{} users online. Press Esc to close.", app.online_users.len());
+                    app.set_status(format!("{} users online. Press Esc to close.", app.online_users.len()), StatusLevel::Info);
                 } else {
-                    app.status_message = "-- NORMAL --".to_string();
+                    app.clear_status();
                 }
             } else {
-                app.status_message = ":users only works inside a room".to_string();
+                app.set_status(":users only works inside a room", StatusLevel::Warn);
             }
         }
         // List rooms (room switcher)
         "l" | "list" => {
             if app.current_screen == CurrentScreen::InRoom {
                 // Request room list from server
-                if let Some(ws_sender) = &app.ws_sender {
+                if let Some(transport) = &app.transport {
                     let list_message = ClientMessage {
                         message_type: "listRooms",
                         payload: ListRoomsPayload {},
                     };
                     if let Ok(json) = serde_json::to_string(&list_message) {
-                        let _ = ws_sender.send(json);
+                        let _ = transport.send(json);
                     }
                 }
                 // Populate user_rooms from public + private rooms
@@ -1731,9 +3669,9 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                 app.user_rooms.extend(app.private_rooms.iter().cloned());
                 app.switcher_selected_index = 0;
                 app.current_screen = CurrentScreen::RoomSwitcher;
-                app.status_message = "Select room to switch".to_string();
+                app.set_status("Select room to switch", StatusLevel::Info);
             } else {
-                app.status_message = ":list only works inside a room".to_string();
+                app.set_status(":list only works inside a room", StatusLevel::Warn);
             }
         }
         // Switch to room by name
@@ -1747,52 +3685,25 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                         .cloned();
                     
                     if let Some(room) = target_room {
-                        // Join the new room
-                        if let Some(ws_sender) = &app.ws_sender {
-                            let join_payload = JoinRoomPayload {
-                                room_id: None,
-                                room_name: Some(&room.name),
-                            };
-                            let join_message = ClientMessage {
-                                message_type: "joinRoom",
-                                payload: join_payload,
-                            };
-                            if let Ok(json) = serde_json::to_string(&join_message) {
-                                let _ = ws_sender.send(json);
-                            }
-                        }
-                        app.messages.clear();
-                        app.status_message = format!("Switching to #{}", room.name);
+                        switch_room(app, &room);
                     } else {
-                        app.status_message = format!("Room '{}' not found", room_name);
+                        app.set_status(format!("Room '{}' not found", room_name), StatusLevel::Warn);
                     }
                 } else {
-                    app.status_message = ":switch only works inside a room".to_string();
+                    app.set_status(":switch only works inside a room", StatusLevel::Warn);
                 }
             } else {
-                app.status_message = "Usage: :switch <room-name>".to_string();
+                app.set_status("Usage: :switch <room-name>", StatusLevel::Warn);
             }
         }
         // Register command
-        "register" | "reg" => {
-            let (keys, from_agent) = ssh::get_available_keys();
-            app.available_keys = keys;
-            app.using_agent = from_agent;
-            if app.available_keys.is_empty() {
-                app.current_screen = CurrentScreen::Registration;
-                app.status_message = "No SSH keys found".to_string();
-            } else {
-                app.current_screen = CurrentScreen::KeySelection;
-                app.selected_key_index = 0;
-                let source = if from_agent { "ssh-agent" } else { "~/.ssh" };
-                app.status_message = format!("Select SSH key ({}).", source);
-            }
-        }
+        "register" | "reg" => choose_login_key(app),
+        "logout" => logout(app).await,
         // Share/Invite command
         "share" | "invite" => {
             if app.current_screen == CurrentScreen::InRoom {
                 if let Some(room_id) = &app.room_id {
-                    if let Some(ws_sender) = &app.ws_sender {
+                    if let Some(transport) = &app.transport {
                         let payload = CreateInvitePayload {
                             room_id,
                         };
@@ -1801,19 +3712,19 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                             payload,
                         };
                         if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = ws_sender.send(json);
-                            app.status_message = "Generating invite code...".to_string();
+                            let _ = transport.send(json);
+                            app.set_status("Generating invite code...", StatusLevel::Info);
                         }
                     }
                 }
             } else {
-                app.status_message = ":share only works inside a room".to_string();
+                app.set_status(":share only works inside a room", StatusLevel::Warn);
             }
         }
         // Join via invite code
         "join" | "j" => {
             if let Some(code) = parts.get(1) {
-                if let Some(ws_sender) = &app.ws_sender {
+                if let Some(transport) = &app.transport {
                     let payload = JoinViaInvitePayload {
                         code,
                     };
@@ -1822,12 +3733,110 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                         payload,
                     };
                     if let Ok(json) = serde_json::to_string(&msg) {
-                        let _ = ws_sender.send(json);
-                        app.status_message = format!("Joining via invite code {}...", code);
+                        let _ = transport.send(json);
+                        app.set_status(format!("Joining via invite code {}...", code), StatusLevel::Info);
+                    }
+                }
+            } else {
+                app.set_status("Usage: :join <code>", StatusLevel::Warn);
+            }
+        }
+        // Local label and accent color for the current room
+        "label" | "color" => {
+            let Some(room_id) = app.room_id.clone().filter(|_| app.current_screen == CurrentScreen::InRoom) else {
+                app.set_status(format!(":{} only works inside a room", command), StatusLevel::Warn);
+                return;
+            };
+            let value = cmd[command.len()..].trim();
+            let value = (!value.is_empty()).then(|| value.to_string());
+            if command == "color" {
+                if let Some(color) = value.as_deref().filter(|c| c.parse::<Color>().is_err()) {
+                    app.set_status(format!("Unknown color '{}'; try a name like 'magenta' or '#ff8800'", color), StatusLevel::Warn);
+                    return;
+                }
+            }
+            let cleared = value.is_none();
+            let result = if command == "label" {
+                app.room_labels.set_label(&room_id, value)
+            } else {
+                app.room_labels.set_color(&room_id, value)
+            };
+            match result {
+                Err(e) => app.set_status(format!("Couldn't save room {}: {}", command, e), StatusLevel::Error),
+                Ok(()) if cleared => app.set_status(format!("Room {} cleared", command), StatusLevel::Info),
+                Ok(()) => app.set_status(format!("Room {} saved", command), StatusLevel::Info),
+            }
+        }
+        // Hide a sender's messages and mute their voice, across restarts
+        "ignore" | "unignore" => {
+            let Some(username) = parts.get(1).map(|name| name.trim_start_matches('@').to_string()) else {
+                if command == "unignore" {
+                    app.set_status("Usage: :unignore <user>", StatusLevel::Warn);
+                } else if app.ignored.users().next().is_none() {
+                    app.set_status("Nobody is ignored", StatusLevel::Info);
+                } else {
+                    let users: Vec<&str> = app.ignored.users().collect();
+                    app.set_status(format!("Ignoring: {}", users.join(", ")), StatusLevel::Info);
+                }
+                return;
+            };
+            if command == "ignore" && app.current_username.as_deref() == Some(username.as_str()) {
+                app.set_status("You can't ignore yourself", StatusLevel::Warn);
+                return;
+            }
+            let ignoring = command == "ignore";
+            let result = if ignoring { app.ignored.ignore(&username) } else { app.ignored.unignore(&username) };
+            match result {
+                Err(e) => app.set_status(format!("Couldn't save the ignore list: {}", e), StatusLevel::Error),
+                Ok(false) if ignoring => app.set_status(format!("Already ignoring {}", username), StatusLevel::Info),
+                Ok(false) => app.set_status(format!("{} isn't ignored", username), StatusLevel::Info),
+                Ok(true) => {
+                    set_voice_volume_for(app, &username, if ignoring { 0.0 } else { 1.0 });
+                    let verb = if ignoring { "Ignoring" } else { "No longer ignoring" };
+                    app.set_status(format!("{} {}", verb, username), StatusLevel::Info);
+                }
+            }
+        }
+        // Per-room notification level; no argument shows the current one
+        "notify" => {
+            let Some(room_id) = app.room_id.clone().filter(|_| app.current_screen == CurrentScreen::InRoom) else {
+                app.set_status(":notify only works inside a room", StatusLevel::Warn);
+                return;
+            };
+            let value = cmd[command.len()..].trim();
+            if value.is_empty() {
+                let level = room_notify_level(app, &room_id);
+                let source = if app.room_labels.notify(&room_id).is_some() { "this room" } else { "default" };
+                app.set_status(format!("Notifications: {} ({}); use :notify all|mentions|none|default", level.name(), source), StatusLevel::Info);
+                return;
+            }
+            let level = match value {
+                "default" => None,
+                other => match NotifyLevel::parse(other) {
+                    Some(level) => Some(level),
+                    None => {
+                        app.set_status(format!("Unknown level '{}'; use all, mentions, none or default", other), StatusLevel::Warn);
+                        return;
                     }
+                },
+            };
+            match app.room_labels.set_notify(&room_id, level) {
+                Err(e) => app.set_status(format!("Couldn't save room notifications: {}", e), StatusLevel::Error),
+                Ok(()) => {
+                    let level = room_notify_level(app, &room_id);
+                    app.set_status(format!("Notifications for this room: {}", level.name()), StatusLevel::Info);
                 }
+            }
+        }
+        // Room details overlay
+        "about" | "version" => {
+            app.show_about = !app.show_about;
+        }
+        "info" => {
+            if app.current_screen == CurrentScreen::InRoom {
+                app.show_room_info = !app.show_room_info;
             } else {
-                app.status_message = "Usage: :join <code>".to_string();
+                app.set_status(":info only works inside a room", StatusLevel::Warn);
             }
         }
         // Rename room
@@ -1835,7 +3844,7 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
             if app.current_screen == CurrentScreen::InRoom {
                 if let Some(room_id) = &app.room_id {
                     if let Some(new_name) = parts.get(1) {
-                        if let Some(ws_sender) = &app.ws_sender {
+                        if let Some(transport) = &app.transport {
                             let payload = RenameRoomPayload {
                                 room_id,
                                 new_name,
@@ -1845,23 +3854,23 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                                 payload,
                             };
                             if let Ok(json) = serde_json::to_string(&msg) {
-                                let _ = ws_sender.send(json);
-                                app.status_message = format!("Renaming room to {}...", new_name);
+                                let _ = transport.send(json);
+                                app.set_status(format!("Renaming room to {}...", new_name), StatusLevel::Info);
                             }
                         }
                     } else {
-                        app.status_message = "Usage: :rename <new_name>".to_string();
+                        app.set_status("Usage: :rename <new_name>", StatusLevel::Warn);
                     }
                 }
             } else {
-                app.status_message = ":rename only works inside a room".to_string();
+                app.set_status(":rename only works inside a room", StatusLevel::Warn);
             }
         }
         // Delete room
         "delete" => {
             if app.current_screen == CurrentScreen::InRoom {
                 if let Some(room_id) = &app.room_id {
-                    if let Some(ws_sender) = &app.ws_sender {
+                    if let Some(transport) = &app.transport {
                         let payload = DeleteRoomPayload {
                             room_id,
                         };
@@ -1870,13 +3879,13 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                             payload,
                         };
                         if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = ws_sender.send(json);
-                            app.status_message = "Deleting room...".to_string();
+                            let _ = transport.send(json);
+                            app.set_status("Deleting room...", StatusLevel::Info);
                         }
                     }
                 }
             } else {
-                app.status_message = ":delete only works inside a room".to_string();
+                app.set_status(":delete only works inside a room", StatusLevel::Warn);
             }
         }
         // Transfer ownership
@@ -1884,7 +3893,7 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
             if app.current_screen == CurrentScreen::InRoom {
                 if let Some(room_id) = &app.room_id {
                     if let Some(new_owner) = parts.get(1) {
-                        if let Some(ws_sender) = &app.ws_sender {
+                        if let Some(transport) = &app.transport {
                             let payload = TransferOwnershipPayload {
                                 room_id,
                                 new_owner_username: new_owner,
@@ -1894,97 +3903,194 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
                                 payload,
                             };
                             if let Ok(json) = serde_json::to_string(&msg) {
-                                let _ = ws_sender.send(json);
-                                app.status_message = format!("Transferring ownership to {}...", new_owner);
+                                let _ = transport.send(json);
+                                app.set_status(format!("Transferring ownership to {}...", new_owner), StatusLevel::Info);
                             }
                         }
                     } else {
-                        app.status_message = "Usage: :transfer <username>".to_string();
+                        app.set_status("Usage: :transfer <username>", StatusLevel::Warn);
+                    }
+                }
+            } else {
+                app.set_status(":transfer only works inside a room", StatusLevel::Warn);
+            }
+        }
+        // Direct Message
+        // Private line to one member, routed by the server
+        "w" | "whisper" => {
+            let rest = cmd[command.len()..].trim_start();
+            let Some((target, text)) = rest.split_once(char::is_whitespace).filter(|(_, text)| !text.trim().is_empty()) else {
+                app.set_status("Usage: /w <user> <message>", StatusLevel::Warn);
+                return;
+            };
+            send_whisper(app, target, text.trim());
+        }
+        "dm" => {
+            if let Some(target_user) = parts.get(1) {
+                if let Some(transport) = &app.transport {
+                    let payload = CreateDMPayload {
+                        target_username: target_user,
+                    };
+                    let msg = ClientMessage {
+                        message_type: "createDM",
+                        payload,
+                    };
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        let _ = transport.send(json);
+                        app.set_status(format!("Opening DM with {}...", target_user), StatusLevel::Info);
                     }
                 }
             } else {
-                app.status_message = ":transfer only works inside a room".to_string();
+                app.set_status("Usage: :dm <username>", StatusLevel::Warn);
+            }
+        }
+        "edit" | "e" => {
+            match own_target_message(app) {
+                Ok(index) => {
+                    let msg = &app.messages[index];
+                    app.editing_message_id = msg.id.clone();
+                    let text = if msg.is_action { format!("/me {}", msg.content) } else { msg.content.clone() };
+                    app.message_input = TextArea::from(text.lines());
+                    app.message_input.move_cursor(tui_textarea::CursorMove::Bottom);
+                    app.message_input.move_cursor(tui_textarea::CursorMove::End);
+                    app.selected_message = None;
+                    app.vim_state.enter_insert_mode();
+                    app.set_sticky_status("Editing message. Enter to save, Esc twice to cancel.", StatusLevel::Info);
+                }
+                Err(e) => app.set_status(e.to_string(), StatusLevel::Warn),
+            }
+        }
+        "del" | "rm" => {
+            match own_target_message(app) {
+                Ok(index) => {
+                    if let (Some(sender), Some(room_id), Some(message_id)) =
+                        (&app.transport, &app.room_id, &app.messages[index].id)
+                    {
+                        let msg = ClientMessage {
+                            message_type: "delete",
+                            payload: DeleteMessagePayload { room_id, message_id },
+                        };
+                        let sent = match serde_json::to_string(&msg) {
+                            Ok(json) => sender.send(json).map_err(|e| e.to_string()),
+                            Err(e) => Err(e.to_string()),
+                        };
+                        match sent {
+                            Ok(()) => {
+                                app.deleting_message_id = Some(message_id.clone());
+                                app.selected_message = None;
+                                app.set_sticky_status("Deleting message...", StatusLevel::Info);
+                            }
+                            Err(e) => app.set_status(format!("Could not delete message: {}", e), StatusLevel::Error),
+                        }
+                    } else {
+                        app.set_status("Not connected", StatusLevel::Warn);
+                    }
+                }
+                Err(e) => app.set_status(e.to_string(), StatusLevel::Warn),
+            }
+        }
+        "react" | "r" => {
+            let Some(arg) = parts.get(1) else {
+                app.set_status("Usage: :react <emoji or shortcode>", StatusLevel::Warn);
+                return;
+            };
+            let shortcode = arg.trim_matches(':');
+            let emoji = emoji::get_emoji(shortcode).map(str::to_string).unwrap_or_else(|| arg.to_string());
+            let target = app.selected_message.or_else(|| app.messages.iter().rposition(|m| !m.is_system));
+            let Some(msg) = target.and_then(|i| app.messages.get(i)).filter(|m| !m.deleted) else {
+                app.set_status("No message to react to", StatusLevel::Warn);
+                return;
+            };
+            let (Some(sender), Some(room_id), Some(message_id), Some(me)) =
+                (&app.transport, &app.room_id, &msg.id, &app.current_username)
+            else {
+                app.set_status("Not connected", StatusLevel::Warn);
+                return;
+            };
+            // Reacting again with the same emoji takes it back
+            let remove = msg.has_reacted(&emoji, me);
+            let payload = ReactionPayload { room_id, message_id, emoji: &emoji, remove };
+            if let Ok(json) = serde_json::to_string(&ClientMessage { message_type: "reaction", payload }) {
+                let _ = sender.send(json);
             }
         }
-        // Direct Message
-        "dm" => {
-            if let Some(target_user) = parts.get(1) {
-                if let Some(ws_sender) = &app.ws_sender {
-                    let payload = CreateDMPayload {
-                        target_username: target_user,
-                    };
-                    let msg = ClientMessage {
-                        message_type: "createDM",
-                        payload,
-                    };
-                    if let Ok(json) = serde_json::to_string(&msg) {
-                        let _ = ws_sender.send(json);
-                        app.status_message = format!("Opening DM with {}...", target_user);
-                    }
+        "pin" | "unpin" => {
+            let pin = command == "pin";
+            let target = app.selected_message.or_else(|| app.messages.iter().rposition(|m| !m.is_system));
+            let Some(message_id) = target.and_then(|i| app.messages.get(i)).and_then(|m| m.id.clone()) else {
+                app.set_status(format!("No message to {}", command), StatusLevel::Warn);
+                return;
+            };
+            if !pin && !app.pinned_messages.contains(&message_id) {
+                app.set_status("That message isn't pinned", StatusLevel::Warn);
+                return;
+            }
+            if let (Some(sender), Some(room_id)) = (&app.transport, &app.room_id) {
+                let msg = ClientMessage {
+                    message_type: command,
+                    payload: PinPayload { room_id, message_id: &message_id },
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.send(json);
                 }
+                app.selected_message = None;
             } else {
-                app.status_message = "Usage: :dm <username>".to_string();
+                app.set_status("Not connected", StatusLevel::Warn);
             }
         }
+        "diag" | "net" => {
+            app.show_diagnostics = !app.show_diagnostics;
+        }
         // Voice Chat
         "vc" => {
             if app.current_screen == CurrentScreen::InRoom {
-                if let Some(room_id) = &app.room_id {
-                    let subcmd = parts.get(1).map(|s| *s).unwrap_or("");
+                if let Some(room_id) = app.room_id.clone() {
+                    let subcmd = parts.get(1).copied().unwrap_or("");
                     if let Some(voice_tx) = &app.voice_tx {
                         match subcmd {
-                            "join" | "" => {
-                                // Check if already connected (using voice state, not server state)
-                                if app.voice.is_connected() {
-                                    app.status_message = "Already in voice chat.".to_string();
-                                } else if matches!(app.voice.status, VoiceConnectionStatus::Connecting) {
-                                    app.status_message = "Already connecting to voice...".to_string();
-                                } else {
-                                    // Just send the command - state changes via VoiceEvent::Connecting/Connected
-                                    let _ = voice_tx.send(voice::manager::VoiceCommand::Join(room_id.clone()));
-                                    app.status_message = "Joining voice...".to_string();
-                                }
-                            }
-                            "leave" | "l" => {
-                                // Just send the command - state changes via VoiceEvent::Disconnected
-                                let _ = voice_tx.send(voice::manager::VoiceCommand::Leave);
-                                app.status_message = "Leaving voice...".to_string();
-                            }
+                            "join" | "" => join_voice(app, room_id.clone()),
+                            "leave" | "l" => leave_voice(app),
                             "mute" | "m" => {
                                 // Just send the command - state changes via VoiceEvent::MuteStateChanged
-                                let _ = voice_tx.send(voice::manager::VoiceCommand::Mute(true));
+                                let _ = voice_tx.send(VoiceCommand::Mute(true));
                             }
                             "unmute" | "um" => {
                                 // Just send the command - state changes via VoiceEvent::MuteStateChanged
-                                let _ = voice_tx.send(voice::manager::VoiceCommand::Mute(false));
+                                let _ = voice_tx.send(VoiceCommand::Mute(false));
                             }
                             _ => {
-                                app.status_message = "Usage: :vc [join|leave|mute|unmute]".to_string();
+                                app.set_status("Usage: :vc [join|leave|mute|unmute]", StatusLevel::Warn);
                             }
                         }
                     } else {
-                        app.status_message = "Voice Chat not initialized.".to_string();
+                        app.set_status(voice::UNAVAILABLE, StatusLevel::Warn);
                     }
                 }
             } else {
-                app.status_message = ":vc only works inside a room".to_string();
+                app.set_status(":vc only works inside a room", StatusLevel::Warn);
             }
         }
         // Aliases - just send commands, state changes via events
         "m" | "mute" => {
             if let Some(voice_tx) = &app.voice_tx {
-                let _ = voice_tx.send(voice::manager::VoiceCommand::Mute(true));
+                let _ = voice_tx.send(VoiceCommand::Mute(true));
+            } else {
+                app.set_status(voice::UNAVAILABLE, StatusLevel::Warn);
             }
         }
         "um" | "unmute" => {
             if let Some(voice_tx) = &app.voice_tx {
-                let _ = voice_tx.send(voice::manager::VoiceCommand::Mute(false));
+                let _ = voice_tx.send(VoiceCommand::Mute(false));
+            } else {
+                app.set_status(voice::UNAVAILABLE, StatusLevel::Warn);
             }
         }
         "vcl" => {
             if let Some(voice_tx) = &app.voice_tx {
-                let _ = voice_tx.send(voice::manager::VoiceCommand::Leave);
-                app.status_message = "Leaving voice...".to_string();
+                let _ = voice_tx.send(VoiceCommand::Leave);
+                app.set_status("Leaving voice...", StatusLevel::Info);
+            } else {
+                app.set_status(voice::UNAVAILABLE, StatusLevel::Warn);
             }
         }
         
@@ -1993,24 +4099,23 @@ async fn execute_command(app: &mut App<'_>, cmd: &str) {
             // Empty command, do nothing
         }
         _ => {
-            app.status_message = format!("Unknown command: {}. Type :help for list.", command);
+            app.set_status(format!("Unknown command: {}. Type :help for list.", command), StatusLevel::Error);
         }
     }
 }
 
 fn handle_mouse_in_room(app: &mut App, mouse: MouseEvent) {
     match mouse.kind {
-        MouseEventKind::ScrollDown => {
-            // Scroll messages down
-            if app.message_scroll_offset > 0 {
-                app.message_scroll_offset = app.message_scroll_offset.saturating_sub(1);
-            }
+        MouseEventKind::ScrollDown if app.message_scroll_offset > 0 => {
+            app.message_scroll_offset = app.message_scroll_offset.saturating_sub(1);
         }
         MouseEventKind::ScrollUp => {
-            // Scroll messages up
+            // Scroll messages up, fetching older ones at the top
             let max_scroll = app.messages.len().saturating_sub(10);
             if app.message_scroll_offset < max_scroll {
                 app.message_scroll_offset += 1;
+            } else {
+                request_older_history(app);
             }
         }
         MouseEventKind::Down(_button) => {
@@ -2023,35 +4128,301 @@ fn handle_mouse_in_room(app: &mut App, mouse: MouseEvent) {
 
 // --- WebSocket & Message Handling ---
 
+/// Messages asked for per fetchHistory request
+const HISTORY_PAGE_SIZE: usize = 50;
+
+/// A room from the server's list with its names made safe to display
+fn sanitize_room_info(mut room: RoomInfo) -> RoomInfo {
+    room.name = render::sanitize(&room.name);
+    room.display_name = render::sanitize(&room.display_name);
+    room
+}
+
+/// Decrypt a message from the server's history for display
+fn history_entry(app: &mut App<'_>, msg: MessagePayload) -> Option<ChatMessage> {
+    let key = app.room_key.as_ref()?;
+    let content = decrypt_text(key, &msg.ciphertext).unwrap_or_else(|_| {
+        app.diagnostics.decrypt_failures += 1;
+        "<Encrypted Message>".to_string()
+    });
+    let is_own = Some(&msg.username) == app.current_username.as_ref();
+    let mut entry = ChatMessage::from_plaintext(&content, msg.username, msg.timestamp, msg.id);
+    if is_own {
+        entry.delivery = Some(DeliveryState::Sent);
+    }
+    entry.from_me = is_own;
+    entry.client_id = msg.client_id;
+    entry.edited = msg.edited;
+    entry.reactions = msg.reactions.into_iter().map(|r| (r.emoji, r.usernames)).collect();
+    Some(entry)
+}
+
+/// Ask the server for the page of messages before the oldest one we have,
+/// unless a request is already out or there's nothing older
+fn request_older_history(app: &mut App<'_>) {
+    if app.history_loading || app.history_exhausted {
+        return;
+    }
+    let (Some(transport), Some(room_id)) = (&app.transport, &app.room_id) else { return };
+    let before = app.messages.iter().find_map(|m| m.id.as_deref());
+    let payload = FetchHistoryPayload { room_id, before, limit: HISTORY_PAGE_SIZE };
+    if let Some(json) = client_json("fetchHistory", payload) {
+        if transport.send(json).is_ok() {
+            app.history_loading = true;
+        }
+    }
+}
+
+/// Wait this long after a rate-limit error that doesn't say how long
+const DEFAULT_RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(5);
+
+/// React to a server error according to its code, if it has one
+fn handle_server_error(app: &mut App<'_>, payload: ErrorPayload) {
+    // Whatever was refused, a pending delete won't be confirmed now
+    app.deleting_message_id = None;
+    app.messages.push(ChatMessage::system(format!("Error: {}", payload.message)));
+    let hint = match payload.code {
+        Some(ErrorCode::RateLimited) => {
+            let wait = payload.retry_after.map(Duration::from_secs).unwrap_or(DEFAULT_RATE_LIMIT_BACKOFF);
+            app.rate_limited_until = Some(std::time::Instant::now() + wait);
+            app.set_status(format!("Rate limited: holding messages for {}s", wait.as_secs().max(1)), StatusLevel::Warn);
+            return;
+        }
+        Some(ErrorCode::Unauthorized) => {
+            app.auth_rejected = true;
+            app.set_sticky_status(format!("Error: {}. Log in again with :register", payload.message), StatusLevel::Error);
+            return;
+        }
+        Some(ErrorCode::RoomFull) => Some("the room is full; try again later"),
+        Some(ErrorCode::RoomNotFound) => Some("check the room id or ask for a new invite"),
+        Some(ErrorCode::Forbidden) => Some("only the room owner can do that"),
+        Some(ErrorCode::MessageTooLarge) => Some("shorten the message or split it up"),
+        Some(ErrorCode::Unknown) | None => None,
+    };
+    match hint {
+        Some(hint) => app.set_status(format!("Error: {} ({})", payload.message, hint), StatusLevel::Error),
+        None => app.set_status(format!("Error: {}", payload.message), StatusLevel::Error),
+    }
+}
+
+/// Send what was held back by a rate limit once it has passed
+fn check_rate_limit(app: &mut App<'_>) {
+    if app.rate_limited_until.is_some_and(|until| std::time::Instant::now() >= until) {
+        app.rate_limited_until = None;
+        app.needs_redraw = true;
+        flush_outbox(app);
+    }
+}
+
 fn handle_server_message(app: &mut App, msg: ServerMessage) {
     match msg {
+        ServerMessage::Heartbeat(payload) => {
+            if payload.seq == app.heartbeat_seq {
+                app.diagnostics.rtt = app.heartbeat_sent_at.take().map(|sent| sent.elapsed());
+                app.last_heartbeat = Some(std::time::Instant::now());
+                app.heartbeats_unanswered = 0;
+                if let Some(room_id) = app.pending_voice_join.take() {
+                    if app.room_id.as_ref() == Some(&room_id) {
+                        send_voice_join(app, room_id);
+                    }
+                }
+            }
+        }
         ServerMessage::Message(payload) => {
+            app.diagnostics.messages_received += 1;
             if let Some(key) = &app.room_key {
-                match decrypt(key, &payload.ciphertext) {
+                match decrypt_text(key, &payload.ciphertext) {
                     Ok(plaintext) => {
-                        app.messages.push(ChatMessage::new(plaintext, Some(payload.username.clone()), Some(payload.timestamp.clone())));
+                        let mut incoming = ChatMessage::from_plaintext(
+                            &plaintext,
+                            payload.username.clone(),
+                            payload.timestamp.clone(),
+                            payload.id.clone(),
+                        );
+                        let is_own = Some(&payload.username) == app.current_username.as_ref();
+                        if is_own {
+                            incoming.delivery = Some(DeliveryState::Sent);
+                        }
+                        incoming.from_me = is_own;
+
+                        // Our own echo replaces the local copy instead of duplicating it.
+                        // Servers that don't return the client id echo our messages in
+                        // the order they got them, so the echo can only be for the
+                        // oldest one still waiting, and only if the text agrees.
+                        let local_echo = match &payload.client_id {
+                            Some(cid) => app.messages.iter().position(|m| m.id.is_none() && m.client_id.as_ref() == Some(cid)),
+                            None if is_own => app
+                                .messages
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, m)| m.id.is_none() && m.delivery == Some(DeliveryState::Pending))
+                                .min_by_key(|(_, m)| m.sent_at)
+                                .filter(|(_, m)| m.content == incoming.content)
+                                .map(|(index, _)| index),
+                            None => None,
+                        };
+                        if let Some(index) = local_echo {
+                            incoming.client_id = app.messages[index].client_id.take();
+                            incoming.from_me = true;
+                            app.messages[index] = incoming;
+                            return;
+                        }
+
+                        let ignored = app.ignored.contains(&payload.username);
+                        let notify = !app.is_focused
+                            && !is_own
+                            && !ignored
+                            && app.room_id.as_deref().is_some_and(|room| should_notify(app, room, &incoming.content));
+                        app.messages.push(incoming);
+                        enforce_message_limit(app);
                         app.message_scroll_offset = 0; // Auto-scroll to bottom
+                        if !app.is_focused && !is_own && !ignored {
+                            app.unread_count += 1;
+                        }
 
                         // Desktop Notification
-                        if !app.is_focused && Some(&payload.username) != app.current_username.as_ref() {
+                        if notify {
                             let _ = Notification::new()
-                                .summary(&format!("New message from {}", payload.username))
+                                .summary(&format!("New message from {}", render::truncate_chars(&payload.username, MAX_LABEL_WIDTH)))
                                 .body("You have a new encrypted message")
                                 .appname("eurus")
                                 .show();
                         }
                     }
-                    Err(_) => app.messages.push(ChatMessage::system(format!(
-                        "Failed to decrypt message from {}",
-                        payload.username
-                    ))),
+                    Err(_) => {
+                        app.diagnostics.decrypt_failures += 1;
+                        app.messages.push(ChatMessage::system(format!(
+                            "Failed to decrypt message from {}",
+                            payload.username
+                        )));
+                    }
+                }
+            }
+        }
+        ServerMessage::Whisper(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            if app.room_key.is_none() {
+                return;
+            }
+            app.diagnostics.messages_received += 1;
+            let from_me = Some(&payload.username) == app.current_username.as_ref();
+            let whisper_key = match &payload.sealed_key {
+                // Sealed to the recipient, so our other sessions can't read what they sent
+                Some(_) if from_me => {
+                    app.messages.push(ChatMessage::system(format!(
+                        "You whispered to {} from another session",
+                        payload.target_username
+                    )));
+                    return;
+                }
+                Some(sealed_key) => match open_whisper_key(app, sealed_key) {
+                    Some(key) => Some(key),
+                    None => {
+                        app.diagnostics.decrypt_failures += 1;
+                        app.messages.push(ChatMessage::system(format!(
+                            "Couldn't open a sealed whisper from {}: no unlocked ed25519 key in ~/.ssh matches",
+                            payload.username
+                        )));
+                        return;
+                    }
+                },
+                None => None,
+            };
+            let decrypted = match (&whisper_key, &app.room_key) {
+                (Some(key), _) => decrypt_text(key, &payload.ciphertext),
+                (None, Some(room_key)) => decrypt_text(room_key, &payload.ciphertext),
+                (None, None) => return,
+            };
+            let Ok(plaintext) = decrypted else {
+                app.diagnostics.decrypt_failures += 1;
+                app.messages.push(ChatMessage::system(format!("Failed to decrypt whisper from {}", payload.username)));
+                return;
+            };
+            let text = MessageEnvelope::decode(&plaintext).text;
+            let mut whisper = ChatMessage::new(text, Some(payload.username.clone()), Some(payload.timestamp));
+            whisper.whisper_to = Some(render::sanitize(&payload.target_username));
+            whisper.whisper_sealed = payload.sealed_key.is_some();
+            whisper.from_me = from_me;
+            let ignored = !whisper.from_me && app.ignored.contains(&payload.username);
+            let notify = !app.is_focused
+                && !whisper.from_me
+                && !ignored
+                && room_notify_level(app, &payload.room_id) != NotifyLevel::Muted;
+            app.messages.push(whisper);
+            app.message_scroll_offset = 0;
+            if !app.is_focused && !ignored {
+                app.unread_count += 1;
+            }
+            if notify {
+                let _ = Notification::new()
+                    .summary(&format!("Whisper from {}", render::truncate_chars(&payload.username, MAX_LABEL_WIDTH)))
+                    .body("You have a new encrypted message")
+                    .appname("eurus")
+                    .show();
+            }
+        }
+        ServerMessage::MessageEdited(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            let Some(key) = &app.room_key else { return };
+            let content = decrypt_text(key, &payload.ciphertext).unwrap_or_else(|_| {
+                app.diagnostics.decrypt_failures += 1;
+                "<Encrypted Message>".to_string()
+            });
+            if let Some(msg) = app.messages.iter_mut().find(|m| m.id.as_deref() == Some(payload.message_id.as_str())) {
+                let envelope = MessageEnvelope::decode(&content);
+                msg.content = envelope.text;
+                msg.reply_to = envelope.reply_to;
+                msg.is_action = envelope.kind == MessageKind::Action;
+                msg.edited = true;
+            }
+        }
+        ServerMessage::MessageDeleted(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            if let Some(msg) = app.messages.iter_mut().find(|m| m.id.as_deref() == Some(payload.message_id.as_str())) {
+                msg.content.clear();
+                msg.deleted = true;
+            }
+            if app.deleting_message_id.as_deref() == Some(payload.message_id.as_str()) {
+                app.deleting_message_id = None;
+                app.set_status("Message deleted", StatusLevel::Info);
+            }
+            if app.editing_message_id.as_deref() == Some(payload.message_id.as_str()) {
+                app.editing_message_id = None;
+                app.set_status("The message you were editing was deleted", StatusLevel::Warn);
+            }
+        }
+        ServerMessage::Pin(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            app.pinned_messages.retain(|id| id != &payload.message_id);
+            if payload.pinned {
+                app.pinned_messages.push(payload.message_id);
+                truncate_pins(&mut app.pinned_messages);
+                if !payload.username.is_empty() {
+                    app.messages.push(ChatMessage::system(format!("{} pinned a message", payload.username)));
                 }
             }
         }
+        ServerMessage::Reaction(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            if let Some(msg) = app.messages.iter_mut().find(|m| m.id.as_deref() == Some(payload.message_id.as_str())) {
+                msg.apply_reaction(&payload.emoji, &payload.username, payload.remove);
+            }
+        }
         ServerMessage::UserJoined(payload) => {
             // Add user to online list if not already present
-            if !app.online_users.contains(&payload.username) {
-                app.online_users.push(payload.username.clone());
+            let username = render::sanitize(&payload.username);
+            if !app.online_users.contains(&username) {
+                app.online_users.push(username);
             }
             app.messages.push(ChatMessage::system(format!(
                 "{} joined the room",
@@ -2060,66 +4431,167 @@ fn handle_server_message(app: &mut App, msg: ServerMessage) {
         }
         ServerMessage::UserLeft(payload) => {
             // Remove user from online list
-            app.online_users.retain(|u| u != &payload.username);
+            let username = render::sanitize(&payload.username);
+            app.online_users.retain(|u| u != &username);
+            app.away_users.remove(&username);
             app.messages.push(ChatMessage::system(format!(
                 "{} left the room",
                 payload.username
             )));
         }
-        ServerMessage::RoomJoined(payload) => {
-            app.status_message = format!("Joined room: {}", payload.display_name);
+        ServerMessage::RoomJoined(mut payload) => {
+            // Names reach the window title, so no escape sequences
+            payload.room_name = render::sanitize(&payload.room_name);
+            payload.display_name = render::sanitize(&payload.display_name);
+            app.set_status(format!("Joined room: {}", payload.display_name), StatusLevel::Info);
             
             // Force switch to InRoom screen
             app.current_screen = CurrentScreen::InRoom;
+
+            // Rejoining the same room (e.g. after a reconnect) keeps our unsent
+            // messages; joining another room ends that room's session
+            let rejoining = app.room_id.as_deref() == Some(payload.room_id.as_str());
+            let unsent: Vec<ChatMessage> = if rejoining {
+                app.messages.drain(..).filter(|m| m.id.is_none() && m.client_id.is_some()).collect()
+            } else {
+                app.outbox.clear();
+                Vec::new()
+            };
             
             // Store room info
             app.room_id = Some(payload.room_id.clone());
+            app.room_requires_signatures = payload.require_signatures;
             app.room_name = Some(payload.room_name.clone());
+            app.unread_count = 0;
             app.room_display_name = Some(payload.display_name.clone());
             
-            // Store the room key from the server
+            // Store the room key from the server, or else one the user gave
+            let supplied_key = app.supplied_room_key.take()
+                .filter(|(room_id, _)| *room_id == payload.room_id)
+                .map(|(_, key)| key);
             if !payload.encrypted_key.is_empty() {
                 if let Some(key) = key_from_hex(&payload.encrypted_key) {
-                    app.room_key = Some(key);
+                    if supplied_key.is_some_and(|supplied| supplied != key) {
+                        app.set_status("The key you gave doesn't match this room's key; using the room's", StatusLevel::Warn);
+                    }
+                    app.room_key = Some(RoomKey::new(key));
                 } else {
-                    app.status_message = "Error: Failed to decode room key".to_string();
+                    app.set_status("Error: Failed to decode room key", StatusLevel::Error);
                 }
+            } else if supplied_key.is_some() {
+                app.room_key = supplied_key.map(RoomKey::new);
+            }
+            if !rejoining {
+                remember_room(app, payload.encrypted_key.is_empty());
             }
             
             // Load message history
             app.messages.clear();
-            for msg in payload.messages {
-                if let Some(key) = &app.room_key {
-                    if let Ok(plaintext) = decrypt(key, &msg.ciphertext) {
-                        app.messages.push(ChatMessage::new(plaintext, Some(msg.username), Some(msg.timestamp)));
-                    } else {
-                        app.messages.push(ChatMessage::new(
-                            "<Encrypted Message>".to_string(),
-                            Some(msg.username),
-                            Some(msg.timestamp),
-                        ));
-                    }
-                } else {
-                     // We need the room key to decrypt!
-                     // The key exchange logic happens separately.
+            app.selected_message = None;
+            app.replying_to = None;
+            app.thread_root = None;
+            app.editing_message_id = None;
+            app.show_pinned = false;
+            app.pinned_messages = payload.pinned;
+            truncate_pins(&mut app.pinned_messages);
+            app.history_loading = false;
+            app.history_exhausted = false;
+            // Without the room key there's nothing we can show
+            let history: Vec<ChatMessage> = payload.messages.into_iter().filter_map(|msg| history_entry(app, msg)).collect();
+            app.messages.extend(history);
+            
+            // Put back unsent messages the server doesn't already have, then send the queue
+            for msg in unsent {
+                let delivered = app.messages.iter().any(|m| m.client_id.is_some() && m.client_id == msg.client_id);
+                if !delivered {
+                    app.messages.push(msg);
                 }
             }
+            flush_outbox(app);
             
             // Update online users
-            app.online_users = payload.online_users.into_iter().map(|u| u.username).collect();
+            app.online_users = payload.online_users.into_iter().map(|u| render::sanitize(&u.username)).collect();
+            app.away_users.clear();
+            app.member_keys.clear();
+
+            // Voice-first rooms: join the call on entry. The join came over
+            // the chat connection, so signaling has somewhere to go; a
+            // reconnect's rejoin leaves the call as the user left it.
+            let auto_join = !rejoining
+                && app.voice_tx.is_some()
+                && app.voice.status == VoiceConnectionStatus::Disconnected
+                && app.config.audio.auto_joins(&payload.room_id, &payload.room_name);
+            if auto_join {
+                join_voice(app, payload.room_id);
+            }
+        }
+        // Servers with rosters send the full member list after the join
+        ServerMessage::RoomMembers(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            app.online_users.clear();
+            app.away_users.clear();
+            app.member_keys.clear();
+            for member in payload.members {
+                let username = render::sanitize(&member.username);
+                if member.status == MemberStatus::Away {
+                    app.away_users.insert(username.clone());
+                }
+                if let Some(public_key) = member.public_key {
+                    app.member_keys.insert(username.clone(), public_key);
+                }
+                if !app.online_users.contains(&username) {
+                    app.online_users.push(username);
+                }
+            }
+        }
+        ServerMessage::Presence(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            let username = render::sanitize(&payload.username);
+            if payload.status == MemberStatus::Away {
+                app.away_users.insert(username);
+            } else {
+                app.away_users.remove(&username);
+            }
+        }
+        ServerMessage::History(payload) => {
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
+            app.history_loading = false;
+            if !payload.has_more.unwrap_or(payload.messages.len() >= HISTORY_PAGE_SIZE) {
+                app.history_exhausted = true;
+            }
+            // Overlapping pages are possible if messages arrived meanwhile
+            let unseen: Vec<MessagePayload> = payload.messages.into_iter()
+                .filter(|msg| !app.messages.iter().any(|m| m.id.as_deref() == Some(msg.id.as_str())))
+                .collect();
+            let older: Vec<ChatMessage> = unseen.into_iter().filter_map(|msg| history_entry(app, msg)).collect();
+            // The scroll offset counts from the bottom, so the view stays put;
+            // only index-based state needs shifting
+            let added = older.len();
+            app.messages.splice(0..0, older);
+            if let Some(selected) = &mut app.selected_message {
+                *selected += added;
+            }
         }
         ServerMessage::RoomCreated(payload) => {
-            app.status_message = format!("Room created: {}", payload.display_name);
+            let display_name = render::sanitize(&payload.display_name);
+            app.set_status(format!("Room created: {}", display_name), StatusLevel::Info);
             app.room_id = Some(payload.room_id);
-            app.room_name = Some(payload.room_name);
-            app.room_display_name = Some(payload.display_name.clone());
+            app.room_requires_signatures = false;
+            app.room_name = Some(render::sanitize(&payload.room_name));
+            app.room_display_name = Some(display_name);
             
             // Store the room key from the server
             if !payload.encrypted_key.is_empty() {
                 if let Some(key) = key_from_hex(&payload.encrypted_key) {
-                    app.room_key = Some(key);
+                    app.room_key = Some(RoomKey::new(key));
                 } else {
-                    app.status_message = "Error: Failed to decode room key".to_string();
+                    app.set_status("Error: Failed to decode room key", StatusLevel::Error);
                 }
             }
             
@@ -2127,25 +4599,22 @@ fn handle_server_message(app: &mut App, msg: ServerMessage) {
             app.current_screen = CurrentScreen::InRoom;
         }
         ServerMessage::RoomsList(payload) => {
-            app.public_rooms = payload.public_rooms;
-            app.private_rooms = payload.private_rooms;
-            app.status_message = format!(
+            app.public_rooms = payload.public_rooms.into_iter().map(sanitize_room_info).collect();
+            app.private_rooms = payload.private_rooms.into_iter().map(sanitize_room_info).collect();
+            app.set_status(format!(
                 "Loaded {} public and {} private rooms",
                 app.public_rooms.len(),
                 app.private_rooms.len()
-            );
+            ), StatusLevel::Info);
         }
         ServerMessage::Info(payload) => {
-            app.status_message = payload.message.clone();
+            app.set_status(payload.message.clone(), StatusLevel::Info);
             app.messages.push(ChatMessage::system(payload.message));
         }
-        ServerMessage::Error(payload) => {
-            app.status_message = format!("Error: {}", payload.message);
-            app.messages.push(ChatMessage::system(format!("Error: {}", payload.message)));
-        }
+        ServerMessage::Error(payload) => handle_server_error(app, payload),
         ServerMessage::UserTyping(payload) => {
             // Add user to typing list with current timestamp
-            app.typing_users.insert(payload.username.clone(), std::time::Instant::now());
+            app.typing_users.insert(render::sanitize(&payload.username), std::time::Instant::now());
         }
         ServerMessage::InviteCreated(payload) => {
             app.messages.push(ChatMessage::system(format!(
@@ -2163,19 +4632,21 @@ fn handle_server_message(app: &mut App, msg: ServerMessage) {
             // Try to copy to clipboard
             if let Some(clipboard) = &mut app.clipboard {
                 match clipboard.copy_text(&payload.code) {
-                    Ok(_) => app.status_message = format!("Invite code {} copied to clipboard!", payload.code),
-                    Err(_) => app.status_message = format!("Invite code: {}", payload.code),
+                    Ok(_) => app.set_status(format!("Invite code {} copied to clipboard!", payload.code), StatusLevel::Info),
+                    Err(_) => app.set_status(format!("Invite code: {}", payload.code), StatusLevel::Info),
                 }
             } else {
-                app.status_message = format!("Invite code: {}", payload.code);
+                app.set_status(format!("Invite code: {}", payload.code), StatusLevel::Info);
             }
         }
-        ServerMessage::RoomRenamed(payload) => {
+        ServerMessage::RoomRenamed(mut payload) => {
+            payload.new_name = render::sanitize(&payload.new_name);
+            payload.display_name = render::sanitize(&payload.display_name);
             if let Some(current_room_id) = &app.room_id {
                 if current_room_id == &payload.room_id {
                     app.room_name = Some(payload.new_name.clone());
                     app.room_display_name = Some(payload.display_name.clone());
-                    app.status_message = format!("Room renamed to {}", payload.display_name);
+                    app.set_status(format!("Room renamed to {}", payload.display_name), StatusLevel::Info);
                 }
             }
             app.messages.push(ChatMessage::system(format!(
@@ -2193,9 +4664,11 @@ fn handle_server_message(app: &mut App, msg: ServerMessage) {
                     app.room_key = None;
                     app.messages.clear();
                     app.online_users.clear();
+                    app.away_users.clear();
+                    app.member_keys.clear();
                     app.typing_users.clear();
                     app.current_screen = CurrentScreen::RoomChoice;
-                    app.status_message = "Room was deleted by owner.".to_string();
+                    app.set_status("Room was deleted by owner.", StatusLevel::Warn);
                 }
             }
         }
@@ -2209,9 +4682,21 @@ fn handle_server_message(app: &mut App, msg: ServerMessage) {
             // Forward signals to VoiceManager regardless of local state
             // The VoiceManager has its own is_joined flag and will handle appropriately
             // This fixes the race condition where signals arrive before Connected event
+            // Signals for a room we've since left would set up peers in the wrong call
+            if app.room_id.as_deref() != Some(payload.room_id.as_str()) {
+                return;
+            }
             if let Some(voice_tx) = &app.voice_tx {
-                if let (Some(sender_id), Some(_sender_username)) = (payload.sender_user_id, payload.sender_username) {
-                    let _ = voice_tx.send(voice::manager::VoiceCommand::Signal {
+                if let (Some(sender_id), Some(sender_username)) = (payload.sender_user_id, payload.sender_username) {
+                    // Silence ignored users before their audio starts
+                    let username = render::sanitize(&sender_username);
+                    if app.voice_peer_names.get(&sender_id) != Some(&username) {
+                        if app.ignored.contains(&username) {
+                            let _ = voice_tx.send(VoiceCommand::SetPeerVolume { peer_id: sender_id.clone(), volume: 0.0 });
+                        }
+                        app.voice_peer_names.insert(sender_id.clone(), username);
+                    }
+                    let _ = voice_tx.send(VoiceCommand::Signal {
                         sender_id,
                         signal_type: payload.signal_type,
                         data: payload.data,
@@ -2263,23 +4748,16 @@ fn extract_username_from_token(token: &str) -> Option<String> {
     None
 }
 
+/// `auth.token_path` with a leading `~/` expanded
+fn token_file(token_path: &str) -> Option<std::path::PathBuf> {
+    match token_path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().map(|home| home.join(rest)),
+        None => Some(std::path::PathBuf::from(token_path)),
+    }
+}
+
 fn load_auth_token(token_path: &str) -> Option<String> {
-    use std::fs;
-    use std::path::Path;
-    
-    // Expand ~ to home directory
-    let expanded_path = if token_path.starts_with("~/") {
-        if let Some(home) = dirs::home_dir() {
-            home.join(&token_path[2..])
-        } else {
-            return None;
-        }
-    } else {
-        Path::new(token_path).to_path_buf()
-    };
-    
-    // Read token file
-    fs::read_to_string(expanded_path)
+    std::fs::read_to_string(token_file(token_path)?)
         .ok()
         .map(|s| s.trim().to_string())
 }
@@ -2545,6 +5023,39 @@ async fn verify_challenge(
     Ok(result.token)
 }
 
+/// The saved login token, from the secret store with `auth.keyring`
+fn auth_token(app: &App<'_>) -> Option<String> {
+    match &app.secrets {
+        Some(secrets) => secrets.get(secrets::TOKEN).ok().flatten(),
+        None => load_auth_token(&app.config.auth.token_path),
+    }
+}
+
+/// Save the login token to the secret store with `auth.keyring`, else to
+/// the token file
+fn store_auth_token(app: &mut App<'_>, token: &str) -> Result<(), Box<dyn Error>> {
+    match &mut app.secrets {
+        Some(secrets) => Ok(secrets.set(secrets::TOKEN, token)?),
+        None => save_auth_token(token),
+    }
+}
+
+/// Once `auth.keyring` is turned on, move a token still in the token file
+/// into the secret store and delete the file
+fn move_token_into_secrets(app: &mut App<'_>) -> Result<(), Box<dyn Error>> {
+    let Some(secrets) = &mut app.secrets else {
+        return Ok(());
+    };
+    let (Some(path), Some(token)) = (token_file(&app.config.auth.token_path), load_auth_token(&app.config.auth.token_path)) else {
+        return Ok(());
+    };
+    if secrets.get(secrets::TOKEN)?.is_none() {
+        secrets.set(secrets::TOKEN, &token)?;
+    }
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
 fn save_auth_token(token: &str) -> Result<(), Box<dyn Error>> {
     use std::fs;
     #[cfg(unix)]
@@ -2573,116 +5084,365 @@ fn save_auth_token(token: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-async fn establish_connection(
+/// Drop the current socket and reconnect in the background if we were in a room.
+/// The room is rejoined once the new connection is up.
+fn connection_lost(app: &mut App<'_>) {
+    app.messages.push(ChatMessage::system("[SYSTEM] Connection lost. Attempting to reconnect...".to_string()));
+    app.set_sticky_status("Connection lost.", StatusLevel::Error);
+    app.transport = None;
+    app.heartbeat_sent_at = None;
+    app.heartbeats_unanswered = 0;
+    // A history reply won't come on the next connection
+    app.history_loading = false;
+
+    // Voice signaling went with the socket. An established call keeps its
+    // audio but can't renegotiate until we're back; one still connecting
+    // never will, so it's abandoned.
+    if app.pending_voice_join.take().is_some() {
+        app.messages.push(ChatMessage::system("[SYSTEM] Cannot join voice: not connected to server.".to_string()));
+    }
+    match app.voice.status {
+        VoiceConnectionStatus::Connected => app.voice.status = VoiceConnectionStatus::Reconnecting,
+        VoiceConnectionStatus::Connecting => {
+            if let Some(voice_tx) = &app.voice_tx {
+                let _ = voice_tx.send(VoiceCommand::Leave);
+            }
+        }
+        _ => {}
+    }
+
+    if app.auth_rejected {
+        app.set_sticky_status("Connection lost. Log in again with :register to reconnect.", StatusLevel::Error);
+    } else if app.current_screen == CurrentScreen::InRoom && app.room_id.is_some() {
+        start_connection(app);
+    }
+}
+
+/// Oldest heartbeat echo that still counts as proof the server is there
+/// when joining voice
+const VOICE_JOIN_HEARTBEAT_AGE: Duration = Duration::from_secs(5);
+
+/// Probes a server that has never echoed gets before we stop sending them
+/// on that connection (it likely doesn't support heartbeats)
+const MAX_UNANSWERED_HEARTBEATS: u32 = 3;
+
+/// Send application-level heartbeats and detect connections that stay open
+/// but silently stop delivering frames (which WebSocket pings can miss).
+fn check_heartbeat(app: &mut App<'_>) {
+    let interval = Duration::from_secs(app.config.network.heartbeat_interval);
+    let timeout = Duration::from_secs(app.config.network.heartbeat_timeout);
+    if interval.is_zero() || app.transport.is_none() {
+        return;
+    }
+
+    let due = match (app.heartbeat_sent_at, app.last_heartbeat) {
+        // Only servers that have echoed before can be judged by a missing
+        // echo; until then the probe stands, so the timeout can run out
+        (Some(sent), Some(_)) => {
+            if sent.elapsed() >= timeout {
+                app.needs_redraw = true;
+                connection_lost(app);
+            }
+            return;
+        }
+        (Some(sent), None) => {
+            app.heartbeats_unanswered < MAX_UNANSWERED_HEARTBEATS && sent.elapsed() >= interval
+        }
+        (None, Some(acked)) => acked.elapsed() >= interval,
+        (None, None) => app.heartbeats_unanswered < MAX_UNANSWERED_HEARTBEATS,
+    };
+    if due {
+        send_heartbeat(app);
+    }
+}
+
+fn send_heartbeat(app: &mut App<'_>) {
+    app.heartbeat_seq += 1;
+    let msg = ClientMessage {
+        message_type: "heartbeat",
+        payload: HeartbeatPayload { seq: app.heartbeat_seq },
+    };
+    if let (Some(sender), Ok(json)) = (&app.transport, serde_json::to_string(&msg)) {
+        let _ = sender.send(json);
+        app.heartbeat_sent_at = Some(std::time::Instant::now());
+        app.heartbeats_unanswered += 1;
+    }
+}
+
+/// Slack on top of `network.connect_timeout` before a connect attempt is
+/// abandoned, so one never hangs forever even without user input
+const CONNECT_GRACE: Duration = Duration::from_secs(5);
+
+/// Start connecting to the server in a background task.
+/// Progress is reported back to the main loop as `ConnectionEvent`s so the UI
+/// keeps redrawing and accepting input while DNS/TLS/handshake are in flight.
+fn start_connection(app: &mut App<'_>) {
+    // A second socket would replace the live one without closing it
+    if app.connect_task.is_some() || app.transport.is_some() {
+        return;
+    }
+    let Some(events_tx) = app.connection_tx.clone() else {
+        return;
+    };
+
+    let handshake = Handshake {
+        url: app.config.server.url.clone(),
+        token: auth_token(app),
+        subprotocol: app.config.network.subprotocol.clone(),
+        extra_headers: app.config.network.extra_headers.clone(),
+    };
+    // Catch bad header values up front instead of on every retry
+    if let Err(e) = handshake.request() {
+        app.set_sticky_status(format!("Invalid handshake configuration: {}", e), StatusLevel::Error);
+        return;
+    }
+    let connector = match tls::build_connector(&app.config.network) {
+        Ok(connector) => connector,
+        Err(e) => {
+            app.set_sticky_status(format!("TLS configuration error: {}", e), StatusLevel::Error);
+            return;
+        }
+    };
+    let proxy = match proxy::for_server(&app.config.network, &app.config.server.url) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            app.set_sticky_status(format!("Invalid proxy: {}", e), StatusLevel::Error);
+            return;
+        }
+    };
+
+    let network = app.config.network.clone();
+    app.connect_id += 1;
+    let connect_id = app.connect_id;
+    app.pending_operation = Some("Connecting... (Esc to cancel)".to_string());
+    app.connect_task = Some(tokio::spawn(establish_connection(handshake, connector, proxy, network, connect_id, events_tx)));
+}
+
+/// Apply pending connection progress and (at most one) incoming server
+/// message. Called once per run loop iteration; also drives the app in tests.
+fn process_network_events(
     app: &mut App<'_>,
-    ws_incoming_tx: mpsc::UnboundedSender<String>,
-) -> Result<(), Box<dyn Error>> {
+    connection_rx: &mut mpsc::UnboundedReceiver<(u64, ConnectionEvent)>,
+) {
+    // Handle connection progress from the background connect task
+    while let Ok((connect_id, event)) = connection_rx.try_recv() {
+        app.needs_redraw = true;
+        // Ignore late reports from a cancelled attempt; dropping a stale
+        // Connected transport shuts down that attempt's socket tasks.
+        if connect_id == app.connect_id {
+            handle_connection_event(app, event);
+        }
+    }
+
+    // Handle incoming server messages without blocking UI
+    let Some(overflow) = app.transport.as_ref().map(|transport| transport.take_overflow()) else {
+        return;
+    };
+    if overflow > 0 {
+        app.needs_redraw = true;
+        let dropped = match app.config.network.send_queue.policy {
+            QueueFullPolicy::Reject => "not sent",
+            QueueFullPolicy::DropOldest => "dropped",
+        };
+        app.set_status(
+            format!("Send queue full: {} message(s) {}. The connection can't keep up.", overflow, dropped),
+            StatusLevel::Warn,
+        );
+    }
+    let Some(transport) = app.transport.as_mut() else {
+        return;
+    };
+    match transport.try_recv() {
+        Ok(None) => {}
+        Ok(Some(text)) => {
+            app.needs_redraw = true;
+            match serde_json::from_str::<ServerMessage>(&text) {
+                Ok(server_msg) => handle_server_message(app, server_msg),
+                // A truncated or garbled JSON line; note it rather than
+                // dumping it into the chat
+                Err(e) if text.starts_with('{') && (e.is_syntax() || e.is_eof()) => {
+                    app.set_status(format!("Ignored a malformed server message: {}", e), StatusLevel::Warn);
+                }
+                Err(_) => {
+                    // Raw info messages from the server
+                    app.messages.push(ChatMessage::system(format!("[SERVER] {}", text)));
+                }
+            };
+        }
+        Err(Closed) => {
+            app.needs_redraw = true;
+            connection_lost(app);
+        }
+    }
+}
+
+/// Abort the in-flight connection attempt and return to a usable screen.
+/// Aborting drops the pending handshake future, which closes its socket; a
+/// socket that already finished connecting is closed when its stale
+/// `Connected` report is discarded.
+fn cancel_connection(app: &mut App<'_>) {
+    if let Some(task) = app.connect_task.take() {
+        task.abort();
+    }
+    app.connect_id += 1;
+    app.pending_operation = None;
+    app.is_reconnecting = false;
+    app.reconnect_attempts = 0;
+
+    // A cancelled reconnect leaves us without a live room
+    if app.current_screen == CurrentScreen::InRoom {
+        app.room_id = None;
+        app.room_name = None;
+        app.room_display_name = None;
+        app.room_key = None;
+        app.messages.clear();
+        app.online_users.clear();
+        app.away_users.clear();
+        app.member_keys.clear();
+        app.typing_users.clear();
+        app.current_screen = CurrentScreen::RoomChoice;
+    }
+    app.set_status("Connection attempt cancelled.", StatusLevel::Info);
+}
+
+/// Apply a connection progress report to the app state
+fn handle_connection_event(app: &mut App<'_>, event: ConnectionEvent) {
+    match event {
+        ConnectionEvent::Attempt { attempt, max_attempts } => {
+            app.reconnect_attempts = attempt - 1;
+            app.is_reconnecting = attempt > 1;
+            if attempt > 1 {
+                app.pending_operation = Some(format!("Reconnecting... attempt {}/{} (Esc to cancel)", attempt, max_attempts));
+                app.set_status(format!("Reconnecting (attempt {}/{})", attempt, max_attempts), StatusLevel::Warn);
+            }
+        }
+        ConnectionEvent::Connected(sender) => {
+            app.diagnostics.connections += 1;
+            app.auth_rejected = false;
+            app.connect_task = None;
+            app.pending_operation = None;
+            app.reconnect_attempts = 0;
+            app.is_reconnecting = false;
+            app.transport = Some(sender);
+            app.heartbeat_sent_at = None;
+            app.last_heartbeat = None;
+            app.heartbeats_unanswered = 0;
+            if app.voice.status == VoiceConnectionStatus::Reconnecting {
+                app.voice.status = VoiceConnectionStatus::Connected;
+            }
+
+            // Rejoin the room if we lost the connection while inside it
+            if app.current_screen == CurrentScreen::InRoom {
+                if let (Some(sender), Some(room_id)) = (&app.transport, &app.room_id) {
+                    let join_payload = JoinRoomPayload {
+                        room_id: Some(room_id),
+                        room_name: None,
+                    };
+                    let join_msg = ClientMessage {
+                        message_type: "joinRoom",
+                        payload: join_payload,
+                    };
+                    if let Ok(json) = serde_json::to_string(&join_msg) {
+                        let _ = sender.send(json);
+                    }
+                    app.messages.push(ChatMessage::system("[SYSTEM] Reconnected successfully!".to_string()));
+                }
+                app.set_status("Connected", StatusLevel::Info);
+            } else {
+                app.set_status("Connected! Create or Join a secure room.", StatusLevel::Info);
+            }
+        }
+        ConnectionEvent::Failed(reason) => {
+            app.connect_task = None;
+            app.pending_operation = None;
+            app.is_reconnecting = false;
+            if app.current_screen == CurrentScreen::InRoom {
+                app.messages.push(ChatMessage::system("[SYSTEM] Failed to reconnect. Please restart.".to_string()));
+            }
+            app.set_sticky_status(format!("Connection failed: {}", reason), StatusLevel::Error);
+        }
+        ConnectionEvent::AuthFailed(reason) => {
+            app.auth_rejected = true;
+            app.connect_task = None;
+            app.pending_operation = None;
+            app.is_reconnecting = false;
+            if app.current_screen == CurrentScreen::InRoom {
+                app.messages.push(ChatMessage::system("[SYSTEM] Authentication failed. Please log in again.".to_string()));
+            }
+            app.set_sticky_status(format!("Authentication failed: {}", reason), StatusLevel::Error);
+        }
+    }
+}
+
+
+async fn establish_connection(
+    handshake: Handshake,
+    connector: Option<Connector>,
+    proxy: Option<Proxy>,
+    network: NetworkConfig,
+    connect_id: u64,
+    events_tx: mpsc::UnboundedSender<(u64, ConnectionEvent)>,
+) {
+    let connect_timeout = Duration::from_secs(network.connect_timeout);
+    let ping_interval = Duration::from_secs(network.ping_interval);
     // Try to connect with exponential backoff
-    let max_attempts = 5;
+    let max_attempts = network.reconnect_attempts;
     let mut delay_ms = 1000;
+    let mut last_error = String::from("Failed to connect after multiple attempts");
     
     for attempt in 0..max_attempts {
-        app.reconnect_attempts = attempt;
-        app.is_reconnecting = attempt > 0;
+        let _ = events_tx.send((connect_id, ConnectionEvent::Attempt {
+            attempt: attempt + 1,
+            max_attempts,
+        }));
         
         if attempt > 0 {
-            app.status_message = format!("Reconnecting... attempt {}/{}", attempt + 1, max_attempts);
             tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms)).await;
             delay_ms = (delay_ms * 2).min(30000); // Max 30 seconds
         }
         
-        match try_connect(app, ws_incoming_tx.clone()).await {
-            Ok(_) => {
-                app.reconnect_attempts = 0;
-                app.is_reconnecting = false;
-                app.status_message = "Connected".to_string();
-                return Ok(());
+        let connect = transport::connect(&handshake, connector.clone(), &network.send_queue, connect_timeout, ping_interval, proxy.as_ref());
+        let Ok(result) = tokio::time::timeout(connect_timeout + CONNECT_GRACE, connect).await else {
+            last_error = "Timed out".to_string();
+            continue;
+        };
+        match result {
+            Ok(transport) => {
+                let _ = events_tx.send((connect_id, ConnectionEvent::Connected(transport)));
+                return;
             }
-            Err(e) if attempt == max_attempts - 1 => {
-                app.status_message = format!("Connection failed: {}", e);
-                return Err(e);
+            // Retrying with the same credentials won't help
+            Err(e) if e.is::<AuthRejected>() => {
+                let _ = events_tx.send((connect_id, ConnectionEvent::AuthFailed(e.to_string())));
+                return;
             }
-            Err(_) => continue,
+            Err(e) if e.is::<ProxyError>() => last_error = format!("Couldn't reach the server through the {}", e),
+            Err(e) => last_error = e.to_string(),
         }
     }
     
-    Err("Failed to connect after multiple attempts".into())
+    let _ = events_tx.send((connect_id, ConnectionEvent::Failed(last_error)));
 }
 
-async fn try_connect(
-    app: &mut App<'_>,
-    ws_incoming_tx: mpsc::UnboundedSender<String>,
-) -> Result<(), Box<dyn Error>> {
-    let mut ws_url = app.config.server.url.clone();
-    
-    // Try to load token and append to URL
-    if let Some(token) = load_auth_token(&app.config.auth.token_path) {
-        // Append token as query parameter
-        let separator = if ws_url.contains('?') { '&' } else { '?' };
-        ws_url = format!("{}{}token={}", ws_url, separator, token);
-    }
-    
-    let (ws_stream, _) = connect_async(&ws_url).await?;
-    let (mut write, mut read) = ws_stream.split();
-
-    // Create a channel for sending messages to the WebSocket task
-    let (ws_outgoing_tx, mut ws_outgoing_rx) = mpsc::unbounded_channel::<String>();
-    app.ws_sender = Some(ws_outgoing_tx);
-
-    // Task to listen for incoming messages from the server
-    let incoming_tx = ws_incoming_tx.clone();
-    tokio::spawn(async move {
-        while let Some(message_result) = read.next().await {
-            match message_result {
-                Ok(msg) => {
-                    if let Message::Text(text) = msg {
-                        if incoming_tx.send(text).is_err() {
-                            break;
-                        }
-                    }
-                }
-                Err(_) => {
-                    let _ = incoming_tx.send("__DISCONNECT__".to_string());
-                    break;
-                }
-            }
-        }
-    });
-
-    // Task to send outgoing messages from the app to the server, with periodic pings
-    tokio::spawn(async move {
-        let mut ping_interval = interval(Duration::from_secs(30));
-        ping_interval.tick().await; // Skip the first immediate tick
-        
-        loop {
-            tokio::select! {
-                // Handle outgoing messages
-                Some(json) = ws_outgoing_rx.recv() => {
-                    if write.send(Message::text(json)).await.is_err() {
-                        break;
-                    }
-                }
-                // Send periodic pings to keep connection alive
-                _ = ping_interval.tick() => {
-                    if write.send(Message::Ping(vec![])).await.is_err() {
-                        break;
-                    }
-                }
-            }
-        }
-    });
-
-    Ok(())
-}
 
 
 // --- UI Rendering ---
 
 fn ui(f: &mut Frame, app: &mut App) {
+    if app.pending_operation.is_some() {
+        app.spinner.tick();
+    }
+
     // Force the entire background to be Pure Black (RGB 0,0,0) to override terminal theme palette
     let background_block = Block::default().style(Style::default().bg(Color::Rgb(0, 0, 0)));
     f.render_widget(background_block, f.area());
 
+    // Below this the layout collapses to zero-height areas
+    if f.area().width < MIN_TERMINAL_WIDTH || f.area().height < MIN_TERMINAL_HEIGHT {
+        render_too_small(f, f.area());
+        return;
+    }
+
     // Layout: Header (1), Chat (Min 1), Status/Padding (3)
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -2694,13 +5454,28 @@ fn ui(f: &mut Frame, app: &mut App) {
         .split(f.area());
 
     // --- Header Rendering ---
-    let header_text = match app.current_screen {
+    let mut header_text = match app.current_screen {
         CurrentScreen::InRoom => {
-            let room_name = app.room_display_name.as_deref().unwrap_or("Unknown");
+            let accent = app.room_id.as_deref().and_then(|id| app.room_labels.get(id)).and_then(|room| room.accent());
             Line::from(vec![
                 Span::styled(" eurus ", Style::default().bg(Color::Blue).fg(Color::Black).add_modifier(Modifier::BOLD)),
                 Span::raw(" "),
-                Span::styled(format!(" {} ", room_name), Style::default().bg(Color::DarkGray).fg(Color::White)),
+                Span::styled(
+                    format!(" {} ", render::truncate_chars(&room_title(app), MAX_LABEL_WIDTH)),
+                    Style::default().bg(accent.unwrap_or(Color::DarkGray)).fg(Color::White),
+                ),
+                Span::styled(
+                    app.room_id.as_deref().map(|id| format!(" {}", short_room_id(id))).unwrap_or_default(),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    match app.room_id.as_deref().map(|id| room_notify_level(app, id)) {
+                        Some(NotifyLevel::Muted) => " 🔕",
+                        Some(NotifyLevel::Mentions) => " @",
+                        _ => "",
+                    },
+                    Style::default().fg(Color::DarkGray),
+                ),
                 Span::raw(" "),
                 Span::styled(format!(" {} online ", app.online_users.len()), Style::default().fg(Color::Gray)),
             ])
@@ -2711,16 +5486,42 @@ fn ui(f: &mut Frame, app: &mut App) {
         ]),
     };
 
+    // Collapsed voice indicator while the sidebar is hidden
+    if app.current_screen == CurrentScreen::InRoom
+        && app.voice.status == VoiceConnectionStatus::Disconnected
+        && !app.voice.room_users.is_empty()
+    {
+        header_text.spans.push(Span::styled(
+            format!(" 🔊 {} in voice (V to join) ", app.voice.room_users.len()),
+            Style::default().fg(Color::Green),
+        ));
+    }
+
+    // Mic state stays visible in a call even with the sidebar hidden
+    if app.current_screen == CurrentScreen::InRoom && app.voice.is_connected() {
+        let (icon, _, style) = app.voice.mic_state().indicator();
+        header_text.spans.push(Span::styled(format!(" {} ", icon), style));
+    }
+
+    // Never let disabled certificate checks go unnoticed
+    if app.config.network.danger_accept_invalid_certs {
+        header_text.spans.push(Span::raw(" "));
+        header_text.spans.push(Span::styled(
+            " ⚠ TLS VERIFICATION DISABLED ",
+            Style::default().bg(Color::Red).fg(Color::White).add_modifier(Modifier::BOLD),
+        ));
+    }
+
     let header = Paragraph::new(header_text).style(Style::default().bg(Color::Rgb(0, 0, 0)));
     f.render_widget(header, chunks[0]);
 
     // --- Body Rendering ---
     
     // Split the body into Sidebar (Voice) and Main Chat area
-    // Sidebar visible only in InRoom (or always? Let's do always for consistency, or active room)
-    // For now, only InRoom makes sense.
-    
-    let (sidebar_area, main_area) = if app.current_screen == CurrentScreen::InRoom {
+    // The sidebar only appears while we're in (or joining) a voice call so
+    // text-only users get the full width
+    let in_call = app.voice.status != VoiceConnectionStatus::Disconnected;
+    let (sidebar_area, main_area) = if app.current_screen == CurrentScreen::InRoom && in_call {
         let body_layout = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([
@@ -2733,10 +5534,22 @@ fn ui(f: &mut Frame, app: &mut App) {
         (None, chunks[1])
     };
 
+    // Member list on the right, when asked for and there's room for it
+    let in_room = matches!(app.current_screen, CurrentScreen::InRoom | CurrentScreen::RoomSwitcher);
+    let (roster_area, main_area) = if in_room && app.show_roster && main_area.width >= ROSTER_WIDTH * 3 {
+        let body_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(1), Constraint::Length(ROSTER_WIDTH)])
+            .split(main_area);
+        (Some(body_layout[1]), body_layout[0])
+    } else {
+        (None, main_area)
+    };
+
     // Calculate the floating input area RECT relative to MAIN AREA
     // Centered in main_area, max width 100 chars, or 90% of main_area
     let input_width = 100.min((main_area.width as f32 * 0.95) as u16); // 95% of remaining space
-    let input_height = 4; 
+    let input_height = composer_height(app, input_width, main_area.height);
     
     let input_y = main_area.y + main_area.height.saturating_sub(input_height); // Bottom of main_area (chunks[1])
     // Wait, chunks[1] does NOT include the footer padding (chunks[2]).
@@ -2758,23 +5571,26 @@ fn ui(f: &mut Frame, app: &mut App) {
     if let Some(area) = sidebar_area {
         render_voice_sidebar(f, app, area);
     }
+    if let Some(area) = roster_area {
+        render_roster_sidebar(f, app, area);
+    }
 
     match app.current_screen {
         // Registration screens
-        CurrentScreen::Registration => render_registration_error(f, main_area),
+        CurrentScreen::Registration => render_registration_error(f, app, main_area),
         CurrentScreen::KeySelection => render_key_selection(f, app, main_area),
         CurrentScreen::UsernameInput => render_username_input(f, app, main_area),
         CurrentScreen::RegistrationSuccess => render_registration_success(f, app, main_area),
         
         // Re-authentication screens
-        CurrentScreen::ReAuthenticate => render_re_authenticate(f, app, main_area),
         CurrentScreen::PassphraseInput => render_passphrase_input(f, app, main_area),
         
         // Main screens
-        CurrentScreen::RoomChoice => render_room_choice(f, main_area),
+        CurrentScreen::RoomChoice => render_room_choice(f, app, main_area),
         CurrentScreen::RoomList => render_room_list(f, app, main_area),
         CurrentScreen::RoomTypeSelection => render_room_type_selection(f, app, main_area),
         CurrentScreen::CreateRoomInput => render_create_room_input(f, app, main_area),
+        CurrentScreen::JoinRoomInput => render_join_room_input(f, app, main_area),
         CurrentScreen::RoomCreation => render_room_creation(f, app, main_area),
         CurrentScreen::InRoom => render_in_room(f, app, main_area, floating_input_area), 
         CurrentScreen::RoomSwitcher => {
@@ -2784,6 +5600,18 @@ fn ui(f: &mut Frame, app: &mut App) {
         CurrentScreen::Help => render_help(f, main_area),
     }
 
+    if app.show_status_log {
+        render_status_log_overlay(f, app, main_area);
+    }
+
+    if app.show_about {
+        render_about_overlay(f, app, main_area);
+    }
+
+    if app.show_diagnostics {
+        render_diagnostics_overlay(f, app, main_area);
+    }
+
     // Render footer status at the very bottom line
     // Use the last line of the screen
     let footer_area = Rect {
@@ -2795,23 +5623,45 @@ fn ui(f: &mut Frame, app: &mut App) {
     render_footer(f, app, footer_area);
 }
 
-fn render_room_choice(f: &mut Frame, area: Rect) {
+/// Smallest terminal the full layout is drawn in
+const MIN_TERMINAL_WIDTH: u16 = 40;
+const MIN_TERMINAL_HEIGHT: u16 = 10;
+
+/// Shown instead of the UI while the terminal is below the minimum size
+fn render_too_small(f: &mut Frame, area: Rect) {
+    let text = format!(
+        "Terminal too small ({}x{}), resize to at least {}x{}",
+        area.width, area.height, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT
+    );
+    let top = area.height.saturating_sub(3) / 2;
+    let widget = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(widget, Rect { y: area.y + top, height: area.height - top, ..area });
+}
+
+fn render_room_choice(f: &mut Frame, app: &App, area: Rect) {
     // Add some padding
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Min(2), Constraint::Percentage(100), Constraint::Min(2)])
         .split(area);
         
-    let text = Text::from(vec![
+    let mut text = Text::from(vec![
         Line::from(""),
         Line::from("Welcome to eurus").style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan)),
         Line::from(""),
         Line::from("  c   Create new room"),
         Line::from("  j   Join / browse rooms"),
-        Line::from(""),
-        Line::from("  :   Command mode"),
-        Line::from(""),
+        Line::from("  i   Join by room id and key"),
+        Line::from("  p   Join from a copied link or id:key"),
     ]);
+    if let Some(room) = app.last_room.for_server(&app.config.server.url) {
+        let name = app.room_labels.label(&room.room_id).unwrap_or(&room.name);
+        text.lines.push(Line::from(format!("  r   Rejoin {}", render::truncate_chars(name, MAX_LABEL_WIDTH))));
+    }
+    text.lines.extend([Line::from(""), Line::from("  :   Command mode"), Line::from("")]);
     
     let widget = Paragraph::new(text).alignment(Alignment::Left);
     f.render_widget(widget, chunks[1]);
@@ -2917,6 +5767,31 @@ fn render_create_room_input(f: &mut Frame, app: &mut App, area: Rect) {
     f.render_widget(&app.room_name_input, chunks[1]);
 }
 
+fn render_join_room_input(f: &mut Frame, app: &mut App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(3), Constraint::Length(3)])
+        .split(area);
+
+    let help_text = Paragraph::new("Join a room by id. The key is only needed if the server doesn't share it.")
+        .style(Style::default().fg(Color::Cyan))
+        .wrap(Wrap { trim: true });
+    f.render_widget(help_text, chunks[0]);
+
+    let editing_key = app.currently_editing == Some(CurrentlyEditing::RoomKey);
+    let key_title = if app.room_key_revealed { "Room Key (Ctrl+R to hide)" } else { "Room Key (Ctrl+R to show)" };
+    for (input, title, focused, area) in [
+        (&mut app.room_id_input, "Room ID", !editing_key, chunks[1]),
+        (&mut app.room_key_input, key_title, editing_key, chunks[2]),
+    ] {
+        let border = if focused { Color::Cyan } else { Color::DarkGray };
+        input.set_block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(border)).title(title));
+        // Only the focused field shows a cursor
+        input.set_cursor_style(if focused { Style::default().add_modifier(Modifier::REVERSED) } else { Style::default() });
+        f.render_widget(&*input, area);
+    }
+}
+
 fn render_room_creation(f: &mut Frame, app: &mut App, area: Rect) {
     let text: Vec<Line> = app.messages.iter().map(|m| Line::from(m.content.clone())).collect();
     let widget = Paragraph::new(Text::from(text))
@@ -2925,6 +5800,16 @@ fn render_room_creation(f: &mut Frame, app: &mut App, area: Rect) {
     f.render_widget(widget, area);
 }
 
+/// Whether a message sent at `time` opens a new group after one sent at
+/// `last`: more than `window` apart, unless grouping by time is off (zero)
+fn starts_new_group(
+    window: chrono::Duration,
+    last: Option<chrono::DateTime<chrono::Local>>,
+    time: chrono::DateTime<chrono::Local>,
+) -> bool {
+    window > chrono::Duration::zero() && last.is_some_and(|last| time - last > window)
+}
+
 fn render_in_room(f: &mut Frame, app: &mut App, chat_area: Rect, input_area: Rect) {
     // --- Message Area ---
     // The chat column should align with the input box horizontally.
@@ -2956,104 +5841,285 @@ fn render_in_room(f: &mut Frame, app: &mut App, chat_area: Rect, input_area: Rec
 
     let mut text_content: Vec<Line> = Vec::new();
     let mut last_sender: Option<String> = None;
-    let mut last_date: Option<String> = None;
+    let mut last_day: Option<chrono::NaiveDate> = None;
+    let today = chrono::Local::now().date_naive();
+    let mut last_time: Option<chrono::DateTime<chrono::Local>> = None;
+    // A pause longer than this starts a new group, even from the same sender
+    let group_window = chrono::Duration::minutes(app.config.ui.group_window_minutes as i64);
+    let divider = |label: &str, width: usize| {
+        let side = "─".repeat(width.saturating_sub(render::display_width(label) + 2) / 2);
+        format!("{} {} {}", side, label, side)
+    };
+    if app.history_loading {
+        text_content.push(Line::from(Span::styled(
+            "loading older messages…",
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC).bg(chat_bg_color),
+        )));
+    }
 
+    // Direct reply counts for the thread badge
+    let mut reply_counts: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
     for msg in &app.messages {
-        // Date Separator
-        if last_date.as_ref() != Some(&msg.date) {
-            let date_str = &msg.date;
-            // Calculate padding based on inner_width
-            let total_padding = inner_width.saturating_sub(date_str.len() + 2);
-            let padding_side = total_padding / 2;
-            
-            let separator = "─".repeat(padding_side);
-            
+        if let Some(parent) = msg.reply_to.as_deref() {
+            *reply_counts.entry(parent).or_default() += 1;
+        }
+    }
+
+    let is_ignored = |msg: &ChatMessage| {
+        !msg.is_system && !msg.from_me && msg.sender.as_deref().is_some_and(|sender| app.ignored.contains(sender))
+    };
+    for (index, msg) in app.messages.iter().enumerate() {
+        let ignored = is_ignored(msg);
+        if ignored && app.config.ui.ignored_messages == IgnoredMessages::Hide {
+            continue;
+        }
+        // Day separator before the first message and wherever the local date
+        // changes. Drawn from the whole list each frame, so history paged in
+        // at the top gets its separators too.
+        let day = msg.time.date_naive();
+        if last_day != Some(day) {
             text_content.push(Line::from(vec![
-                Span::styled(format!("{} {} {}", separator, date_str, separator), Style::default().fg(Color::DarkGray).bg(chat_bg_color)),
+                Span::styled(divider(&day_label(day, today), inner_width), Style::default().fg(Color::DarkGray).bg(chat_bg_color)),
             ]));
-            last_date = Some(msg.date.clone());
+            last_day = Some(day);
             last_sender = None; // Reset sender on new date
+        } else if starts_new_group(group_window, last_time, msg.time) {
+            // Same day after a long pause: a quieter, shorter divider with the time
+            text_content.push(Line::from(""));
+            if app.show_timestamps {
+                text_content.push(Line::from(vec![
+                    Span::styled(
+                        divider(&msg.timestamp(), inner_width / 3),
+                        Style::default().fg(Color::DarkGray).add_modifier(Modifier::DIM).bg(chat_bg_color),
+                    ),
+                ]).centered());
+            }
+            last_sender = None;
         }
+        last_time = Some(msg.time);
 
         if msg.is_system {
             text_content.push(Line::from(vec![
                 Span::styled(format!("! {}", msg.content), Style::default().fg(Color::Magenta).bg(chat_bg_color)),
             ]));
             last_sender = None;
+        } else if ignored && app.selected_message != Some(index) {
+            // Collapsed; selecting it shows the message
+            text_content.push(Line::from(vec![
+                Span::styled(
+                    format!("   · message from {} hidden", msg.sender.as_deref().unwrap_or("Unknown")),
+                    Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC).bg(chat_bg_color),
+                ),
+            ]));
+            last_sender = None;
+        } else if let Some(target) = &msg.whisper_to {
+            // Whispers stand apart from the conversation around them
+            let sender = msg.sender.as_deref().unwrap_or("Unknown");
+            let lock = if msg.whisper_sealed { "🔒" } else { "" };
+            let unsigned = if msg.unsigned { ", unsigned" } else { "" };
+            let label = if msg.from_me { format!("(whisper{} to {}{}) ", lock, target, unsigned) } else { format!("(whisper{}) ", lock) };
+            let style = Style::default().fg(Color::LightMagenta).bg(chat_bg_color);
+            text_content.push(Line::from(""));
+            text_content.push(Line::from(vec![
+                Span::styled(label, style.add_modifier(Modifier::DIM)),
+                Span::styled(format!("{}: ", sender), style.add_modifier(Modifier::BOLD)),
+                Span::styled(msg.content.clone(), style.add_modifier(Modifier::ITALIC)),
+            ]));
+            last_sender = None;
         } else {
             // Group consecutive messages
             let is_consecutive = last_sender.as_ref() == msg.sender.as_ref();
             
+            let own_style = if msg.from_me { app.config.ui.own_messages } else { OwnMessageStyle::Plain };
             if !is_consecutive {
-                // Render User Header: [Username] ... [Time]
+                // Render User Header: [Avatar] Username ... [Time]
                 let sender_name = msg.sender.as_deref().unwrap_or("Unknown");
-                let timestamp = &msg.timestamp;
-                let prefix = " > ";
+                let timestamp = if app.show_timestamps { msg.timestamp() } else { String::new() };
+                let name_color = render::name_color(sender_name);
+                let avatar = render::avatar(sender_name);
                 
                 // Calculate space between name and timestamp
-                let content_len = prefix.len() + sender_name.len() + timestamp.len();
+                let content_len = 1 + render::display_width(&avatar) + 1 + render::display_width(sender_name) + render::display_width(&timestamp);
                 let spacer_len = inner_width.saturating_sub(content_len);
                 
-                text_content.push(Line::from("")); // Spacing between groups
-                text_content.push(Line::from(vec![
-                    // Prefix Symbol
-                    Span::styled(prefix, Style::default().fg(Color::Green).add_modifier(Modifier::BOLD).bg(chat_bg_color)),
+                let mut header = vec![
+                    Span::styled(" ", Style::default().bg(chat_bg_color)),
+                    // Initial in the sender's color
+                    Span::styled(avatar, Style::default().fg(Color::Black).bg(name_color).add_modifier(Modifier::BOLD)),
+                    Span::styled(" ", Style::default().bg(chat_bg_color)),
                     // Username
-                    Span::styled(sender_name, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD).bg(chat_bg_color)),
+                    Span::styled(sender_name, Style::default().fg(name_color).add_modifier(Modifier::BOLD).bg(chat_bg_color)),
                     // Spacer
                     Span::styled(" ".repeat(spacer_len), Style::default().bg(chat_bg_color)),
                     // Timestamp
                     Span::styled(timestamp, Style::default().fg(Color::DarkGray).bg(chat_bg_color)),
-                ]));
+                ];
+                // Right-aligned messages mirror the header: [Time] ... Username [Avatar]
+                if own_style == OwnMessageStyle::Right {
+                    header.reverse();
+                }
+                text_content.push(Line::from("")); // Spacing between groups
+                text_content.push(Line::from(header));
                 last_sender = msg.sender.clone();
             }
             
             // Render content 
             // Manual wrapping logic to preserve indentation on wrapped lines
-            let content = &msg.content;
+            let (content, body_style) = if msg.deleted {
+                ("(deleted)", Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC).bg(chat_bg_color))
+            } else if msg.is_action {
+                (msg.content.as_str(), Style::default().fg(Color::LightMagenta).add_modifier(Modifier::ITALIC).bg(chat_bg_color))
+            } else if own_style == OwnMessageStyle::Tint {
+                (msg.content.as_str(), Style::default().fg(OWN_MESSAGE_TINT).bg(chat_bg_color))
+            } else {
+                (msg.content.as_str(), Style::default().bg(chat_bg_color))
+            };
+            // Selected messages get a marker in the indentation gutter
+            let indent = if app.selected_message == Some(index) {
+                Span::styled(" ▌ ", Style::default().fg(Color::Yellow).bg(chat_bg_color))
+            } else {
+                Span::styled("   ", Style::default().bg(chat_bg_color))
+            };
+            // Quote the original above a reply
+            if let Some(parent_id) = &msg.reply_to {
+                let quote_style = Style::default().fg(Color::DarkGray).bg(chat_bg_color);
+                let quoted = match app.messages.iter().find(|m| m.id.as_ref() == Some(parent_id)) {
+                    Some(parent) if is_ignored(parent) => "(hidden)".to_string(),
+                    Some(parent) if !parent.deleted => format!(
+                        "{}: {}",
+                        parent.sender.as_deref().unwrap_or("Unknown"),
+                        snippet(&parent.content, inner_width.saturating_sub(8)),
+                    ),
+                    Some(_) => "(deleted)".to_string(),
+                    None => "original unavailable".to_string(),
+                };
+                text_content.push(Line::from(vec![
+                    Span::styled("   ┃ ", quote_style),
+                    Span::styled(quoted, quote_style.add_modifier(Modifier::ITALIC)),
+                ]));
+            }
+
+            let link_style = body_style.fg(Color::Blue).add_modifier(Modifier::UNDERLINED);
+            // IRC-style actions read "* alice waves"
+            let action_prefix = (msg.is_action && !msg.deleted)
+                .then(|| format!("* {}", msg.sender.as_deref().unwrap_or("Unknown")));
+            let first_line = text_content.len();
             let available_width = inner_width.saturating_sub(3); // 3 spaces indentation
             
             if available_width > 0 {
                 // Simple wrapping logic (char based for simplicity in TUI context, or could split by words)
                 // Ideally use textwrap crate but we don't have it.
                 // We'll iterate chars.
-                let mut current_line = String::new();
+                let mut current_line: Vec<Span> = Vec::new();
                 let mut current_width = 0;
+                if let Some(prefix) = &action_prefix {
+                    current_width = render::display_width(prefix);
+                    current_line.push(Span::styled(prefix.clone(), body_style.add_modifier(Modifier::BOLD)));
+                }
                 
-                for word in content.split_whitespace() {
-                    let word_len = word.chars().count();
+                // Widths are in terminal columns: CJK and emoji take two,
+                // combining marks none. Words wider than a line (long URLs,
+                // unspaced CJK) are broken between graphemes.
+                for word in content.split_whitespace().flat_map(|word| render::split_to_width(word, available_width)) {
+                    let word_len = render::display_width(word);
                     
                     if current_width + word_len + (if current_width > 0 { 1 } else { 0 }) > available_width {
                         // Flush current line
-                        text_content.push(Line::from(vec![
-                            Span::styled("   ", Style::default().bg(chat_bg_color)), 
-                            Span::styled(current_line.clone(), Style::default().bg(chat_bg_color)),
-                        ]));
-                        current_line.clear();
+                        let mut spans = vec![indent.clone()];
+                        spans.append(&mut current_line);
+                        text_content.push(Line::from(spans));
                         current_width = 0;
                     }
                     
                     if current_width > 0 {
-                        current_line.push(' ');
+                        current_line.push(Span::styled(" ", body_style));
                         current_width += 1;
                     }
-                    current_line.push_str(word);
+                    current_line.extend(styled_word(word, body_style, link_style));
                     current_width += word_len;
                 }
                 // Flush remaining
                 if !current_line.is_empty() {
-                    text_content.push(Line::from(vec![
-                        Span::styled("   ", Style::default().bg(chat_bg_color)), 
-                        Span::styled(current_line, Style::default().bg(chat_bg_color)),
-                    ]));
+                    let mut spans = vec![indent];
+                    spans.append(&mut current_line);
+                    text_content.push(Line::from(spans));
                 }
             } else {
                 // Fallback if width is too small
+                let content = match &action_prefix {
+                    Some(prefix) => format!("{} {}", prefix, content),
+                    None => content.to_string(),
+                };
+                text_content.push(Line::from(vec![
+                    indent,
+                    Span::styled(content, body_style),
+                ]));
+            }
+
+            if msg.edited && !msg.deleted && text_content.len() > first_line {
+                if let Some(last) = text_content.last_mut() {
+                    last.spans.push(Span::styled(" (edited)", Style::default().fg(Color::DarkGray).bg(chat_bg_color)));
+                }
+            }
+            let delivery_icon = match msg.delivery {
+                Some(DeliveryState::Queued) => Some((" ◷ queued", Color::DarkGray)),
+                Some(DeliveryState::Pending) => Some((" ◷", Color::DarkGray)),
+                Some(DeliveryState::Sent) => Some((" ✓", Color::DarkGray)),
+                Some(DeliveryState::Failed) => Some((" ⚠ not sent (R to retry)", Color::Red)),
+                None => None,
+            };
+            if let Some((icon, color)) = delivery_icon.filter(|_| text_content.len() > first_line) {
+                if let Some(last) = text_content.last_mut() {
+                    last.spans.push(Span::styled(icon, Style::default().fg(color).bg(chat_bg_color)));
+                }
+            }
+            if msg.unsigned && !msg.deleted && text_content.len() > first_line {
+                if let Some(last) = text_content.last_mut() {
+                    last.spans.push(Span::styled(" unsigned", Style::default().fg(Color::DarkGray).add_modifier(Modifier::DIM).bg(chat_bg_color)));
+                }
+            }
+            let pinned = msg.id.as_ref().is_some_and(|id| app.pinned_messages.contains(id));
+            if pinned && text_content.len() > first_line {
+                if let Some(last) = text_content.last_mut() {
+                    last.spans.push(Span::styled(" 📌", Style::default().bg(chat_bg_color)));
+                }
+            }
+            // Right-align by padding each finished line after its gutter, so
+            // wrapped lines and the status suffixes line up on the right edge
+            if own_style == OwnMessageStyle::Right {
+                for line in &mut text_content[first_line..] {
+                    let width: usize = line.spans.iter().map(|span| render::display_width(&span.content)).sum();
+                    let padding = inner_width.saturating_sub(width);
+                    line.spans.insert(1, Span::styled(" ".repeat(padding), Style::default().bg(chat_bg_color)));
+                }
+            }
+
+            // Thread badge
+            if let Some(count) = msg.id.as_deref().and_then(|id| reply_counts.get(id)) {
                 text_content.push(Line::from(vec![
-                    Span::styled("   ", Style::default().bg(chat_bg_color)), 
-                    Span::styled(content.clone(), Style::default().bg(chat_bg_color)),
+                    Span::styled("   ", Style::default().bg(chat_bg_color)),
+                    Span::styled(
+                        format!("💬 {} {}", count, if *count == 1 { "reply" } else { "replies" }),
+                        Style::default().fg(Color::Blue).bg(chat_bg_color),
+                    ),
                 ]));
             }
+
+            // Reaction summary, e.g. "👍 3  ❤️ 1"; your own reactions are highlighted
+            if !msg.reactions.is_empty() && !msg.deleted {
+                let mut spans = vec![Span::styled("   ", Style::default().bg(chat_bg_color))];
+                for (emoji, users) in &msg.reactions {
+                    let mine = app.current_username.as_ref().is_some_and(|me| users.contains(me));
+                    let style = if mine {
+                        Style::default().fg(Color::Cyan).bg(Color::Rgb(40, 40, 60))
+                    } else {
+                        Style::default().fg(Color::Gray).bg(Color::Rgb(40, 40, 40))
+                    };
+                    spans.push(Span::styled(format!(" {} {} ", emoji, users.len()), style));
+                    spans.push(Span::styled(" ", Style::default().bg(chat_bg_color)));
+                }
+                text_content.push(Line::from(spans));
+            }
         }
     }
 
@@ -3084,7 +6150,7 @@ fn render_in_room(f: &mut Frame, app: &mut App, chat_area: Rect, input_area: Rec
         } else {
             // How many rows does this line take when wrapped at inner_width?
             // Use rounding up division: (len + width - 1) / width
-            let rows = (content_len + inner_width - 1) / inner_width;
+            let rows = content_len.div_ceil(inner_width);
             total_visual_lines += rows.max(1);
         }
     }
@@ -3108,6 +6174,39 @@ fn render_in_room(f: &mut Frame, app: &mut App, chat_area: Rect, input_area: Rec
         VimMode::Insert => "INSERT",
     };
     
+    // "Replying to" bar in the gap row just above the composer
+    if let Some(parent_id) = &app.replying_to {
+        let quoted = match app.messages.iter().find(|m| m.id.as_ref() == Some(parent_id)) {
+            Some(parent) => format!(
+                "{}: {}",
+                parent.sender.as_deref().unwrap_or("Unknown"),
+                snippet(&parent.content, (input_area.width as usize).saturating_sub(40)),
+            ),
+            None => "original unavailable".to_string(),
+        };
+        let bar_area = Rect { y: input_area.y.saturating_sub(1), height: 1, ..input_area };
+        let bar = Paragraph::new(Line::from(vec![
+            Span::styled(" ↪ Replying to ", Style::default().fg(Color::Yellow)),
+            Span::styled(quoted, Style::default().fg(Color::Gray).add_modifier(Modifier::ITALIC)),
+            Span::styled("  (Esc to cancel)", Style::default().fg(Color::DarkGray)),
+        ]));
+        f.render_widget(Clear, bar_area);
+        f.render_widget(bar, bar_area);
+    }
+
+    // Attached image bar, above the reply bar when there's one
+    if let Some(image) = &app.pending_image {
+        let gap = if app.replying_to.is_some() { 2 } else { 1 };
+        let bar_area = Rect { y: input_area.y.saturating_sub(gap), height: 1, ..input_area };
+        let bar = Paragraph::new(Line::from(vec![
+            Span::styled(" 🖼 Attached ", Style::default().fg(Color::Yellow)),
+            Span::styled(image.label(), Style::default().fg(Color::Gray)),
+            Span::styled("  (Esc to remove)", Style::default().fg(Color::DarkGray)),
+        ]));
+        f.render_widget(Clear, bar_area);
+        f.render_widget(bar, bar_area);
+    }
+
     // Clear area behind the floating input
     f.render_widget(Clear, input_area);
     
@@ -3115,7 +6214,7 @@ fn render_in_room(f: &mut Frame, app: &mut App, chat_area: Rect, input_area: Rec
     let input_bg_color = Color::Rgb(45, 45, 45);
     
     // Floating Input Block style
-    let input_block = Block::default()
+    let mut input_block = Block::default()
         .borders(Borders::ALL)
         .title(format!(" {} ", vim_mode_str))
         .title_style(Style::default().fg(match app.vim_state.mode {
@@ -3125,23 +6224,419 @@ fn render_in_room(f: &mut Frame, app: &mut App, chat_area: Rect, input_area: Rec
         .border_style(Style::default().fg(Color::Gray)) // Lighter border
         .style(Style::default().bg(input_bg_color)); 
 
-    app.message_input.set_block(input_block);
-    app.message_input.set_cursor_style(Style::default().add_modifier(Modifier::REVERSED));
-    app.message_input.set_style(Style::default().fg(Color::White).bg(input_bg_color));
-    
-    f.render_widget(&app.message_input, input_area);
-    
-    // Removed "? for help" hint as requested
-    
-    // Render emoji picker overlay if active
-    if app.emoji_picker_active && !app.emoji_matches.is_empty() {
-        render_emoji_picker(f, app, input_area);
+    // Size counter once within 10% of the limit. Counted after encryption,
+    // which is what the server sees.
+    let limit = app.config.ui.max_message_length;
+    let size = composer_encrypted_len(app);
+    if size * 10 >= limit * 9 {
+        let color = if size > limit { Color::Red } else { Color::Yellow };
+        input_block = input_block.title(
+            Line::from(Span::styled(format!(" {}/{} bytes encrypted ", size, limit), Style::default().fg(color)))
+                .right_aligned(),
+        );
+    }
+
+    // Spell-check underlines come from the textarea's search pattern, so
+    // the text itself is never touched
+    if app.spellcheck_enabled {
+        if let Some(checker) = &mut app.spell {
+            let misspelled = checker.check_lines(app.message_input.lines());
+            let pattern = spell::highlight_pattern(&misspelled);
+            if app.message_input.search_pattern().map_or("", |re| re.as_str()) != pattern {
+                let _ = app.message_input.set_search_pattern(&pattern);
+            }
+        }
+    }
+
+    // The textarea only scrolls sideways, so draw its text soft-wrapped
+    // ourselves. One column is kept free for the cursor at the end of a full row.
+    let text_area = input_block.inner(input_area);
+    app.composer_width = (text_area.width as usize).saturating_sub(1).max(1);
+    let cursor_style = Style::default().add_modifier(Modifier::REVERSED);
+    let lines = app.message_input.lines();
+    let rows = composer::wrap(lines, app.composer_width);
+    let (cursor_row, _) = composer::locate(&rows, lines, app.message_input.cursor());
+    // Scroll just enough to keep the cursor row in view
+    let visible = (text_area.height as usize).max(1);
+    if cursor_row < app.composer_scroll {
+        app.composer_scroll = cursor_row;
+    } else if cursor_row >= app.composer_scroll + visible {
+        app.composer_scroll = cursor_row + 1 - visible;
+    }
+    app.composer_scroll = app.composer_scroll.min(rows.len().saturating_sub(visible));
+
+    let text = if lines.len() == 1 && lines[0].is_empty() {
+        vec![Line::from(vec![
+            Span::styled(" ", cursor_style),
+            Span::styled(app.message_input.placeholder_text().to_string(), Style::default().fg(Color::DarkGray)),
+        ])]
+    } else {
+        let misspelled = app.spellcheck_enabled.then(|| app.message_input.search_pattern()).flatten();
+        composer::styled_rows(
+            lines,
+            &rows,
+            Some(app.message_input.cursor()),
+            misspelled,
+            cursor_style,
+            Style::default().fg(Color::LightRed).add_modifier(Modifier::UNDERLINED),
+        )
+    };
+    let composer = Paragraph::new(text)
+        .block(input_block)
+        .style(Style::default().fg(Color::White).bg(input_bg_color))
+        .scroll((app.composer_scroll as u16, 0));
+    f.render_widget(composer, input_area);
+    
+    // Removed "? for help" hint as requested
+    
+    // Render emoji picker overlay if active
+    if app.emoji_picker_active && !app.emoji_matches.is_empty() {
+        render_emoji_picker(f, app, input_area);
+    }
+    
+    // Render user list overlay if active
+    if app.show_user_list {
+        render_user_list_overlay(f, app, f.area());
+    }
+
+    if let Some(root) = &app.thread_root {
+        render_thread_overlay(f, app, root, chat_area);
+    }
+
+    if app.show_pinned {
+        render_pinned_overlay(f, app, chat_area);
+    }
+
+    if app.show_room_info {
+        render_room_info_overlay(f, app, chat_area);
+    }
+}
+
+fn render_status_log_overlay(f: &mut Frame, app: &App, area: Rect) {
+    let overlay_width = (area.width * 4 / 5).max(20).min(area.width);
+    let overlay_height = (area.height * 4 / 5).max(5).min(area.height);
+    let overlay_area = Rect {
+        x: area.x + (area.width - overlay_width) / 2,
+        y: area.y + (area.height - overlay_height) / 2,
+        width: overlay_width,
+        height: overlay_height,
+    };
+    f.render_widget(Clear, overlay_area);
+
+    // Newest at the bottom; scrolling moves back in time
+    let visible = overlay_height.saturating_sub(2) as usize;
+    let end = app.status_log.len().saturating_sub(app.status_log_scroll);
+    let start = end.saturating_sub(visible);
+    let mut lines: Vec<Line> = app
+        .status_log
+        .range(start..end)
+        .map(|entry| {
+            Line::from(vec![
+                Span::styled(format!("{} ", entry.time), Style::default().fg(Color::DarkGray)),
+                Span::styled(entry.message.clone(), entry.level.style()),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::styled("Nothing yet", Style::default().fg(Color::DarkGray)));
+    }
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title("Status log - j/k scroll, Esc to close"),
+    );
+    f.render_widget(paragraph, overlay_area);
+}
+
+/// Optional parts of the client compiled into this build
+fn build_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "voice") {
+        features.push("voice");
+    }
+    features.push("notifications");
+    if cfg!(feature = "clipboard") {
+        features.push("clipboard");
+    }
+    features
+}
+
+fn render_about_overlay(f: &mut Frame, app: &App, area: Rect) {
+    let overlay_width = (area.width * 4 / 5).max(20).min(area.width);
+    let overlay_height = 10.min(area.height);
+    let overlay_area = Rect {
+        x: area.x + (area.width - overlay_width) / 2,
+        y: area.y + (area.height - overlay_height) / 2,
+        width: overlay_width,
+        height: overlay_height,
+    };
+    f.render_widget(Clear, overlay_area);
+
+    let field = |name: &'static str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<10}", name), Style::default().fg(Color::DarkGray)),
+            Span::raw(value),
+        ])
+    };
+    let config_path = Config::config_path().map(|path| path.display().to_string());
+    let images = match render::image::inline_protocol(app.config.ui.inline_images) {
+        Some(protocol) => format!("inline ({})", protocol.name()),
+        None if app.config.ui.inline_images => "placeholder (terminal can't draw them)".to_string(),
+        None => "placeholder (ui.inline_images is off)".to_string(),
+    };
+    let lines = vec![
+        field("Version", env!("CARGO_PKG_VERSION").to_string()),
+        field("Commit", env!("EURUS_GIT_COMMIT").to_string()),
+        field("Features", build_features().join(", ")),
+        field("Images", images),
+        field("Server", config::redact_url(&app.config.server.url)),
+        field("Config", config_path.unwrap_or_else(|| "(none)".to_string())),
+        Line::from(""),
+        Line::styled("Include this when reporting a bug", Style::default().fg(Color::DarkGray)),
+    ];
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(format!("About {} - Esc to close", env!("CARGO_PKG_NAME"))),
+    );
+    f.render_widget(paragraph, overlay_area);
+}
+
+/// Which optional terminal features are on, e.g. "mouse off, focus on, paste on"
+fn terminal_caps_summary(caps: TerminalCaps) -> String {
+    let on = |enabled: bool| if enabled { "on" } else { "off" };
+    format!("mouse {}, focus {}, paste {}", on(caps.mouse), on(caps.focus_change), on(caps.bracketed_paste))
+}
+
+fn render_diagnostics_overlay(f: &mut Frame, app: &App, area: Rect) {
+    let diag = &app.diagnostics;
+    let voice = &diag.voice;
+    let overlay_width = (area.width * 4 / 5).max(20).min(area.width);
+    let overlay_height = (voice.peers.len() as u16 + 16).min(area.height);
+    let overlay_area = Rect {
+        x: area.x + (area.width - overlay_width) / 2,
+        y: area.y + (area.height - overlay_height) / 2,
+        width: overlay_width,
+        height: overlay_height,
+    };
+    f.render_widget(Clear, overlay_area);
+
+    let field = |name: &'static str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<18}", name), Style::default().fg(Color::DarkGray)),
+            Span::raw(value),
+        ])
+    };
+    let heartbeat = match app.last_heartbeat {
+        Some(at) => format!("{:.1}s ago", at.elapsed().as_secs_f32()),
+        None if app.config.network.heartbeat_interval == 0 => "disabled".to_string(),
+        None => "none yet".to_string(),
+    };
+    let connection = if app.transport.is_some() {
+        "connected".to_string()
+    } else if app.connect_task.is_some() {
+        format!("connecting (attempt {})", app.reconnect_attempts + 1)
+    } else {
+        "disconnected".to_string()
+    };
+    let mut lines = vec![
+        field("Connection", connection),
+        field("Reconnects", diag.connections.saturating_sub(1).to_string()),
+        field("Heartbeat RTT", diag.rtt.map_or_else(|| "-".to_string(), |rtt| format!("{} ms", rtt.as_millis()))),
+        field("Last heartbeat", heartbeat),
+        field("Send queue", format!(
+            "{} on the socket, {} waiting in the outbox",
+            app.transport.as_ref().map_or(0, |transport| transport.queued()),
+            app.outbox.len(),
+        )),
+        field("Messages sent", diag.messages_sent.to_string()),
+        field("Messages received", diag.messages_received.to_string()),
+        field("Decrypt failures", diag.decrypt_failures.to_string()),
+        field("Terminal", terminal_caps_summary(app.terminal_caps)),
+        Line::from(""),
+        field("Voice peers", app.voice.connected_peers.len().to_string()),
+        field("Audio underruns", voice.underruns.to_string()),
+        field("Drift correction", format!("{} ms trimmed, {} ms padded", voice.trimmed_ms, voice.padded_ms)),
+    ];
+    for peer in &voice.peers {
+        lines.push(field(
+            "  peer",
+            format!("{}  loss {:.1}%  jitter {:.1} ms", short_room_id(&peer.peer_id), peer.loss_percent, peer.jitter_ms),
+        ));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::styled("Screenshot this for bug reports (F1 has the version)", Style::default().fg(Color::DarkGray)));
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title("Diagnostics - Esc to close"),
+    );
+    f.render_widget(paragraph, overlay_area);
+}
+
+fn render_pinned_overlay(f: &mut Frame, app: &App, area: Rect) {
+    let overlay_width = (area.width * 4 / 5).max(20).min(area.width);
+    let overlay_height = (app.pinned_messages.len() as u16 * 2 + 3).max(5).min(area.height);
+    let overlay_area = Rect {
+        x: area.x + (area.width - overlay_width) / 2,
+        y: area.y + (area.height - overlay_height) / 2,
+        width: overlay_width,
+        height: overlay_height,
+    };
+    f.render_widget(Clear, overlay_area);
+
+    let snippet_width = (overlay_width as usize).saturating_sub(6);
+    let mut lines: Vec<Line> = Vec::new();
+    // Newest pins first
+    for id in app.pinned_messages.iter().rev() {
+        match app.messages.iter().find(|m| m.id.as_ref() == Some(id)) {
+            Some(msg) => {
+                lines.push(Line::from(vec![
+                    Span::raw("📌 "),
+                    Span::styled(msg.sender.as_deref().unwrap_or("Unknown"), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+                    Span::styled(format!("  {} {}", msg.date(), msg.timestamp()), Style::default().fg(Color::DarkGray)),
+                ]));
+                let text = if msg.deleted { "(deleted)".to_string() } else { snippet(&msg.content, snippet_width) };
+                lines.push(Line::from(format!("   {}", text)));
+            }
+            // Pinned before our history window starts; we only have the id
+            None => {
+                lines.push(Line::styled("📌 (not in local history)", Style::default().fg(Color::DarkGray)));
+                lines.push(Line::from(""));
+            }
+        }
+    }
+    if lines.is_empty() {
+        lines.push(Line::styled("No pinned messages", Style::default().fg(Color::DarkGray)));
+    }
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(format!("Pinned ({}) - Esc to close", app.pinned_messages.len())),
+    );
+    f.render_widget(paragraph, overlay_area);
+}
+
+fn render_room_info_overlay(f: &mut Frame, app: &App, area: Rect) {
+    let overlay_width = (area.width * 4 / 5).max(20).min(area.width);
+    let overlay_height = 10.min(area.height);
+    let overlay_area = Rect {
+        x: area.x + (area.width - overlay_width) / 2,
+        y: area.y + (area.height - overlay_height) / 2,
+        width: overlay_width,
+        height: overlay_height,
+    };
+    f.render_widget(Clear, overlay_area);
+
+    let label = app.room_id.as_deref().and_then(|id| app.room_labels.label(id));
+    let field = |name: &'static str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<8}", name), Style::default().fg(Color::DarkGray)),
+            Span::raw(value),
+        ])
+    };
+    let lines = vec![
+        field("Name", app.room_display_name.clone().or_else(|| app.room_name.clone()).unwrap_or_default()),
+        field("Label", label.unwrap_or("(none, set with /label)").to_string()),
+        field("Room id", app.room_id.clone().unwrap_or_default()),
+        field("Notify", app.room_id.as_deref().map(|id| room_notify_level(app, id).name()).unwrap_or_default().to_string()),
+        field("Online", app.online_users.len().to_string()),
+        Line::from(""),
+        Line::styled("y copies the room id (yi outside this view)", Style::default().fg(Color::DarkGray)),
+    ];
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title("Room details - Esc to close"),
+    );
+    f.render_widget(paragraph, overlay_area);
+}
+
+fn render_thread_overlay(f: &mut Frame, app: &App, root: &str, area: Rect) {
+    let overlay_width = (area.width * 4 / 5).max(20).min(area.width);
+    let overlay_height = (area.height * 4 / 5).max(5).min(area.height);
+    let overlay_area = Rect {
+        x: area.x + (area.width - overlay_width) / 2,
+        y: area.y + (area.height - overlay_height) / 2,
+        width: overlay_width,
+        height: overlay_height,
+    };
+    f.render_widget(Clear, overlay_area);
+
+    let members = thread_members(&app.messages, root);
+    let mut lines: Vec<Line> = Vec::new();
+    for (n, i) in members.iter().enumerate() {
+        let msg = &app.messages[*i];
+        // Indent replies under the root
+        let prefix = if n == 0 { "" } else { "  ↳ " };
+        lines.push(Line::from(vec![
+            Span::raw(prefix),
+            Span::styled(msg.sender.as_deref().unwrap_or("Unknown"), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::styled(format!("  {}", msg.timestamp()), Style::default().fg(Color::DarkGray)),
+        ]));
+        let indent = if n == 0 { "  " } else { "      " };
+        if msg.deleted {
+            lines.push(Line::styled(format!("{}(deleted)", indent), Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)));
+        } else {
+            for text_line in msg.content.lines() {
+                lines.push(Line::from(format!("{}{}", indent, text_line)));
+            }
+        }
     }
-    
-    // Render user list overlay if active
-    if app.show_user_list {
-        render_user_list_overlay(f, app, f.area());
+
+    // Keep the newest replies in view
+    let inner_height = overlay_height.saturating_sub(2) as usize;
+    let scroll = lines.len().saturating_sub(inner_height) as u16;
+    let paragraph = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan))
+                .title(format!("Thread ({} messages) - r to reply, Esc to close", members.len())),
+        );
+    f.render_widget(paragraph, overlay_area);
+}
+
+/// Split a word into spans, styling any URLs inside it as links
+fn styled_word(word: &str, base: Style, link: Style) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for (start, end) in links::find_urls(word) {
+        if start > pos {
+            spans.push(Span::styled(word[pos..start].to_string(), base));
+        }
+        spans.push(Span::styled(word[start..end].to_string(), link));
+        pos = end;
+    }
+    if pos < word.len() {
+        spans.push(Span::styled(word[pos..].to_string(), base));
+    }
+    spans
+}
+
+/// First line of `text` in at most `max` columns, with an ellipsis if
+/// anything was left out
+fn snippet(text: &str, max: usize) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or("");
+    if lines.next().is_none() {
+        return render::truncate_chars(first, max);
     }
+    // More lines follow, so there's always an ellipsis
+    let cut = render::truncate_chars(first, max.saturating_sub(1));
+    if cut.ends_with('…') { cut } else { format!("{}…", cut) }
 }
 
 fn render_user_list_overlay(f: &mut Frame, app: &App, area: Rect) {
@@ -3199,24 +6694,8 @@ fn render_voice_sidebar(f: &mut Frame, app: &App, area: Rect) {
         VoiceConnectionStatus::Reconnecting => ("Reconnecting...", Style::default().fg(Color::Yellow)),
     };
 
-    // Mute status from VoiceState
-    let mute_label = if app.voice.is_muted { "Muted" } else { "Live" };
-    let mute_style = if app.voice.is_muted {
-        Style::default().fg(Color::Red)
-    } else {
-        Style::default().fg(Color::Cyan)
-    };
-
-    // TX activity - show "TX" if we transmitted recently (within 900ms)
-    let tx_active = app.voice.tx_last_time
-        .map(|ts| ts.elapsed() < std::time::Duration::from_millis(900))
-        .unwrap_or(false);
-    let tx_label = if tx_active { "TX" } else { "Quiet" };
-    let tx_style = if tx_active {
-        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
-    } else {
-        Style::default().fg(Color::DarkGray)
-    };
+    // Mute and transmit activity from VoiceState, as one mic indicator
+    let (mic_icon, mic_label, mic_style) = app.voice.mic_state().indicator();
 
     let mut items: Vec<ListItem> = vec![
         ListItem::new(Line::from(vec![
@@ -3224,12 +6703,8 @@ fn render_voice_sidebar(f: &mut Frame, app: &App, area: Rect) {
             Span::styled(connection_label, connection_style),
         ])),
         ListItem::new(Line::from(vec![
-            Span::styled("🎙 ", mute_style),
-            Span::styled(mute_label, mute_style),
-        ])),
-        ListItem::new(Line::from(vec![
-            Span::styled("⇅ ", tx_style),
-            Span::styled(tx_label, tx_style),
+            Span::styled(format!("{} ", mic_icon), mic_style),
+            Span::styled(mic_label, mic_style),
         ])),
     ];
 
@@ -3246,6 +6721,9 @@ fn render_voice_sidebar(f: &mut Frame, app: &App, area: Rect) {
         }
     }
 
+    items.push(ListItem::new(""));
+    items.push(ListItem::new("M mute · V leave").style(Style::default().fg(Color::DarkGray)));
+
     let list = List::new(items)
         .block(
             Block::default()
@@ -3258,6 +6736,42 @@ fn render_voice_sidebar(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(list, area);
 }
 
+/// Columns taken by the member sidebar
+const ROSTER_WIDTH: u16 = 24;
+
+fn render_roster_sidebar(f: &mut Frame, app: &App, area: Rect) {
+    let name_width = (area.width as usize).saturating_sub(4);
+    let items: Vec<ListItem> = app
+        .online_users
+        .iter()
+        .map(|user| {
+            let away = app.away_users.contains(user);
+            let (dot, style) = if away {
+                ("○ ", Style::default().fg(Color::DarkGray))
+            } else if Some(user) == app.current_username.as_ref() {
+                ("● ", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+            } else {
+                ("● ", Style::default().fg(render::name_color(user)))
+            };
+            let dot_color = if away { Color::DarkGray } else { Color::Green };
+            let name = if away { format!("{} (away)", user) } else { user.clone() };
+            ListItem::new(Line::from(vec![
+                Span::styled(dot, Style::default().fg(dot_color)),
+                Span::styled(render::truncate_chars(&name, name_width), style),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::LEFT)
+            .border_style(Style::default().fg(Color::DarkGray))
+            .title(format!(" Members ({}) ", app.online_users.len()))
+            .title_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(list, area);
+}
+
 fn render_emoji_picker(f: &mut Frame, app: &App, input_area: Rect) {
     // Position the picker above the input area
     let picker_height = (app.emoji_matches.len() as u16).min(8) + 2; // +2 for borders
@@ -3324,7 +6838,14 @@ fn render_room_switcher_overlay(f: &mut Frame, app: &App, area: Rect) {
         .map(|(i, room)| {
             let is_current = app.room_name.as_ref() == Some(&room.name);
             let marker = if is_current { "* " } else { "  " };
-            let content = format!("{}#{}", marker, room.display_name);
+            let mut content = match app.room_labels.label(&room.room_id) {
+                Some(label) => format!("{}{} (#{})", marker, label, room.display_name),
+                None => format!("{}#{}", marker, room.display_name),
+            };
+            if room_notify_level(app, &room.room_id) == NotifyLevel::Muted {
+                content.push_str(" 🔕");
+            }
+            let content = render::truncate_chars(&content, overlay_width.saturating_sub(2) as usize);
             
             let style = if i == app.switcher_selected_index {
                 Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
@@ -3349,16 +6870,27 @@ fn render_room_switcher_overlay(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(list, overlay_area);
 }
 
-fn render_registration_error(f: &mut Frame, area: Rect) {
+fn render_registration_error(f: &mut Frame, app: &App, area: Rect) {
+    let key_path = ssh::default_key_path()
+        .map_or_else(|| "~/.ssh/id_ed25519".to_string(), |path| path.display().to_string());
+    let (agent_hint, agent_command) = if app.using_agent {
+        ("ssh-agent is running but holds no keys. Add one with:", "  ssh-add ~/.ssh/<key>")
+    } else {
+        ("No ssh-agent found. To use one, start it and add a key:", "  eval \"$(ssh-agent)\" && ssh-add ~/.ssh/<key>")
+    };
     let text = Text::from(vec![
         Line::from(""),
         Line::from("No SSH keys found!").style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
         Line::from(""),
         Line::from("eurus requires an SSH key for authentication."),
         Line::from(""),
-        Line::from("To create one, run:"),
+        Line::from(format!("Press 'g' to generate an ed25519 key at {}", key_path)),
+        Line::from("or create one yourself:"),
         Line::from("  ssh-keygen -t ed25519").style(Style::default().fg(Color::Cyan)),
         Line::from(""),
+        Line::from(agent_hint),
+        Line::from(agent_command).style(Style::default().fg(Color::Cyan)),
+        Line::from(""),
         Line::from("Then press 'r' to retry or 'q' to quit."),
     ]);
     
@@ -3394,7 +6926,7 @@ fn render_key_selection(f: &mut Frame, app: &App, area: Rect) {
         .collect();
     
     let title = if app.using_agent {
-        "Select SSH Key (from ssh-agent) - [j/k] Navigate [Enter] Select [q] Quit"
+        "Select SSH Key (from ssh-agent + ~/.ssh) - [j/k] Navigate [Enter] Select [q] Quit"
     } else {
         "Select SSH Key (from ~/.ssh) - [j/k] Navigate [Enter] Select [q] Quit"
     };
@@ -3480,32 +7012,6 @@ fn render_registration_success(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(paragraph, area);
 }
 
-fn render_re_authenticate(f: &mut Frame, app: &App, area: Rect) {
-    let username = app.username_input.lines().join("");
-    let key_info = app.available_keys.get(app.selected_key_index)
-        .map(|k| format!("{} ({})", k.name, k.key_type))
-        .unwrap_or_else(|| "None".to_string());
-    
-    let text = Text::from(vec![
-        Line::from(""),
-        Line::from("Re-Authentication Required").style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-        Line::from(""),
-        Line::from(format!("Username: {}", username)).style(Style::default().fg(Color::Cyan)),
-        Line::from(format!("Key: {}", key_info)).style(Style::default().fg(Color::Green)),
-        Line::from(""),
-        Line::from("Your account exists. Please wait while we verify your identity..."),
-        Line::from(""),
-    ]);
-    
-    let paragraph = Paragraph::new(text)
-        .alignment(Alignment::Center)
-        .block(Block::default()
-            .borders(Borders::ALL)
-            .title("Re-Authentication")
-            .border_style(Style::default().fg(Color::Yellow)));
-    f.render_widget(paragraph, area);
-}
-
 fn render_passphrase_input(f: &mut Frame, app: &mut App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -3538,7 +7044,8 @@ fn render_passphrase_input(f: &mut Frame, app: &mut App, area: Rect) {
     f.render_widget(&app.passphrase_input, chunks[1]);
     
     // Help text
-    let help = Paragraph::new("Enter the passphrase to decrypt your SSH key.\nPress Enter to continue, Esc to go back.")
+    let purpose = if app.pending_rejoin.is_some() { "unlock the saved room key" } else { "decrypt your SSH key" };
+    let help = Paragraph::new(format!("Enter the passphrase to {}.\nPress Enter to continue, Esc to go back.", purpose))
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL).title("Help"));
     f.render_widget(help, chunks[2]);
@@ -3553,20 +7060,38 @@ fn render_help(f: &mut Frame, area: Rect) {
         Line::from("  :qq, :qa, :quit!     Force quit from anywhere"),
         Line::from("  :help, :h            Show this help screen"),
         Line::from("  :users, :u           Show online users in room"),
+        Line::from("  :roster              Show/hide the member sidebar (also U)"),
+        Line::from("  :timestamps, :ts     Show/hide message times (also T)"),
         Line::from("  :register, :reg      Start registration flow"),
+        Line::from("  :logout              Delete the saved login and remembered room"),
         Line::from("  :list, :l            Show room switcher (in room)"),
         Line::from("  :switch <room>, :s   Switch to room by name"),
         Line::from("  :share, :invite      Generate invite code for current room"),
         Line::from("  :join <code>, :j     Join room using invite code"),
         Line::from("  :rename <name>       Rename current room (owner only)"),
+        Line::from("  :info                Room details, including the full room id"),
+        Line::from("  :label [name]        Label this room locally (also /label); no name clears"),
+        Line::from("  :color [color]       Accent color for this room's label (also /color)"),
+        Line::from("  :notify [level]      Notify for all, mentions or none in this room; default follows config"),
         Line::from("  :delete              Delete current room (owner only)"),
         Line::from("  :transfer <user>     Transfer ownership (owner only)"),
         Line::from("  :dm <username>       Start a direct message chat"),
+        Line::from("  :ignore [user]       Hide a user's messages and mute their voice; no user lists them"),
+        Line::from("  :unignore <user>     Stop ignoring a user"),
+        Line::from("  /me <action>         Send an action, shown as \"* you <action>\""),
+        Line::from("  /w <user> <message>  Whisper to one member (sealed to their SSH key if known)"),
+        Line::from("  :edit, :e            Edit selected (or your last) message"),
+        Line::from("  :del, :rm            Delete selected (or your last) message"),
+        Line::from("  :react <emoji>, :r   Toggle a reaction on selected/last message"),
+        Line::from("  :pin, :unpin         Pin/unpin selected (or last) message"),
+        Line::from("  F12, :diag, :net     Connection, message and voice counters (any screen)"),
         Line::from(""),
         Line::from("MAIN MENU").style(Style::default().add_modifier(Modifier::BOLD)),
         Line::from("  c                    Create a new room"),
         Line::from("  j                    Join / browse rooms"),
+        Line::from("  r                    Rejoin the last room"),
         Line::from("  :                    Enter command mode"),
+        Line::from("  Esc                  Quit"),
         Line::from(""),
         Line::from("ROOM LIST").style(Style::default().add_modifier(Modifier::BOLD)),
         Line::from("  j/k or Up/Down       Navigate rooms"),
@@ -3578,12 +7103,28 @@ fn render_help(f: &mut Frame, area: Rect) {
         Line::from("IN ROOM (Vim Mode)").style(Style::default().add_modifier(Modifier::BOLD)),
         Line::from("  i, a, A, I, o, O     Enter insert mode"),
         Line::from("  Esc                  Exit to normal mode"),
+        Line::from("  Esc (normal mode)    Leave voice call, then the room"),
         Line::from("  h/j/k/l              Move cursor"),
         Line::from("  w/b                  Word forward/back"),
         Line::from("  0/$                  Line start/end"),
         Line::from("  gg/G                 Document top/bottom"),
         Line::from("  dd                   Delete line"),
+        Line::from("  J/K                  Select next/previous message"),
+        Line::from("  r                    Reply to selected message"),
+        Line::from("  Enter (on selection) Open thread view"),
+        Line::from("  P                    Show pinned messages"),
+        Line::from("  gx                   Open link in selected message"),
+        Line::from("  yc                   Copy code block from selected message"),
+        Line::from("  F1, :about           Version and build info (any screen)"),
+        Line::from("  F2                   Show status log (any screen)"),
+        Line::from("  F7 / zg              Toggle spell-check / add word to dictionary"),
+        Line::from("  R                    Retry a failed message"),
+        Line::from("  V                    Join/leave voice chat"),
+        Line::from("  U                    Show/hide room members"),
+        Line::from("  T                    Show/hide message times"),
+        Line::from("  M                    Mute/unmute microphone (in call)"),
         Line::from("  yy                   Yank (copy) line"),
+        Line::from("  yi                   Copy the full room id"),
         Line::from("  p                    Paste"),
         Line::from("  u / Ctrl+r           Undo / Redo"),
         Line::from("  Enter                Send message"),
@@ -3606,17 +7147,11 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
     // Show command input if in command mode, otherwise show status message
     let (text, style) = if let Some(ref cmd) = app.command_input {
         (format!(":{}", cmd), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+    } else if let Some(label) = &app.pending_operation {
+        // Animate while something is in flight so the UI never looks frozen
+        (format!("{} {}", app.spinner.frame(), label), Style::default().fg(Color::Yellow))
     } else {
-        let status = &app.status_message;
-        
-        // Suppress duplicate mode messages in the footer
-        if status == "-- NORMAL --" || status == "-- INSERT --" {
-            ("".to_string(), Style::default())
-        } else if status.to_lowercase().contains("error") || status.to_lowercase().contains("unknown") {
-            (status.clone(), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
-        } else {
-            (status.clone(), Style::default().fg(Color::White))
-        }
+        (app.status_message.clone(), app.status_level.style())
     };
     
     // Render without block to keep it minimal
@@ -3629,25 +7164,1203 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
 
 // --- Terminal Helper Functions ---
 
-fn init_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>, Box<dyn Error>> {
+/// Push the current window title onto the terminal's title stack (xterm
+/// extension, widely supported) so restore_terminal can put it back
+fn save_terminal_title() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[22;0t")?;
+    stdout.flush()
+}
+
+fn copy_room_id(app: &mut App<'_>) {
+    let Some(room_id) = app.room_id.clone() else {
+        return;
+    };
+    match app.clipboard.as_mut().map(|clipboard| clipboard.copy_text(&room_id)) {
+        Some(Ok(())) => app.set_status(format!("Copied room id {}", short_room_id(&room_id)), StatusLevel::Info),
+        Some(Err(e)) => app.set_status(format!("Failed to copy: {}", e), StatusLevel::Error),
+        None => app.set_status(clipboard::UNAVAILABLE, StatusLevel::Warn),
+    }
+}
+
+/// Day separator text, like "Tuesday, March 4"; the year is added for days
+/// outside the current one
+fn day_label(day: chrono::NaiveDate, today: chrono::NaiveDate) -> String {
+    use chrono::Datelike;
+    if day.year() == today.year() {
+        day.format("%A, %B %-d").to_string()
+    } else {
+        day.format("%A, %B %-d, %Y").to_string()
+    }
+}
+
+/// Widest a room or user name gets in the header, window title and
+/// notifications
+const MAX_LABEL_WIDTH: usize = 32;
+
+/// Name to show for the current room: the user's label, else the server's
+/// display name, else a shortened room id
+fn room_title(app: &App) -> String {
+    let label = app.room_id.as_deref().and_then(|id| app.room_labels.label(id));
+    match (label, &app.room_display_name, &app.room_id) {
+        (Some(label), _, _) => label.to_string(),
+        (None, Some(name), _) => name.clone(),
+        (None, None, Some(id)) => short_room_id(id),
+        (None, None, None) => "Unknown".to_string(),
+    }
+}
+
+/// Set the playback volume of every voice peer signed in as `username`
+fn set_voice_volume_for(app: &App, username: &str, volume: f32) {
+    let Some(voice_tx) = &app.voice_tx else { return };
+    for (peer_id, _) in app.voice_peer_names.iter().filter(|(_, name)| *name == username) {
+        let _ = voice_tx.send(VoiceCommand::SetPeerVolume { peer_id: peer_id.clone(), volume });
+    }
+}
+
+/// Notification level for a room: its own setting, else `ui.notifications`
+fn room_notify_level(app: &App, room_id: &str) -> NotifyLevel {
+    app.room_labels.notify(room_id).unwrap_or(app.config.ui.notifications)
+}
+
+/// Whether a message in `room_id` should raise a notification
+fn should_notify(app: &App, room_id: &str, text: &str) -> bool {
+    match room_notify_level(app, room_id) {
+        NotifyLevel::All => true,
+        NotifyLevel::Mentions => app.current_username.as_deref().is_some_and(|name| mentions(text, name)),
+        NotifyLevel::Muted => false,
+    }
+}
+
+/// Whether `text` names `username` as a whole word, with or without a
+/// leading '@', ignoring case
+fn mentions(text: &str, username: &str) -> bool {
+    if username.is_empty() {
+        return false;
+    }
+    let username = username.to_lowercase();
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .any(|word| word == username)
+}
+
+/// Reflect the room and unread count in the terminal title, e.g.
+/// "eurus — #general (2)". Only writes when the title changes.
+fn update_terminal_title(app: &mut App<'_>) {
+    if !app.config.ui.terminal_title {
+        return;
+    }
+    let label = app.room_id.as_deref().and_then(|id| app.room_labels.label(id));
+    let room = match (label, &app.room_name) {
+        (Some(label), _) => Some(render::truncate_chars(label, MAX_LABEL_WIDTH)),
+        (None, Some(name)) => Some(format!("#{}", render::truncate_chars(name, MAX_LABEL_WIDTH))),
+        (None, None) => None,
+    };
+    let title = match (room, app.current_screen == CurrentScreen::InRoom) {
+        (Some(room), true) if app.unread_count > 0 => format!("eurus — {} ({})", room, app.unread_count),
+        (Some(room), true) => format!("eurus — {}", room),
+        _ => "eurus".to_string(),
+    };
+    if app.terminal_title.as_ref() != Some(&title) {
+        let _ = execute!(io::stdout(), SetTitle(&title));
+        app.terminal_title = Some(title);
+    }
+}
+
+/// Optional terminal features that init_terminal turned on. Restricted
+/// terminals refuse some of them; code relying on one checks here first.
+#[derive(Debug, Clone, Copy, Default)]
+struct TerminalCaps {
+    mouse: bool,           // Mouse events are reported (only tried with `ui.mouse`)
+    focus_change: bool,    // FocusGained/FocusLost are reported
+    bracketed_paste: bool, // Pastes arrive as one Event::Paste rather than keystrokes
+}
+
+/// The terminal can't run the TUI at all
+#[derive(Debug)]
+struct TerminalUnsupported {
+    feature: &'static str,
+    source: io::Error,
+}
+
+impl std::fmt::Display for TerminalUnsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "this terminal doesn't support {} ({}). Run eurus in an interactive terminal, or use `eurus send` from scripts.",
+            self.feature, self.source
+        )
+    }
+}
+
+impl Error for TerminalUnsupported {}
+
+/// Raw mode and the alternate screen are required; focus reporting,
+/// bracketed paste and (if `mouse`) mouse capture are used when the terminal
+/// accepts them
+fn init_terminal(mouse: bool) -> Result<(Terminal<CrosstermBackend<io::Stdout>>, TerminalCaps), TerminalUnsupported> {
     let mut stdout = io::stdout();
-    // Removed EnableMouseCapture to allow native terminal selection
-    execute!(stdout, EnterAlternateScreen, EnableFocusChange)?;
-    enable_raw_mode()?;
-    let backend = CrosstermBackend::new(stdout);
-    let terminal = Terminal::new(backend)?;
-    Ok(terminal)
+    enable_raw_mode().map_err(|source| TerminalUnsupported { feature: "raw mode", source })?;
+    execute!(stdout, EnterAlternateScreen).map_err(|source| TerminalUnsupported { feature: "the alternate screen", source })?;
+    let caps = TerminalCaps {
+        mouse: mouse && execute!(stdout, EnableMouseCapture).is_ok(),
+        focus_change: execute!(stdout, EnableFocusChange).is_ok(),
+        bracketed_paste: execute!(stdout, EnableBracketedPaste).is_ok(),
+    };
+    MOUSE_CAPTURED.store(caps.mouse, Ordering::SeqCst);
+    let terminal = Terminal::new(CrosstermBackend::new(stdout))
+        .map_err(|source| TerminalUnsupported { feature: "size queries", source })?;
+    Ok((terminal, caps))
+}
+
+/// Set once the terminal has been restored, so the guard, the panic hook and
+/// any explicit call only do it once
+static TERMINAL_RESTORED: AtomicBool = AtomicBool::new(false);
+
+/// Whether init_terminal turned on mouse capture, for restore_terminal
+static MOUSE_CAPTURED: AtomicBool = AtomicBool::new(false);
+
+/// Restores the terminal when dropped
+struct TerminalGuard {
+    restore_title: bool,  // Whether save_terminal_title pushed a title to pop
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore_terminal(self.restore_title);
+    }
 }
 
-fn restore_terminal(
-    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-) -> Result<(), Box<dyn Error>> {
+/// Restore the terminal before the default hook prints, so the panic message
+/// lands on the normal screen instead of the alternate one
+fn install_panic_hook(restore_title: bool) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal(restore_title);
+        default_hook(info);
+    }));
+}
+
+fn restore_terminal(restore_title: bool) -> io::Result<()> {
+    if TERMINAL_RESTORED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
     disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen
-        // Removed DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    if MOUSE_CAPTURED.load(Ordering::SeqCst) {
+        execute!(stdout, DisableMouseCapture)?;
+    }
+    execute!(stdout, LeaveAlternateScreen, DisableFocusChange, DisableBracketedPaste, cursor::Show)?;
+    if restore_title {
+        // Pop the title saved by save_terminal_title
+        write!(stdout, "\x1b[23;0t")?;
+        stdout.flush()?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QueueFullPolicy;
+    use crate::transport::{ChannelTransport, ServerEnd};
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// Drive the network side of the app loop until `done` holds
+    async fn pump_until(
+        app: &mut App<'_>,
+        connection_rx: &mut mpsc::UnboundedReceiver<(u64, ConnectionEvent)>,
+        done: impl Fn(&App<'_>) -> bool,
+    ) {
+        let wait = async {
+            while !done(app) {
+                process_network_events(app, connection_rx);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait).await.expect("timed out driving the app");
+    }
+
+    /// A transport whose other end the test holds
+    fn test_transport() -> (Box<dyn Transport>, ServerEnd) {
+        let (transport, server) = ChannelTransport::pair(256, QueueFullPolicy::Reject);
+        (Box::new(transport), server)
+    }
+
+    fn press(app: &mut App<'_>, code: KeyCode) -> Vec<Action> {
+        app.handle_key(event::KeyEvent::new(code, event::KeyModifiers::NONE))
+    }
+
+    fn sent_type(action: &Action) -> String {
+        match action {
+            Action::Send(json) => serde_json::from_str::<serde_json::Value>(json).unwrap()["type"].to_string(),
+            other => panic!("expected a send, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_create_and_join_transitions() {
+        let mut app = App {
+            transport: Some(test_transport().0),
+            current_screen: CurrentScreen::RoomChoice,
+            ..App::default()
+        };
+
+        // Create: choose type, name it, then join it
+        assert!(press(&mut app, KeyCode::Char('c')).is_empty());
+        assert_eq!(app.current_screen, CurrentScreen::RoomTypeSelection);
+        press(&mut app, KeyCode::Tab);
+        assert!(app.selected_room_type);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.current_screen, CurrentScreen::CreateRoomInput);
+        assert!(press(&mut app, KeyCode::Enter).is_empty(), "empty names are rejected");
+        for c in "lounge".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        let actions = press(&mut app, KeyCode::Enter);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(sent_type(&actions[0]), "\"createRoom\"");
+        assert_eq!(app.current_screen, CurrentScreen::RoomCreation);
+
+        app.room_name = Some("lounge".to_string());
+        let actions = press(&mut app, KeyCode::Enter);
+        assert_eq!(sent_type(&actions[0]), "\"joinRoom\"");
+        assert_eq!(app.current_screen, CurrentScreen::InRoom);
+    }
+
+    #[test]
+    fn test_room_menu_back_paths() {
+        let mut app = App { current_screen: CurrentScreen::RoomChoice, ..App::default() };
+
+        // ':' opens the command line from the menus
+        assert!(press(&mut app, KeyCode::Char(':')).is_empty());
+        assert_eq!(app.command_input.as_deref(), Some(""));
+        app.command_input = None;
+
+        // Esc backs out of each step to the menu
+        press(&mut app, KeyCode::Char('c'));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+        press(&mut app, KeyCode::Char('c'));
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.current_screen, CurrentScreen::CreateRoomInput);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+
+        // Creating without a connection keeps the name for a retry
+        press(&mut app, KeyCode::Char('c'));
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Char('x'));
+        assert!(press(&mut app, KeyCode::Enter).is_empty());
+        assert_eq!(app.current_screen, CurrentScreen::CreateRoomInput);
+        assert_eq!(app.status_message, "Not connected to the server.");
+        assert_eq!(app.room_name_input.lines().join(""), "x");
+
+        // A failed create has nothing to join: Enter goes back to the menu
+        app.current_screen = CurrentScreen::RoomCreation;
+        app.room_name = None;
+        assert!(press(&mut app, KeyCode::Enter).is_empty());
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+        app.current_screen = CurrentScreen::RoomCreation;
+        press(&mut app, KeyCode::Char('q'));
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+
+        // ':' is text on the join form, and other screens ignore handle_key
+        app.current_screen = CurrentScreen::JoinRoomInput;
+        press(&mut app, KeyCode::Char(':'));
+        assert!(app.command_input.is_none());
+        app.current_screen = CurrentScreen::InRoom;
+        assert!(press(&mut app, KeyCode::Char('x')).is_empty());
+    }
+
+    #[test]
+    fn test_voice_join_needs_a_live_connection() {
+        let (voice_tx, mut voice_rx) = mpsc::unbounded_channel();
+        let mut app = App { voice_tx: Some(voice_tx), room_id: Some("r1".to_string()), ..App::default() };
+        join_voice(&mut app, "r1".to_string());
+        assert_eq!(app.status_message, "Cannot join voice: not connected to server.");
+        assert!(voice_rx.try_recv().is_err());
+
+        // The last echo is stale: probe first, join once it's answered.
+        // A freshly booted host can't go back 30s, so skip the rest there.
+        let Some(stale) = std::time::Instant::now().checked_sub(Duration::from_secs(30)) else { return };
+        let (transport, server) = test_transport();
+        app.transport = Some(transport);
+        app.last_heartbeat = Some(stale);
+        join_voice(&mut app, "r1".to_string());
+        assert_eq!(sent_type(&Action::Send(server.outgoing.try_pop().unwrap())), "\"heartbeat\"");
+        assert!(voice_rx.try_recv().is_err());
+        let seq = app.heartbeat_seq;
+        handle_server_message(&mut app, ServerMessage::Heartbeat(HeartbeatPayload { seq }));
+        assert!(matches!(voice_rx.try_recv(), Ok(VoiceCommand::Join(room)) if room == "r1"));
+
+        // Losing the socket mid-call leaves the call waiting on it
+        app.voice.status = VoiceConnectionStatus::Connected;
+        connection_lost(&mut app);
+        assert_eq!(app.voice.status, VoiceConnectionStatus::Reconnecting);
+    }
+
+    #[test]
+    fn test_auto_join_voice_on_entry() {
+        let joined = |room_id: &str| {
+            serde_json::from_value::<ServerMessage>(serde_json::json!({
+                "type": "roomJoined",
+                "payload": {
+                    "roomId": room_id, "roomName": room_id, "displayName": room_id,
+                    "roomType": "public", "encryptedKey": "", "messages": [],
+                },
+            }))
+            .unwrap()
+        };
+        let (voice_tx, mut voice_rx) = mpsc::unbounded_channel();
+        let mut app = App { transport: Some(test_transport().0), voice_tx: Some(voice_tx), ..App::default() };
+        app.config.audio.auto_join_rooms = vec!["radio".to_string()];
+
+        handle_server_message(&mut app, joined("lobby"));
+        assert!(voice_rx.try_recv().is_err());
+
+        handle_server_message(&mut app, joined("radio"));
+        assert!(matches!(voice_rx.try_recv(), Ok(VoiceCommand::Join(room)) if room == "radio"));
+        assert_eq!(app.voice.status, VoiceConnectionStatus::Connecting, "shown before the manager reports");
+
+        // Rejoining after a reconnect doesn't join again
+        app.voice.reset();
+        handle_server_message(&mut app, joined("radio"));
+        assert!(voice_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_message_limit_drops_oldest() {
+        let mut app = App::default();
+        app.config.ui.message_limit = 10;
+        for i in 0..12 {
+            app.messages.push(ChatMessage::system(format!("m{}", i)));
+        }
+        app.selected_message = Some(5);
+        app.code_copy_cycle = Some((1, 2));
+        enforce_message_limit(&mut app);
+
+        assert_eq!(app.messages.len(), 10);
+        assert_eq!(app.messages[0].content, "m2");
+        assert_eq!(app.selected_message, Some(3));
+        assert_eq!(app.code_copy_cycle, None);
+    }
+
+    #[test]
+    fn test_room_names_are_sanitized() {
+        let event = |value: serde_json::Value| serde_json::from_value::<ServerMessage>(value).unwrap();
+        let evil = "lobby\x1b]0;pwned\x07";
+        let mut app = App { transport: Some(test_transport().0), ..App::default() };
+
+        handle_server_message(&mut app, event(serde_json::json!({"type": "roomJoined", "payload": {
+            "roomId": "r1", "roomName": evil, "displayName": evil,
+            "roomType": "public", "encryptedKey": "", "messages": [],
+        }})));
+        assert_eq!(app.room_name.as_deref(), Some("lobby"));
+        assert_eq!(app.room_display_name.as_deref(), Some("lobby"));
+
+        handle_server_message(&mut app, event(serde_json::json!({"type": "roomRenamed", "payload": {
+            "roomId": "r1", "newName": evil, "displayName": evil,
+        }})));
+        assert!(!app.room_name.as_deref().unwrap().contains('\x1b'));
+        assert!(app.messages.iter().all(|m| !m.content.contains('\x1b')));
+
+        let room = serde_json::json!({
+            "roomId": "r2", "name": evil, "displayName": evil,
+            "roomType": "public", "memberCount": 1, "isJoined": false,
+        });
+        handle_server_message(&mut app, event(serde_json::json!({"type": "roomsList", "payload": {
+            "publicRooms": [room.clone()], "privateRooms": [room],
+        }})));
+        for room in app.public_rooms.iter().chain(&app.private_rooms) {
+            assert!(!room.name.contains('\x1b') && !room.display_name.contains('\x07'));
+        }
+
+        // Notices that quote a username go through the same door
+        app.room_id = Some("r1".to_string());
+        app.messages.clear();
+        handle_server_message(&mut app, event(serde_json::json!({"type": "pin", "payload": {
+            "roomId": "r1", "messageId": "m1", "pinned": true, "username": evil,
+        }})));
+        handle_server_message(&mut app, event(serde_json::json!({"type": "ownershipTransferred", "payload": {
+            "newOwnerUsername": evil, "newOwnerId": "u2",
+        }})));
+        let notices: Vec<&str> = app.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(notices, ["lobby pinned a message", "Room ownership transferred to lobby"]);
+    }
+
+    #[tokio::test]
+    async fn test_leave_room_hangs_up_first() {
+        let (transport, server) = test_transport();
+        let (voice_tx, mut voice_rx) = mpsc::unbounded_channel();
+        let mut app = App {
+            transport: Some(transport),
+            voice_tx: Some(voice_tx),
+            room_id: Some("r1".to_string()),
+            current_screen: CurrentScreen::InRoom,
+            ..App::default()
+        };
+        app.voice.status = VoiceConnectionStatus::Connected;
+
+        // Stands in for the voice manager: by the time it's asked to leave,
+        // peers must already have been told over the socket
+        let outgoing = server.outgoing.clone();
+        let manager = tokio::spawn(async move {
+            let Some(VoiceCommand::LeaveAndNotify(done)) = voice_rx.recv().await else {
+                panic!("expected a leave that reports back");
+            };
+            let signal: serde_json::Value = serde_json::from_str(&outgoing.try_pop().unwrap()).unwrap();
+            assert_eq!(signal["payload"]["type"], "leave_voice");
+            done.send(()).unwrap();
+        });
+
+        leave_room(&mut app).await;
+        manager.await.unwrap();
+        assert_eq!(app.voice.status, VoiceConnectionStatus::Disconnected);
+        assert_eq!(app.room_id, None);
+        assert!(app.transport.is_some(), "the socket outlives the room");
+    }
+
+    #[test]
+    fn test_join_pasted_link() {
+        let mut app = App { current_screen: CurrentScreen::RoomChoice, ..App::default() };
+        assert!(app.join_pasted("hello there: friend").is_empty());
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let actions = app.join_pasted(&format!("radiochat://join/abc123#key={}", hex));
+        assert_eq!(sent_type(&actions[0]), "\"joinRoom\"");
+        assert_eq!(app.current_screen, CurrentScreen::InRoom);
+        assert_eq!(app.supplied_room_key, Some(("abc123".to_string(), key_from_hex(hex).unwrap())));
+    }
+
+    #[test]
+    fn test_rejoin_last_room() {
+        use ssh_key::private::{Ed25519Keypair, PrivateKey};
+        let me = PrivateKey::from(Ed25519Keypair::from_seed(&[1; 32]));
+        let mut app = App { current_screen: CurrentScreen::RoomChoice, ..App::default() };
+        assert!(press(&mut app, KeyCode::Char('r')).is_empty(), "nothing remembered yet");
+
+        let room_key = generate_key();
+        let mut room = SavedRoom {
+            server_url: app.config.server.url.clone(),
+            room_id: "abc123".to_string(),
+            name: "standup".to_string(),
+            sealed_key: None,
+            key_needed: true,
+            key_in_secrets: false,
+        };
+        room.seal_key(&room_key, &me.public_key().to_openssh().unwrap()).unwrap();
+        app.last_room.set(room.clone()).unwrap();
+        app.whisper_keys = Some(vec![me]);
+        let actions = press(&mut app, KeyCode::Char('r'));
+        assert_eq!(sent_type(&actions[0]), "\"joinRoom\"");
+        assert_eq!(app.supplied_room_key, Some(("abc123".to_string(), room_key)));
+
+        // A key nothing could seal has to be typed in again
+        app.current_screen = CurrentScreen::RoomChoice;
+        app.last_room.set(SavedRoom { sealed_key: None, key_needed: true, ..room.clone() }).unwrap();
+        assert!(press(&mut app, KeyCode::Char('r')).is_empty());
+        assert_eq!(app.current_screen, CurrentScreen::JoinRoomInput);
+        assert_eq!(app.room_id_input.lines().join(""), "abc123");
+
+        // Rooms on another server aren't offered
+        app.current_screen = CurrentScreen::RoomChoice;
+        app.last_room.set(SavedRoom { server_url: "wss://elsewhere.example.com".to_string(), ..room }).unwrap();
+        assert!(press(&mut app, KeyCode::Char('r')).is_empty());
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+    }
+
+    #[test]
+    fn test_paste_credentials_into_join_form() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let mut app = App { current_screen: CurrentScreen::RoomChoice, ..App::default() };
+        press(&mut app, KeyCode::Char('i'));
+        handle_paste(&mut app, &format!("ID: abc123 / Key: {}\n", hex));
+        assert_eq!(app.room_id_input.lines(), ["abc123"]);
+        assert_eq!(app.room_key_input.lines(), [hex]);
+        assert_eq!(app.currently_editing, Some(CurrentlyEditing::RoomKey));
+
+        // No key in the paste: it's just the id
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('i'));
+        handle_paste(&mut app, "  def456\n");
+        assert_eq!(app.room_id_input.lines(), ["def456"]);
+        assert_eq!(app.room_key_input.lines(), [""]);
+        assert_eq!(app.currently_editing, Some(CurrentlyEditing::RoomId));
+    }
+
+    #[test]
+    fn test_join_form_masks_key() {
+        let mut app = App { current_screen: CurrentScreen::RoomChoice, ..App::default() };
+        press(&mut app, KeyCode::Char('i'));
+        assert_eq!(app.current_screen, CurrentScreen::JoinRoomInput);
+        for c in "abc:1".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Tab);
+        for c in "00ff".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert_eq!(app.room_id_input.lines(), ["abc:1"]);
+        assert_eq!(app.room_key_input.lines(), ["00ff"], "masking keeps the real value");
+        assert_eq!(app.room_key_input.mask_char(), Some(KEY_MASK));
+
+        app.handle_key(event::KeyEvent::new(KeyCode::Char('r'), event::KeyModifiers::CONTROL));
+        assert_eq!(app.room_key_input.mask_char(), None);
+        assert!(press(&mut app, KeyCode::Enter).is_empty(), "short keys are rejected");
+        assert_eq!(app.status_message, "Room key: 4 hex characters, need 64");
+
+        // Ctrl+T checks the form without sending anything
+        let check = |app: &mut App| {
+            assert!(app.handle_key(event::KeyEvent::new(KeyCode::Char('t'), event::KeyModifiers::CONTROL)).is_empty());
+            app.status_message.clone()
+        };
+        assert_eq!(check(&mut app), "Room key: 4 hex characters, need 64");
+        app.room_key_input.insert_str("zz");
+        assert_eq!(check(&mut app), "Room key: 'z' at position 5 isn't a hex digit");
+        app.room_key_input = room_key_input();
+        app.room_key_input.insert_str("00".repeat(32));
+        assert_eq!(check(&mut app), "Invalid room id can't contain ':'");
+        app.room_id_input = room_id_input();
+        app.room_id_input.insert_str("abc1");
+        assert!(check(&mut app).ends_with(&key_fingerprint(&key_from_hex(&"00".repeat(32)).unwrap())));
+        assert_eq!(app.current_screen, CurrentScreen::JoinRoomInput);
+
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.current_screen, CurrentScreen::RoomChoice);
+        assert_eq!(app.room_key_input.lines(), [""]);
+        assert_eq!(app.room_key_input.mask_char(), Some(KEY_MASK), "leaving hides the key again");
+    }
+
+    #[tokio::test]
+    async fn test_server_error_codes() {
+        let error = |json: &str| serde_json::from_str::<ServerMessage>(json).unwrap();
+        let (connection_tx, _connection_rx) = mpsc::unbounded_channel();
+        let mut app = App {
+            connection_tx: Some(connection_tx),
+            current_screen: CurrentScreen::InRoom,
+            room_id: Some("room".into()),
+            ..App::default()
+        };
+
+        handle_server_message(&mut app, error(r#"{"type":"error","payload":{"message":"Slow down","code":"rateLimited","retryAfter":30}}"#));
+        assert!(app.rate_limited_until.is_some());
+        check_rate_limit(&mut app);
+        assert!(app.rate_limited_until.is_some(), "still waiting");
+
+        handle_server_message(&mut app, error(r#"{"type":"error","payload":{"message":"Bad token","code":"unauthorized"}}"#));
+        connection_lost(&mut app);
+        assert!(app.connect_task.is_none(), "no reconnect after the server refused our token");
+
+        // Unknown codes and code-less errors are just shown
+        handle_server_message(&mut app, error(r#"{"type":"error","payload":{"message":"Huh","code":"somethingNew"}}"#));
+        handle_server_message(&mut app, error(r#"{"type":"error","payload":{"message":"Room is full"}}"#));
+        assert_eq!(app.status_message, "Error: Room is full");
+    }
+
+    #[test]
+    fn test_history_pages_prepend() {
+        let (transport, server) = test_transport();
+        let key = generate_key();
+        let mut app = App { transport: Some(transport), room_id: Some("room".into()), room_key: Some(RoomKey::new(key)), ..App::default() };
+        let message = |id: &str, text: &str| serde_json::json!({
+            "id": id, "username": "bob", "timestamp": "12:00",
+            "ciphertext": encrypt(&key, text.as_bytes()).unwrap(),
+        });
+        app.messages.push(ChatMessage::new("newest".into(), Some("bob".into()), None).with_id("3".into()));
+        app.selected_message = Some(0);
+
+        request_older_history(&mut app);
+        let request: serde_json::Value = serde_json::from_str(&server.outgoing.try_pop().unwrap()).unwrap();
+        assert_eq!(request["type"], "fetchHistory");
+        assert_eq!(request["payload"]["before"], "3");
+        request_older_history(&mut app);
+        assert!(server.outgoing.try_pop().is_none(), "one request at a time");
+
+        let page = serde_json::json!({"type": "history", "payload": {
+            "roomId": "room", "hasMore": false,
+            "messages": [message("1", "oldest"), message("2", "older"), message("3", "newest")],
+        }});
+        handle_server_message(&mut app, serde_json::from_value(page).unwrap());
+        let contents: Vec<&str> = app.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["oldest", "older", "newest"]);
+        assert_eq!(app.selected_message, Some(2), "selection follows its message");
+        assert!(!app.history_loading && app.history_exhausted);
+
+        request_older_history(&mut app);
+        assert!(server.outgoing.try_pop().is_none(), "nothing older to ask for");
+    }
+
+    #[test]
+    fn test_composer_grows_with_content() {
+        let mut app = App::default();
+        assert_eq!(composer_height(&app, 40, 30), 4, "empty composer keeps two rows");
+
+        app.message_input = TextArea::new((1..=4).map(|n| format!("line {}", n)).collect());
+        assert_eq!(composer_height(&app, 40, 30), 6);
+        app.message_input = TextArea::new(vec!["word ".repeat(100)]);
+        assert_eq!(composer_height(&app, 40, 30), 8, "capped at composer_max_rows");
+
+        // A short terminal leaves the message list its rows first
+        assert_eq!(composer_height(&app, 40, 10), 5);
+        assert_eq!(composer_height(&app, 40, 3), 3);
+    }
+
+    #[test]
+    fn test_room_list_transitions() {
+        let mut app = App { current_screen: CurrentScreen::RoomChoice, ..App::default() };
+        assert_eq!(press(&mut app, KeyCode::Esc), vec![Action::Quit]);
+
+        let actions = press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.current_screen, CurrentScreen::RoomList);
+        assert_eq!(sent_type(&actions[0]), "\"listRooms\"");
+
+        app.public_rooms = ["one", "two"]
+            .iter()
+            .map(|name| RoomInfo {
+                room_id: format!("id-{}", name),
+                name: name.to_string(),
+                display_name: name.to_string(),
+                room_type: "public".to_string(),
+                member_count: 1,
+                is_joined: false,
+            })
+            .collect();
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected_room_index, 1, "selection stops at the last room");
+
+        // Without a connection, Enter stays put and says why
+        assert!(press(&mut app, KeyCode::Enter).is_empty());
+        assert_eq!(app.current_screen, CurrentScreen::RoomList);
+        assert_eq!(app.status_message, "Not connected to the server.");
+
+        let (transport, server) = test_transport();
+        app.transport = Some(transport);
+        let actions = press(&mut app, KeyCode::Enter);
+        match &actions[0] {
+            Action::Send(json) => assert!(json.contains("\"roomName\":\"two\"")),
+            other => panic!("expected a send, got {:?}", other),
+        }
+        assert_eq!(app.current_screen, CurrentScreen::InRoom);
+
+        // Switching to the room we're in keeps it as is
+        app.room_id = Some("id-two".to_string());
+        app.messages.push(ChatMessage::system("hi".to_string()));
+        let rooms = app.public_rooms.clone();
+        switch_room(&mut app, &rooms[1]);
+        assert!(server.outgoing.try_pop().is_none());
+        assert_eq!(app.messages.len(), 1);
+        switch_room(&mut app, &rooms[0]);
+        assert!(server.outgoing.try_pop().unwrap().contains("\"roomName\":\"one\""));
+        assert!(app.messages.is_empty());
+    }
+
+    #[test]
+    fn test_take_config_flag() {
+        let mut args: Vec<String> = ["send", "--config", "~/p.toml", "--room", "r"].map(String::from).to_vec();
+        assert_eq!(take_config_flag(&mut args), Ok(Some("~/p.toml".to_string())));
+        assert_eq!(args, ["send", "--room", "r"]);
+
+        let mut args = vec!["--config=work.toml".to_string()];
+        assert_eq!(take_config_flag(&mut args), Ok(Some("work.toml".to_string())));
+        assert!(args.is_empty());
+
+        assert!(take_config_flag(&mut vec!["--config".to_string()]).is_err());
+        assert_eq!(take_config_flag(&mut Vec::new()), Ok(None));
+    }
+
+    #[test]
+    fn test_roster_follows_presence() {
+        let mut app = App { room_id: Some("room".into()), ..App::default() };
+        let event = |value: serde_json::Value| serde_json::from_value::<ServerMessage>(value).unwrap();
+        handle_server_message(&mut app, event(serde_json::json!({"type": "roomMembers", "payload": {
+            "roomId": "room",
+            "members": [{"username": "alice"}, {"username": "bob", "status": "away"}, {"username": "bob"}],
+        }})));
+        assert_eq!(app.online_users, ["alice", "bob"]);
+        assert!(app.away_users.contains("bob"));
+
+        handle_server_message(&mut app, event(serde_json::json!({"type": "presence", "payload": {
+            "roomId": "room", "username": "bob", "status": "active",
+        }})));
+        handle_server_message(&mut app, event(serde_json::json!({"type": "presence", "payload": {
+            "roomId": "elsewhere", "username": "alice", "status": "away",
+        }})));
+        assert!(app.away_users.is_empty(), "other rooms' presence is ignored");
+    }
+
+    #[test]
+    fn test_message_signing_is_optional() {
+        let (transport, server) = test_transport();
+        let key = generate_key();
+        let mut app = App {
+            transport: Some(transport),
+            room_key: Some(RoomKey::new(key)),
+            room_id: Some("room".into()),
+            current_username: Some("me".into()),
+            ..App::default()
+        };
+        let send = |app: &mut App<'_>, text: &str| {
+            let mut msg = ChatMessage::new(text.to_string(), Some("me".into()), None);
+            msg.from_me = true;
+            app.messages.push(msg);
+            transmit_message(app, app.messages.len() - 1);
+            app.messages.last().unwrap().clone()
+        };
+        let sent_envelope = |json: String| {
+            let sent: serde_json::Value = serde_json::from_str(&json).unwrap();
+            let plaintext = decrypt_bytes(&key, sent["payload"]["ciphertext"].as_str().unwrap()).unwrap();
+            MessageEnvelope::decode(&String::from_utf8(plaintext).unwrap())
+        };
+
+        // No key: sent anyway, and marked as unsigned
+        assert!(matches!(should_sign(&app), Signing::Unsigned));
+        let echo = send(&mut app, "hello");
+        assert_eq!((echo.delivery, echo.unsigned), (Some(DeliveryState::Pending), true));
+        assert!(sent_envelope(server.outgoing.try_pop().unwrap()).sig.is_none());
+
+        // Unless the room insists on signatures
+        app.room_requires_signatures = true;
+        assert!(matches!(should_sign(&app), Signing::Refuse));
+        assert_eq!(send(&mut app, "hello").delivery, Some(DeliveryState::Failed));
+        assert!(server.outgoing.try_pop().is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("id_ed25519");
+        let private = ssh_key::PrivateKey::from(ssh_key::private::Ed25519Keypair::from_seed(&[4; 32]));
+        std::fs::write(&path, private.to_openssh(ssh_key::LineEnding::LF).unwrap()).unwrap();
+        let key = ssh::SshKey {
+            name: "id_ed25519".into(),
+            public_key: private.public_key().to_openssh().unwrap(),
+            key_type: "ed25519".into(),
+            source: ssh::KeySource::File(path),
+        };
+        app.signing_key = Some(ssh::SigningKey::open(&key, None).unwrap());
+        let echo = send(&mut app, "signed");
+        assert!(!echo.unsigned);
+        let envelope = sent_envelope(server.outgoing.try_pop().unwrap());
+        assert_eq!(envelope.text, "signed");
+        let sig = envelope.sig.clone().unwrap();
+        assert_eq!(sig.key, private.public_key().to_openssh().unwrap());
+        let public = private.public_key().key_data().ed25519().unwrap().0;
+        let verifying = ed25519_dalek::VerifyingKey::from_bytes(&public).unwrap();
+        let signature = ed25519_dalek::Signature::from_slice(&hex::decode(&sig.sig).unwrap()).unwrap();
+        assert!(verifying.verify_strict(&envelope.signed_bytes("room"), &signature).is_ok());
+        assert!(verifying.verify_strict(&envelope.signed_bytes("other-room"), &signature).is_err());
+
+        // Whispers go through the same decision
+        app.signing_key = None;
+        send_whisper(&mut app, "bob", "psst");
+        assert!(server.outgoing.try_pop().is_none());
+        app.room_requires_signatures = false;
+        send_whisper(&mut app, "bob", "psst");
+        assert!(server.outgoing.try_pop().is_some());
+        assert!(app.messages.last().unwrap().unsigned);
+    }
+
+    #[test]
+    fn test_group_window_edges() {
+        let window = chrono::Duration::minutes(5);
+        let last = chrono::Local::now();
+        let after = |secs| last + chrono::Duration::seconds(secs);
+        assert!(!starts_new_group(window, Some(last), after(299)));
+        assert!(!starts_new_group(window, Some(last), after(300)), "exactly the window still groups");
+        assert!(starts_new_group(window, Some(last), after(301)));
+        assert!(!starts_new_group(window, None, after(3600)), "the first message has nothing to split from");
+        assert!(!starts_new_group(chrono::Duration::zero(), Some(last), after(3600)), "0 turns it off");
+    }
+
+    #[test]
+    fn test_heartbeats_stop_without_echoes() {
+        let (transport, server) = test_transport();
+        let mut app = App { transport: Some(transport), ..App::default() };
+        app.config.network.heartbeat_interval = 20;
+        app.config.network.heartbeat_timeout = 60;
+        let ago = |secs| std::time::Instant::now().checked_sub(Duration::from_secs(secs));
+        // A freshly booted host can't go back far enough
+        if ago(61).is_none() {
+            return;
+        }
+
+        // A server that never echoes gets a few probes, then none
+        for _ in 0..MAX_UNANSWERED_HEARTBEATS {
+            check_heartbeat(&mut app);
+            assert!(server.outgoing.try_pop().is_some());
+            check_heartbeat(&mut app);
+            assert!(server.outgoing.try_pop().is_none(), "one probe per interval");
+            app.heartbeat_sent_at = ago(21);
+        }
+        check_heartbeat(&mut app);
+        assert!(server.outgoing.try_pop().is_none());
+        assert!(app.transport.is_some());
+
+        // Once it echoes, a probe waits out the whole timeout
+        let seq = app.heartbeat_seq;
+        handle_server_message(&mut app, ServerMessage::Heartbeat(HeartbeatPayload { seq }));
+        assert_eq!(app.heartbeats_unanswered, 0);
+        app.last_heartbeat = ago(21);
+        check_heartbeat(&mut app);
+        assert!(server.outgoing.try_pop().is_some());
+        app.heartbeat_sent_at = ago(45);
+        check_heartbeat(&mut app);
+        assert!(server.outgoing.try_pop().is_none(), "no resend while a probe is out");
+        assert!(app.transport.is_some());
+        app.heartbeat_sent_at = ago(61);
+        check_heartbeat(&mut app);
+        assert!(app.transport.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_waits_for_the_server() {
+        let (transport, server) = test_transport();
+        let mut app = App {
+            transport: Some(transport),
+            room_id: Some("room".into()),
+            current_username: Some("me".into()),
+            ..App::default()
+        };
+        let mut mine = ChatMessage::system("oops".to_string());
+        mine.is_system = false;
+        mine.sender = Some("me".into());
+        mine.id = Some("m1".into());
+        app.messages.push(mine);
+
+        execute_command(&mut app, "del").await;
+        assert_eq!(sent_type(&Action::Send(server.outgoing.try_pop().unwrap())), "\"delete\"");
+        assert_eq!(app.status_message, "Deleting message...");
+        assert!(!app.messages[0].deleted);
+
+        let deleted = |id: &str| ServerMessage::MessageDeleted(api::MessageDeletedPayload {
+            room_id: "room".into(),
+            message_id: id.into(),
+        });
+        handle_server_message(&mut app, deleted("other"));
+        assert_eq!(app.status_message, "Deleting message...");
+        handle_server_message(&mut app, deleted("m1"));
+        assert_eq!(app.status_message, "Message deleted");
+        assert!(app.messages[0].deleted);
+        assert!(app.deleting_message_id.is_none());
+
+        // A refused delete is never reported as done
+        app.messages[0].deleted = false;
+        execute_command(&mut app, "del").await;
+        handle_server_message(&mut app, ServerMessage::Error(ErrorPayload {
+            message: "not yours".into(),
+            code: Some(ErrorCode::Forbidden),
+            retry_after: None,
+        }));
+        assert!(app.deleting_message_id.is_none());
+        assert!(app.status_message.starts_with("Error: not yours"));
+    }
+
+    #[tokio::test]
+    async fn test_whispers() {
+        let (transport, server) = test_transport();
+        let key = generate_key();
+        let mut app = App {
+            transport: Some(transport),
+            room_key: Some(RoomKey::new(key)),
+            room_id: Some("room".into()),
+            current_username: Some("me".into()),
+            ..App::default()
+        };
+        execute_command(&mut app, "w bob").await;
+        assert!(server.outgoing.try_pop().is_none(), "nothing to say");
+
+        execute_command(&mut app, "w bob  meet at 5").await;
+        let sent: serde_json::Value = serde_json::from_str(&server.outgoing.try_pop().unwrap()).unwrap();
+        assert_eq!(sent["type"], "whisper");
+        assert_eq!(sent["payload"]["targetUsername"], "bob");
+        let ciphertext = sent["payload"]["ciphertext"].as_str().unwrap();
+        assert_eq!(decrypt_text(&key, ciphertext).unwrap(), "meet at 5");
+        let echo = app.messages.last().unwrap();
+        assert_eq!((echo.whisper_to.as_deref(), echo.from_me), (Some("bob"), true));
+
+        let whisper = |room: &str| serde_json::from_value::<ServerMessage>(serde_json::json!({"type": "whisper", "payload": {
+            "roomId": room, "username": "alice", "targetUsername": "me",
+            "ciphertext": encrypt(&key, b"psst").unwrap(), "timestamp": "2025-03-04T10:00:00Z",
+        }})).unwrap();
+        handle_server_message(&mut app, whisper("elsewhere"));
+        assert_eq!(app.messages.len(), 1);
+        handle_server_message(&mut app, whisper("room"));
+        let received = app.messages.last().unwrap();
+        assert_eq!(received.content, "psst");
+        assert_eq!(received.sender.as_deref(), Some("alice"));
+        assert!(!received.from_me && received.whisper_to.is_some());
+
+        // With the roster's public keys, whispers are sealed end to end
+        let bob = ssh_key::PrivateKey::from(ssh_key::private::Ed25519Keypair::from_seed(&[2; 32]));
+        let me = ssh_key::PrivateKey::from(ssh_key::private::Ed25519Keypair::from_seed(&[3; 32]));
+        app.whisper_keys = Some(vec![me.clone()]);
+        handle_server_message(&mut app, serde_json::from_value(serde_json::json!({"type": "roomMembers", "payload": {
+            "roomId": "room",
+            "members": [
+                {"username": "bob", "publicKey": bob.public_key().to_openssh().unwrap()},
+                {"username": "carol", "publicKey": "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQDRqlHMSv6xKiSUQS/7gca0QfX85moxbKLb5msOwnCqaYCGs3EXAN+3tnWBmfLwlLbADT7/5IqQsUmJ5YHaUwGyOOR3BDu+lVuzUidKDuYFXyYMtdhDpepSgx/OfBCgjFe6lOGzwsN0ZBcS3Dj/TjWd6eQTdMODoJW8Orfb8oeJFQ== x"},
+            ],
+        }})).unwrap());
+        execute_command(&mut app, "w carol hi").await;
+        assert!(server.outgoing.try_pop().is_none(), "RSA-only members are refused");
+        assert!(app.status_message.contains("only ed25519"), "{}", app.status_message);
+
+        execute_command(&mut app, "w bob sealed").await;
+        let sent: serde_json::Value = serde_json::from_str(&server.outgoing.try_pop().unwrap()).unwrap();
+        let sealed_key = sent["payload"]["sealedKey"].as_str().unwrap();
+        let content_key = AesKey::clone_from_slice(&crypto::open_with_privkey(&bob, sealed_key).unwrap());
+        assert_eq!(decrypt_text(&content_key, sent["payload"]["ciphertext"].as_str().unwrap()).unwrap(), "sealed");
+        assert!(decrypt_text(&key, sent["payload"]["ciphertext"].as_str().unwrap()).is_err(), "not under the room key");
+
+        let content_key = generate_key();
+        handle_server_message(&mut app, serde_json::from_value(serde_json::json!({"type": "whisper", "payload": {
+            "roomId": "room", "username": "bob", "targetUsername": "me",
+            "ciphertext": encrypt(&content_key, b"just us").unwrap(),
+            "sealedKey": seal_for_pubkey(me.public_key(), &content_key).unwrap(),
+            "timestamp": "2025-03-04T10:00:00Z",
+        }})).unwrap());
+        let received = app.messages.last().unwrap();
+        assert_eq!(received.content, "just us");
+        assert!(received.whisper_sealed);
+    }
+
+    #[test]
+    fn test_echoes_without_client_id_match_in_order() {
+        let (transport, server) = test_transport();
+        let key = generate_key();
+        let mut app = App {
+            transport: Some(transport),
+            room_key: Some(RoomKey::new(key)),
+            room_id: Some("room".into()),
+            current_username: Some("me".into()),
+            ..App::default()
+        };
+        let mut failed = ChatMessage::new("ok".into(), Some("me".into()), None);
+        failed.client_id = Some("failed".into());
+        failed.delivery = Some(DeliveryState::Failed);
+        app.messages.push(failed);
+        for _ in 0..2 {
+            let mut msg = ChatMessage::new("ok".into(), Some("me".into()), None);
+            msg.client_id = Some(hex::encode(rand::random::<[u8; 8]>()));
+            msg.from_me = true;
+            app.messages.push(msg);
+            let index = app.messages.len() - 1;
+            transmit_message(&mut app, index);
+            server.outgoing.try_pop().unwrap();
+        }
+        let echo = |id: &str, text: &str| {
+            serde_json::from_value::<ServerMessage>(serde_json::json!({"type": "message", "payload": {
+                "id": id, "username": "me", "ciphertext": encrypt(&key, text.as_bytes()).unwrap(),
+                "timestamp": "2025-03-04T10:00:00Z",
+            }}))
+            .unwrap()
+        };
+
+        // Something else we said from another session doesn't take a pending slot
+        handle_server_message(&mut app, echo("m0", "elsewhere"));
+        assert_eq!(app.messages.len(), 4);
+
+        handle_server_message(&mut app, echo("m1", "ok"));
+        handle_server_message(&mut app, echo("m2", "ok"));
+        let states: Vec<_> = app.messages.iter().map(|m| (m.id.as_deref(), m.delivery)).collect();
+        assert_eq!(states, [
+            (None, Some(DeliveryState::Failed)),
+            (Some("m1"), Some(DeliveryState::Sent)),
+            (Some("m2"), Some(DeliveryState::Sent)),
+            (Some("m0"), Some(DeliveryState::Sent)),
+        ]);
+    }
+
+    #[test]
+    fn test_toggle_timestamps() {
+        let mut app = App::default();
+        app.config.ui.show_timestamps = true;
+        app.show_timestamps = true;
+        toggle_timestamps(&mut app);
+        assert!(!app.show_timestamps);
+        assert_eq!(app.status_message, "Timestamps hidden");
+        assert!(app.config.ui.show_timestamps, "the config value is left alone");
+
+        // A reload only overrides the toggle when the setting itself changed
+        let mut config = app.config.clone();
+        apply_config(&mut app, config.clone());
+        assert!(!app.show_timestamps);
+        config.ui.show_timestamps = false;
+        apply_config(&mut app, config);
+        toggle_timestamps(&mut app);
+        assert!(app.show_timestamps);
+    }
+
+    #[test]
+    fn test_mic_state_combines_mute_and_activity() {
+        let mut voice = VoiceState::default();
+        assert_eq!(voice.mic_state(), MicState::Idle);
+        voice.is_transmitting = true;
+        assert_eq!(voice.mic_state(), MicState::Transmitting);
+        voice.is_muted = true;
+        assert_eq!(voice.mic_state(), MicState::Muted);
+        voice.listen_only = true;
+        assert_eq!(voice.mic_state(), MicState::ListenOnly);
+        voice.reset();
+        assert_eq!(voice.mic_state(), MicState::Idle);
+    }
+
+    #[test]
+    fn test_day_labels() {
+        let day = |y, m, d| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(day_label(day(2025, 3, 4), day(2025, 12, 31)), "Tuesday, March 4");
+        assert_eq!(day_label(day(2024, 12, 31), day(2025, 1, 1)), "Tuesday, December 31, 2024");
+    }
+
+    #[test]
+    fn test_room_notify_levels() {
+        let mut app = App { current_username: Some("Alice".into()), ..App::default() };
+        assert!(should_notify(&app, "room", "hello"), "defaults to ui.notifications");
+
+        app.room_labels.set_notify("room", Some(NotifyLevel::Mentions)).unwrap();
+        assert!(!should_notify(&app, "room", "hello"));
+        assert!(should_notify(&app, "room", "thanks @alice!"));
+        assert!(!should_notify(&app, "room", "alicedoe said hi"), "whole words only");
+        assert!(should_notify(&app, "other", "hello"), "other rooms keep the default");
+
+        app.room_labels.set_notify("room", Some(NotifyLevel::Muted)).unwrap();
+        assert!(!should_notify(&app, "room", "@alice"));
+        app.config.ui.notifications = NotifyLevel::Muted;
+        assert!(!should_notify(&app, "other", "hello"));
+    }
+
+    #[tokio::test]
+    async fn test_me_actions() {
+        assert_eq!(split_action("/me waves".into()), ("waves".to_string(), MessageKind::Action));
+        assert_eq!(split_action("/meow".into()), ("/meow".to_string(), MessageKind::Text));
+
+        let encoded = MessageEnvelope { kind: MessageKind::Action, ..MessageEnvelope::new("waves".into()) }.encode();
+        let msg = ChatMessage::from_plaintext(&encoded, "alice".into(), "12:00".into(), "1".into());
+        assert!(msg.is_action);
+        assert_eq!(msg.content, "waves");
+
+        // An action with no text is never sent
+        let mut app = App { room_id: Some("room".into()), room_key: Some(RoomKey::new(generate_key())), ..App::default() };
+        app.message_input = TextArea::from(["/me  "]);
+        send_message(&mut app).await;
+        assert!(app.messages.is_empty());
+    }
+
+    #[test]
+    fn test_transport_messages_and_close() {
+        let (transport, server) = test_transport();
+        let mut app = App { transport: Some(transport), ..App::default() };
+        let (_connection_tx, mut connection_rx) = mpsc::unbounded_channel::<(u64, ConnectionEvent)>();
+
+        server.incoming.send("not json".to_string()).unwrap();
+        process_network_events(&mut app, &mut connection_rx);
+        assert_eq!(app.messages.last().unwrap().content, "[SERVER] not json");
+
+        // The server going away reads as a lost connection
+        drop(server);
+        process_network_events(&mut app, &mut connection_rx);
+        assert!(app.transport.is_none());
+        assert_eq!(app.status_message, "Connection lost.");
+    }
+
+    #[tokio::test]
+    async fn test_message_round_trip_through_mock_server() {
+        // Mock server: forwards what it receives, sends what it's given
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (received_tx, mut received_rx) = mpsc::unbounded_channel::<String>();
+        let (push_tx, mut push_rx) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let (mut write, mut read) = ws.split();
+            loop {
+                tokio::select! {
+                    Some(Ok(Message::Text(text))) = read.next() => { let _ = received_tx.send(text); }
+                    Some(text) = push_rx.recv() => { write.send(Message::text(text)).await.unwrap(); }
+                    else => break,
+                }
+            }
+        });
+
+        let key = generate_key();
+        let mut app = App::default();
+        app.config.server.url = format!("ws://{}", addr);
+        app.config.auth.token_path = "/nonexistent/eurus-token".to_string();
+        app.config.network.ca_cert_path = None;
+        app.config.network.danger_accept_invalid_certs = false;
+        app.config.network.extra_headers.clear();
+        app.config.network.subprotocol = None;
+        app.current_username = Some("alice".to_string());
+        app.current_screen = CurrentScreen::InRoom;
+        app.room_id = Some("room-1".to_string());
+        app.room_key = Some(RoomKey::new(key));
+
+        let (connection_tx, mut connection_rx) = mpsc::unbounded_channel::<(u64, ConnectionEvent)>();
+        app.connection_tx = Some(connection_tx);
+
+        // Connecting while in a room rejoins it
+        start_connection(&mut app);
+        pump_until(&mut app, &mut connection_rx, |app| app.transport.is_some()).await;
+        let join: serde_json::Value = serde_json::from_str(&received_rx.recv().await.unwrap()).unwrap();
+        assert_eq!(join["type"], "joinRoom");
+        assert_eq!(join["payload"]["roomId"], "room-1");
+
+        // Sending encrypts with the room key
+        app.message_input.insert_str("hello over the wire");
+        send_message(&mut app).await;
+        let sent: serde_json::Value = serde_json::from_str(&received_rx.recv().await.unwrap()).unwrap();
+        assert_eq!(sent["type"], "sendMessage");
+        assert_eq!(sent["payload"]["roomId"], "room-1");
+        let ciphertext = sent["payload"]["ciphertext"].as_str().unwrap();
+        assert_eq!(decrypt_text(&key, ciphertext).unwrap(), "hello over the wire");
+
+        // A message from someone else is decrypted into the timeline
+        let reply = serde_json::json!({
+            "type": "message",
+            "payload": {
+                "id": "m-2",
+                "username": "bob",
+                "ciphertext": encrypt(&key, b"hi alice").unwrap(),
+                "timestamp": "2024-01-01T12:00:00Z",
+            }
+        });
+        push_tx.send(reply.to_string()).unwrap();
+        pump_until(&mut app, &mut connection_rx, |app| {
+            app.messages.iter().any(|m| m.id.as_deref() == Some("m-2"))
+        })
+        .await;
+        let received = app.messages.iter().find(|m| m.id.as_deref() == Some("m-2")).unwrap();
+        assert_eq!(received.content, "hi alice");
+        assert_eq!(received.sender.as_deref(), Some("bob"));
+    }
+
+    #[test]
+    fn test_tiny_terminal_shows_resize_hint() {
+        let draw = |app: &mut App<'_>, width: u16, height: u16| {
+            let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(width, height)).unwrap();
+            terminal.draw(|f| ui(f, app)).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            buffer.content().iter().map(|cell| cell.symbol()).collect::<String>()
+        };
+        let mut app = App { current_screen: CurrentScreen::InRoom, ..App::default() };
+        app.messages.push(ChatMessage::system("hello".to_string()));
+
+        let tiny = draw(&mut app, 10, 3);
+        assert!(tiny.contains("Terminal"), "{:?}", tiny);
+        assert!(draw(&mut app, 80, 5).contains("resize to at least 40x10"));
+
+        // Every screen still draws at exactly the minimum size
+        app.voice.status = VoiceConnectionStatus::Connected;
+        app.show_roster = true;
+        for screen in [CurrentScreen::RoomChoice, CurrentScreen::RoomList, CurrentScreen::InRoom, CurrentScreen::Help] {
+            app.current_screen = screen;
+            assert!(!draw(&mut app, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT).contains("too small"));
+        }
+    }
+}
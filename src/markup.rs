@@ -0,0 +1,44 @@
+//! Lightweight markdown-ish parsing of message text
+
+/// Contents of every ``` fenced block in `text`, without the fences.
+/// The info string after the opening fence (e.g. ```rust) is dropped, and an
+/// unclosed fence runs to the end of the message.
+pub fn fenced_code_blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+
+    for line in text.lines() {
+        let is_fence = line.trim_start().starts_with("```");
+        match (&mut current, is_fence) {
+            (None, true) => current = Some(Vec::new()),
+            (Some(lines), true) => {
+                blocks.push(lines.join("\n"));
+                current = None;
+            }
+            (Some(lines), false) => lines.push(line),
+            (None, false) => {}
+        }
+    }
+    if let Some(lines) = current {
+        if !lines.is_empty() {
+            blocks.push(lines.join("\n"));
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fenced_code_blocks() {
+        let text = "look:\n```rust\nfn main() {\n    println!(\"hi\");\n}\n```\nand\n```\nls -la\n```";
+        assert_eq!(
+            fenced_code_blocks(text),
+            vec!["fn main() {\n    println!(\"hi\");\n}".to_string(), "ls -la".to_string()]
+        );
+        assert!(fenced_code_blocks("no code here").is_empty());
+        assert_eq!(fenced_code_blocks("```\nunclosed"), vec!["unclosed".to_string()]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Soft-wrapped view of the composer. tui-textarea scrolls long lines
+//! sideways, so the textarea stays in charge of editing while we draw its
+//! lines word-wrapped to the box and move the cursor by visual rows.
+//!
+//! Positions are (line, column) in chars, the same as `TextArea::cursor`.
+
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::grapheme_width;
+
+/// One row on screen: chars `start..end` of logical line `line`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualRow {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Word-wrap `lines` to `width` columns. Rows break after the last space
+/// that fits, or between graphemes when a word is wider than the row. Every
+/// logical line gets at least one row.
+pub fn wrap(lines: &[String], width: usize) -> Vec<VisualRow> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    for (line, text) in lines.iter().enumerate() {
+        let mut start = 0;
+        let mut pos = 0;
+        let mut row_width = 0;
+        // Char index just after the last space on this row, and the row width up to it
+        let mut last_break: Option<(usize, usize)> = None;
+        for grapheme in text.graphemes(true) {
+            let grapheme_cols = grapheme_width(grapheme);
+            if row_width + grapheme_cols > width && row_width > 0 {
+                let end = match last_break {
+                    Some((index, cols)) if index > start => {
+                        row_width -= cols;
+                        index
+                    }
+                    _ => {
+                        row_width = 0;
+                        pos
+                    }
+                };
+                rows.push(VisualRow { line, start, end });
+                start = end;
+                last_break = None;
+            }
+            row_width += grapheme_cols;
+            pos += grapheme.chars().count();
+            if grapheme.chars().all(char::is_whitespace) {
+                last_break = Some((pos, row_width));
+            }
+        }
+        rows.push(VisualRow { line, start, end: pos });
+    }
+    rows
+}
+
+/// The visual row holding `cursor`, and the cursor's column on it
+pub fn locate(rows: &[VisualRow], lines: &[String], cursor: (usize, usize)) -> (usize, usize) {
+    let (line, col) = cursor;
+    // A column on a row boundary belongs to the row it starts
+    let index = rows
+        .iter()
+        .rposition(|row| row.line == line && row.start <= col)
+        .unwrap_or(0);
+    let x = rows
+        .get(index)
+        .and_then(|row| lines.get(row.line).map(|text| char_slice(text, row.start, col.max(row.start))))
+        .map(super::display_width)
+        .unwrap_or(0);
+    (index, x)
+}
+
+/// Where the cursor lands one visual row up or down, staying as close to
+/// its column as the target row allows. `None` at the first or last row.
+pub fn step(rows: &[VisualRow], lines: &[String], cursor: (usize, usize), down: bool) -> Option<(usize, usize)> {
+    let (current, x) = locate(rows, lines, cursor);
+    let target = if down { current + 1 } else { current.checked_sub(1)? };
+    let row = *rows.get(target)?;
+    let text = char_slice(lines.get(row.line)?, row.start, row.end);
+    // Stopping on a wrapped row's end would put the cursor on the next row
+    let ends_line = rows.get(target + 1).is_none_or(|next| next.line != row.line);
+
+    let mut col = row.start;
+    let mut cols = 0;
+    for grapheme in text.graphemes(true) {
+        let chars = grapheme.chars().count();
+        let last = col + chars == row.end;
+        if cols + grapheme_width(grapheme) > x || (last && !ends_line) {
+            break;
+        }
+        cols += grapheme_width(grapheme);
+        col += chars;
+    }
+    Some((row.line, col))
+}
+
+/// Styled rows for display. `cursor` is drawn with `cursor_style` (on a
+/// blank cell at the end of a line); text matching `highlight` gets
+/// `highlight_style`.
+pub fn styled_rows(
+    lines: &[String],
+    rows: &[VisualRow],
+    cursor: Option<(usize, usize)>,
+    highlight: Option<&Regex>,
+    cursor_style: Style,
+    highlight_style: Style,
+) -> Vec<Line<'static>> {
+    let cursor_row = cursor.map(|cursor| locate(rows, lines, cursor).0);
+    rows.iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let text = &lines[row.line];
+            let highlighted: Vec<(usize, usize)> = highlight
+                .map(|re| {
+                    re.find_iter(text)
+                        .map(|m| (text[..m.start()].chars().count(), text[..m.end()].chars().count()))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let mut spans = Vec::new();
+            let mut col = row.start;
+            for grapheme in char_slice(text, row.start, row.end).graphemes(true) {
+                let mut style = Style::default();
+                if highlighted.iter().any(|&(start, end)| start <= col && col < end) {
+                    style = style.patch(highlight_style);
+                }
+                if cursor_row == Some(index) && cursor.is_some_and(|(_, c)| c == col) {
+                    style = style.patch(cursor_style);
+                }
+                spans.push(Span::styled(grapheme.to_string(), style));
+                col += grapheme.chars().count();
+            }
+            if cursor_row == Some(index) && cursor.is_some_and(|(_, c)| c >= row.end) {
+                spans.push(Span::styled(" ", cursor_style));
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
+
+fn char_slice(text: &str, start: usize, end: usize) -> &str {
+    let byte = |n: usize| text.char_indices().nth(n).map_or(text.len(), |(i, _)| i);
+    &text[byte(start)..byte(end)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_line_wraps_and_steps_by_row() {
+        let lines = vec!["the quick brown fox jumps over the lazy dog".to_string(), "end".to_string()];
+        let rows = wrap(&lines, 12);
+        let text: Vec<&str> = rows.iter().map(|r| char_slice(&lines[r.line], r.start, r.end)).collect();
+        assert_eq!(text, ["the quick ", "brown fox ", "jumps over ", "the lazy dog", "end"]);
+
+        // Column 4 ("quick") moves straight down through the wrapped rows
+        assert_eq!(locate(&rows, &lines, (0, 4)), (0, 4));
+        let down = step(&rows, &lines, (0, 4), true).unwrap();
+        assert_eq!(down, (0, 14));
+        assert_eq!(locate(&rows, &lines, down), (1, 4));
+        assert_eq!(step(&rows, &lines, (0, 35), true), Some((1, 3)), "onto the next logical line");
+        assert_eq!(step(&rows, &lines, (0, 1), false), None);
+
+        // From the end of a long row, stop before the wrap point
+        assert_eq!(step(&rows, &lines, (0, 43), false), Some((0, 30)));
+
+        // Words wider than the row break anywhere
+        let rows = wrap(&["abcdefgh".to_string()], 3);
+        assert_eq!(rows.iter().map(|r| (r.start, r.end)).collect::<Vec<_>>(), [(0, 3), (3, 6), (6, 8)]);
+        assert_eq!(wrap(&[String::new()], 5), [VisualRow { line: 0, start: 0, end: 0 }]);
+    }
+}
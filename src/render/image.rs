@@ -0,0 +1,129 @@
+//! Inline images for terminals that can show them, via the Kitty graphics
+//! protocol or iTerm2's inline images. Other terminals (and tmux, which
+//! swallows both) get a text placeholder instead.
+//!
+//! Nothing receives images yet; the encoders are here for when image
+//! messages exist.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Shown in place of an image the terminal can't draw
+#[allow(dead_code)]
+pub const PLACEHOLDER: &str = "[image — press v to open]";
+
+/// Base64 bytes per Kitty escape; the protocol caps chunks at 4096
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Iterm2,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Kitty => "kitty",
+            Protocol::Iterm2 => "iTerm2",
+        }
+    }
+}
+
+/// The inline image protocol to use, or `None` for the placeholder.
+/// `enabled` is the `ui.inline_images` setting.
+pub fn inline_protocol(enabled: bool) -> Option<Protocol> {
+    enabled.then(|| detect_from(|name| std::env::var(name).ok())).flatten()
+}
+
+/// Work out the terminal's image protocol from its environment variables
+fn detect_from(env: impl Fn(&str) -> Option<String>) -> Option<Protocol> {
+    if env("TMUX").is_some() {
+        return None;
+    }
+    let term = env("TERM").unwrap_or_default();
+    let program = env("TERM_PROGRAM").unwrap_or_default();
+    if env("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || term.contains("ghostty") {
+        Some(Protocol::Kitty)
+    } else if program == "iTerm.app"
+        || program == "WezTerm"
+        || env("LC_TERMINAL").is_some_and(|terminal| terminal == "iTerm2")
+    {
+        Some(Protocol::Iterm2)
+    } else {
+        None
+    }
+}
+
+/// Escape sequence that draws `png` scaled into `cols` x `rows` cells at the
+/// cursor
+#[allow(dead_code)]
+pub fn encode(protocol: Protocol, png: &[u8], cols: u16, rows: u16) -> String {
+    let data = STANDARD.encode(png);
+    match protocol {
+        Protocol::Kitty => {
+            // First chunk carries the options; m=1 means more chunks follow
+            let chunks: Vec<&str> = data
+                .as_bytes()
+                .chunks(KITTY_CHUNK)
+                .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+                .collect();
+            let mut out = String::with_capacity(data.len() + chunks.len() * 16 + 32);
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(i + 1 < chunks.len());
+                if i == 0 {
+                    out.push_str(&format!("\x1b_Ga=T,f=100,q=2,c={},r={},m={};{}\x1b\\", cols, rows, more, chunk));
+                } else {
+                    out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+                }
+            }
+            out
+        }
+        Protocol::Iterm2 => format!(
+            "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+            png.len(),
+            cols,
+            rows,
+            data
+        ),
+    }
+}
+
+/// Open a saved image in the system viewer, for the placeholder's `v`
+#[allow(dead_code)]
+pub fn open_external(path: &std::path::Path) -> Result<(), String> {
+    open::that_detached(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+    }
+
+    #[test]
+    fn test_detect_from_env() {
+        assert_eq!(detect_from(env(&[("TERM", "xterm-kitty")])), Some(Protocol::Kitty));
+        assert_eq!(detect_from(env(&[("TERM", "xterm-256color"), ("KITTY_WINDOW_ID", "1")])), Some(Protocol::Kitty));
+        assert_eq!(detect_from(env(&[("TERM_PROGRAM", "iTerm.app")])), Some(Protocol::Iterm2));
+        assert_eq!(detect_from(env(&[("LC_TERMINAL", "iTerm2")])), Some(Protocol::Iterm2));
+        assert_eq!(detect_from(env(&[("TERM", "xterm-256color")])), None);
+        assert_eq!(detect_from(env(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux-0/default,1,0")])), None);
+        assert_eq!(inline_protocol(false), None);
+    }
+
+    #[test]
+    fn test_encode() {
+        let iterm = encode(Protocol::Iterm2, b"png", 20, 5);
+        assert_eq!(iterm, "\x1b]1337;File=inline=1;size=3;width=20;height=5;preserveAspectRatio=1:cG5n\x07");
+
+        // 4000 bytes is 5336 base64 characters: two chunks
+        let kitty = encode(Protocol::Kitty, &[0u8; 4000], 20, 5);
+        let chunks: Vec<&str> = kitty.split("\x1b\\").filter(|chunk| !chunk.is_empty()).collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].starts_with("\x1b_Ga=T,f=100,q=2,c=20,r=5,m=1;"));
+        assert!(chunks[1].starts_with("\x1b_Gm=0;"));
+        assert_eq!(chunks[0].len() - chunks[0].find(';').unwrap() - 1, KITTY_CHUNK);
+    }
+}
@@ -0,0 +1,252 @@
+//! Small rendering helpers shared across screens
+
+pub mod composer;
+pub mod image;
+
+use ratatui::style::Color;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// Frames for the pending-state spinner
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Spinner for pending operations (connecting, reconnecting, joining voice).
+/// Advances one frame per draw tick, so it animates as long as the app keeps redrawing.
+#[derive(Debug, Default)]
+pub struct Spinner {
+    frame: usize,
+}
+
+impl Spinner {
+    /// Advance to the next frame
+    pub fn tick(&mut self) {
+        self.frame = (self.frame + 1) % SPINNER_FRAMES.len();
+    }
+
+    /// Current frame glyph
+    pub fn frame(&self) -> &'static str {
+        SPINNER_FRAMES[self.frame]
+    }
+}
+
+/// Strip terminal control sequences and control characters from untrusted
+/// text before it's displayed. Removes ANSI escape sequences (CSI, OSC and
+/// two-byte escapes) and C0/C1 controls other than tab and newline, so a
+/// message can't restyle, move the cursor or retitle the terminal.
+pub fn sanitize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\t' | '\n' => out.push(c),
+            '\x1b' => match chars.next() {
+                // CSI: parameters, then a final byte in @..~
+                Some('[') => skip_csi(&mut chars),
+                // OSC/DCS/APC/PM/SOS: a string ended by BEL or ST (ESC \)
+                Some(']' | 'P' | '_' | '^' | 'X') => skip_control_string(&mut chars),
+                // Two-byte escape; the second byte goes with it
+                _ => {}
+            },
+            '\u{9b}' => skip_csi(&mut chars),
+            '\u{9d}' | '\u{90}' | '\u{9f}' | '\u{9e}' | '\u{98}' => skip_control_string(&mut chars),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn skip_csi(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    for c in chars.by_ref() {
+        if ('\u{40}'..='\u{7e}').contains(&c) {
+            break;
+        }
+    }
+}
+
+fn skip_control_string(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' | '\u{9c}' => break,
+            '\x1b' if chars.peek() == Some(&'\\') => {
+                chars.next();
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Terminal columns `text` occupies: wide (CJK, emoji) graphemes take two,
+/// combining marks and other zero-width characters none
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Width of one grapheme cluster. An emoji sequence (skin tone, ZWJ family)
+/// is drawn as one glyph, so it counts as its widest part, not the sum.
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.chars().map(|c| c.width().unwrap_or(0)).max().unwrap_or(0)
+}
+
+/// Split `text` into pieces at most `width` columns wide, breaking only
+/// between graphemes. A grapheme wider than `width` gets a piece of its own.
+pub fn split_to_width(text: &str, width: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let (mut start, mut used) = (0, 0);
+    for (offset, grapheme) in text.grapheme_indices(true) {
+        let w = grapheme_width(grapheme);
+        if used + w > width && offset > start {
+            pieces.push(&text[start..offset]);
+            start = offset;
+            used = 0;
+        }
+        used += w;
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
+
+/// Shorten `text` to at most `max` columns, ending in "…" when anything was
+/// cut. Cuts fall between graphemes, so a multi-byte character, an accent
+/// or an emoji sequence is kept whole or dropped whole.
+pub fn truncate_chars(text: &str, max: usize) -> String {
+    if display_width(text) <= max {
+        return text.to_string();
+    }
+    let budget = max.saturating_sub(1); // Room for the ellipsis
+    let mut used = 0;
+    let mut cut = String::new();
+    for grapheme in text.graphemes(true) {
+        let w = grapheme_width(grapheme);
+        if used + w > budget {
+            break;
+        }
+        used += w;
+        cut.push_str(grapheme);
+    }
+    if max > 0 {
+        cut.push('…');
+    }
+    cut
+}
+
+/// Whether char index `col` of `line` falls inside a grapheme cluster (e.g.
+/// between a letter and its combining accent), where no cursor should stop
+pub fn inside_grapheme(line: &str, col: usize) -> bool {
+    let mut chars = 0;
+    for grapheme in line.graphemes(true) {
+        if chars >= col {
+            return false;
+        }
+        chars += grapheme.chars().count();
+        if chars > col {
+            return true;
+        }
+    }
+    false
+}
+
+/// Colors for sender names, chosen to read well on the dark chat background
+const NAME_COLORS: [Color; 8] = [
+    Color::Cyan,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightMagenta,
+    Color::LightBlue,
+    Color::LightRed,
+    Color::Rgb(255, 165, 80),
+    Color::Rgb(150, 220, 200),
+];
+
+/// Stable per-name color, so a sender looks the same in every session
+pub fn name_color(name: &str) -> Color {
+    // FNV-1a: tiny, and unlike std's hasher it doesn't change between releases
+    let hash = name.bytes().fold(0x811c9dc5u32, |h, b| (h ^ b as u32).wrapping_mul(0x01000193));
+    NAME_COLORS[hash as usize % NAME_COLORS.len()]
+}
+
+/// Initial-letter "avatar" for a name, always three columns wide: " A " for
+/// a narrow first character, " 漢" for a wide one (CJK, emoji)
+pub fn avatar(name: &str) -> String {
+    let first = name.graphemes(true).next().unwrap_or("?");
+    let initial: String = if first.chars().count() == 1 { first.to_uppercase() } else { first.to_string() };
+    match display_width(&initial) {
+        0 => " ? ".to_string(),
+        1 => format!(" {} ", initial),
+        _ => format!(" {}", initial),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_strips_escapes_and_controls() {
+        assert_eq!(sanitize("plain\ttext\nnext line"), "plain\ttext\nnext line");
+        assert_eq!(sanitize("\x1b[31mred\x1b[0m"), "red");
+        assert_eq!(sanitize("\x1b[2J\x1b[Hcleared"), "cleared");
+        assert_eq!(sanitize("\x1b]0;pwned\x07title"), "title");
+        assert_eq!(sanitize("\x1b]8;;http://x\x1b\\link\x1b]8;;\x1b\\"), "link");
+        assert_eq!(sanitize("bell\x07 back\x08space\r"), "bell backspace");
+        assert_eq!(sanitize("c1\u{9b}31mcsi \u{85}nel"), "c1csi nel");
+        assert_eq!(sanitize("émoji 🎉 ok"), "émoji 🎉 ok");
+        // A dangling escape at the end doesn't swallow anything else
+        assert_eq!(sanitize("end\x1b"), "end");
+    }
+
+    #[test]
+    fn test_display_width_and_splitting() {
+        let accent = "e\u{301}"; // e + combining acute
+        let thumbs = "👍🏽"; // emoji + skin tone modifier
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("漢字"), 4);
+        assert_eq!(display_width(thumbs), 2);
+        assert_eq!(display_width(accent), 1);
+        assert_eq!(display_width(&format!("a漢{}{}", thumbs, accent)), 6);
+
+        assert_eq!(split_to_width("漢字漢字", 5), vec!["漢字", "漢字"]);
+        assert_eq!(split_to_width(&format!("{}{}x", thumbs, thumbs), 2), vec![thumbs, thumbs, "x"]);
+        assert_eq!(split_to_width(&format!("ab{}cd", accent), 3), vec![format!("ab{}", accent).as_str(), "cd"]);
+        // Too narrow for a wide grapheme: it still gets placed
+        assert_eq!(split_to_width("漢", 1), vec!["漢"]);
+        assert!(split_to_width("", 4).is_empty());
+
+        assert!(inside_grapheme(accent, 1));
+        assert!(!inside_grapheme(accent, 0));
+        assert!(!inside_grapheme(accent, 2));
+        assert!(inside_grapheme(thumbs, 1));
+        assert!(!inside_grapheme("ab", 1));
+    }
+
+    #[test]
+    fn test_truncate_chars_keeps_graphemes_whole() {
+        assert_eq!(truncate_chars("short", 5), "short");
+        assert_eq!(truncate_chars("héllo wörld", 7), "héllo …");
+        // The cut lands on a multi-byte char, a wide char and a combining accent
+        assert_eq!(truncate_chars("abcdé", 4), "abc…");
+        assert_eq!(truncate_chars("a漢字", 3), "a…", "a wide char that doesn't fit is dropped");
+        assert_eq!(truncate_chars("abe\u{301}cd", 4), "abe\u{301}…");
+        assert_eq!(truncate_chars("👍🏽👍🏽👍🏽", 5), "👍🏽👍🏽…");
+        assert_eq!(truncate_chars("👨‍👩‍👧x", 2), "…");
+        assert_eq!(truncate_chars("abc", 1), "…");
+        assert_eq!(truncate_chars("abc", 0), "");
+    }
+
+    #[test]
+    fn test_avatars() {
+        assert_eq!(avatar("alice"), " A ");
+        assert_eq!(avatar("émile"), " É ");
+        assert_eq!(avatar("e\u{301}mile"), " e\u{301} ");
+        assert_eq!(avatar("漢字"), " 漢");
+        assert_eq!(avatar("👍🏽fan"), " 👍🏽");
+        assert_eq!(avatar(""), " ? ");
+        for name in ["alice", "漢字", "👍🏽fan", ""] {
+            assert_eq!(display_width(&avatar(name)), 3, "{:?}", name);
+        }
+        assert_eq!(name_color("alice"), name_color("alice"));
+    }
+}
@@ -0,0 +1,151 @@
+//! User-assigned labels, accent colors and notification levels for rooms,
+//! kept in `~/.config/eurus/rooms.toml` keyed by room id:
+//!
+//! ```toml
+//! [3f8a9c...]
+//! label = "Team Standup"
+//! color = "magenta"
+//! notify = "mentions"
+//! ```
+
+use crate::config::NotifyLevel;
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomLabel {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Any color ratatui can parse: a name ("magenta") or "#rrggbb"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Overrides `ui.notifications` for this room
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<NotifyLevel>,
+}
+
+impl RoomLabel {
+    pub fn accent(&self) -> Option<Color> {
+        self.color.as_deref().and_then(|c| Color::from_str(c).ok())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.label.is_none() && self.color.is_none() && self.notify.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RoomLabels {
+    rooms: BTreeMap<String, RoomLabel>,
+    path: Option<PathBuf>,
+}
+
+impl RoomLabels {
+    pub fn load() -> Self {
+        let path = dirs::config_dir().map(|dir| dir.join("eurus").join("rooms.toml"));
+        let rooms = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self { rooms, path }
+    }
+
+    pub fn get(&self, room_id: &str) -> Option<&RoomLabel> {
+        self.rooms.get(room_id)
+    }
+
+    pub fn label(&self, room_id: &str) -> Option<&str> {
+        self.get(room_id).and_then(|room| room.label.as_deref())
+    }
+
+    /// The room's own notification level, if it has one
+    pub fn notify(&self, room_id: &str) -> Option<NotifyLevel> {
+        self.get(room_id).and_then(|room| room.notify)
+    }
+
+    /// Set or (with `None`) clear the label, then save
+    pub fn set_label(&mut self, room_id: &str, label: Option<String>) -> io::Result<()> {
+        self.update(room_id, |room| room.label = label)
+    }
+
+    /// Set or (with `None`) clear the accent color, then save
+    pub fn set_color(&mut self, room_id: &str, color: Option<String>) -> io::Result<()> {
+        self.update(room_id, |room| room.color = color)
+    }
+
+    /// Set or (with `None`) clear the notification level, then save
+    pub fn set_notify(&mut self, room_id: &str, level: Option<NotifyLevel>) -> io::Result<()> {
+        self.update(room_id, |room| room.notify = level)
+    }
+
+    fn update(&mut self, room_id: &str, change: impl FnOnce(&mut RoomLabel)) -> io::Result<()> {
+        let room = self.rooms.entry(room_id.to_string()).or_default();
+        change(room);
+        if room.is_empty() {
+            self.rooms.remove(room_id);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string(&self.rooms).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+}
+
+/// Short form of a room id for display, e.g. "3f8a…b2c1". Ids too short
+/// to gain anything from truncating are returned whole.
+pub fn short_room_id(room_id: &str) -> String {
+    const KEEP: usize = 4;
+    let chars: Vec<char> = room_id.chars().collect();
+    if chars.len() <= KEEP * 2 + 1 {
+        return room_id.to_string();
+    }
+    let head: String = chars[..KEEP].iter().collect();
+    let tail: String = chars[chars.len() - KEEP..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_round_trip_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eurus").join("rooms.toml");
+        let mut labels = RoomLabels { rooms: BTreeMap::new(), path: Some(path.clone()) };
+
+        labels.set_label("3f8a9c1d2e", Some("Team Standup".to_string())).unwrap();
+        labels.set_color("3f8a9c1d2e", Some("#ff8800".to_string())).unwrap();
+        labels.set_notify("3f8a9c1d2e", Some(NotifyLevel::Muted)).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("notify = \"none\""));
+        let saved: BTreeMap<String, RoomLabel> = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, labels.rooms);
+        assert_eq!(labels.label("3f8a9c1d2e"), Some("Team Standup"));
+        assert_eq!(labels.get("3f8a9c1d2e").unwrap().accent(), Some(Color::Rgb(0xff, 0x88, 0x00)));
+
+        labels.set_label("3f8a9c1d2e", None).unwrap();
+        labels.set_color("3f8a9c1d2e", None).unwrap();
+        assert_eq!(labels.notify("3f8a9c1d2e"), Some(NotifyLevel::Muted));
+        labels.set_notify("3f8a9c1d2e", None).unwrap();
+        assert!(labels.get("3f8a9c1d2e").is_none(), "empty entries are dropped");
+
+        assert_eq!(short_room_id("3f8a9c1d2e4b6a8c0d2e4f6a8b0cb2c1"), "3f8a…b2c1");
+        assert_eq!(short_room_id("3f8a9b2c1"), "3f8a9b2c1");
+        assert_eq!(short_room_id("abc"), "abc");
+        assert_eq!(short_room_id(""), "");
+    }
+}
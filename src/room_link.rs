@@ -0,0 +1,137 @@
+//! Room credentials as people share them: a `radiochat://` link, or a room
+//! id and hex key as "id:key", on separate lines, or labelled.
+//!
+//! ```text
+//! radiochat://join/3f8a9c...b2c1#key=0011...eeff
+//! radiochat://3f8a9c...b2c1?key=0011...eeff
+//! 3f8a9c...b2c1:0011...eeff
+//! ID: 3f8a9c...b2c1 / Key: 0011...eeff
+//! ```
+
+use crate::crypto::{key_from_hex, AesKey};
+
+pub const URI_SCHEME: &str = "radiochat://";
+
+/// Longest room id we'll send; created rooms use 32 hex characters
+pub const MAX_ROOM_ID_LEN: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomLink {
+    pub room_id: String,
+    pub key: Option<AesKey>,
+}
+
+/// Parse shared room credentials. Errors say what was wrong, for showing to
+/// the user.
+pub fn parse(text: &str) -> Result<RoomLink, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("nothing to parse".to_string());
+    }
+    let (room_id, key) = match text.strip_prefix(URI_SCHEME) {
+        Some(rest) => split_uri(rest),
+        None => split_pair(text),
+    };
+    if room_id.is_empty() {
+        return Err("no room id found".to_string());
+    }
+    check_room_id(room_id)?;
+    let key = match key {
+        Some(hex) => Some(key_from_hex(hex).ok_or_else(|| {
+            format!("room key must be 64 hex characters (got {})", hex.chars().count())
+        })?),
+        None => None,
+    };
+    Ok(RoomLink { room_id: room_id.to_string(), key })
+}
+
+/// Room ids are letters, digits, '-' and '_', at most `MAX_ROOM_ID_LEN` long.
+/// Anything else is refused before it reaches the server or a title bar.
+pub fn check_room_id(room_id: &str) -> Result<(), String> {
+    if room_id.is_empty() {
+        return Err("room id is empty".to_string());
+    }
+    if room_id.len() > MAX_ROOM_ID_LEN {
+        return Err(format!("room id is {} characters, at most {} allowed", room_id.len(), MAX_ROOM_ID_LEN));
+    }
+    match room_id.chars().find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_')) {
+        Some(c) => Err(format!("room id can't contain {:?}", c)),
+        None => Ok(()),
+    }
+}
+
+/// `join/<id>?key=<hex>`, `<id>#key=<hex>` or `<id>#<hex>`
+fn split_uri(rest: &str) -> (&str, Option<&str>) {
+    let rest = rest.strip_prefix("join/").unwrap_or(rest);
+    let (room_id, params) = match rest.find(['?', '#']) {
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+        None => (rest, None),
+    };
+    let key = params.and_then(|params| {
+        params
+            .split(['&', '#'])
+            .find_map(|param| param.strip_prefix("key="))
+            .or_else(|| (!params.contains('=')).then_some(params))
+    });
+    (room_id.trim_end_matches('/'), key.filter(|key| !key.is_empty()))
+}
+
+/// "id:key", "id key" or the two on separate lines; a lone word is an id
+fn split_pair(text: &str) -> (&str, Option<&str>) {
+    if let Some(labelled) = split_labelled(text) {
+        return labelled;
+    }
+    if let Some((room_id, key)) = text.split_once(':') {
+        if !key.contains(':') {
+            return (room_id.trim(), Some(key.trim()));
+        }
+    }
+    let mut words = text.split_whitespace();
+    let room_id = words.next().unwrap_or("");
+    (room_id, words.next())
+}
+
+/// "ID: x" and "Key: y", on separate lines or joined by " / "
+fn split_labelled(text: &str) -> Option<(&str, Option<&str>)> {
+    let mut room_id = None;
+    let mut key = None;
+    for part in text.split(['\n', '/']) {
+        let Some((label, value)) = part.split_once(':') else { continue };
+        match label.trim().to_ascii_lowercase().as_str() {
+            "id" | "room" | "room id" => room_id = Some(value.trim()),
+            "key" | "room key" => key = Some(value.trim()),
+            _ => {}
+        }
+    }
+    room_id.map(|room_id| (room_id, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_room_links() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let key = key_from_hex(hex);
+        for text in [
+            format!("radiochat://join/abc123#key={}", hex),
+            format!("radiochat://abc123?key={}", hex),
+            format!("radiochat://abc123#{}", hex),
+            format!("abc123:{}", hex),
+            format!("  abc123\n{}\n", hex),
+            format!("ID: abc123 / Key: {}", hex),
+            format!("Room ID: abc123\nRoom key: {}", hex),
+        ] {
+            assert_eq!(parse(&text), Ok(RoomLink { room_id: "abc123".to_string(), key }), "{}", text);
+        }
+
+        assert_eq!(parse("radiochat://abc123"), Ok(RoomLink { room_id: "abc123".to_string(), key: None }));
+        assert!(parse("abc123:00ff").unwrap_err().contains("64 hex"));
+        assert!(parse("radiochat://?key=00").is_err());
+        assert!(parse("   ").is_err());
+        assert!(parse("abc\u{1b}[2J").unwrap_err().contains("can't contain"));
+        assert!(parse(&"a".repeat(MAX_ROOM_ID_LEN + 1)).unwrap_err().contains("at most"));
+        assert!(check_room_id("0b6c41d2-7e8a-4f0e-9a51-3c2d_x").is_ok());
+    }
+}
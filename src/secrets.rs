@@ -0,0 +1,320 @@
+//! Credentials kept out of plain files when `auth.keyring` is on: the login
+//! token and remembered room keys, each stored under a name.
+//!
+//! They go to the OS keyring when there is one: the Secret Service (GNOME
+//! Keyring, KWallet) on Linux and the BSDs, the Keychain on macOS, and the
+//! Credential Manager on Windows. Elsewhere, and on machines with no keyring
+//! running, they go to `~/.config/eurus/secrets.toml`, each value encrypted
+//! under a key derived from a passphrase asked for at startup:
+//!
+//! ```toml
+//! salt = "5d1e..."
+//! rounds = 600000
+//! check = "a3f0..."
+//!
+//! [entries]
+//! token = "9c01..."
+//! "room-key:3f8a9c..." = "77b2..."
+//! ```
+
+use crate::crypto::{decrypt_with_aad, encrypt_with_aad, AesKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Name the login token is stored under
+pub const TOKEN: &str = "token";
+
+/// Name a room's key is stored under
+pub fn room_key_entry(room_id: &str) -> String {
+    format!("room-key:{}", room_id)
+}
+
+/// PBKDF2-HMAC-SHA256 rounds for new secret files
+const ROUNDS: u32 = 600_000;
+
+/// Encrypted in every secret file so a wrong passphrase is caught on open,
+/// before anything is written under it
+const CHECK_TEXT: &str = "eurus secrets";
+
+/// What each value is bound to, so a sealed value only opens under its own
+/// name and can't be swapped with another entry's
+const CHECK_AAD: &[u8] = b"eurus:check";
+
+fn entry_aad(name: &str) -> Vec<u8> {
+    format!("eurus:entry:{}", name).into_bytes()
+}
+
+/// Times the passphrase is asked for before giving up
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+#[derive(Debug)]
+pub struct SecretError(String);
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+impl From<io::Error> for SecretError {
+    fn from(e: io::Error) -> Self {
+        SecretError(e.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub enum Secrets {
+    Keyring,
+    File(SecretFile),
+}
+
+impl Secrets {
+    /// The OS keyring, or the passphrase-protected file if there's no keyring
+    /// to talk to. The passphrase is read from the terminal, so call this
+    /// before the TUI takes it over.
+    pub fn open() -> Result<Self, SecretError> {
+        if os_keyring::available() {
+            return Ok(Secrets::Keyring);
+        }
+        let path = dirs::config_dir()
+            .map(|dir| dir.join("eurus").join("secrets.toml"))
+            .ok_or_else(|| SecretError("Config directory not found".to_string()))?;
+        let prompt = if path.exists() {
+            format!("No OS keyring found. Passphrase for {}: ", path.display())
+        } else {
+            format!("No OS keyring found. Choose a passphrase to protect {}: ", path.display())
+        };
+        for _ in 0..PASSPHRASE_ATTEMPTS {
+            let passphrase = rpassword::prompt_password(&prompt)?;
+            match SecretFile::open(path.clone(), &passphrase, ROUNDS) {
+                Ok(file) => return Ok(Secrets::File(file)),
+                Err(e) if path.exists() => eprintln!("{}", e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(SecretError("Couldn't unlock the secrets file".to_string()))
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<String>, SecretError> {
+        match self {
+            Secrets::Keyring => os_keyring::get(name),
+            Secrets::File(file) => file.get(name),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), SecretError> {
+        match self {
+            Secrets::Keyring => os_keyring::set(name, value),
+            Secrets::File(file) => file.set(name, value),
+        }
+    }
+
+    /// Remove `name`; not an error if it isn't there
+    pub fn delete(&mut self, name: &str) -> Result<(), SecretError> {
+        match self {
+            Secrets::Keyring => os_keyring::delete(name),
+            Secrets::File(file) => file.delete(name),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecretFileContents {
+    salt: String,
+    rounds: u32,
+    check: String,
+    #[serde(default)]
+    entries: BTreeMap<String, String>,
+}
+
+/// Secrets encrypted with AES-256-GCM under a PBKDF2 key from the passphrase,
+/// each with its name as associated data
+pub struct SecretFile {
+    contents: SecretFileContents,
+    key: AesKey,
+    path: PathBuf,
+}
+
+impl fmt::Debug for SecretFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretFile").field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+impl SecretFile {
+    /// Unlock the file at `path`, or start a new one protected by `passphrase`
+    /// (with `rounds` of PBKDF2) if there's none yet
+    fn open(path: PathBuf, passphrase: &str, rounds: u32) -> Result<Self, SecretError> {
+        if passphrase.is_empty() {
+            return Err(SecretError("The passphrase can't be empty".to_string()));
+        }
+        let existing = match fs::read_to_string(&path) {
+            Ok(text) => Some(text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let Some(text) = existing else {
+            let mut salt = [0u8; 16];
+            rand::rngs::OsRng.fill_bytes(&mut salt);
+            let key = derive_key(passphrase, &salt, rounds);
+            let check = encrypt_with_aad(&key, CHECK_TEXT.as_bytes(), CHECK_AAD).map_err(|e| SecretError(e.to_string()))?;
+            let contents = SecretFileContents { salt: hex::encode(salt), rounds, check, entries: BTreeMap::new() };
+            let file = SecretFile { contents, key, path };
+            file.save()?;
+            return Ok(file);
+        };
+        let contents: SecretFileContents = toml::from_str(&text)
+            .map_err(|e| SecretError(format!("{} is damaged: {}", path.display(), e)))?;
+        let salt = hex::decode(&contents.salt).map_err(|e| SecretError(format!("Bad salt in {}: {}", path.display(), e)))?;
+        let key = derive_key(passphrase, &salt, contents.rounds);
+        if decrypt_with_aad(&key, &contents.check, CHECK_AAD).ok().as_deref() != Some(CHECK_TEXT.as_bytes()) {
+            return Err(SecretError("Wrong passphrase".to_string()));
+        }
+        Ok(SecretFile { contents, key, path })
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>, SecretError> {
+        self.contents
+            .entries
+            .get(name)
+            .map(|sealed| {
+                let value = decrypt_with_aad(&self.key, sealed, &entry_aad(name))
+                    .map_err(|e| SecretError(format!("Can't read {}: {}", name, e)))?;
+                String::from_utf8(value).map_err(|e| SecretError(format!("Can't read {}: {}", name, e)))
+            })
+            .transpose()
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), SecretError> {
+        let sealed = encrypt_with_aad(&self.key, value.as_bytes(), &entry_aad(name)).map_err(|e| SecretError(e.to_string()))?;
+        self.contents.entries.insert(name.to_string(), sealed);
+        self.save()
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), SecretError> {
+        if self.contents.entries.remove(name).is_none() {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), SecretError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let text = toml::to_string(&self.contents).map_err(|e| SecretError(e.to_string()))?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&self.path)?.write_all(text.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> AesKey {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+    *AesKey::from_slice(&key)
+}
+
+/// The OS keyring, through the `keyring` crate. Entries are stored under
+/// the service `eurus` with the entry's name as the account.
+mod os_keyring {
+    use super::SecretError;
+    use keyring::{Entry, Error};
+
+    const SERVICE: &str = "eurus";
+
+    fn entry(name: &str) -> Result<Entry, SecretError> {
+        Entry::new(SERVICE, name).map_err(failure)
+    }
+
+    fn failure(e: Error) -> SecretError {
+        SecretError(format!("Keyring: {}", e))
+    }
+
+    /// A keyring answers: looking up an entry that was never stored finds
+    /// nothing, where one with no keyring behind it fails. Platforms without
+    /// a backend would get the crate's in-memory mock, which forgets
+    /// everything on exit, so they use the file instead.
+    pub fn available() -> bool {
+        let supported = cfg!(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "macos",
+            target_os = "windows",
+        ));
+        supported && Entry::new(SERVICE, "probe").is_ok_and(|entry| matches!(entry.get_password(), Ok(_) | Err(Error::NoEntry)))
+    }
+
+    pub fn get(name: &str) -> Result<Option<String>, SecretError> {
+        match entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NoEntry) => Ok(None),
+            Err(e) => Err(failure(e)),
+        }
+    }
+
+    pub fn set(name: &str, value: &str) -> Result<(), SecretError> {
+        entry(name)?.set_password(value).map_err(failure)
+    }
+
+    pub fn delete(name: &str) -> Result<(), SecretError> {
+        match entry(name)?.delete_credential() {
+            Ok(()) | Err(Error::NoEntry) => Ok(()),
+            Err(e) => Err(failure(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eurus").join("secrets.toml");
+        let mut file = SecretFile::open(path.clone(), "correct horse", 1_000).unwrap();
+        file.set(TOKEN, "eyJhbGciOi.token").unwrap();
+        file.set(&room_key_entry("room-1"), "00ff").unwrap();
+        file.delete("never-stored").unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("eyJhbGciOi") && !text.contains("00ff"), "stored in the clear: {}", text);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        let debug = format!("{:?}", file);
+        assert!(!debug.contains(&format!("{:?}", file.key)) && !debug.contains("entries"), "leaks the key: {}", debug);
+
+        assert_eq!(SecretFile::open(path.clone(), "wrong horse", 1_000).unwrap_err().to_string(), "Wrong passphrase");
+        assert!(SecretFile::open(path.clone(), "", 1_000).is_err());
+        let mut reopened = SecretFile::open(path.clone(), "correct horse", ROUNDS).unwrap();
+        assert_eq!(reopened.contents.rounds, 1_000, "rounds come from the file");
+        assert_eq!(reopened.get(TOKEN).unwrap().as_deref(), Some("eyJhbGciOi.token"));
+        assert_eq!(reopened.get(&room_key_entry("room-1")).unwrap().as_deref(), Some("00ff"));
+        assert_eq!(reopened.get(&room_key_entry("room-2")).unwrap(), None);
+
+        // A value moved under another name doesn't open
+        let token = reopened.contents.entries[TOKEN].clone();
+        reopened.contents.entries.insert(room_key_entry("room-1"), token);
+        assert!(reopened.get(&room_key_entry("room-1")).is_err());
+
+        reopened.delete(TOKEN).unwrap();
+        let reopened = SecretFile::open(path, "correct horse", 1_000).unwrap();
+        assert_eq!(reopened.get(TOKEN).unwrap(), None);
+    }
+}
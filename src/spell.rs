@@ -0,0 +1,218 @@
+//! Optional spell-checking for the message composer.
+//!
+//! Words are checked against a system word list plus a personal dictionary
+//! (`~/.config/eurus/dictionary.txt`). Results are cached per composer line so
+//! an edit only re-checks the line that changed.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Word lists tried when no dictionary is configured
+const SYSTEM_DICTIONARIES: &[&str] = &["/usr/share/dict/words", "/usr/dict/words"];
+
+pub struct SpellChecker {
+    words: HashSet<String>,
+    personal: HashSet<String>,
+    personal_path: Option<PathBuf>,
+    /// (line text, misspelled words) for each composer line last checked
+    line_cache: Vec<(String, Vec<String>)>,
+}
+
+impl SpellChecker {
+    /// Load the configured dictionary, or the first system word list found
+    pub fn load(dictionary_path: Option<&str>) -> Result<Self, String> {
+        let candidates: Vec<&str> = match dictionary_path {
+            Some(path) => vec![path],
+            None => SYSTEM_DICTIONARIES.to_vec(),
+        };
+        let contents = candidates
+            .iter()
+            .find_map(|path| fs::read_to_string(path).ok())
+            .ok_or_else(|| match dictionary_path {
+                Some(path) => format!("Can't read dictionary {}", path),
+                None => "No word list found; set ui.spellcheck_dictionary".to_string(),
+            })?;
+
+        let personal_path = dirs::config_dir().map(|dir| dir.join("eurus").join("dictionary.txt"));
+        let personal = personal_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| parse_words(&contents))
+            .unwrap_or_default();
+
+        Ok(Self {
+            words: parse_words(&contents),
+            personal,
+            personal_path,
+            line_cache: Vec::new(),
+        })
+    }
+
+    fn is_known(&self, word: &str) -> bool {
+        let lower = word.to_lowercase();
+        let stem = lower.strip_suffix("'s").unwrap_or(&lower);
+        self.words.contains(stem) || self.personal.contains(stem)
+    }
+
+    /// Misspelled words across `lines`, re-checking only lines that changed
+    pub fn check_lines(&mut self, lines: &[String]) -> Vec<String> {
+        self.line_cache.truncate(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            if self.line_cache.get(i).is_some_and(|(cached, _)| cached == line) {
+                continue;
+            }
+            let misspelled = candidate_words(line)
+                .filter(|word| !self.is_known(word))
+                .map(str::to_string)
+                .collect();
+            if i < self.line_cache.len() {
+                self.line_cache[i] = (line.clone(), misspelled);
+            } else {
+                self.line_cache.push((line.clone(), misspelled));
+            }
+        }
+
+        let mut all: Vec<String> = self.line_cache.iter().flat_map(|(_, words)| words.iter().cloned()).collect();
+        all.sort();
+        all.dedup();
+        all
+    }
+
+    /// Remember `word` in the personal dictionary file
+    pub fn add_word(&mut self, word: &str) -> io::Result<()> {
+        let word = word.to_lowercase();
+        if !self.personal.insert(word.clone()) {
+            return Ok(());
+        }
+        self.line_cache.clear();
+
+        if let Some(path) = &self.personal_path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", word)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_words(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Words worth checking: purely alphabetic (apostrophes allowed), longer than
+/// one letter. Skips things like URLs, numbers, :shortcodes: and mixed tokens.
+fn candidate_words(line: &str) -> impl Iterator<Item = &str> {
+    line.split_whitespace()
+        .map(|token| token.trim_matches(|c: char| !c.is_alphanumeric() && c != '\''))
+        .map(|token| token.trim_matches('\''))
+        .filter(|word| word.chars().count() > 1 && word.chars().all(|c| c.is_alphabetic() || c == '\''))
+}
+
+/// Case-insensitive regex matching any of `words` as whole words, or an empty
+/// pattern (which clears highlighting) when there are none
+pub fn highlight_pattern(words: &[String]) -> String {
+    if words.is_empty() {
+        return String::new();
+    }
+    let alternatives: Vec<String> = words.iter().map(|w| regex::escape(w)).collect();
+    format!(r"(?i)\b(?:{})\b", alternatives.join("|"))
+}
+
+/// The word under (or just before) the cursor column in `line`
+pub fn word_at(line: &str, col: usize) -> Option<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: &char| c.is_alphabetic() || *c == '\'';
+    let mut start = col.min(chars.len());
+    if start == chars.len() || !is_word(&chars[start]) {
+        start = start.checked_sub(1).filter(|&i| is_word(&chars[i]))?;
+    }
+    while start > 0 && is_word(&chars[start - 1]) {
+        start -= 1;
+    }
+    let end = (start..chars.len()).find(|&i| !is_word(&chars[i])).unwrap_or(chars.len());
+    let word: String = chars[start..end].iter().collect();
+    let word = word.trim_matches('\'').to_string();
+    (!word.is_empty()).then_some(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker(words: &str) -> SpellChecker {
+        SpellChecker {
+            words: parse_words(words),
+            personal: HashSet::new(),
+            personal_path: None,
+            line_cache: Vec::new(),
+        }
+    }
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_candidate_words() {
+        let words: Vec<&str> = candidate_words("Hello, world! 'quoted' don't (aside)").collect();
+        assert_eq!(words, ["Hello", "world", "quoted", "don't", "aside"]);
+
+        // Links, numbers, shortcodes, mixed tokens and single letters are skipped
+        let words: Vec<&str> = candidate_words("see https://x.io 42 :wave: abc123 a I ok").collect();
+        assert_eq!(words, ["see", "wave", "ok"]);
+    }
+
+    #[test]
+    fn test_check_lines() {
+        let mut spell = checker("the\ncat\nSat\n");
+        assert_eq!(spell.check_lines(&lines(&["The cat sat"])), Vec::<String>::new());
+        assert_eq!(spell.check_lines(&lines(&["the cat's hat, teh cat"])), ["hat", "teh"]);
+
+        // Duplicates across lines are reported once, in order
+        assert_eq!(spell.check_lines(&lines(&["zzz teh", "teh"])), ["teh", "zzz"]);
+        // Dropped lines drop their words
+        assert_eq!(spell.check_lines(&lines(&["teh"])), ["teh"]);
+        assert!(spell.check_lines(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_personal_words() {
+        let mut spell = checker("the\n");
+        assert_eq!(spell.check_lines(&lines(&["the Eurus"])), ["Eurus"]);
+        spell.add_word("Eurus").unwrap();
+        assert!(spell.check_lines(&lines(&["the Eurus"])).is_empty(), "added words clear the cache");
+        assert!(spell.is_known("EURUS's"));
+        spell.add_word("eurus").unwrap();
+        assert_eq!(spell.personal.len(), 1);
+    }
+
+    #[test]
+    fn test_highlight_pattern() {
+        assert_eq!(highlight_pattern(&[]), "");
+        let pattern = highlight_pattern(&["teh".to_string(), "a.b".to_string()]);
+        let re = regex::Regex::new(&pattern).unwrap();
+        assert!(re.is_match("TEH cat"));
+        assert!(!re.is_match("tehran"));
+        assert!(!re.is_match("axb"), "words are matched literally");
+    }
+
+    #[test]
+    fn test_word_at() {
+        let line = "fix teh 'typo'";
+        assert_eq!(word_at(line, 0).as_deref(), Some("fix"));
+        assert_eq!(word_at(line, 5).as_deref(), Some("teh"));
+        // Just past a word counts, inside the quotes too
+        assert_eq!(word_at(line, 7).as_deref(), Some("teh"));
+        assert_eq!(word_at(line, 10).as_deref(), Some("typo"));
+        assert_eq!(word_at(line, 99).as_deref(), Some("typo"), "a cursor at the end of the line");
+        assert_eq!(word_at("a  b", 2), None);
+    }
+}
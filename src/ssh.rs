@@ -169,13 +169,55 @@ pub fn scan_ssh_key_files() -> Vec<SshKey> {
         }
     }
 
-    // Sort: ed25519 keys first, then by name
+    sort_keys(&mut keys);
+    keys
+}
+
+/// Sort: ed25519 keys first, then by name
+fn sort_keys(keys: &mut [SshKey]) {
     keys.sort_by(|a, b| match (&a.key_type[..], &b.key_type[..]) {
         ("ed25519", "rsa") => std::cmp::Ordering::Less,
         ("rsa", "ed25519") => std::cmp::Ordering::Greater,
         _ => a.name.cmp(&b.name),
     });
+}
 
+/// Private key file in ~/.ssh for `public_key`, if there is one
+pub fn find_key_file(public_key: &str) -> Option<PathBuf> {
+    let identity = key_identity(public_key);
+    scan_ssh_key_files().into_iter().find_map(|key| match key.source {
+        KeySource::File(path) if key_identity(&key.public_key) == identity => Some(path),
+        _ => None,
+    })
+}
+
+/// Identity used to deduplicate keys across sources.
+/// Uses the SHA256 fingerprint, falling back to the raw key blob if parsing fails.
+fn key_identity(public_key: &str) -> String {
+    match PublicKey::from_openssh(public_key) {
+        Ok(pk) => pk.fingerprint(ssh_key::HashAlg::Sha256).to_string(),
+        Err(_) => public_key
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or(public_key)
+            .to_string(),
+    }
+}
+
+/// Merge agent and file keys into one list.
+/// A key present in both is kept once with the agent as its source, since
+/// signing through the agent never needs a passphrase.
+fn merge_keys(agent_keys: Vec<SshKey>, file_keys: Vec<SshKey>) -> Vec<SshKey> {
+    let mut seen = std::collections::HashSet::new();
+    let mut keys = Vec::new();
+
+    for key in agent_keys.into_iter().chain(file_keys) {
+        if seen.insert(key_identity(&key.public_key)) {
+            keys.push(key);
+        }
+    }
+
+    sort_keys(&mut keys);
     keys
 }
 
@@ -186,37 +228,12 @@ pub fn sign_with_file(
     data: &[u8],
     passphrase: Option<&str>,
 ) -> Result<Vec<u8>, SignError> {
-    use ssh_key::private::PrivateKey;
-
-    // Read private key file
-    let key_data =
-        fs::read_to_string(private_key_path).map_err(|e| SignError::FileRead(e.to_string()))?;
-
-    // Try to parse the private key
-    let private_key = if let Some(pass) = passphrase {
-        // Key is encrypted, decrypt with passphrase
-        PrivateKey::from_openssh(&key_data)
-            .map_err(|e| SignError::Parse(e.to_string()))
-            .and_then(|key| {
-                if key.is_encrypted() {
-                    key.decrypt(pass.as_bytes())
-                        .map_err(|e| SignError::Decrypt(e.to_string()))
-                } else {
-                    Ok(key)
-                }
-            })?
-    } else {
-        match PrivateKey::from_openssh(&key_data) {
-            Ok(key) => {
-                if key.is_encrypted() {
-                    return Err(SignError::NeedsPassphrase);
-                }
-                key
-            }
-            Err(e) => return Err(SignError::Parse(e.to_string())),
-        }
-    };
+    let private_key = load_private_key(private_key_path, passphrase)?;
+    sign_with_private_key(&private_key, data)
+}
 
+/// Sign data with a private key that has already been read (and decrypted)
+fn sign_with_private_key(private_key: &ssh_key::PrivateKey, data: &[u8]) -> Result<Vec<u8>, SignError> {
     // Sign the data based on key type
     let signature_bytes = match private_key.key_data() {
         ssh_key::private::KeypairData::Ed25519(keypair) => {
@@ -235,11 +252,11 @@ pub fn sign_with_file(
             use sha2::Sha256;
 
             // Reconstruct RSA private key from components
-            let n = rsa::BigUint::from_bytes_be(&keypair.public.n.as_bytes());
-            let e = rsa::BigUint::from_bytes_be(&keypair.public.e.as_bytes());
-            let d = rsa::BigUint::from_bytes_be(&keypair.private.d.as_bytes());
-            let p = rsa::BigUint::from_bytes_be(&keypair.private.p.as_bytes());
-            let q = rsa::BigUint::from_bytes_be(&keypair.private.q.as_bytes());
+            let n = rsa::BigUint::from_bytes_be(keypair.public.n.as_bytes());
+            let e = rsa::BigUint::from_bytes_be(keypair.public.e.as_bytes());
+            let d = rsa::BigUint::from_bytes_be(keypair.private.d.as_bytes());
+            let p = rsa::BigUint::from_bytes_be(keypair.private.p.as_bytes());
+            let q = rsa::BigUint::from_bytes_be(keypair.private.q.as_bytes());
 
             let primes = vec![p, q];
             let rsa_key = RsaPrivateKey::from_components(n, e, d, primes)
@@ -255,6 +272,56 @@ pub fn sign_with_file(
     Ok(signature_bytes)
 }
 
+/// Read a private key file, decrypting it with `passphrase` if it's encrypted
+pub fn load_private_key(
+    private_key_path: &Path,
+    passphrase: Option<&str>,
+) -> Result<ssh_key::PrivateKey, SignError> {
+    use ssh_key::private::PrivateKey;
+
+    // Read private key file
+    let key_data =
+        fs::read_to_string(private_key_path).map_err(|e| SignError::FileRead(e.to_string()))?;
+
+    // Try to parse the private key
+    if let Some(pass) = passphrase {
+        // Key is encrypted, decrypt with passphrase
+        PrivateKey::from_openssh(&key_data)
+            .map_err(|e| SignError::Parse(e.to_string()))
+            .and_then(|key| {
+                if key.is_encrypted() {
+                    key.decrypt(pass.as_bytes())
+                        .map_err(|e| SignError::Decrypt(e.to_string()))
+                } else {
+                    Ok(key)
+                }
+            })
+    } else {
+        match PrivateKey::from_openssh(&key_data) {
+            Ok(key) => {
+                if key.is_encrypted() {
+                    return Err(SignError::NeedsPassphrase);
+                }
+                Ok(key)
+            }
+            Err(e) => Err(SignError::Parse(e.to_string())),
+        }
+    }
+}
+
+/// ed25519 private keys in ~/.ssh that open without a passphrase, for
+/// reading sealed whispers. Agent-held and encrypted keys can't be used.
+pub fn unlocked_ed25519_keys() -> Vec<ssh_key::PrivateKey> {
+    scan_ssh_key_files()
+        .into_iter()
+        .filter(|key| key.key_type == "ed25519")
+        .filter_map(|key| match key.source {
+            KeySource::File(path) => load_private_key(&path, None).ok(),
+            KeySource::Agent => None,
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 pub enum SignError {
     FileRead(String),
@@ -276,19 +343,126 @@ impl std::fmt::Display for SignError {
     }
 }
 
-/// Get all available SSH keys (from agent first, then files as fallback)
+/// Where a first-run key is generated: ~/.ssh/id_ed25519
+pub fn default_key_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".ssh").join("id_ed25519"))
+}
+
+/// Generate an ed25519 key pair without a passphrase at `path` and
+/// `path.pub`. Never overwrites: fails if either file already exists. The
+/// private key is created 0600, and a missing directory 0700.
+pub fn generate_ed25519_key(path: &Path, comment: &str) -> Result<SshKey, String> {
+    use ssh_key::{Algorithm, LineEnding, PrivateKey};
+    use std::io::Write;
+
+    let public_path = path.with_extension("pub");
+    for existing in [path, public_path.as_path()] {
+        if existing.exists() {
+            return Err(format!("{} already exists", existing.display()));
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder
+            .create(dir)
+            .map_err(|e| format!("Can't create {}: {}", dir.display(), e))?;
+    }
+
+    let mut key = PrivateKey::random(&mut rand::rngs::OsRng, Algorithm::Ed25519)
+        .map_err(|e| format!("Key generation failed: {}", e))?;
+    key.set_comment(comment);
+    let private_pem = key
+        .to_openssh(LineEnding::LF)
+        .map_err(|e| format!("Can't encode key: {}", e))?;
+    let public_line = key
+        .public_key()
+        .to_openssh()
+        .map_err(|e| format!("Can't encode key: {}", e))?;
+
+    // create_new so a file appearing since the check above is left alone
+    let write_new = |target: &Path, contents: &[u8], mode: u32| {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+        options
+            .open(target)
+            .and_then(|mut file| file.write_all(contents))
+            .map_err(|e| format!("Can't write {}: {}", target.display(), e))
+    };
+    write_new(path, private_pem.as_bytes(), 0o600)?;
+    write_new(&public_path, format!("{}\n", public_line).as_bytes(), 0o644)?;
+
+    Ok(SshKey {
+        name: path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("id_ed25519")
+            .to_string(),
+        public_key: public_line,
+        key_type: "ed25519".to_string(),
+        source: KeySource::File(path.to_path_buf()),
+    })
+}
+
+/// Get all available SSH keys from both ssh-agent and ~/.ssh.
+/// Returns the merged key list and whether the agent was reachable.
 pub fn get_available_keys() -> (Vec<SshKey>, bool) {
-    // Try agent first
-    match list_agent_keys() {
-        Ok(keys) if !keys.is_empty() => (keys, true),
-        _ => {
-            // Fall back to file-based keys
-            let keys = scan_ssh_key_files();
-            (keys, false)
+    let (agent_keys, agent_reachable) = match list_agent_keys() {
+        Ok(keys) => (keys, true),
+        Err(_) => (Vec::new(), false),
+    };
+
+    let keys = merge_keys(agent_keys, scan_ssh_key_files());
+    (keys, agent_reachable)
+}
+
+/// The available key with the same fingerprint as `public_key`
+pub fn find_key(public_key: &str) -> Option<SshKey> {
+    let identity = key_identity(public_key);
+    get_available_keys().0.into_iter().find(|key| key_identity(&key.public_key) == identity)
+}
+
+/// A key that can sign for the rest of the session without asking for
+/// anything: held by ssh-agent, or a private key already read from its file
+/// (and unlocked, if it has a passphrase)
+#[derive(Clone)]
+pub struct SigningKey {
+    pub key: SshKey,
+    private: Option<ssh_key::PrivateKey>,
+}
+
+impl SigningKey {
+    /// Ready `key` for signing. File keys are read now, so an encrypted one
+    /// without `passphrase` fails with `SignError::NeedsPassphrase`.
+    pub fn open(key: &SshKey, passphrase: Option<&str>) -> Result<Self, SignError> {
+        let private = match &key.source {
+            KeySource::Agent => None,
+            KeySource::File(path) => Some(load_private_key(path, passphrase)?),
+        };
+        Ok(Self { key: key.clone(), private })
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        match &self.private {
+            Some(private) => sign_with_private_key(private, data),
+            None => sign_with_agent(&self.key.public_key, data).map_err(SignError::Sign),
         }
     }
 }
 
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SigningKey({})", self.key.name)
+    }
+}
+
 /// Sign a challenge using the appropriate method based on key source
 pub fn sign_challenge(
     key: &SshKey,
@@ -314,4 +488,97 @@ mod tests {
             println!("  - {} ({})", key.name, key.key_type);
         }
     }
+
+    #[test]
+    fn test_signing_key_unlocks_once() {
+        use ssh_key::private::{Ed25519Keypair, PrivateKey};
+        use ssh_key::LineEnding;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("id_ed25519");
+        let private = PrivateKey::from(Ed25519Keypair::from_seed(&[5; 32]));
+        let locked = private.encrypt(&mut rand::rngs::OsRng, "hunter2").unwrap();
+        fs::write(&path, locked.to_openssh(LineEnding::LF).unwrap()).unwrap();
+        let key = SshKey {
+            name: "id_ed25519".to_string(),
+            public_key: private.public_key().to_openssh().unwrap(),
+            key_type: "ed25519".to_string(),
+            source: KeySource::File(path.clone()),
+        };
+
+        assert!(matches!(SigningKey::open(&key, None), Err(SignError::NeedsPassphrase)));
+        assert!(matches!(SigningKey::open(&key, Some("wrong")), Err(SignError::Decrypt(_))));
+        let signing = SigningKey::open(&key, Some("hunter2")).unwrap();
+        assert_eq!(format!("{:?}", signing), "SigningKey(id_ed25519)");
+
+        // Still signs after the file is gone: nothing is asked for again
+        fs::remove_file(&path).unwrap();
+        let sig = signing.sign(b"hello").unwrap();
+        let public = private.public_key().key_data().ed25519().unwrap().0;
+        let verifying = ed25519_dalek::VerifyingKey::from_bytes(&public).unwrap();
+        let sig = ed25519_dalek::Signature::from_slice(&sig).unwrap();
+        assert!(verifying.verify_strict(b"hello", &sig).is_ok());
+    }
+
+    #[test]
+    fn test_generate_ed25519_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ssh").join("id_ed25519");
+
+        let key = generate_ed25519_key(&path, "alice@laptop").unwrap();
+        assert!(key.public_key.starts_with("ssh-ed25519 ") && key.public_key.ends_with(" alice@laptop"));
+        let private = load_private_key(&path, None).unwrap();
+        assert_eq!(private.public_key().to_openssh().unwrap(), key.public_key);
+        assert_eq!(fs::read_to_string(path.with_extension("pub")).unwrap().trim(), key.public_key);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // A second run refuses rather than replacing the key
+        let before = fs::read(&path).unwrap();
+        assert!(generate_ed25519_key(&path, "alice@laptop").unwrap_err().contains("already exists"));
+        assert_eq!(fs::read(&path).unwrap(), before);
+    }
+
+    const ALICE: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAICzoGp7lZbbJYffMz1UAtUcYTjmAwk2iT0pZhIDEv66C alice@laptop";
+    const BOB: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIPxzwTOV4rjsSJSrw6C3FgT8j80X0OOMi0sA/igrEIol bob@desk";
+
+    fn key(name: &str, public_key: &str, key_type: &str, source: KeySource) -> SshKey {
+        SshKey {
+            name: name.to_string(),
+            public_key: public_key.to_string(),
+            key_type: key_type.to_string(),
+            source,
+        }
+    }
+
+    #[test]
+    fn test_merge_keys_prefers_agent_for_duplicates() {
+        let agent = vec![key("alice@laptop", ALICE, "ed25519", KeySource::Agent)];
+        let files = vec![
+            key("id_alice", ALICE, "ed25519", KeySource::File(PathBuf::from("/tmp/id_alice"))),
+            key("id_bob", BOB, "ed25519", KeySource::File(PathBuf::from("/tmp/id_bob"))),
+        ];
+
+        let merged = merge_keys(agent, files);
+        assert_eq!(merged.len(), 2);
+
+        let alice = merged.iter().find(|k| k.public_key == ALICE).unwrap();
+        assert!(matches!(alice.source, KeySource::Agent));
+        let bob = merged.iter().find(|k| k.public_key == BOB).unwrap();
+        assert!(matches!(bob.source, KeySource::File(_)));
+    }
+
+    #[test]
+    fn test_merge_keys_keeps_ed25519_first() {
+        let agent = vec![key("a-rsa", "ssh-rsa AAAAB3Nza rsa", "rsa", KeySource::Agent)];
+        let files = vec![key("z-ed", BOB, "ed25519", KeySource::File(PathBuf::from("/tmp/z")))];
+
+        let merged = merge_keys(agent, files);
+        assert_eq!(merged[0].key_type, "ed25519");
+        assert_eq!(merged[1].key_type, "rsa");
+    }
 }
@@ -0,0 +1,98 @@
+//! TLS setup for the WebSocket connection.
+//!
+//! By default we trust the platform's native roots. Self-hosted servers can
+//! add a private CA via `network.ca_cert_path`, or (explicitly, and loudly)
+//! turn off certificate verification with `network.danger_accept_invalid_certs`.
+
+use anyhow::{anyhow, Context, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+use std::sync::Arc;
+use tokio_tungstenite::Connector;
+
+use crate::config::{expand_home, NetworkConfig};
+
+/// Build the connector for `connect_async_tls_with_config`.
+/// Returns `None` when the defaults are fine, so tungstenite picks its own.
+pub fn build_connector(network: &NetworkConfig) -> Result<Option<Connector>> {
+    if network.ca_cert_path.is_none() && !network.danger_accept_invalid_certs {
+        return Ok(None);
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| anyhow!("TLS setup failed: {}", e))?;
+
+    let config = if network.danger_accept_invalid_certs {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+            .with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_native_certs::load_native_certs().certs {
+            let _ = roots.add(cert);
+        }
+        if let Some(path) = &network.ca_cert_path {
+            let path = expand_home(path);
+            let certs = CertificateDer::pem_file_iter(&path)
+                .with_context(|| format!("Failed to read CA certificate {}", path.display()))?;
+            let mut added = 0;
+            for cert in certs {
+                let cert = cert.with_context(|| format!("Invalid PEM in {}", path.display()))?;
+                roots.add(cert).with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+                added += 1;
+            }
+            if added == 0 {
+                return Err(anyhow!("No certificates found in {}", path.display()));
+            }
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+
+    Ok(Some(Connector::Rustls(Arc::new(config))))
+}
+
+/// Accepts any server certificate. Handshake signatures are still checked so
+/// the session is at least bound to whatever key the server presented.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,486 @@
+//! The connection to the server, as a channel of JSON text messages.
+//!
+//! The app only talks to a `Box<dyn Transport>`; the WebSocket is the one
+//! real implementation, and tests (or a future long-poll or local-socket
+//! transport) can supply their own.
+
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, timeout, Duration};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::header::{HeaderName, HeaderValue, AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL};
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    client_async_tls_with_config, connect_async_tls_with_config, tungstenite::Message, Connector, MaybeTlsStream,
+    WebSocketStream,
+};
+
+use crate::config::{QueueFullPolicy, SendQueueConfig};
+use crate::proxy::Proxy;
+
+/// How long the writer waits to get a close frame out before giving up
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The connection has closed; nothing more will be sent or received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl std::fmt::Display for Closed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "connection closed")
+    }
+}
+
+impl Error for Closed {}
+
+/// Why a message couldn't be queued for the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    Closed,
+    /// The outgoing queue is at capacity and the policy is `Reject`
+    QueueFull,
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Closed => write!(f, "connection closed"),
+            SendError::QueueFull => write!(f, "send queue full"),
+        }
+    }
+}
+
+impl Error for SendError {}
+
+pub trait Transport: Send {
+    /// Queue a JSON message for the server
+    fn send(&self, json: String) -> Result<(), SendError>;
+
+    /// The next message from the server if one has arrived
+    fn try_recv(&mut self) -> Result<Option<String>, Closed>;
+
+    /// Wait for the next message from the server; `None` once closed
+    fn recv(&mut self) -> BoxFuture<'_, Option<String>>;
+
+    /// Messages rejected or dropped because the outgoing queue was full,
+    /// since the last call
+    fn take_overflow(&self) -> usize;
+
+    /// Messages queued but not yet written to the connection
+    fn queued(&self) -> usize;
+}
+
+/// Bounded queue of messages waiting to go to the server, shared between a
+/// transport and whatever writes them out
+pub struct OutgoingQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+    capacity: usize,
+    policy: QueueFullPolicy,
+    overflow: AtomicUsize,
+}
+
+struct QueueState {
+    messages: VecDeque<String>,
+    closed: bool,
+}
+
+impl OutgoingQueue {
+    pub fn new(capacity: usize, policy: QueueFullPolicy) -> Self {
+        Self {
+            state: Mutex::new(QueueState { messages: VecDeque::new(), closed: false }),
+            notify: Notify::new(),
+            capacity: capacity.max(1),
+            policy,
+            overflow: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, json: String) -> Result<(), SendError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.closed {
+            return Err(SendError::Closed);
+        }
+        if state.messages.len() >= self.capacity {
+            self.overflow.fetch_add(1, Ordering::Relaxed);
+            match self.policy {
+                QueueFullPolicy::Reject => return Err(SendError::QueueFull),
+                QueueFullPolicy::DropOldest => {
+                    state.messages.pop_front();
+                }
+            }
+        }
+        state.messages.push_back(json);
+        drop(state);
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Messages waiting to be written
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).messages.len()
+    }
+
+    /// The next queued message, without waiting
+    #[cfg(test)]
+    pub fn try_pop(&self) -> Option<String> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).messages.pop_front()
+    }
+
+    /// Wait for the next queued message; `None` once closed and drained
+    pub async fn pop(&self) -> Option<String> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(json) = state.messages.pop_front() {
+                    return Some(json);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Refuse further messages and wake the reader
+    pub fn close(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        self.notify.notify_one();
+    }
+}
+
+/// A transport over an outgoing queue and an incoming channel. The WebSocket
+/// transport is one of these fed by socket tasks; tests can hold the
+/// `ServerEnd` directly.
+pub struct ChannelTransport {
+    outgoing: Arc<OutgoingQueue>,
+    incoming: mpsc::UnboundedReceiver<String>,
+    /// Socket read task, stopped on drop. It would otherwise live until the
+    /// server closed its side, which a dead peer never does.
+    reader: Option<JoinHandle<()>>,
+}
+
+/// The other side of a `ChannelTransport`
+pub struct ServerEnd {
+    pub outgoing: Arc<OutgoingQueue>,
+    pub incoming: mpsc::UnboundedSender<String>,
+}
+
+impl ChannelTransport {
+    pub fn pair(capacity: usize, policy: QueueFullPolicy) -> (Self, ServerEnd) {
+        let outgoing = Arc::new(OutgoingQueue::new(capacity, policy));
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+        let transport = Self { outgoing: outgoing.clone(), incoming: incoming_rx, reader: None };
+        (transport, ServerEnd { outgoing, incoming: incoming_tx })
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&self, json: String) -> Result<(), SendError> {
+        self.outgoing.push(json)
+    }
+
+    fn try_recv(&mut self) -> Result<Option<String>, Closed> {
+        match self.incoming.try_recv() {
+            Ok(text) => Ok(Some(text)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(Closed),
+        }
+    }
+
+    fn recv(&mut self) -> BoxFuture<'_, Option<String>> {
+        self.incoming.recv().boxed()
+    }
+
+    fn take_overflow(&self) -> usize {
+        self.outgoing.overflow.swap(0, Ordering::Relaxed)
+    }
+
+    fn queued(&self) -> usize {
+        self.outgoing.len()
+    }
+}
+
+impl Drop for ChannelTransport {
+    fn drop(&mut self) {
+        // Lets the writer finish, which closes the socket
+        self.outgoing.close();
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+    }
+}
+
+/// Everything needed to build the WebSocket handshake request.
+/// Kept around so every retry gets a fresh `Request`.
+#[derive(Clone)]
+pub struct Handshake {
+    pub url: String,
+    pub token: Option<String>,
+    pub subprotocol: Option<String>,
+    pub extra_headers: BTreeMap<String, String>,
+}
+
+impl Handshake {
+    pub fn request(&self) -> Result<Request, String> {
+        let mut ws_url = self.url.clone();
+
+        // Older servers only read the token from the query string
+        if let Some(token) = &self.token {
+            let separator = if ws_url.contains('?') { '&' } else { '?' };
+            ws_url = format!("{}{}token={}", ws_url, separator, token);
+        }
+
+        let mut request = ws_url.into_client_request().map_err(|e| e.to_string())?;
+        let headers = request.headers_mut();
+
+        for (name, value) in &self.extra_headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name '{}'", name))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| format!("invalid value for header '{}'", name))?;
+            headers.insert(name, value);
+        }
+        if let Some(token) = &self.token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| "auth token contains characters not allowed in a header".to_string())?;
+            headers.insert(AUTHORIZATION, value);
+        }
+        if let Some(protocol) = &self.subprotocol {
+            let value = HeaderValue::from_str(protocol)
+                .map_err(|_| format!("invalid subprotocol '{}'", protocol))?;
+            headers.insert(SEC_WEBSOCKET_PROTOCOL, value);
+        }
+        headers.insert("X-Client-Version", HeaderValue::from_static(env!("CARGO_PKG_VERSION")));
+
+        Ok(request)
+    }
+}
+
+/// Handshake rejected with 401/403
+#[derive(Debug)]
+pub struct AuthRejected(u16);
+
+impl std::fmt::Display for AuthRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "server rejected credentials (HTTP {})", self.0)
+    }
+}
+
+impl Error for AuthRejected {}
+
+/// The server didn't complete the handshake in time
+#[derive(Debug)]
+pub struct ConnectTimedOut(Duration);
+
+impl std::fmt::Display for ConnectTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "connection timed out after {}s", self.0.as_secs_f32())
+    }
+}
+
+impl Error for ConnectTimedOut {}
+
+/// The messages in one text frame. Usually that's the frame itself, but a
+/// server may batch newline-delimited JSON, so a frame that isn't a single
+/// JSON value is split into its non-blank lines.
+fn split_frame(text: &str) -> Box<dyn Iterator<Item = &str> + '_> {
+    if !text.contains('\n') || serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok() {
+        return Box::new(std::iter::once(text));
+    }
+    Box::new(text.lines().map(str::trim).filter(|line| !line.is_empty()))
+}
+
+/// TCP (directly or through the proxy), then TLS and the WebSocket handshake
+async fn open_websocket(
+    request: Request,
+    connector: Option<Connector>,
+    proxy: Option<&Proxy>,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Box<dyn Error + Send + Sync>> {
+    let Some(proxy) = proxy else {
+        let (ws_stream, _) = connect_async_tls_with_config(request, None, false, connector).await?;
+        return Ok(ws_stream);
+    };
+    let uri = request.uri();
+    let host = uri.host().ok_or("server URL has no host")?;
+    let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("wss") { 443 } else { 80 });
+    let stream = proxy.connect(host, port).await?;
+    let (ws_stream, _) = client_async_tls_with_config(request, stream, None, connector).await?;
+    Ok(ws_stream)
+}
+
+/// Open a WebSocket to the server, through `proxy` if given, giving up
+/// after `connect_timeout`. The socket is pinged every `ping_interval`.
+/// Dropping the returned transport closes the socket.
+pub async fn connect(
+    handshake: &Handshake,
+    connector: Option<Connector>,
+    queue: &SendQueueConfig,
+    connect_timeout: Duration,
+    ping_interval: Duration,
+    proxy: Option<&Proxy>,
+) -> Result<Box<dyn Transport>, Box<dyn Error + Send + Sync>> {
+    let request = handshake.request()?;
+
+    let ws_stream = match timeout(connect_timeout, open_websocket(request, connector, proxy)).await {
+        Err(_) => return Err(Box::new(ConnectTimedOut(connect_timeout))),
+        Ok(Ok(ws_stream)) => ws_stream,
+        Ok(Err(e)) => match e.downcast::<WsError>() {
+            Ok(e) => match *e {
+                WsError::Http(response) if matches!(response.status().as_u16(), 401 | 403) => {
+                    return Err(Box::new(AuthRejected(response.status().as_u16())));
+                }
+                e => return Err(e.into()),
+            },
+            Err(e) => return Err(e),
+        },
+    };
+    let (mut write, mut read) = ws_stream.split();
+
+    let (mut transport, server) = ChannelTransport::pair(queue.capacity, queue.policy);
+    let ServerEnd { outgoing, incoming: incoming_tx } = server;
+
+    // Task to listen for incoming messages from the server. Ending it (socket
+    // error or close) closes the incoming channel, which is how the app sees
+    // the connection drop.
+    transport.reader = Some(tokio::spawn(async move {
+        while let Some(Ok(msg)) = read.next().await {
+            if let Message::Text(text) = msg {
+                if split_frame(&text).any(|line| incoming_tx.send(line.to_string()).is_err()) {
+                    break;
+                }
+            }
+        }
+    }));
+
+    // Task to send outgoing messages from the app to the server, with periodic pings
+    tokio::spawn(async move {
+        let mut ping_interval = interval(ping_interval);
+        ping_interval.tick().await; // Skip the first immediate tick
+
+        loop {
+            tokio::select! {
+                // Handle outgoing messages
+                outgoing = outgoing.pop() => {
+                    let Some(json) = outgoing else {
+                        // The transport was dropped (disconnect or cancelled connect):
+                        // close the socket, but don't wait on a peer that's gone
+                        let _ = timeout(CLOSE_TIMEOUT, write.close()).await;
+                        break;
+                    };
+                    if write.send(Message::text(json)).await.is_err() {
+                        break;
+                    }
+                }
+                // Send periodic pings to keep connection alive
+                _ = ping_interval.tick() => {
+                    if write.send(Message::Ping(vec![])).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        // Stop accepting messages nobody will write
+        outgoing.close();
+    });
+
+    Ok(Box::new(transport))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_frame() {
+        let single = "{\"type\":\"message\"}";
+        assert_eq!(split_frame(single).collect::<Vec<_>>(), [single]);
+        // A pretty-printed message stays whole
+        let pretty = "{\n  \"type\": \"message\"\n}";
+        assert_eq!(split_frame(pretty).collect::<Vec<_>>(), [pretty]);
+
+        let batch = "{\"a\":1}\n{\"b\":2}\r\n\n{broken\n";
+        assert_eq!(split_frame(batch).collect::<Vec<_>>(), ["{\"a\":1}", "{\"b\":2}", "{broken"]);
+        assert_eq!(split_frame("Welcome!").collect::<Vec<_>>(), ["Welcome!"]);
+    }
+
+    #[test]
+    fn test_full_queue_policies() {
+        let (reject, server) = ChannelTransport::pair(2, QueueFullPolicy::Reject);
+        reject.send("1".into()).unwrap();
+        reject.send("2".into()).unwrap();
+        assert_eq!(reject.send("3".into()), Err(SendError::QueueFull));
+        assert_eq!(reject.take_overflow(), 1);
+        assert_eq!(reject.take_overflow(), 0);
+        assert_eq!(server.outgoing.try_pop().as_deref(), Some("1"));
+
+        let (drop_oldest, server) = ChannelTransport::pair(2, QueueFullPolicy::DropOldest);
+        for n in ["1", "2", "3"] {
+            drop_oldest.send(n.into()).unwrap();
+        }
+        assert_eq!(drop_oldest.take_overflow(), 1);
+        assert_eq!(server.outgoing.try_pop().as_deref(), Some("2"));
+        assert_eq!(server.outgoing.try_pop().as_deref(), Some("3"));
+
+        // A closed queue (writer gone) refuses new messages
+        server.outgoing.close();
+        assert_eq!(drop_oldest.send("4".into()), Err(SendError::Closed));
+    }
+
+    #[tokio::test]
+    async fn test_connect_times_out_on_silent_server() {
+        // Accepts the TCP connection but never answers the handshake
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let handshake = Handshake { url, token: None, subprotocol: None, extra_headers: BTreeMap::new() };
+        let result = connect(&handshake, None, &SendQueueConfig::default(), Duration::from_millis(100), Duration::from_secs(30), None).await;
+        let err = result.err().expect("connect should time out");
+        assert!(err.is::<ConnectTimedOut>());
+        assert_eq!(err.to_string(), "connection timed out after 0.1s");
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_dont_leak_tasks() {
+        // A server that accepts and then never reads or closes, like a dead peer
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(tokio_tungstenite::accept_async(stream).await.unwrap());
+            }
+        });
+
+        let handshake = Handshake { url, token: None, subprotocol: None, extra_headers: BTreeMap::new() };
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let baseline = metrics.num_alive_tasks();
+        for _ in 0..5 {
+            let transport = connect(&handshake, None, &SendQueueConfig::default(), Duration::from_secs(5), Duration::from_secs(30), None).await.unwrap();
+            drop(transport);
+        }
+        // Each connect leaves nothing behind once its writer has sent the close
+        let settled = timeout(CLOSE_TIMEOUT * 2, async {
+            while metrics.num_alive_tasks() > baseline {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        assert!(settled.await.is_ok(), "{} tasks still alive, expected {}", metrics.num_alive_tasks(), baseline);
+    }
+}
@@ -7,15 +7,6 @@ pub enum VimMode {
     Insert,
 }
 
-impl VimMode {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            VimMode::Normal => "-- NORMAL --",
-            VimMode::Insert => "-- INSERT --",
-        }
-    }
-}
-
 #[derive(Debug, Clone, Copy)]
 pub struct VimState {
     pub mode: VimMode,
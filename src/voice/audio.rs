@@ -3,28 +3,206 @@ use audiopus::{coder::Decoder, coder::Encoder, Application, Channels, SampleRate
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::StreamError;
 use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
-// Wrapper to make cpal::Stream Send (required for tokio::spawn)
-struct SendStream(cpal::Stream);
+/// Frame durations (ms) Opus can encode. 2.5ms is legal too but not worth
+/// the overhead.
+pub const OPUS_FRAME_DURATIONS_MS: [u32; 5] = [5, 10, 20, 40, 60];
+
+/// Default frame duration when the configured one isn't usable
+pub const DEFAULT_FRAME_MS: u32 = 20;
+
+/// Default playback latency target when the configured one isn't usable
+pub const DEFAULT_PLAYBACK_LATENCY_MS: u32 = 150;
+
+/// Largest packet Opus can produce (120ms at 48k). Peers may use a different
+/// frame duration from ours, so decode buffers are sized for the worst case.
+const MAX_DECODE_SAMPLES: usize = 5760;
+
+/// Longest run of lost packets worth concealing. Past this the buffer has
+/// run dry anyway and drift control pads it with silence.
+const MAX_CONCEALED_FRAMES: u16 = 5;
+
+/// Expected packet loss the encoder plans its FEC data around, in percent
+const EXPECTED_LOSS_PERCENT: u8 = 10;
+
+/// An Opus payload from the network with its RTP sequence number, so the
+/// decoder can spot gaps
+pub struct ReceivedPacket {
+    pub seq: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Packets lost between the last one decoded and `seq`, or `None` if `seq`
+/// is a duplicate or arrived too late to play
+fn packets_missing(last_seq: Option<u16>, seq: u16) -> Option<u16> {
+    let Some(last) = last_seq else { return Some(0) };
+    let step = seq.wrapping_sub(last) as i16;
+    (step > 0).then(|| step as u16 - 1)
+}
+
+/// Samples per frame at 48kHz, or `None` if Opus can't use that duration
+pub fn frame_samples_48k(frame_ms: u32) -> Option<usize> {
+    OPUS_FRAME_DURATIONS_MS
+        .contains(&frame_ms)
+        .then(|| (48000 * frame_ms as usize) / 1000)
+}
+
+/// Samples of audio at `rate` that make up `latency_ms`
+fn latency_samples(rate: u32, latency_ms: u32) -> usize {
+    rate as usize * latency_ms as usize / 1000
+}
+
+/// Packets in a row the buffer has to sit outside its band before drift
+/// correction kicks in, so a single late or bunched packet is left alone
+const DRIFT_STREAK: u32 = 10;
+
+/// While trimming a long buffer, drop one sample in this many (1%)
+const MICRO_DROP_EVERY: usize = 100;
+
+/// Microseconds of audio trimmed or padded by drift correction, across all peers
+#[derive(Default)]
+struct DriftCounters {
+    trimmed_us: AtomicU64,
+    padded_us: AtomicU64,
+}
+
+/// What `DriftControl::push` changed, in samples
+#[derive(Debug, Default, PartialEq)]
+struct DriftAdjust {
+    dropped: usize,
+    padded: usize,
+}
+
+/// Keeps a playback buffer near its latency target. A buffer that keeps
+/// running long is trimmed a sample at a time rather than in audible chunks,
+/// and one that keeps running dry is topped up with faded silence.
+struct DriftControl {
+    target: usize,
+    /// Length of the fades around inserted silence
+    fade: usize,
+    long_streak: u32,
+    short_streak: u32,
+    trimming: bool,
+    /// Samples since the last micro-drop, carried across chunks
+    phase: usize,
+}
+
+impl DriftControl {
+    fn new(rate: u32, latency_ms: u32) -> Self {
+        Self {
+            target: latency_samples(rate, latency_ms),
+            fade: latency_samples(rate, 2),
+            long_streak: 0,
+            short_streak: 0,
+            trimming: false,
+            phase: 0,
+        }
+    }
+
+    /// Append `chunk` to `buffer`, correcting for drift on the way
+    fn push(&mut self, buffer: &mut VecDeque<f32>, chunk: &[f32]) -> DriftAdjust {
+        let buffered = buffer.len();
+        let mut adjust = DriftAdjust::default();
+
+        if buffered > self.target * 3 / 2 {
+            self.long_streak += 1;
+            self.short_streak = 0;
+        } else if buffered < self.target / 4 {
+            self.short_streak += 1;
+            self.long_streak = 0;
+        } else {
+            self.long_streak = 0;
+            self.short_streak = 0;
+        }
+        if self.long_streak >= DRIFT_STREAK {
+            self.trimming = true;
+        } else if buffered <= self.target {
+            self.trimming = false;
+        }
+
+        if buffered == 0 || self.short_streak >= DRIFT_STREAK {
+            // Ran dry, or keeps nearly doing so: fade out what's left, then
+            // rebuild the cushion with silence and fade the new audio in
+            self.short_streak = 0;
+            let fade_out = self.fade.min(buffered);
+            for (i, sample) in buffer.iter_mut().skip(buffered - fade_out).enumerate() {
+                *sample *= 1.0 - (i + 1) as f32 / fade_out as f32;
+            }
+            adjust.padded = self.target.saturating_sub(buffered);
+            buffer.extend(std::iter::repeat_n(0.0, adjust.padded));
+            let fade_in = self.fade.min(chunk.len());
+            buffer.extend(chunk.iter().enumerate().map(|(i, &sample)| {
+                if i < fade_in { sample * i as f32 / fade_in as f32 } else { sample }
+            }));
+        } else if self.trimming {
+            // Drop evenly spaced samples, folding each into its neighbour
+            for &sample in chunk {
+                self.phase += 1;
+                if self.phase >= MICRO_DROP_EVERY {
+                    self.phase = 0;
+                    if let Some(last) = buffer.back_mut() {
+                        *last = (*last + sample) / 2.0;
+                        adjust.dropped += 1;
+                        continue;
+                    }
+                }
+                buffer.push_back(sample);
+            }
+        } else {
+            buffer.extend(chunk);
+        }
+
+        // A burst after a network stall is more than trimming can absorb
+        // in reasonable time; skip straight back to the target
+        if buffer.len() > self.target * 2 {
+            let drain_count = buffer.len() - self.target;
+            buffer.drain(0..drain_count);
+            adjust.dropped += drain_count;
+        }
+        adjust
+    }
+}
+
+// Wrapper to make cpal::Stream Send (required for tokio::spawn). It only
+// keeps the stream playing until dropped, so any value will do in tests.
+struct SendStream(#[allow(dead_code)] Box<dyn std::any::Any>);
 unsafe impl Send for SendStream {}
 
 /// Audio errors that can be reported to the VoiceManager
 #[derive(Debug, Clone)]
 pub enum AudioDeviceError {
-    /// Output device disconnected or failed
-    OutputDeviceError(String),
+    /// A peer's output stream lost its device or failed
+    OutputDeviceError { peer_id: String, error: String },
     /// Input device disconnected or failed  
     InputDeviceError(String),
+    /// Output device picked from the fallback chain (name of the device in use)
+    OutputDeviceSelected(String),
 }
 
 pub struct AudioEngine {
-    input_stream: Option<SendStream>,
+    input_stream: Option<InputStream>,
     /// Output streams keyed by peer_id for proper cleanup on peer disconnect
-    output_streams: HashMap<String, SendStream>,
+    output_streams: HashMap<String, OutputStream>,
     /// Channel to report audio errors back to VoiceManager
     error_tx: Option<mpsc::UnboundedSender<AudioDeviceError>>,
+    /// Output device names to try, in order, before the system default
+    preferred_outputs: Vec<String>,
+    /// Last output device reported, so we only announce changes
+    announced_output: Option<String>,
+    /// Per-peer playback volume, kept across streams (and calls) so an
+    /// ignored user stays silent when they rejoin
+    peer_volumes: HashMap<String, f32>,
+    /// Opus frame duration for capture
+    frame_ms: u32,
+    /// Playback latency to hold each peer's buffer near
+    playback_latency_ms: u32,
+    /// Playback callbacks that ran dry partway through, across all peers
+    underruns: Arc<AtomicU64>,
+    /// Audio trimmed or padded to keep playback near the latency target
+    drift: Arc<DriftCounters>,
 }
 
 struct StatefulResampler {
@@ -87,14 +265,72 @@ impl StatefulResampler {
     }
 }
 
+/// A playing output stream plus what's needed to reopen it on another device.
+/// The decode task keeps writing into `buffer` and follows `device_rate`.
+struct OutputStream {
+    _stream: SendStream,
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    device_rate: Arc<AtomicU32>,
+    /// Playback volume as `f32` bits, applied by the decode task
+    volume: Arc<AtomicU32>,
+    decode_task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for OutputStream {
+    /// Stop decoding along with playback. This also closes the packet channel,
+    /// so the peer's RTP read loop ends too.
+    fn drop(&mut self) {
+        self.decode_task.abort();
+    }
+}
+
+/// The capture stream plus what's needed to reopen it on another device.
+/// The encode task keeps reading from `raw_tx` and follows `device_rate`.
+struct InputStream {
+    _stream: SendStream,
+    raw_tx: mpsc::UnboundedSender<Vec<f32>>,
+    device_rate: Arc<AtomicU32>,
+}
+
 impl AudioEngine {
     pub fn new() -> Self {
         Self { 
             input_stream: None,
             output_streams: HashMap::new(),
             error_tx: None,
+            preferred_outputs: Vec::new(),
+            announced_output: None,
+            peer_volumes: HashMap::new(),
+            frame_ms: DEFAULT_FRAME_MS,
+            playback_latency_ms: DEFAULT_PLAYBACK_LATENCY_MS,
+            underruns: Arc::new(AtomicU64::new(0)),
+            drift: Arc::new(DriftCounters::default()),
         }
     }
+
+    /// Set the Opus frame duration used for capture; rejects durations Opus
+    /// can't encode
+    pub fn set_frame_duration(&mut self, frame_ms: u32) -> Result<()> {
+        if frame_samples_48k(frame_ms).is_none() {
+            return Err(anyhow!(
+                "unsupported Opus frame duration {}ms (use one of {:?})",
+                frame_ms,
+                OPUS_FRAME_DURATIONS_MS
+            ));
+        }
+        self.frame_ms = frame_ms;
+        Ok(())
+    }
+
+    /// Set the playback latency target for streams started from now on
+    pub fn set_playback_latency(&mut self, latency_ms: u32) {
+        self.playback_latency_ms = latency_ms;
+    }
+
+    /// Set the ordered list of preferred output device names
+    pub fn set_output_devices(&mut self, names: Vec<String>) {
+        self.preferred_outputs = names;
+    }
     
     /// Set the error channel for reporting audio device errors
     pub fn set_error_channel(&mut self, tx: mpsc::UnboundedSender<AudioDeviceError>) {
@@ -110,27 +346,204 @@ impl AudioEngine {
         self.output_streams.clear();
     }
 
-    /// Remove the output stream associated with a specific peer.
-    /// Called when a peer disconnects or leaves voice to prevent stream accumulation.
+    /// Remove the output stream associated with a specific peer, stopping its
+    /// playback and decode task. Called when a peer disconnects or leaves voice
+    /// to prevent stream accumulation.
     pub fn remove_peer_stream(&mut self, peer_id: &str) {
         self.output_streams.remove(peer_id);
     }
 
     /// Start playback for a specific peer, replacing any existing stream for that peer.
-    pub fn start_playback_for_peer(&mut self, peer_id: &str, packet_rx: mpsc::UnboundedReceiver<Vec<u8>>) -> Result<()> {
+    /// Playback underruns since the engine was created
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Milliseconds of audio trimmed and padded by drift correction since the
+    /// engine was created
+    pub fn drift_ms(&self) -> (u64, u64) {
+        (
+            self.drift.trimmed_us.load(Ordering::Relaxed) / 1000,
+            self.drift.padded_us.load(Ordering::Relaxed) / 1000,
+        )
+    }
+
+    /// Set a peer's playback volume (1.0 is unchanged, 0.0 silent), now and
+    /// for any stream they get later
+    pub fn set_peer_volume(&mut self, peer_id: &str, volume: f32) {
+        let volume = volume.max(0.0);
+        self.peer_volumes.insert(peer_id.to_string(), volume);
+        if let Some(output) = self.output_streams.get(peer_id) {
+            output.volume.store(volume.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn start_playback_for_peer(&mut self, peer_id: &str, packet_rx: mpsc::UnboundedReceiver<ReceivedPacket>) -> Result<()> {
         // Remove any existing stream for this peer first
         self.output_streams.remove(peer_id);
         
-        let stream = self.build_playback_stream(packet_rx)?;
-        self.output_streams.insert(peer_id.to_string(), SendStream(stream));
+        let volume = self.peer_volumes.get(peer_id).copied().unwrap_or(1.0);
+        let stream = self.build_playback_stream(peer_id, packet_rx, volume)?;
+        self.output_streams.insert(peer_id.to_string(), stream);
         Ok(())
     }
 
+    /// Reopen `peer_id`'s output stream after it failed; other peers' streams
+    /// report their own failures and are left playing. The decode task keeps
+    /// feeding the same buffer. Returns the name of the new device.
+    pub fn recover_output(&mut self, peer_id: &str) -> Result<String> {
+        self.reopen_output(peer_id, |engine, buffer| {
+            let (stream, rate, name) = engine.open_output_with(peer_id, buffer)?;
+            Ok((SendStream(Box::new(stream)), rate, name))
+        })
+    }
+
+    /// `recover_output` with the opening of the new stream supplied, so tests
+    /// don't need an audio device
+    fn reopen_output(
+        &mut self,
+        peer_id: &str,
+        open: impl FnOnce(&Self, Arc<Mutex<VecDeque<f32>>>) -> Result<(SendStream, u32, String)>,
+    ) -> Result<String> {
+        let buffer = self.output_streams.get(peer_id).ok_or(anyhow!("No playback for that peer"))?.buffer.clone();
+        let (stream, rate, name) = open(self, buffer)?;
+        if let Some(output) = self.output_streams.get_mut(peer_id) {
+            output._stream = stream;
+            output.device_rate.store(rate, Ordering::Relaxed);
+        }
+        self.announced_output = Some(name.clone());
+        Ok(name)
+    }
+
+    /// Reopen the capture stream on the default input after a device failure.
+    /// The encode task keeps reading from the same channel.
+    pub fn recover_input(&mut self) -> Result<String> {
+        let input = self.input_stream.as_mut().ok_or(anyhow!("Not capturing"))?;
+        let (stream, rate, name) = open_input(input.raw_tx.clone(), self.error_tx.clone())?;
+        input._stream = SendStream(Box::new(stream));
+        input.device_rate.store(rate, Ordering::Relaxed);
+        Ok(name)
+    }
+
     /// Build and start a cpal output stream that decodes Opus packets from packet_rx.
-    fn build_playback_stream(&self, mut packet_rx: mpsc::UnboundedReceiver<Vec<u8>>) -> Result<cpal::Stream> {
+    fn build_playback_stream(&mut self, peer_id: &str, mut packet_rx: mpsc::UnboundedReceiver<ReceivedPacket>, volume: f32) -> Result<OutputStream> {
+        let shared_buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let (stream, device_sample_rate, device_name) = self.open_output_with(peer_id, shared_buffer.clone())?;
+
+        if !self.preferred_outputs.is_empty() && self.announced_output.as_deref() != Some(device_name.as_str()) {
+            if let Some(tx) = &self.error_tx {
+                let _ = tx.send(AudioDeviceError::OutputDeviceSelected(device_name.clone()));
+            }
+            self.announced_output = Some(device_name);
+        }
+
+        // Opus only supports specific rates. We decode to 48k and resample if needed.
+        let opus_rate = SampleRate::Hz48000;
+        let device_rate = Arc::new(AtomicU32::new(device_sample_rate));
+
+        // Spawn Decoding Task
+        let buffer_for_decode = shared_buffer.clone();
+        let rate_for_decode = device_rate.clone();
+        let volume = Arc::new(AtomicU32::new(volume.to_bits()));
+        let volume_for_decode = volume.clone();
+        let latency_ms = self.playback_latency_ms;
+        let drift_counters = self.drift.clone();
+        let decode_task = tokio::spawn(async move {
+            let mut decoder = match Decoder::new(opus_rate, Channels::Mono) {
+                Ok(d) => d,
+                Err(_) => return,
+            };
+            // Resampler: 48k -> device_rate
+            let mut resampler = StatefulResampler::new(48000, rate_for_decode.load(Ordering::Relaxed));
+            let mut drift = DriftControl::new(resampler.to_rate, latency_ms);
+
+            let mut output = vec![0.0f32; MAX_DECODE_SAMPLES];
+            let mut last_seq = None;
+            // Length of the last frame decoded; lost frames are assumed to match
+            let mut frame_len = 0;
+            while let Some(packet) = packet_rx.recv().await {
+                let Some(missing) = packets_missing(last_seq, packet.seq) else {
+                    continue;
+                };
+                last_seq = Some(packet.seq);
+
+                // The device may have been swapped under us; follow its rate
+                let device_sample_rate = rate_for_decode.load(Ordering::Relaxed);
+                if resampler.to_rate != device_sample_rate {
+                    resampler = StatefulResampler::new(48000, device_sample_rate);
+                    drift = DriftControl::new(device_sample_rate, latency_ms);
+                    if let Ok(mut buffer) = buffer_for_decode.lock() {
+                        buffer.clear();
+                    }
+                }
+
+                let mut decoded = Vec::new();
+                if missing > 0 && missing <= MAX_CONCEALED_FRAMES && frame_len > 0 {
+                    // Packet loss concealment for all but the last lost frame,
+                    // then rebuild that one from the FEC data in this packet
+                    for _ in 1..missing {
+                        if let Ok(len) = decoder.decode_float(None::<&[u8]>, &mut output[..frame_len], false) {
+                            decoded.extend_from_slice(&output[..len]);
+                        }
+                    }
+                    if let Ok(len) = decoder.decode_float(Some(&packet.payload), &mut output[..frame_len], true) {
+                        decoded.extend_from_slice(&output[..len]);
+                    }
+                }
+                if let Ok(len) = decoder.decode_float(Some(&packet.payload), &mut output[..], false) {
+                    frame_len = len;
+                    decoded.extend_from_slice(&output[..len]);
+                }
+
+                if !decoded.is_empty() {
+                    // Keep decoding a silenced peer so unmuting picks up cleanly
+                    let gain = f32::from_bits(volume_for_decode.load(Ordering::Relaxed));
+                    if gain != 1.0 {
+                        decoded.iter_mut().for_each(|sample| *sample *= gain);
+                    }
+                    // Resample if needed
+                    let resampled = resampler.process(&decoded);
+                    
+                    if let Ok(mut buffer) = buffer_for_decode.lock() {
+                        let adjust = drift.push(&mut buffer, &resampled);
+                        let to_us = |samples: usize| samples as u64 * 1_000_000 / device_sample_rate as u64;
+                        drift_counters.trimmed_us.fetch_add(to_us(adjust.dropped), Ordering::Relaxed);
+                        drift_counters.padded_us.fetch_add(to_us(adjust.padded), Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+
+        Ok(OutputStream { _stream: SendStream(Box::new(stream)), buffer: shared_buffer, device_rate, volume, decode_task })
+    }
+
+    /// Open an output stream on the first preferred device that works, falling
+    /// back to the system default. Returns the stream, its sample rate and the
+    /// name of the device used.
+    fn open_output_with(&self, peer_id: &str, shared_buffer: Arc<Mutex<VecDeque<f32>>>) -> Result<(cpal::Stream, u32, String)> {
         let host = cpal::default_host();
+
+        for name in &self.preferred_outputs {
+            let device = host
+                .output_devices()
+                .ok()
+                .and_then(|mut devices| devices.find(|d| d.name().is_ok_and(|n| &n == name)));
+            if let Some(device) = device {
+                if let Ok((stream, rate)) = self.build_output_on(&device, peer_id, shared_buffer.clone()) {
+                    return Ok((stream, rate, name.clone()));
+                }
+            }
+        }
+
         let device = host.default_output_device().ok_or(anyhow!("No output device"))?;
-        
+        let name = device.name().unwrap_or_else(|_| "default output".to_string());
+        let (stream, rate) = self.build_output_on(&device, peer_id, shared_buffer)?;
+        Ok((stream, rate, name))
+    }
+
+    /// Build and start an output stream on `device` that plays from
+    /// `shared_buffer`. Its errors are reported as `peer_id`'s.
+    fn build_output_on(&self, device: &cpal::Device, peer_id: &str, shared_buffer: Arc<Mutex<VecDeque<f32>>>) -> Result<(cpal::Stream, u32)> {
         // Try to find a config that supports 48kHz (Opus native)
         let preferred_rates = [48000, 24000, 16000, 12000, 8000];
         let mut selected_config = None;
@@ -155,50 +568,7 @@ impl AudioEngine {
         let stream_config: cpal::StreamConfig = config.clone().into();
         let device_sample_rate = stream_config.sample_rate.0;
 
-        // Opus only supports specific rates. We decode to 48k and resample if needed.
-        let opus_rate = SampleRate::Hz48000;
-        
-        // Resampler: 48k -> device_rate
-        let mut resampler = StatefulResampler::new(48000, device_sample_rate);
-        
-        let max_buffer_samples = device_sample_rate as usize * 2; // 2 seconds buffer
-        let shared_buffer = Arc::new(Mutex::new(VecDeque::with_capacity(max_buffer_samples)));
-
-        // Spawn Decoding Task
-        let buffer_for_decode = shared_buffer.clone();
-        tokio::spawn(async move {
-            let mut decoder = match Decoder::new(opus_rate, Channels::Mono) {
-                Ok(d) => d,
-                Err(_) => return,
-            };
-
-            while let Some(packet) = packet_rx.recv().await {
-                let mut output = [0.0f32; 1920]; // 40ms at 48k
-                if let Ok(len) = decoder.decode_float(Some(&packet), &mut output[..], false) {
-                    let decoded_frames = &output[..len];
-                    // Resample if needed
-                    let resampled = resampler.process(decoded_frames);
-                    
-                    if let Ok(mut buffer) = buffer_for_decode.lock() {
-                        buffer.extend(resampled);
-                        // Prevent bufferbloat / drift
-                        if buffer.len() > max_buffer_samples {
-                            let drain_count = buffer.len() - max_buffer_samples;
-                            buffer.drain(0..drain_count);
-                        }
-                    }
-                }
-            }
-        });
-
-        // Setup CPAL Output Stream with proper error handling
-        let error_tx_for_output = self.error_tx.clone();
-        let err_fn = move |err: StreamError| {
-            if let Some(tx) = &error_tx_for_output {
-                let _ = tx.send(AudioDeviceError::OutputDeviceError(format!("{:?}", err)));
-            }
-        };
-        
+        let underruns = self.underruns.clone();
         let stream = device.build_output_stream(
             &stream_config,
             move |data: &mut [f32], _: &_| {
@@ -212,9 +582,13 @@ impl AudioEngine {
                             break;
                         }
                     }
+                    // An empty buffer is just silence; running out mid-callback is a glitch
+                    if written > 0 && written < data.len() {
+                        underruns.fetch_add(1, Ordering::Relaxed);
+                    }
                     // Fill remainder with silence
-                    for i in written..data.len() {
-                        data[i] = 0.0;
+                    for sample in data[written..].iter_mut() {
+                        *sample = 0.0;
                     }
                 } else {
                     for sample in data.iter_mut() {
@@ -222,114 +596,292 @@ impl AudioEngine {
                     }
                 }
             },
-            err_fn,
+            stream_error_reporter(self.error_tx.clone(), {
+                let peer_id = peer_id.to_string();
+                move |error| AudioDeviceError::OutputDeviceError { peer_id: peer_id.clone(), error }
+            }),
             None
         )?;
 
         stream.play()?;
-        Ok(stream)
+        Ok((stream, device_sample_rate))
     }
 
     pub fn start_capture(&mut self, encoded_tx: mpsc::UnboundedSender<Vec<u8>>) -> Result<()> {
-        let host = cpal::default_host();
-        let device = host.default_input_device().ok_or(anyhow!("No input device"))?;
-        
-        // Try to find a config that supports 48kHz
-        let preferred_rates = [48000, 24000, 16000, 12000, 8000];
-        let mut selected_config = None;
-        
-        for &rate in &preferred_rates {
-            let configs = device.supported_input_configs()?;
-            if let Some(c) = configs.into_iter().find(|c| {
-                c.min_sample_rate().0 <= rate && c.max_sample_rate().0 >= rate
-            }) {
-                selected_config = Some(c.with_sample_rate(cpal::SampleRate(rate)));
-                break;
-            }
-        }
-        
-        // Fallback to default
-        let config = if let Some(c) = selected_config {
-            c
-        } else {
-            device.default_input_config()?
-        };
-
-        let stream_config: cpal::StreamConfig = config.clone().into();
-        let device_sample_rate = stream_config.sample_rate.0;
+        // Channel from CPAL -> Encoder
+        let (raw_tx, mut raw_rx) = mpsc::unbounded_channel::<Vec<f32>>();
+        let (stream, device_sample_rate, _) = open_input(raw_tx.clone(), self.error_tx.clone())?;
+        let device_rate = Arc::new(AtomicU32::new(device_sample_rate));
 
         // We encode at 48k. Resample input -> 48k.
         let opus_rate = SampleRate::Hz48000;
-        let frame_size_48k = (48000 * 20) / 1000; // 960 samples for 20ms
-
-        // Resampler: device_rate -> 48k
-        let mut resampler = StatefulResampler::new(device_sample_rate, 48000);
-
-        // Channel from CPAL -> Encoder
-        let (raw_tx, mut raw_rx) = mpsc::unbounded_channel::<Vec<f32>>();
+        let frame_size_48k = frame_samples_48k(self.frame_ms).unwrap_or(960);
 
         // Spawn Encoding Task
+        let rate_for_encode = device_rate.clone();
         tokio::spawn(async move {
-            let encoder = match Encoder::new(opus_rate, Channels::Mono, Application::Voip) {
+            let mut encoder = match Encoder::new(opus_rate, Channels::Mono, Application::Voip) {
                 Ok(e) => e,
                 Err(_) => return,
             };
+            // Carry a low-bitrate copy of each frame in the next packet, so
+            // receivers can rebuild a single lost packet
+            let _ = encoder.set_inband_fec(true);
+            let _ = encoder.set_packet_loss_perc(EXPECTED_LOSS_PERCENT);
+            // Resampler: device_rate -> 48k
+            let mut resampler = StatefulResampler::new(rate_for_encode.load(Ordering::Relaxed), 48000);
             
-            // We need to buffer incoming resampled samples until we have a full Opus frame (960 samples)
+            // We need to buffer incoming resampled samples until we have a full Opus frame
             let mut buffer: Vec<f32> = Vec::with_capacity(frame_size_48k * 2);
 
             while let Some(samples) = raw_rx.recv().await {
+                // The device may have been swapped under us; follow its rate
+                let device_sample_rate = rate_for_encode.load(Ordering::Relaxed);
+                if resampler.from_rate != device_sample_rate {
+                    resampler = StatefulResampler::new(device_sample_rate, 48000);
+                }
+
                 // Resample incoming chunk
                 let resampled = resampler.process(&samples);
                 buffer.extend(resampled);
 
                 while buffer.len() >= frame_size_48k {
                     let frame: Vec<f32> = buffer.drain(0..frame_size_48k).collect();
-                    let mut output = [0u8; 1024];
+                    let mut output = [0u8; 4000]; // Opus recommended max packet size
                     
-                    match encoder.encode_float(&frame, &mut output) {
-                        Ok(len) => {
-                            let packet = output[..len].to_vec();
-                            if encoded_tx.send(packet).is_err() {
-                                return; // Channel closed
-                            }
-                        },
-                        Err(_) => {},
+                    if let Ok(len) = encoder.encode_float(&frame, &mut output) {
+                        let packet = output[..len].to_vec();
+                        if encoded_tx.send(packet).is_err() {
+                            return; // Channel closed
+                        }
                     }
                 }
             }
         });
 
-        // Setup CPAL Input Stream with proper error handling
-        let error_tx_for_input = self.error_tx.clone();
-        let err_fn = move |err: StreamError| {
-            if let Some(tx) = &error_tx_for_input {
-                let _ = tx.send(AudioDeviceError::InputDeviceError(format!("{:?}", err)));
-            }
-        };
-        
-        let stream = device.build_input_stream(
-            &stream_config,
-            move |data: &[f32], _: &_| {
-                // Downmix to Mono if needed - proper averaging across channels
-                let channels = config.channels() as usize;
-                if channels == 1 {
-                    let _ = raw_tx.send(data.to_vec());
-                } else {
-                    // Average all channels together for proper mono conversion
-                    let mono: Vec<f32> = data.chunks(channels)
-                        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
-                        .collect();
-                    let _ = raw_tx.send(mono);
-                }
-            },
-            err_fn,
-            None
-        )?;
-
-        stream.play()?;
-        self.input_stream = Some(SendStream(stream));
+        self.input_stream = Some(InputStream { _stream: SendStream(Box::new(stream)), raw_tx, device_rate });
         
         Ok(())
     }
 }
+
+/// Open a capture stream on the default input device that sends mono chunks
+/// to `raw_tx`. Returns the stream, its sample rate and the device name.
+fn open_input(
+    raw_tx: mpsc::UnboundedSender<Vec<f32>>,
+    error_tx: Option<mpsc::UnboundedSender<AudioDeviceError>>,
+) -> Result<(cpal::Stream, u32, String)> {
+    let host = cpal::default_host();
+    let device = host.default_input_device().ok_or(anyhow!("No input device"))?;
+    let name = device.name().unwrap_or_else(|_| "default input".to_string());
+    
+    // Try to find a config that supports 48kHz
+    let preferred_rates = [48000, 24000, 16000, 12000, 8000];
+    let mut selected_config = None;
+    
+    for &rate in &preferred_rates {
+        let configs = device.supported_input_configs()?;
+        if let Some(c) = configs.into_iter().find(|c| {
+            c.min_sample_rate().0 <= rate && c.max_sample_rate().0 >= rate
+        }) {
+            selected_config = Some(c.with_sample_rate(cpal::SampleRate(rate)));
+            break;
+        }
+    }
+    
+    // Fallback to default
+    let config = if let Some(c) = selected_config {
+        c
+    } else {
+        device.default_input_config()?
+    };
+
+    let stream_config: cpal::StreamConfig = config.clone().into();
+    let device_sample_rate = stream_config.sample_rate.0;
+
+    let stream = device.build_input_stream(
+        &stream_config,
+        move |data: &[f32], _: &_| {
+            // Downmix to Mono if needed - proper averaging across channels
+            let channels = config.channels() as usize;
+            if channels == 1 {
+                let _ = raw_tx.send(data.to_vec());
+            } else {
+                // Average all channels together for proper mono conversion
+                let mono: Vec<f32> = data.chunks(channels)
+                    .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                    .collect();
+                let _ = raw_tx.send(mono);
+            }
+        },
+        stream_error_reporter(error_tx, AudioDeviceError::InputDeviceError),
+        None
+    )?;
+
+    stream.play()?;
+    Ok((stream, device_sample_rate, name))
+}
+
+/// cpal error callback that forwards stream errors to the VoiceManager
+fn stream_error_reporter(
+    error_tx: Option<mpsc::UnboundedSender<AudioDeviceError>>,
+    kind: impl Fn(String) -> AudioDeviceError + Send + 'static,
+) -> impl FnMut(StreamError) + Send + 'static {
+    move |err: StreamError| {
+        if let Some(tx) = &error_tx {
+            let _ = tx.send(kind(err.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_errors_are_reported() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut on_error = stream_error_reporter(Some(tx), AudioDeviceError::InputDeviceError);
+        on_error(StreamError::DeviceNotAvailable);
+
+        match rx.try_recv() {
+            Ok(AudioDeviceError::InputDeviceError(msg)) => assert!(msg.contains("no longer available")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_output_reopens_only_the_failed_stream() {
+        let mut engine = AudioEngine::new();
+        for peer_id in ["alice", "bob"] {
+            let output = OutputStream {
+                _stream: SendStream(Box::new(())),
+                buffer: Arc::default(),
+                device_rate: Arc::new(AtomicU32::new(48_000)),
+                volume: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+                decode_task: tokio::spawn(async {}),
+            };
+            engine.output_streams.insert(peer_id.to_string(), output);
+        }
+
+        let alice_buffer = engine.output_streams["alice"].buffer.clone();
+        let mut opened = 0;
+        let name = engine
+            .reopen_output("alice", |_, buffer| {
+                opened += 1;
+                assert!(Arc::ptr_eq(&buffer, &alice_buffer), "keeps feeding the same buffer");
+                Ok((SendStream(Box::new(())), 44_100, "USB headset".to_string()))
+            })
+            .unwrap();
+        assert_eq!((name.as_str(), opened), ("USB headset", 1));
+        let rate = |peer_id: &str| engine.output_streams[peer_id].device_rate.load(Ordering::Relaxed);
+        assert_eq!((rate("alice"), rate("bob")), (44_100, 48_000), "bob's stream is left alone");
+
+        assert!(engine.reopen_output("carol", |_, _| unreachable!("no stream to reopen")).is_err());
+    }
+
+    #[test]
+    fn test_resampler_output_length_matches_ratio() {
+        for &(from, to) in &[(48000, 44100), (44100, 48000), (16000, 48000), (48000, 8000)] {
+            let mut resampler = StatefulResampler::new(from, to);
+            let input = vec![0.5f32; from as usize / 10]; // 100ms
+            let output = resampler.process(&input);
+            let expected = (input.len() as f64 * to as f64 / from as f64) as i64;
+            assert!(
+                (output.len() as i64 - expected).abs() <= 1,
+                "{} -> {}: got {} samples, expected {}",
+                from, to, output.len(), expected
+            );
+            // The first few samples interpolate from the initial silence
+            let warmup = (to as usize).div_ceil(from as usize);
+            assert!(output.iter().skip(warmup).all(|&s| (s - 0.5).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_resampler_preserves_ramp_monotonicity() {
+        let mut resampler = StatefulResampler::new(44100, 48000);
+        let input: Vec<f32> = (0..4410).map(|i| i as f32 / 4410.0).collect();
+        let output = resampler.process(&input);
+        assert!(output.windows(2).all(|w| w[1] >= w[0]));
+    }
+
+    #[test]
+    fn test_resampler_chunked_matches_single_pass() {
+        let input: Vec<f32> = (0..9600).map(|i| (i as f32 * 0.01).sin()).collect();
+        for &(from, to) in &[(48000, 44100), (44100, 48000), (48000, 16000)] {
+            let whole = StatefulResampler::new(from, to).process(&input);
+
+            let mut resampler = StatefulResampler::new(from, to);
+            let chunked: Vec<f32> = input.chunks(137).flat_map(|chunk| resampler.process(chunk)).collect();
+
+            assert!(
+                (whole.len() as i64 - chunked.len() as i64).abs() <= 1,
+                "{} -> {}: {} vs {} samples",
+                from, to, whole.len(), chunked.len()
+            );
+            for (a, b) in whole.iter().zip(&chunked) {
+                assert!((a - b).abs() < 1e-3, "{} -> {}: {} vs {}", from, to, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_drift_control_trims_gradually_and_pads_when_dry() {
+        // 100ms target at 10kHz: 1000 samples, 20-sample fades
+        let mut drift = DriftControl::new(10_000, 100);
+        let mut buffer = VecDeque::new();
+        let chunk = vec![0.5f32; 200];
+
+        // Starting dry pads up to the target, fading the first packet in
+        let adjust = drift.push(&mut buffer, &chunk);
+        assert_eq!(adjust, DriftAdjust { dropped: 0, padded: 1000 });
+        assert_eq!(buffer.len(), 1200);
+        assert_eq!(buffer[1000], 0.0);
+        assert_eq!(buffer[1199], 0.5);
+
+        // Running long only briefly is left alone
+        buffer.extend(vec![0.5f32; 600]);
+        for _ in 0..DRIFT_STREAK - 1 {
+            buffer.drain(0..200);
+            assert_eq!(drift.push(&mut buffer, &chunk), DriftAdjust::default());
+        }
+
+        // Persistently long gets 1% micro-drops, not a chunk drain
+        buffer.drain(0..200);
+        let adjust = drift.push(&mut buffer, &chunk);
+        assert_eq!(adjust.dropped, 2);
+        assert_eq!(adjust.padded, 0);
+        assert_eq!(buffer.len(), 1798);
+        assert!(buffer.iter().all(|&s| (s - 0.5).abs() < 1e-6));
+
+        // Trimming stops once back at the target
+        buffer.truncate(900);
+        assert_eq!(drift.push(&mut buffer, &chunk), DriftAdjust::default());
+
+        // A burst far over the cap skips back to the target
+        let burst = vec![0.5f32; 2000];
+        let adjust = drift.push(&mut buffer, &burst);
+        assert_eq!(adjust.dropped, 2100);
+        assert_eq!(buffer.len(), 1000);
+    }
+
+    #[test]
+    fn test_packets_missing() {
+        assert_eq!(packets_missing(None, 7), Some(0));
+        assert_eq!(packets_missing(Some(7), 8), Some(0));
+        assert_eq!(packets_missing(Some(7), 10), Some(2));
+        assert_eq!(packets_missing(Some(u16::MAX), 1), Some(1), "wraps around");
+        assert_eq!(packets_missing(Some(7), 7), None, "duplicate");
+        assert_eq!(packets_missing(Some(7), 5), None, "late");
+    }
+
+    #[test]
+    fn test_frame_durations() {
+        assert_eq!(frame_samples_48k(10), Some(480));
+        assert_eq!(frame_samples_48k(20), Some(960));
+        assert_eq!(frame_samples_48k(60), Some(2880));
+        assert_eq!(frame_samples_48k(15), None);
+        assert!(AudioEngine::new().set_frame_duration(30).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 use tokio::sync::{mpsc, Mutex};
 use anyhow::Result;
 use webrtc::api::interceptor_registry::register_default_interceptors;
@@ -10,6 +11,7 @@ use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::signaling_state::RTCSignalingState;
 use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
 use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
@@ -17,62 +19,49 @@ use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSampl
 use webrtc::track::track_local::TrackLocal;
 use webrtc::media::Sample;
 
-use crate::voice::audio::{AudioEngine, AudioDeviceError};
+use crate::config::AudioConfig;
+use crate::voice::audio::{AudioEngine, AudioDeviceError, ReceivedPacket, DEFAULT_FRAME_MS};
+use crate::voice::stats::{PeerStats, ReceiveStats, VoiceStats};
+use crate::voice::{VoiceCommand, VoiceEvent};
 
-/// Internal commands sent from async callbacks back to the VoiceManager
-enum InternalCmd {
-    /// Peer connection entered Failed/Disconnected/Closed state - clean it up
-    CleanupPeer(String),
-}
+/// How often to send `VoiceEvent::Stats` while in a call
+const STATS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How long without an outgoing packet before `TxActivity(false)`
+const TX_IDLE_AFTER: std::time::Duration = std::time::Duration::from_millis(300);
 
-/// Voice connection status for UI display
-#[derive(Debug, Clone, PartialEq)]
-pub enum VoiceConnectionStatus {
-    Disconnected,
-    Connecting,
-    Connected,
-    Reconnecting,
+/// Outgoing packets reduced to `TxActivity` transitions: active from the
+/// first packet of a burst until `TX_IDLE_AFTER` passes without one
+#[derive(Debug, Default)]
+struct TxActivity {
+    last_sent: Option<Instant>,
 }
 
-impl Default for VoiceConnectionStatus {
-    fn default() -> Self {
-        VoiceConnectionStatus::Disconnected
+impl TxActivity {
+    /// Note a sent packet; true if that starts a burst
+    fn sent(&mut self, now: Instant) -> bool {
+        self.last_sent.replace(now).is_none()
+    }
+
+    /// When the current burst ends if nothing more is sent
+    fn idle_at(&self) -> Option<Instant> {
+        self.last_sent.map(|last| last + TX_IDLE_AFTER)
     }
-}
 
-/// Events sent from VoiceManager to main app
-/// ALL voice state changes in the app should be driven by these events
-#[derive(Debug)]
-pub enum VoiceEvent {
-    /// WebRTC signaling data to send to server
-    Signal { target_id: Option<String>, signal_type: String, data: String },
-    
-    /// Voice connection state changes (single source of truth)
-    Connecting,                    // Starting to join voice
-    Connected,                     // Successfully joined and audio is ready
-    Disconnected,                  // Clean disconnect completed
-    ConnectionFailed(String),      // Failed to connect (with reason)
-    
-    /// Peer connection state changes  
-    PeerConnected(String),         // WebRTC connection to peer established
-    PeerDisconnected(String),      // WebRTC connection to peer lost
-    PeerConnectionFailed(String),  // Failed to connect to specific peer
-    
-    /// Mute state changes (single source of truth)
-    MuteStateChanged(bool),        // true = muted, false = unmuted
-    
-    /// Transmit activity for UI indicator
-    TxActivity(bool),              // true = transmitting, false = quiet
-    
-    /// Audio system errors
-    AudioError(String),            // Audio device/stream error
+    /// True if a burst just ended
+    fn idle(&mut self, now: Instant) -> bool {
+        if self.idle_at().is_some_and(|idle_at| now >= idle_at) {
+            self.last_sent = None;
+            return true;
+        }
+        false
+    }
 }
 
-pub enum VoiceCommand {
-    Join(String),
-    Leave,
-    Mute(bool),
-    Signal { sender_id: String, signal_type: String, data: String },
+/// Internal commands sent from async callbacks back to the VoiceManager
+enum InternalCmd {
+    /// Peer connection entered Failed/Disconnected/Closed state - clean it up
+    CleanupPeer(String),
 }
 
 pub struct VoiceManager {
@@ -88,19 +77,35 @@ pub struct VoiceManager {
     /// FIX for Bug 1: candidates that arrive before the offer are queued here
     /// and applied once the peer connection is created via the offer handler.
     pending_candidates: HashMap<String, Vec<RTCIceCandidateInit>>,
+    /// Answers that arrived before we had an offer out to that peer (reordering),
+    /// applied once we do
+    pending_answers: HashMap<String, RTCSessionDescription>,
     /// Channel for internal commands from async callbacks (e.g., peer cleanup)
     internal_tx: mpsc::UnboundedSender<InternalCmd>,
     internal_rx: Option<mpsc::UnboundedReceiver<InternalCmd>>,
+    /// Opus frame duration, for the timing of samples written to the track
+    frame_ms: u32,
+    /// Loss and jitter per peer, updated by the RTP read loops
+    receive_stats: Arc<std::sync::Mutex<HashMap<String, ReceiveStats>>>,
 }
 
 impl VoiceManager {
-    pub fn new(event_tx: mpsc::UnboundedSender<VoiceEvent>) -> Self {
+    pub fn new(event_tx: mpsc::UnboundedSender<VoiceEvent>, audio_config: AudioConfig) -> Self {
         // Create audio error channel
         let (audio_error_tx, audio_error_rx) = mpsc::unbounded_channel::<AudioDeviceError>();
         
         // Create audio engine with error channel
         let mut audio_engine = AudioEngine::new();
         audio_engine.set_error_channel(audio_error_tx);
+        audio_engine.set_output_devices(audio_config.output_devices);
+        audio_engine.set_playback_latency(audio_config.playback_latency_ms);
+        let frame_ms = match audio_engine.set_frame_duration(audio_config.frame_ms) {
+            Ok(()) => audio_config.frame_ms,
+            Err(e) => {
+                let _ = event_tx.send(VoiceEvent::AudioError(format!("{}; using {}ms", e, DEFAULT_FRAME_MS)));
+                DEFAULT_FRAME_MS
+            }
+        };
         
         // Create internal command channel for async callback -> manager communication
         let (internal_tx, internal_rx) = mpsc::unbounded_channel::<InternalCmd>();
@@ -115,8 +120,11 @@ impl VoiceManager {
             is_muted: Arc::new(AtomicBool::new(false)),
             is_joined: Arc::new(AtomicBool::new(false)),
             pending_candidates: HashMap::new(),
+            pending_answers: HashMap::new(),
             internal_tx,
             internal_rx: Some(internal_rx),
+            frame_ms,
+            receive_stats: Arc::new(std::sync::Mutex::new(HashMap::new())),
         }
     }
 
@@ -124,6 +132,7 @@ impl VoiceManager {
         // Take ownership of the audio error receiver and internal command receiver
         let mut audio_error_rx = self.audio_error_rx.take();
         let mut internal_rx = self.internal_rx.take();
+        let mut stats_interval = tokio::time::interval(STATS_INTERVAL);
         
         loop {
             tokio::select! {
@@ -131,17 +140,33 @@ impl VoiceManager {
                 Some(cmd) = command_rx.recv() => {
                     match cmd {
                         VoiceCommand::Join(room_id) => {
-                            let _ = self.join_voice(room_id).await;
+                            if let Err(e) = self.join_voice(room_id).await {
+                                self.is_joined.store(false, Ordering::Relaxed);
+                                self.room_id = None;
+                                let _ = self.event_tx.send(VoiceEvent::ConnectionFailed(e.to_string()));
+                            }
                         }
                         VoiceCommand::Leave => {
                             let _ = self.leave_voice().await;
                         }
+                        VoiceCommand::LeaveAndNotify(done) => {
+                            let _ = self.leave_voice().await;
+                            let _ = done.send(());
+                        }
                         VoiceCommand::Mute(muted) => {
                             self.is_muted.store(muted, Ordering::Relaxed);
                             let _ = self.event_tx.send(VoiceEvent::MuteStateChanged(muted));
                         }
+                        VoiceCommand::SetPeerVolume { peer_id, volume } => {
+                            self.audio_engine.lock().await.set_peer_volume(&peer_id, volume);
+                        }
                         VoiceCommand::Signal { sender_id, signal_type, data } => {
-                            let _ = self.handle_signal(&sender_id, &signal_type, &data).await;
+                            if let Err(e) = self.handle_signal(&sender_id, &signal_type, &data).await {
+                                let _ = self.event_tx.send(VoiceEvent::SignalingFailed {
+                                    peer_id: sender_id,
+                                    reason: format!("{} not applied: {}", signal_type, e),
+                                });
+                            }
                         }
                     }
                 }
@@ -165,6 +190,10 @@ impl VoiceManager {
                                 audio.remove_peer_stream(&peer_id);
                             }
                             self.pending_candidates.remove(&peer_id);
+                            self.pending_answers.remove(&peer_id);
+                            if let Ok(mut stats) = self.receive_stats.lock() {
+                                stats.remove(&peer_id);
+                            }
                         }
                     }
                 }
@@ -176,17 +205,65 @@ impl VoiceManager {
                         None 
                     } 
                 } => {
-                    let err_msg = match err {
-                        AudioDeviceError::OutputDeviceError(e) => format!("Output device error: {}", e),
-                        AudioDeviceError::InputDeviceError(e) => format!("Input device error: {}", e),
-                    };
+                    let err_msg = self.recover_audio(err).await;
                     let _ = self.event_tx.send(VoiceEvent::AudioError(err_msg));
                 }
+                _ = stats_interval.tick() => {
+                    if self.is_joined.load(Ordering::Relaxed) {
+                        let stats = self.stats().await;
+                        let _ = self.event_tx.send(VoiceEvent::Stats(stats));
+                    }
+                }
                 else => break,
             }
         }
     }
 
+    async fn stats(&self) -> VoiceStats {
+        let mut peers: Vec<PeerStats> = self
+            .receive_stats
+            .lock()
+            .map(|stats| {
+                stats
+                    .iter()
+                    .map(|(peer_id, stats)| PeerStats {
+                        peer_id: peer_id.clone(),
+                        loss_percent: stats.loss_percent(),
+                        jitter_ms: stats.jitter_ms(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        let engine = self.audio_engine.lock().await;
+        let (trimmed_ms, padded_ms) = engine.drift_ms();
+        VoiceStats { peers, underruns: engine.underruns(), trimmed_ms, padded_ms }
+    }
+
+    /// Try to reopen a failed audio stream on another device, and describe
+    /// what happened for the UI
+    async fn recover_audio(&self, err: AudioDeviceError) -> String {
+        if !self.is_joined.load(Ordering::Relaxed) {
+            return match err {
+                AudioDeviceError::OutputDeviceError { error, .. } => format!("Output device error: {}", error),
+                AudioDeviceError::InputDeviceError(e) => format!("Input device error: {}", e),
+                AudioDeviceError::OutputDeviceSelected(name) => format!("Playing through {}", name),
+            };
+        }
+        let mut audio = self.audio_engine.lock().await;
+        match err {
+            AudioDeviceError::OutputDeviceError { peer_id, error } => match audio.recover_output(&peer_id) {
+                Ok(name) => format!("Output device lost ({}), switched to {}", error, name),
+                Err(recover_err) => format!("Output device lost ({}), no fallback: {}", error, recover_err),
+            },
+            AudioDeviceError::InputDeviceError(e) => match audio.recover_input() {
+                Ok(name) => format!("Input device lost ({}), switched to {}", e, name),
+                Err(recover_err) => format!("Input device lost ({}), no fallback: {}", e, recover_err),
+            },
+            AudioDeviceError::OutputDeviceSelected(name) => format!("Playing through {}", name),
+        }
+    }
+
     async fn join_voice(&mut self, room_id: String) -> Result<()> {
         // Send Connecting event immediately
         let _ = self.event_tx.send(VoiceEvent::Connecting);
@@ -198,6 +275,7 @@ impl VoiceManager {
         }
         self.local_track = None;
         self.pending_candidates.clear();
+        self.pending_answers.clear();
         
         // Close any existing peer connections from previous session
         {
@@ -210,16 +288,13 @@ impl VoiceManager {
         self.room_id = Some(room_id.clone());
         self.is_joined.store(true, Ordering::Relaxed);
         
-        // 1. Setup Audio Engine
+        // 1. Setup Audio Engine. Without a microphone we still join to
+        // listen; the track below just never gets samples.
         let (encoded_tx, mut encoded_rx) = mpsc::unbounded_channel();
         {
             let mut audio = self.audio_engine.lock().await;
             if let Err(e) = audio.start_capture(encoded_tx) {
-                let err_msg = format!("Failed to start microphone: {}", e);
-                self.is_joined.store(false, Ordering::Relaxed);
-                self.room_id = None;
-                let _ = self.event_tx.send(VoiceEvent::ConnectionFailed(err_msg));
-                return Err(anyhow::anyhow!("Microphone capture failed"));
+                let _ = self.event_tx.send(VoiceEvent::ListenOnly(e.to_string()));
             }
         }
 
@@ -237,22 +312,41 @@ impl VoiceManager {
         // 3. Spawn Task to feed audio to track
         let is_muted = self.is_muted.clone();
         let event_tx = self.event_tx.clone();
+        let frame_duration = std::time::Duration::from_millis(self.frame_ms as u64);
         tokio::spawn(async move {
-            while let Some(packet) = encoded_rx.recv().await {
+            let mut activity = TxActivity::default();
+            loop {
+                let received = match activity.idle_at() {
+                    Some(idle_at) => tokio::time::timeout_at(idle_at.into(), encoded_rx.recv()).await,
+                    None => Ok(encoded_rx.recv().await),
+                };
+                let packet = match received {
+                    Ok(Some(packet)) => packet,
+                    Ok(None) => break,
+                    // Nothing sent for a while (mute, DTX or silence): tell the UI we're quiet
+                    Err(_) => {
+                        if activity.idle(Instant::now()) {
+                            let _ = event_tx.send(VoiceEvent::TxActivity(false));
+                        }
+                        continue;
+                    }
+                };
                 // Check mute state
                 if is_muted.load(Ordering::Relaxed) {
                     continue; // Skip sending packets
                 }
 
-                let _ = event_tx.send(VoiceEvent::TxActivity(true));
+                if activity.sent(Instant::now()) {
+                    let _ = event_tx.send(VoiceEvent::TxActivity(true));
+                }
                 
                 // Send sample to WebRTC track
                 let sample = Sample {
                     data: packet.into(),
-                    duration: std::time::Duration::from_millis(20), // 20ms frame
+                    duration: frame_duration,
                     ..Default::default()
                 };
-                if let Err(_) = track.write_sample(&sample).await {
+                if track.write_sample(&sample).await.is_err() {
                     break;
                 }
             }
@@ -271,7 +365,7 @@ impl VoiceManager {
         Ok(())
     }
 
-    async fn create_peer_connection(&self, remote_user_id: String, initiate_offer: bool) -> Result<Arc<RTCPeerConnection>> {
+    async fn create_peer_connection(&mut self, remote_user_id: String, initiate_offer: bool) -> Result<Arc<RTCPeerConnection>> {
         // Close any existing peer connection to this user first
         // This handles the case where a user leaves and rejoins quickly
         {
@@ -379,11 +473,13 @@ impl VoiceManager {
         let is_joined = self.is_joined.clone();
         let event_tx_clone = self.event_tx.clone();
         let remote_user_for_track = remote_user_id.clone();
+        let receive_stats_clone = self.receive_stats.clone();
         pc.on_track(Box::new(move |track, _, _| {
             let audio_engine = audio_engine_clone.clone();
             let joined_state = is_joined.clone();
             let event_tx = event_tx_clone.clone();
             let peer_id = remote_user_for_track.clone();
+            let receive_stats = receive_stats_clone.clone();
             Box::pin(async move {
                 if !joined_state.load(Ordering::Relaxed) {
                     return;
@@ -400,8 +496,20 @@ impl VoiceManager {
                 }
 
                 // Loop reading RTP packets
+                let started = std::time::Instant::now();
                 while let Ok((rtp, _attr)) = track.read_rtp().await {
-                    let _ = packet_tx.send(rtp.payload.to_vec());
+                    if let Ok(mut stats) = receive_stats.lock() {
+                        stats.entry(peer_id.clone()).or_default().record(
+                            rtp.header.sequence_number,
+                            rtp.header.timestamp,
+                            started.elapsed(),
+                        );
+                    }
+                    // Receiver gone: the peer's stream was removed or replaced
+                    let packet = ReceivedPacket { seq: rtp.header.sequence_number, payload: rtp.payload.to_vec() };
+                    if packet_tx.send(packet).is_err() {
+                        break;
+                    }
                 }
             })
         }));
@@ -416,16 +524,33 @@ impl VoiceManager {
             // Send Offer
             if let Ok(json) = serde_json::to_string(&offer) {
                 self.event_tx.send(VoiceEvent::Signal {
-                    target_id: Some(remote_user_id),
+                    target_id: Some(remote_user_id.clone()),
                     signal_type: "offer".to_string(),
                     data: json,
                 })?;
             }
+
+            // An answer that beat us here can be applied now
+            if let Some(answer) = self.pending_answers.remove(&remote_user_id) {
+                self.apply_remote_description(&remote_user_id, &pc, answer).await?;
+            }
         }
 
         Ok(pc)
     }
 
+    /// Set the peer's offer or answer, then add the ICE candidates that were
+    /// waiting for it
+    async fn apply_remote_description(&mut self, peer_id: &str, pc: &RTCPeerConnection, desc: RTCSessionDescription) -> Result<()> {
+        pc.set_remote_description(desc).await?;
+        if let Some(candidates) = self.pending_candidates.remove(peer_id) {
+            for candidate in candidates {
+                let _ = pc.add_ice_candidate(candidate).await;
+            }
+        }
+        Ok(())
+    }
+
     async fn leave_voice(&mut self) -> Result<()> {
         // Set joined flag to false FIRST to stop on_track callbacks
         self.is_joined.store(false, Ordering::Relaxed);
@@ -450,6 +575,10 @@ impl VoiceManager {
         
         // Clear pending candidates
         self.pending_candidates.clear();
+        self.pending_answers.clear();
+        if let Ok(mut stats) = self.receive_stats.lock() {
+            stats.clear();
+        }
         
         // Close all peers and wait for completion (don't fire-and-forget)
         {
@@ -494,14 +623,8 @@ impl VoiceManager {
                 }
                 let pc = self.create_peer_connection(sender_id.to_string(), false).await?;
                 let desc: RTCSessionDescription = serde_json::from_str(data)?;
-                pc.set_remote_description(desc).await?;
-                
-                // Bug 1 fix: apply any buffered ICE candidates that arrived before the offer
-                if let Some(candidates) = self.pending_candidates.remove(sender_id) {
-                    for candidate in candidates {
-                        let _ = pc.add_ice_candidate(candidate).await;
-                    }
-                }
+                // Bug 1 fix: this also applies ICE candidates that arrived before the offer
+                self.apply_remote_description(sender_id, &pc, desc).await?;
                 
                 let answer = pc.create_answer(None).await?;
                 pc.set_local_description(answer.clone()).await?;
@@ -515,26 +638,39 @@ impl VoiceManager {
                 }
             }
             "answer" => {
-                let peers = self.peers.lock().await;
-                if let Some(pc) = peers.get(sender_id) {
-                    let desc: RTCSessionDescription = serde_json::from_str(data)?;
-                    pc.set_remote_description(desc).await?;
+                let desc: RTCSessionDescription = serde_json::from_str(data)?;
+                let pc = self.peers.lock().await.get(sender_id).cloned();
+                match pc {
+                    // Only an outstanding offer can take an answer
+                    Some(pc) if pc.signaling_state() == RTCSignalingState::HaveLocalOffer => {
+                        self.apply_remote_description(sender_id, &pc, desc).await?;
+                    }
+                    Some(pc) => {
+                        return Err(anyhow::anyhow!("no offer outstanding ({})", pc.signaling_state()));
+                    }
+                    // Reordered ahead of our offer: hold it until the offer is out
+                    None if self.is_joined.load(Ordering::Relaxed) => {
+                        self.pending_answers.insert(sender_id.to_string(), desc);
+                    }
+                    None => {}
                 }
             }
             "candidate" => {
-                let peers = self.peers.lock().await;
-                if let Some(pc) = peers.get(sender_id) {
-                    // Peer connection exists - add candidate directly
-                    let candidate: RTCIceCandidateInit = serde_json::from_str(data)?;
-                    pc.add_ice_candidate(candidate).await?;
-                } else {
-                    // Bug 1 fix: peer connection doesn't exist yet (candidate arrived
-                    // before the offer). Buffer it for later application.
-                    let candidate: RTCIceCandidateInit = serde_json::from_str(data)?;
-                    self.pending_candidates
-                        .entry(sender_id.to_string())
-                        .or_insert_with(Vec::new)
-                        .push(candidate);
+                let candidate: RTCIceCandidateInit = serde_json::from_str(data)?;
+                let pc = self.peers.lock().await.get(sender_id).cloned();
+                match pc {
+                    // Peer connection ready for candidates - add it directly
+                    Some(pc) if pc.remote_description().await.is_some() => {
+                        pc.add_ice_candidate(candidate).await?;
+                    }
+                    // Bug 1 fix: no peer connection or remote description yet (the
+                    // candidate beat the offer or answer). Buffer it for later.
+                    _ => {
+                        self.pending_candidates
+                            .entry(sender_id.to_string())
+                            .or_default()
+                            .push(candidate);
+                    }
                 }
             }
             "leave_voice" => {
@@ -548,6 +684,7 @@ impl VoiceManager {
                     audio.remove_peer_stream(sender_id);
                 }
                 self.pending_candidates.remove(sender_id);
+                self.pending_answers.remove(sender_id);
                 let _ = self.event_tx.send(VoiceEvent::PeerDisconnected(sender_id.to_string()));
             }
             _ => {}
@@ -555,3 +692,26 @@ impl VoiceManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tx_activity_reports_transitions_only() {
+        let start = Instant::now();
+        let at = |ms| start + std::time::Duration::from_millis(ms);
+        let mut activity = TxActivity::default();
+        assert_eq!(activity.idle_at(), None, "nothing to wait for before the first packet");
+        assert!(!activity.idle(at(0)));
+
+        assert!(activity.sent(at(0)));
+        assert!(!activity.sent(at(20)), "later packets in a burst stay quiet");
+        assert_eq!(activity.idle_at(), Some(at(20) + TX_IDLE_AFTER));
+        assert!(!activity.idle(at(100)));
+
+        assert!(activity.idle(at(20) + TX_IDLE_AFTER));
+        assert!(!activity.idle(at(1000)), "only one false per burst");
+        assert!(activity.sent(at(1000)));
+    }
+}
@@ -1,2 +1,76 @@
+//! Voice chat. The WebRTC and audio side needs the `voice` feature; the
+//! command and event types the UI is written against are always here, so
+//! without it the app builds with `voice_tx` left empty.
+
+#[cfg(feature = "voice")]
 pub mod manager;
+#[cfg(feature = "voice")]
 pub mod audio;
+pub mod stats;
+
+use crate::voice::stats::VoiceStats;
+
+/// Status line text for voice commands when there's no voice manager
+#[cfg(feature = "voice")]
+pub const UNAVAILABLE: &str = "Voice Chat not initialized.";
+#[cfg(not(feature = "voice"))]
+pub const UNAVAILABLE: &str = "Voice support not compiled in.";
+
+/// Voice connection status for UI display
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum VoiceConnectionStatus {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    /// In a call whose signaling socket dropped; audio carries on while the
+    /// chat connection comes back
+    Reconnecting,
+}
+
+/// Events sent from VoiceManager to main app
+/// ALL voice state changes in the app should be driven by these events
+#[derive(Debug)]
+#[cfg_attr(not(feature = "voice"), allow(dead_code))]
+pub enum VoiceEvent {
+    /// WebRTC signaling data to send to server
+    Signal { target_id: Option<String>, signal_type: String, data: String },
+    
+    /// Voice connection state changes (single source of truth)
+    Connecting,                    // Starting to join voice
+    Connected,                     // Successfully joined and audio is ready
+    Disconnected,                  // Clean disconnect completed
+    ConnectionFailed(String),      // Failed to connect (with reason)
+    ListenOnly(String),            // Joined without a microphone (with reason)
+    
+    /// Peer connection state changes  
+    PeerConnected(String),         // WebRTC connection to peer established
+    PeerDisconnected(String),      // WebRTC connection to peer lost
+    PeerConnectionFailed(String),  // Failed to connect to specific peer
+    /// A signaling message from a peer couldn't be applied
+    SignalingFailed { peer_id: String, reason: String },
+    
+    /// Mute state changes (single source of truth)
+    MuteStateChanged(bool),        // true = muted, false = unmuted
+    
+    /// Transmit activity for UI indicator
+    TxActivity(bool),              // true = transmitting, false = quiet
+    
+    /// Audio system errors
+    AudioError(String),            // Audio device/stream error
+
+    /// Call quality, sent about once a second while joined
+    Stats(VoiceStats),
+}
+
+#[cfg_attr(not(feature = "voice"), allow(dead_code))]
+pub enum VoiceCommand {
+    Join(String),
+    Leave,
+    /// Leave, then signal once peers and audio are torn down
+    LeaveAndNotify(tokio::sync::oneshot::Sender<()>),
+    Mute(bool),
+    /// Playback volume for one peer (by user id), 0.0 silences them
+    SetPeerVolume { peer_id: String, volume: f32 },
+    Signal { sender_id: String, signal_type: String, data: String },
+}
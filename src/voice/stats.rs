@@ -0,0 +1,104 @@
+//! Receive-side quality numbers for the diagnostics view. webrtc-rs doesn't
+//! report loss or jitter for incoming streams, so they're worked out from
+//! the RTP headers as RFC 3550 (appendix A.3 and A.8) describes.
+
+#[cfg(feature = "voice")]
+use std::time::Duration;
+
+/// Opus RTP clock rate
+#[cfg(feature = "voice")]
+const CLOCK_RATE: f64 = 48_000.0;
+
+/// Loss and jitter of one peer's incoming stream
+#[cfg(feature = "voice")]
+#[derive(Debug, Default, Clone)]
+pub struct ReceiveStats {
+    /// First and highest sequence numbers seen, extended past 16-bit wraps
+    base_seq: Option<i64>,
+    max_seq: i64,
+    received: u64,
+    /// Interarrival jitter in RTP timestamp units
+    jitter: f64,
+    /// Arrival time (in timestamp units) and RTP timestamp of the last packet
+    last: Option<(f64, u32)>,
+}
+
+#[cfg(feature = "voice")]
+impl ReceiveStats {
+    /// Note a packet that arrived `arrival` after the stream started
+    pub fn record(&mut self, seq: u16, timestamp: u32, arrival: Duration) {
+        let seq = match self.base_seq {
+            None => {
+                self.base_seq = Some(seq as i64);
+                seq as i64
+            }
+            // The sequence number moves by a signed 16-bit step from the highest seen
+            Some(_) => self.max_seq + seq.wrapping_sub(self.max_seq as u16) as i16 as i64,
+        };
+        self.max_seq = self.max_seq.max(seq);
+        self.received += 1;
+
+        let arrival = arrival.as_secs_f64() * CLOCK_RATE;
+        if let Some((last_arrival, last_timestamp)) = self.last {
+            let sent = timestamp.wrapping_sub(last_timestamp) as i32 as f64;
+            let d = ((arrival - last_arrival) - sent).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last = Some((arrival, timestamp));
+    }
+
+    /// Share of expected packets that never arrived, 0-100
+    pub fn loss_percent(&self) -> f64 {
+        let Some(base) = self.base_seq else { return 0.0 };
+        let expected = (self.max_seq - base + 1) as f64;
+        let lost = (expected - self.received as f64).max(0.0);
+        lost * 100.0 / expected
+    }
+
+    pub fn jitter_ms(&self) -> f64 {
+        self.jitter * 1000.0 / CLOCK_RATE
+    }
+}
+
+/// One peer's line in `VoiceStats`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(not(feature = "voice"), allow(dead_code))]
+pub struct PeerStats {
+    pub peer_id: String,
+    pub loss_percent: f64,
+    pub jitter_ms: f64,
+}
+
+/// Snapshot the voice manager sends the app while in a call
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VoiceStats {
+    pub peers: Vec<PeerStats>,
+    /// Playback callbacks that ran out of audio partway through
+    pub underruns: u64,
+    /// Audio dropped to keep playback from running long, in ms
+    pub trimmed_ms: u64,
+    /// Silence inserted to keep playback from running dry, in ms
+    pub padded_ms: u64,
+}
+
+#[cfg(all(test, feature = "voice"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_and_jitter() {
+        // 20ms frames arriving on time, with a sequence wrap and one packet missing
+        let mut stats = ReceiveStats::default();
+        for (i, seq) in [65534u16, 65535, 0, 2, 3].into_iter().enumerate() {
+            let frame = seq.wrapping_sub(65534) as u32;
+            stats.record(seq, frame * 960, Duration::from_millis(frame as u64 * 20));
+            assert!(stats.jitter_ms() < 0.01, "packet {}", i);
+        }
+        assert_eq!(stats.loss_percent(), 100.0 / 6.0);
+
+        // A packet 16ms late moves the estimate by 1/16th of the delay
+        stats.record(4, 6 * 960, Duration::from_millis(6 * 20 + 16));
+        assert!((stats.jitter_ms() - 1.0).abs() < 0.01, "{}", stats.jitter_ms());
+        assert_eq!(ReceiveStats::default().loss_percent(), 0.0);
+    }
+}